@@ -0,0 +1,71 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// The system allocator, counting the compiler's allocations and how many
+/// bytes are allocated at once, so each phase's timing can report the memory
+/// it used. Only enabled with the `alloc-stats` feature, since counting slows
+/// every allocation down. LLVM allocates through its own allocator, so only
+/// the compiler's own memory is counted.
+struct Counting;
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// The bytes allocated right now.
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+/// The most bytes allocated at once since the last call to [`take()`].
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+/// The allocations made since the last call to [`take()`].
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+impl Counting {
+    fn grow(&self, size: usize) {
+        let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(current, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            self.grow(new_size);
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+/// Gets the most bytes allocated at once and the number of allocations made
+/// since the last call, i.e. during the phase that just finished, and starts
+/// counting the next phase's.
+pub fn take() -> (usize, u64) {
+    let peak = PEAK.swap(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+    (peak, ALLOCATIONS.swap(0, Ordering::Relaxed))
+}
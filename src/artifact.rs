@@ -0,0 +1,99 @@
+use std::{
+    error::Error,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// An output file being written. It's written under a temporary name beside
+/// where it's going, then renamed into place by [`Artifact::commit`] once it's
+/// complete, so an interrupted or failed build never leaves a truncated output
+/// behind for the linker or other tools to trip over. Staying in the same
+/// directory keeps the rename on the same filesystem, where it's atomic.
+///
+/// If it's dropped without being committed, the temporary file is removed.
+pub struct Artifact {
+    temp: PathBuf,
+    dest: PathBuf,
+    committed: bool,
+}
+
+impl Artifact {
+    /// Starts writing an output to `dest`, creating the directories it goes in.
+    pub fn new(dest: &Path) -> Result<Artifact, Box<dyn Error>> {
+        let Some(name) = dest.file_name().filter(|_| !dest.is_dir()) else {
+            return Err(format!("output `{}` isn't a file name", dest.display()).into());
+        };
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // hidden, and named for the process, so concurrent builds don't share
+        // it
+        let temp = dest.with_file_name(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+        Ok(Artifact { temp, dest: dest.to_owned(), committed: false })
+    }
+
+    /// Gets the temporary path to write the output to, for tools that take a
+    /// path. Nothing is there until something writes to it.
+    pub fn path(&self) -> &Path {
+        &self.temp
+    }
+
+    /// Creates the temporary file to write the output to.
+    pub fn create(&self) -> Result<File, Box<dyn Error>> {
+        Ok(File::create(&self.temp)?)
+    }
+
+    /// Moves the finished output into place, replacing anything there.
+    pub fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        std::fs::rename(&self.temp, &self.dest)
+            .map_err(|e| format!("failed to write `{}`: {}", self.dest.display(), e))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for Artifact {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Checks that an output can be written to `path`: that it isn't one of the
+/// `inputs`, and that nothing is there already, unless `force` is given.
+pub fn check_output<'a>(
+    path: &Path,
+    inputs: impl IntoIterator<Item = &'a Path>,
+    force: bool
+) -> Result<(), Box<dyn Error>> {
+    let same = |input: &Path| {
+        input == path || std::fs::canonicalize(input)
+            .ok()
+            .zip(std::fs::canonicalize(path).ok())
+            .is_some_and(|(input, path)| input == path)
+    };
+    for input in inputs {
+        if same(input) {
+            return Err(format!("output `{}` is also an input", path.display()).into());
+        }
+    }
+    if path.exists() && !force {
+        return Err(format!(
+            "output `{}` already exists; pass `--force` to overwrite it",
+            path.display()
+        ).into());
+    }
+    Ok(())
+}
+
+/// Writes a whole output file at once, through an [`Artifact`].
+pub fn write(dest: &Path, contents: impl AsRef<[u8]>) -> Result<(), Box<dyn Error>> {
+    let artifact = Artifact::new(dest)?;
+    std::fs::write(artifact.path(), contents)?;
+    artifact.commit()
+}
@@ -0,0 +1,486 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fmt::Write, time::Instant};
+
+use crate::{
+    artifact::{self, Artifact},
+    bin::{self, LinkOptions},
+    error::Diagnostics,
+    get_output_path,
+    limits::MAX_DIRECT_PARAMS,
+    llvm::PrintFormat,
+    mangle::mangle,
+    messages::Messages,
+    parse::{Contract, Expr},
+    prelude,
+    read_export_list,
+    temp::TempDir,
+    timings::DeclTimes,
+    Args,
+    FloatModel,
+    FloatWidth,
+    Inputs,
+    OutputType,
+};
+
+use super::Backend;
+
+/// C's keywords, which Foo identifiers have to be renamed from.
+const C_KEYWORDS: [&str; 44] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double",
+    "else", "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long",
+    "register", "restrict", "return", "short", "signed", "sizeof", "static", "struct",
+    "switch", "typedef", "union", "unsigned", "void", "volatile", "while", "_Alignas",
+    "_Alignof", "_Atomic", "_Bool", "_Complex", "_Generic", "_Imaginary", "_Noreturn",
+    "_Static_assert", "_Thread_local",
+];
+
+/// Defines `FOO_SYMBOL(name)`, which gives a function declared with it the
+/// symbol `name` under GCC and Clang, with the platform's prefix for C symbols
+/// (e.g. `_` on macOS), and does nothing under compilers without asm labels.
+const SYMBOL_MACRO: &str = "\
+#ifdef __GNUC__
+#define FOO_STRINGIFY(x) #x
+#define FOO_PREFIX(x) FOO_STRINGIFY(x)
+#define FOO_SYMBOL(name) __asm__(FOO_PREFIX(__USER_LABEL_PREFIX__) name)
+#else
+#define FOO_SYMBOL(name)
+#endif
+
+";
+
+/// Translates the program into readable C, to write as it is, or to compile
+/// with the system's C compiler into an object or executable.
+///
+/// Foo functions become `static` C functions prefixed with `foo_` (or keep
+/// their names if they're exported), declared with the same symbols as the
+/// LLVM backend gives them, [`mangle`]d, wherever the C compiler can rename
+/// them. The top-level `let`s become variables of
+/// `main`, and `main` prints the result with `printf`, as the LLVM backend's
+/// does. Every number is a `double`, and functions with more than
+/// [`MAX_DIRECT_PARAMS`] parameters take a `const double *` to an array of
+/// their arguments, as they do in the LLVM backend.
+pub struct CBackend<'a> {
+    args: &'a Args,
+    inputs: &'a Inputs<'a>,
+    messages: Messages,
+    /// The C source, once it's generated.
+    code: String,
+    /// The symbols to export from an executable.
+    exports: Vec<String>,
+    /// The time spent translating each declaration.
+    decl_times: DeclTimes,
+}
+
+impl<'a> CBackend<'a> {
+    /// Checks that the options are ones the C backend supports.
+    pub fn new(
+        args: &'a Args,
+        inputs: &'a Inputs<'a>,
+        float_model: FloatModel,
+        float_width: FloatWidth,
+        messages: Messages
+    ) -> Result<Self, Box<dyn Error>> {
+        if !matches!(args.produce, OutputType::C | OutputType::Object | OutputType::Executable) {
+            return Err(
+                "the C backend only produces C source, objects, or executables".into()
+            );
+        }
+        // the code is only as portable as the C compiler makes it, so these are
+        // all left to it
+        let unsupported = [
+            (!args.target.is_empty(), "--target"),
+            (!inputs.ir.is_empty(), "LLVM IR or bitcode inputs"),
+            (float_model != FloatModel::Strict, "relaxed floating point"),
+            (float_width != FloatWidth::F64, "floats other than 64-bit"),
+            (!args.instrument.is_empty() || args.profile_generate, "instrumentation"),
+            (args.profile_use.is_some(), "--profile-use"),
+            (
+                args.debug_info || args.asm_source_comments || args.with_cpp_line_directives,
+                "debug info"
+            ),
+            (!args.emit.is_empty(), "--emit"),
+            (args.remarks.is_some(), "--remarks"),
+            (args.stack_probes, "--stack-probes"),
+            (args.opt_level.is_some(), "-O"),
+        ];
+        if let Some((_, option)) = unsupported.iter().find(|(given, _)| *given) {
+            return Err(format!("the C backend doesn't support {}", option).into());
+        }
+        Ok(CBackend {
+            args,
+            inputs,
+            messages,
+            code: String::new(),
+            exports: vec![],
+            decl_times: DeclTimes::default(),
+        })
+    }
+}
+
+impl<'a> Backend<'a> for CBackend<'a> {
+    fn generate(&mut self, ast: &'a Expr, _: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        let mut exports = match &args.export_list {
+            Some(path) => read_export_list(path)?,
+            None => vec![],
+        };
+        let mut translator = Translator::default();
+        let module = args.module_name.clone().unwrap_or_else(|| {
+            self.inputs.src.file_stem().unwrap().to_string_lossy().into_owned()
+        });
+
+        // functions only see their own parameters, so they can all be moved
+        // out to the top level of the C file, leaving the `let`s and the final
+        // expression for `main`
+        let mut externs = String::new();
+        let mut functions = vec![];
+        let mut main = String::new();
+        let mut expr = ast;
+        let result = loop {
+            let started = Instant::now();
+            match expr {
+                Expr::Let { name, rhs, then, .. } => {
+                    let rhs = translator.expr(rhs, 0);
+                    let var = translator.declare(&name.0);
+                    let _ = writeln!(main, "    double {} = {};", var, rhs);
+                    self.decl_times.record(expr, started.elapsed());
+                    expr = then;
+                }
+                Expr::Fn { export, name, args: params, contracts, body, then, .. } => {
+                    if *export && !exports.contains(&name.0) {
+                        exports.push(name.0.clone());
+                    }
+                    let (symbol, mangled) = if exports.contains(&name.0) {
+                        (name.0.clone(), None)
+                    } else {
+                        let mangled = mangle(&module, &name.0, params.len());
+                        (format!("foo_{}", c_ident(&name.0)), Some(mangled))
+                    };
+                    // a function's body only sees its parameters, and can call
+                    // itself
+                    translator.functions.insert(name.0.clone(), symbol.clone());
+                    let globals = (
+                        std::mem::take(&mut translator.vars),
+                        std::mem::take(&mut translator.taken),
+                    );
+                    let names = params.iter()
+                        .map(|(param, _)| translator.declare(param))
+                        .collect::<Vec<_>>();
+                    let mut unpack = String::new();
+                    let params = if params.len() > MAX_DIRECT_PARAMS {
+                        translator.arg_arrays.insert(name.0.clone());
+                        let array = translator.fresh("args");
+                        for (i, name) in names.iter().enumerate() {
+                            let _ = writeln!(
+                                unpack,
+                                "    const double {} = {}[{}];",
+                                name,
+                                array,
+                                i
+                            );
+                        }
+                        vec![format!("const double *{}", array)]
+                    } else {
+                        names.iter().map(|name| format!("double {}", name)).collect()
+                    };
+                    let body = translator.expr(body, 0);
+                    (translator.vars, translator.taken) = globals;
+                    functions.push((symbol, mangled, params, unpack, contracts, body));
+                    self.decl_times.record(expr, started.elapsed());
+                    expr = then;
+                }
+                Expr::Extern { name, args: params, then, .. } => {
+                    let params = params.iter()
+                        .map(|(param, _)| format!("double {}", c_ident(param)))
+                        .collect::<Vec<_>>();
+                    let _ = writeln!(
+                        externs,
+                        "double {}({});",
+                        name.0,
+                        params_list(&params)
+                    );
+                    translator.functions.insert(name.0.clone(), name.0.clone());
+                    self.decl_times.record(expr, started.elapsed());
+                    expr = then;
+                }
+                expr => break expr,
+            }
+        };
+
+        // once any function is exported the rest are kept private, as they are
+        // in the LLVM backend
+        let mut definitions = String::new();
+        // the mangled symbols are given in declarations, since an asm label
+        // can't go on a definition
+        let mut declarations = String::new();
+        for (symbol, mangled, params, unpack, contracts, body) in functions {
+            for (Contract { text, .. }, _) in contracts {
+                let _ = writeln!(definitions, "/* {} */", text.replace("*/", "* /"));
+            }
+            let private = !exports.is_empty() && !exports.contains(&symbol);
+            let linkage = if private { "static " } else { "" };
+            if let Some(mangled) = mangled {
+                let _ = writeln!(
+                    declarations,
+                    "{}double {}({}) FOO_SYMBOL(\"{}\");",
+                    linkage,
+                    symbol,
+                    params_list(&params),
+                    mangled
+                );
+            }
+            let _ = writeln!(
+                definitions,
+                "{}double {}({}) {{\n{}    return {};\n}}\n",
+                linkage,
+                symbol,
+                params_list(&params),
+                unpack,
+                body
+            );
+        }
+
+        let mut code = format!(
+            "/* Generated by foo_llvm from {}. */\n\n#include <math.h>\n\n\
+            int printf(const char *format, ...);\n\n",
+            self.inputs.src.file_name().unwrap().to_string_lossy()
+        );
+        if !externs.is_empty() {
+            code.push_str(&externs);
+            code.push('\n');
+        }
+        if !declarations.is_empty() {
+            code.push_str(SYMBOL_MACRO);
+            code.push_str(&declarations);
+            code.push('\n');
+        }
+        code.push_str(&definitions);
+        if !args.no_main {
+            let started = Instant::now();
+            let translated = match &args.entry {
+                Some(entry) => format!("{}()", translator.call_name(entry)),
+                None => translator.expr(result, 0),
+            };
+            self.decl_times.record(result, started.elapsed());
+            let format = PrintFormat {
+                notation: args.print_format,
+                precision: args.precision,
+                newline: !args.no_newline,
+            }.to_format_string(FloatWidth::F64);
+            let _ = write!(
+                code,
+                "int {}(void) {{\n{}    printf(\"{}\", {});\n    return 0;\n}}\n",
+                args.entry_symbol,
+                main,
+                format.escape_default(),
+                translated
+            );
+        }
+        self.code = code;
+        self.exports = exports;
+        Ok(())
+    }
+
+    fn decl_times(&self) -> DeclTimes {
+        self.decl_times.clone()
+    }
+
+    fn emit(&mut self, _: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        if args.produce == OutputType::C {
+            let path = get_output_path(args.output.clone(), "foo.c")?;
+            self.inputs.check_output(&path, args.force)?;
+            artifact::write(&path, &self.code)?;
+            self.messages.artifact("c", &path);
+            return Ok(());
+        }
+
+        TempDir::scoped(self.messages, |temp| {
+            let link_options = LinkOptions {
+                linker: args.linker,
+                pie: !args.no_pie,
+                exports: &self.exports,
+                reproducible: args.reproducible,
+                temp_dir: temp.path(),
+                messages: self.messages,
+            };
+            let c_path = temp.file("foo.c");
+            std::fs::write(&c_path, &self.code)?;
+
+            if args.produce == OutputType::Object {
+                let path = get_output_path(args.output.clone(), "foo.o")?;
+                self.inputs.check_output(&path, args.force)?;
+                let object = Artifact::new(&path)?;
+                bin::compile_c(&c_path, object.path(), &link_options)?;
+                object.commit()?;
+                self.messages.artifact("object", &path);
+                return Ok(());
+            }
+
+            let out_path = get_output_path(args.output.clone(), "foo")?;
+            self.inputs.check_output(&out_path, args.force)?;
+            let obj_path = temp.file("foo.o");
+            bin::compile_c(&c_path, &obj_path, &link_options)?;
+            let mut objects = vec![obj_path];
+            // numbered, since C files from different directories can share a
+            // name
+            for (i, c) in self.inputs.c.iter().enumerate() {
+                let path = temp.file(&format!(
+                    "{}_{}.o",
+                    i,
+                    c.file_stem().unwrap().to_string_lossy()
+                ));
+                bin::compile_c(c, &path, &link_options)?;
+                objects.push(path);
+            }
+            objects.extend(self.inputs.objects.iter().copied().cloned());
+            let out = Artifact::new(&out_path)?;
+            bin::try_to_bin(&objects, out.path(), &link_options)?;
+            out.commit()?;
+            self.messages.artifact("executable", &out_path);
+            Ok(())
+        })
+    }
+}
+
+/// Translates expressions to C, keeping track of what each Foo name is called
+/// in C.
+#[derive(Default)]
+struct Translator {
+    /// The C name of each variable in scope.
+    vars: HashMap<String, String>,
+    /// The C name of each function declared so far.
+    functions: HashMap<String, String>,
+    /// The functions taking their arguments in an array, by their Foo names.
+    arg_arrays: HashSet<String>,
+    /// Every C name given to a variable, so that a variable shadowing another
+    /// gets a name of its own.
+    taken: HashSet<String>,
+}
+
+impl Translator {
+    /// Declares a variable, returning the C name [`fresh()`] takes for it.
+    ///
+    /// [`fresh()`]:    Self::fresh()
+    fn declare(&mut self, name: &str) -> String {
+        let c_name = self.fresh(name);
+        self.vars.insert(name.to_owned(), c_name.clone());
+        c_name
+    }
+
+    /// Takes a C name for a variable: its own, unless that's a keyword or
+    /// already taken, in which case it's numbered.
+    fn fresh(&mut self, name: &str) -> String {
+        let base = c_ident(name);
+        let mut c_name = base.clone();
+        let mut n = 2;
+        // a variable named like a function would hide it from calls
+        while C_KEYWORDS.contains(&c_name.as_str())
+            || self.taken.contains(&c_name)
+            || self.functions.values().any(|function| *function == c_name)
+        {
+            c_name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        self.taken.insert(c_name.clone());
+        c_name
+    }
+
+    /// Gets the C name of the function a call calls: a Foo or `extern`
+    /// function declared before it, or else a builtin.
+    fn call_name(&self, name: &str) -> String {
+        match (self.functions.get(name), name) {
+            (Some(c_name), _) => c_name.clone(),
+            (None, "abs") => "fabs".to_owned(),
+            (None, "min") => "fmin".to_owned(),
+            (None, "max") => "fmax".to_owned(),
+            (None, name) if prelude::builtin(name).is_some() => name.to_owned(),
+            // left for the C compiler to report
+            (None, name) => c_ident(name),
+        }
+    }
+
+    /// Translates an expression, parenthesized if it binds less tightly than
+    /// `precedence` (0 for none, 1 for `+` and `-`, 2 for `*` and `/`, and 3
+    /// for negation).
+    fn expr(&self, expr: &Expr, precedence: u8) -> String {
+        let (code, own) = match expr {
+            Expr::Num(value, _) => (format!("{:?}", value), 4),
+            Expr::Var(name, _) => (
+                self.vars.get(name).cloned().unwrap_or_else(|| c_ident(name)),
+                4
+            ),
+            Expr::Neg(expr, _) => {
+                let operand = self.expr(expr, 3);
+                // `--` would decrement
+                let space = if operand.starts_with('-') { " " } else { "" };
+                (format!("-{}{}", space, operand), 3)
+            }
+            Expr::Add(left, right, _) => (self.binary(left, "+", right, 1), 1),
+            Expr::Sub(left, right, _) => (self.binary(left, "-", right, 1), 1),
+            Expr::Mul(left, right, _) => (self.binary(left, "*", right, 2), 2),
+            Expr::Div(left, right, _) => (self.binary(left, "/", right, 2), 2),
+            // C gives comparisons an `int` of 1 or 0, and doesn't order them
+            // all the same, so each is parenthesized on its own
+            Expr::Cmp(op, left, right, _) => (
+                format!(
+                    "(double)({} {} {})",
+                    self.expr(left, 1),
+                    op.symbol(),
+                    self.expr(right, 1)
+                ),
+                3
+            ),
+            Expr::Call((name, _), args, _) => {
+                let args = args.iter().map(|arg| self.expr(arg, 0)).collect::<Vec<_>>();
+                let args = if self.arg_arrays.contains(name) {
+                    format!("(const double[]){{{}}}", args.join(", "))
+                } else {
+                    args.join(", ")
+                };
+                (format!("{}({})", self.call_name(name), args), 4)
+            }
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => {
+                unreachable!("declarations are only at the top level")
+            }
+        };
+        if own < precedence {
+            format!("({})", code)
+        } else {
+            code
+        }
+    }
+
+    /// Translates a left-associative binary operation.
+    fn binary(&self, left: &Expr, op: &str, right: &Expr, precedence: u8) -> String {
+        format!(
+            "{} {} {}",
+            self.expr(left, precedence),
+            op,
+            self.expr(right, precedence + 1)
+        )
+    }
+}
+
+/// Makes a Foo identifier a valid C identifier, writing characters outside of
+/// ASCII as universal character names, e.g. `área` as `\u00e1rea`.
+fn c_ident(name: &str) -> String {
+    let mut ident = String::new();
+    for c in name.chars() {
+        match c as u32 {
+            _ if c.is_ascii() => ident.push(c),
+            code @ ..=0xffff => { let _ = write!(ident, "\\u{:04x}", code); }
+            code => { let _ = write!(ident, "\\U{:08x}", code); }
+        }
+    }
+    ident
+}
+
+/// Joins the parameters of a function, which C writes as `void` if there are
+/// none.
+fn params_list(params: &[String]) -> String {
+    if params.is_empty() {
+        "void".to_owned()
+    } else {
+        params.join(", ")
+    }
+}
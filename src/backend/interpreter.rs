@@ -0,0 +1,128 @@
+use std::{error::Error, ffi::CString};
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    error::Diagnostics,
+    eval::Interpreter,
+    llvm::PrintFormat,
+    parse::Expr,
+    Args,
+    FloatWidth,
+    Inputs,
+    OutputType,
+};
+
+use super::Backend;
+
+/// Runs the program with the interpreter used for compile-time evaluation,
+/// instead of compiling it, and prints its result the way the compiled
+/// program would.
+///
+/// The interpreter computes in `double`s and can't call `extern` functions,
+/// and it gives up on programs that take too long, like compile-time
+/// evaluation does.
+pub struct InterpreterBackend<'a> {
+    interpreter: Interpreter<'a>,
+    print_format: PrintFormat,
+    entry: Option<&'a str>,
+    /// The expression the program evaluates, once it's generated.
+    result: Option<Expr>,
+}
+
+impl<'a> InterpreterBackend<'a> {
+    pub fn new(args: &'a Args, inputs: &Inputs) -> Result<Self, Box<dyn Error>> {
+        if args.produce != OutputType::Executable
+            || args.output.is_some()
+            || !args.target.is_empty()
+            || !(inputs.ir.is_empty() && inputs.c.is_empty() && inputs.objects.is_empty())
+        {
+            return Err(
+                "the interpreter backend runs the program instead of producing output, so \
+                it can't be given `-p`, `-o`, `--target`, or inputs other than the Foo \
+                source".into()
+            );
+        }
+        if args.no_main || !args.instrument.is_empty() || args.profile_generate {
+            return Err(
+                "the interpreter backend can't be used with `--no-main` or \
+                instrumentation".into()
+            );
+        }
+        if args.float_width.is_some_and(|width| width != FloatWidth::F64) {
+            return Err("the interpreter backend only computes with 64-bit floats".into());
+        }
+        Ok(InterpreterBackend {
+            interpreter: Interpreter::new(),
+            print_format: PrintFormat {
+                notation: args.print_format,
+                precision: args.precision,
+                newline: !args.no_newline,
+            },
+            entry: args.entry.as_deref(),
+            result: None,
+        })
+    }
+}
+
+impl<'a> Backend<'a> for InterpreterBackend<'a> {
+    fn generate(&mut self, ast: &'a Expr, _: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let mut expr = ast;
+        let result = loop {
+            match expr {
+                Expr::Let { name, rhs, then, .. } => {
+                    let value = self.interpreter.eval(rhs);
+                    if value.is_none() {
+                        return Err(cant_evaluate(&format!("`{}`", name.0)).into());
+                    }
+                    self.interpreter.define_var(&name.0, value);
+                    expr = then;
+                }
+                Expr::Fn { name, args, body, then, .. } => {
+                    self.interpreter.define_fn(&name.0, args, body);
+                    expr = then;
+                }
+                Expr::Extern { name, then, .. } => {
+                    self.interpreter.declare_extern(&name.0);
+                    expr = then;
+                }
+                expr => break expr.clone(),
+            }
+        };
+        // `--entry` evaluates a call to the function instead
+        let span = SimpleSpan::from(0..0);
+        self.result = Some(match self.entry {
+            Some(entry) => Expr::Call((entry.to_owned(), span), vec![], span),
+            None => result,
+        });
+        Ok(())
+    }
+
+    fn emit(&mut self, _: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let result = self.result.as_ref().expect("the program is generated before it's run");
+        let value = self.interpreter.eval(result);
+        if let Some((_, limit)) = self.interpreter.take_exceeded().last() {
+            return Err(format!("running the program {}", limit).into());
+        }
+        let Some(value) = value else {
+            return Err(cant_evaluate("the program's result").into());
+        };
+
+        // printed by C's `printf`, so it's formatted exactly as a compiled
+        // program would print it
+        let format = CString::new(self.print_format.to_format_string(FloatWidth::F64))?;
+        unsafe {
+            libc::printf(format.as_ptr(), value);
+            libc::fflush(std::ptr::null_mut());
+        }
+        Ok(())
+    }
+}
+
+fn cant_evaluate(what: &str) -> String {
+    format!(
+        "{} couldn't be evaluated: it uses an undefined name, calls an `extern` function, \
+        or doesn't finish",
+        what
+    )
+}
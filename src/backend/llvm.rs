@@ -0,0 +1,772 @@
+use std::{error::Error, path::{Path, PathBuf}, time::Instant};
+
+use inkwell::{
+    context::Context,
+    module::Module,
+    targets::{FileType, Target, TargetMachine, TargetTriple},
+    OptimizationLevel,
+};
+
+use crate::{
+    artifact::Artifact,
+    bin::{self, LinkOptions},
+    buildinfo,
+    coverage,
+    error::Diagnostics,
+    get_output_path,
+    llvm::{
+        self, print_module, AsmAnnotations, CodegenOptions, CodegenOutput, DebugInfo, FunctionSize,
+        Instrumentation, LinkError, LlvmGenerator, PrintFormat, ReadableNames,
+        RemarkCollector
+    },
+    mangle,
+    messages::Messages,
+    parse::Expr,
+    profile::Profile,
+    read_export_list,
+    sourcemap,
+    temp::TempDir,
+    timings::DeclTimes,
+    Args,
+    Emit,
+    FloatModel,
+    FloatWidth,
+    Inputs,
+    Instrument,
+    OutputType,
+};
+
+use super::Backend;
+
+/// Compiles the program with LLVM, to any of the outputs `-p` chooses.
+pub struct LlvmBackend<'ctx, 'a> {
+    context: &'ctx Context,
+    module: Module<'ctx>,
+    args: &'a Args,
+    inputs: &'a Inputs<'a>,
+    src: &'a str,
+    /// The targets machine code is generated for, none when producing IR or
+    /// bitcode.
+    targets: Vec<BuildTarget>,
+    float_model: FloatModel,
+    float_width: FloatWidth,
+    instrument: &'a [Instrument],
+    messages: Messages,
+    /// The program and what generating it produced, once it's generated.
+    generated: Option<(&'a Expr, CodegenOutput)>,
+    /// Collects the remarks LLVM reports from generating on, if they're
+    /// asked for, or needed for the stack sizes.
+    remarks: Option<RemarkCollector<'ctx>>,
+}
+
+impl<'ctx, 'a> LlvmBackend<'ctx, 'a> {
+    /// Checks that the options can be used together, and creates the module
+    /// the program is generated into, for the targets in `triples`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        context: &'ctx Context,
+        args: &'a Args,
+        inputs: &'a Inputs<'a>,
+        src: &'a str,
+        triples: Vec<TargetTriple>,
+        float_model: FloatModel,
+        float_width: FloatWidth,
+        instrument: &'a [Instrument],
+        includes: bool,
+        messages: Messages,
+        diags: &mut Diagnostics
+    ) -> Result<Self, Box<dyn Error>> {
+        // debug info and the instrumentation's reports can only point to lines
+        // of the file being compiled
+        if includes
+            && (args.debug_info
+                || args.asm_source_comments
+                || args.with_cpp_line_directives
+                || args.remarks.is_some()
+                || args.emit.contains(&Emit::SourceMap)
+                || instrument.iter()
+                    .any(|kind| {
+                        matches!(kind, Instrument::Nan | Instrument::Coverage | Instrument::DivZero)
+                    }))
+        {
+            return Err(
+                "debug info, source maps, remarks, and `nan`, `coverage`, and `div-zero` \
+                instrumentation can't be used with `include` yet".into()
+            );
+        }
+        if let Some(triple) = triples.iter().find(|t| !float_width.supported_on(t)) {
+            return Err(
+                format!(
+                    "{}-bit floats aren't supported on `{}`",
+                    float_width.bits(),
+                    triple.as_str().to_string_lossy()
+                ).into()
+            );
+        }
+        if args.no_main
+            && instrument.iter()
+                .any(|kind| !matches!(kind, Instrument::Contracts | Instrument::DivZero))
+        {
+            return Err(
+                "instrumentation reports its results when `main` returns, so it can't be \
+                used with `--no-main`".into()
+            );
+        }
+        if args.produce == OutputType::Jit {
+            let host = llvm::target_triple(None);
+            if args.output.is_some() || triples.iter().any(|triple| *triple != host) {
+                return Err(
+                    "`-p jit` runs the program instead of producing output, so it can't be \
+                    given `-o` or a target other than this machine".into()
+                );
+            }
+            if args.no_main {
+                return Err("`-p jit` runs the program's `main`, so it can't be used with \
+                    `--no-main`".into());
+            }
+        }
+        if args.bitcode_portable {
+            if args.produce != OutputType::Bitcode {
+                return Err("`--bitcode-portable` only applies when producing bitcode".into());
+            }
+            if args.opt_level.is_some() {
+                return Err(
+                    "`-O` optimizes for a target, which portable bitcode leaves out, so they \
+                    can't be used together".into()
+                );
+            }
+            // the other widths are the same on every target
+            if float_width == FloatWidth::F80 {
+                return Err("80-bit floats can't be used in portable bitcode".into());
+            }
+            // the counters are written with C's `size_t`, whose width differs
+            if instrument.iter()
+                .any(|kind| matches!(kind, Instrument::Coverage | Instrument::Profile))
+            {
+                return Err(
+                    "coverage and profiling instrumentation can't be used in portable bitcode"
+                        .into()
+                );
+            }
+        }
+        if args.with_cpp_line_directives
+            && !matches!(args.produce, OutputType::Assembly | OutputType::LlvmIR)
+        {
+            return Err(
+                "`--with-cpp-line-directives` only applies when producing assembly or LLVM IR"
+                    .into()
+            );
+        }
+        if triples.len() > 1
+            && !matches!(args.produce, OutputType::Object | OutputType::Assembly)
+        {
+            return Err(
+                "multiple targets can only be given when producing objects or assembly"
+                    .into()
+            );
+        }
+        // the passes run once, on the module every target's code is generated
+        // from, and they're tuned to the target they run for
+        if triples.len() > 1 && args.opt_level.is_some() {
+            return Err(
+                "`-O` optimizes for one target, so it can't be used with multiple targets; \
+                build for each target on its own".into()
+            );
+        }
+        if args.emit.contains(&Emit::StackSizes)
+            && matches!(args.produce, OutputType::Bitcode | OutputType::LlvmIR)
+        {
+            return Err(
+                "stack sizes are only known when producing machine code".into()
+            );
+        }
+        if args.emit.contains(&Emit::SizeReport)
+            && matches!(args.produce, OutputType::Bitcode | OutputType::LlvmIR)
+        {
+            return Err(
+                "the size of the code is only known when producing machine code".into()
+            );
+        }
+        if args.emit.contains(&Emit::Disasm)
+            && !matches!(args.produce, OutputType::Executable | OutputType::Object)
+        {
+            return Err(
+                "disassembly is only available when producing objects or executables"
+                    .into()
+            );
+        }
+
+        let module_name = args.module_name.clone().unwrap_or_else(|| {
+            inputs.src.file_stem().unwrap().to_string_lossy().into_owned()
+        });
+        let module = context.create_module(&module_name);
+        // the directory compiled in is left out of reproducible builds
+        if args.reproducible {
+            module.set_source_file_name(&inputs.src.file_name().unwrap().to_string_lossy());
+        } else {
+            module.set_source_file_name(&inputs.src.to_string_lossy());
+        }
+
+        // IR only names its target, so it can be produced for targets that
+        // aren't compiled into this build of LLVM; the data layout is left for
+        // whatever compiles the IR to fill in. Portable bitcode doesn't even
+        // name it, so that `llc` compiles it for whichever target it's given.
+        // The target `--test-target` pins is always initialized, so golden IR
+        // has its data layout too, and so is the one `-O` optimizes for, since
+        // the passes need to know it
+        let mut targets = vec![];
+        if matches!(args.produce, OutputType::Bitcode | OutputType::LlvmIR)
+            && !args.test_target
+            && args.opt_level.is_none()
+        {
+            if !args.bitcode_portable {
+                module.set_triple(&triples[0]);
+            }
+        } else {
+            for triple in triples {
+                match llvm::init_target(&triple) {
+                    Ok(target) => targets.push((triple, target)),
+                    Err(e) if matches!(args.produce, OutputType::Bitcode | OutputType::LlvmIR)
+                        && !args.test_target =>
+                    {
+                        diags.error(
+                            format!(
+                                "{}; `-O` can only optimize for targets this build of LLVM \
+                                supports, but IR can be produced for any without it",
+                                e
+                            )
+                        );
+                        diags.abort();
+                    }
+                    Err(e) => {
+                        diags.error(e);
+                        diags.abort();
+                    }
+                }
+            }
+        }
+
+        // best practice: optionally set the data layout for the module based
+        // on target machine. The code is generated once, for the first target,
+        // and only the backend is rerun for the others
+        if let Some((triple, target)) = targets.first() {
+            if let Some(machine) = llvm::machine_from_target(
+                target,
+                triple,
+                !args.no_pie,
+                llvm::codegen_level(args.opt_level)
+            ) {
+                llvm::set_module_target(&module, &machine);
+            }
+        }
+
+        Ok(LlvmBackend {
+            context,
+            module,
+            args,
+            inputs,
+            src,
+            targets,
+            float_model,
+            float_width,
+            instrument,
+            messages,
+            generated: None,
+            remarks: None,
+        })
+    }
+
+    /// Adds the build info to the module, and links the IR and bitcode inputs
+    /// into it, before it's optimized, so the passes see the whole program
+    /// and can e.g. inline across it.
+    fn link(&self, output: &CodegenOutput, diags: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let Self { context, module, args, inputs, src, targets, .. } = self;
+        // recorded in the machine code, for `foo_llvm inspect`
+        if let Some((triple, _)) = targets.first() {
+            llvm::add_build_info(context, module, &buildinfo::describe(args, src), triple);
+        }
+
+        let others = inputs.ir.iter()
+            .map(|path| llvm::load_module(context, path))
+            .collect::<Result<Vec<_>, _>>()?;
+        match llvm::link_modules(module, others) {
+            Ok(()) => (),
+            Err(LinkError::Duplicates(dups)) => {
+                for dup in dups {
+                    let msg = format!(
+                        "symbol `{}` is also defined in `{}`",
+                        mangle::display(&dup.symbol),
+                        dup.module
+                    );
+                    match output.symbols.get(&dup.symbol) {
+                        Some(span) => diags.error_at(*span, msg, "defined here"),
+                        None => diags.error(msg),
+                    }
+                }
+                diags.abort();
+            }
+            Err(LinkError::Llvm(e)) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Whether debug info is generated, which some outputs need.
+    fn debug_info(&self) -> bool {
+        self.args.debug_info
+            || self.args.asm_source_comments
+            || self.args.with_cpp_line_directives
+            || self.args.remarks.is_some()
+            || self.args.emit.contains(&Emit::SourceMap)
+    }
+}
+
+impl<'a> Backend<'a> for LlvmBackend<'_, 'a> {
+    fn generate(&mut self, ast: &'a Expr, diags: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let args = self.args;
+        let options = CodegenOptions {
+            print_format: PrintFormat {
+                notation: args.print_format,
+                precision: args.precision,
+                newline: !args.no_newline,
+            },
+            entry: args.entry.clone(),
+            entry_symbol: (!args.no_main).then(|| args.entry_symbol.clone()),
+            float_model: self.float_model,
+            float_width: self.float_width,
+            stack_probes: args.stack_probes,
+            stack_probe_size: args.stack_probe_size,
+            exports: match &args.export_list {
+                Some(path) => read_export_list(path)?,
+                None => vec![],
+            },
+            profile: args.profile_use
+                .as_deref()
+                .map(|path| Profile::read(path, ast))
+                .transpose()?,
+            readable_names: args.readable_ir
+                .then(|| ReadableNames::new(diags.lines(), self.src)),
+        };
+
+        let debug = self.debug_info()
+            .then(|| {
+                DebugInfo::new(
+                    self.context,
+                    &self.module,
+                    self.inputs.src,
+                    diags.lines(),
+                    self.float_width,
+                    args.reproducible
+                )
+            });
+        let instrumentation = Instrumentation::new(
+            self.context,
+            &self.module,
+            self.instrument,
+            self.inputs.src,
+            diags.lines(),
+            ast
+        );
+
+        let builder = self.context.create_builder();
+        let generated = LlvmGenerator::generate(
+            ast,
+            self.context,
+            &self.module,
+            &builder,
+            options,
+            debug,
+            instrumentation
+        );
+        match generated {
+            Ok(output) => {
+                if !llvm::verify_functions(&self.module, &output.symbols, diags) {
+                    diags.abort();
+                }
+                self.link(&output, diags)?;
+                // collecting from here on, so the remarks of the passes `-O`
+                // runs are collected too
+                self.remarks = (args.remarks.is_some() || args.emit.contains(&Emit::StackSizes))
+                    .then(|| RemarkCollector::new(self.context, args.remarks.is_some()));
+                self.generated = Some((ast, output));
+                Ok(())
+            }
+            Err(e) => {
+                diags.semantic_error(e);
+                diags.abort();
+            }
+        }
+    }
+
+    fn decl_times(&self) -> DeclTimes {
+        self.generated
+            .as_ref()
+            .map(|(_, output)| output.decl_times.clone())
+            .unwrap_or_default()
+    }
+
+    /// Runs the module through the pipeline for the `-O` level, if there is
+    /// one.
+    fn optimize(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(opt_level) = self.args.opt_level else {
+            return Ok(());
+        };
+        let timer = Instant::now();
+        let machine = any_machine(
+            &self.module,
+            &self.targets,
+            !self.args.no_pie,
+            llvm::codegen_level(self.args.opt_level)
+        )?;
+        llvm::run_passes(&self.module, &machine, opt_level.pipeline())?;
+        self.messages.timing("optimize", timer.elapsed());
+        Ok(())
+    }
+
+    fn emit(&mut self, diags: &mut Diagnostics) -> Result<(), Box<dyn Error>> {
+        let Self { module, args, inputs, src, targets, messages, remarks, .. } = self;
+        let (args, inputs, src, messages) = (*args, *inputs, *src, *messages);
+        let Some((ast, CodegenOutput { symbols, values, exports, .. })) = &self.generated else {
+            panic!("the program is generated before it's emitted");
+        };
+        let pie = !args.no_pie;
+        let level = llvm::codegen_level(args.opt_level);
+        let stack_sizes = args.emit.contains(&Emit::StackSizes);
+        let disasm = args.emit.contains(&Emit::Disasm);
+        let check_output = |path: &Path| inputs.check_output(path, args.force);
+
+        if args.instrument.contains(&Instrument::Coverage) {
+            check_output(&coverage::map_path(inputs.src))?;
+            coverage::write_map(inputs.src, ast)?;
+            messages.artifact("coverage-map", &coverage::map_path(inputs.src));
+        }
+
+        let remarks = remarks.take();
+
+        if args.emit.contains(&Emit::AnalysisDot) {
+            let path = llvm::analysis_dot_path(inputs.src);
+            check_output(&path)?;
+            llvm::write_analysis_dot(module, &path)?;
+            messages.artifact("analysis-dot", &path);
+        }
+
+        for emit in &args.emit {
+            let (name, asm) = match emit {
+                Emit::IrFor(name) => (name, false),
+                Emit::AsmFor(name) => (name, true),
+                _ => continue,
+            };
+            print_function(module, targets, name, asm, pie, level)?;
+        }
+
+        // the assembly files written, in the order of the targets
+        let mut asm_paths = vec![];
+
+        let timer = Instant::now();
+        match args.produce {
+            OutputType::Executable => {
+                let (triple, target) = &targets[0];
+                let Some(machine) = llvm::machine_from_target(target, triple, pie, level)
+                else {
+                    return Err("failed to build target machine".into())
+                };
+                let out_path = get_output_path(args.output.clone(), "foo")?;
+                check_output(&out_path)?;
+
+                // intermediates go in their own directory, so concurrent
+                // builds can't overwrite each other's
+                TempDir::scoped(messages, |temp| {
+                    let link_options = LinkOptions {
+                        linker: args.linker,
+                        pie,
+                        exports,
+                        reproducible: args.reproducible,
+                        temp_dir: temp.path(),
+                        messages,
+                    };
+
+                    let obj_path = temp.file("foo.o");
+                    llvm::write_code_to_file(
+                        &machine,
+                        module,
+                        &obj_path,
+                        FileType::Object
+                    )?;
+                    if disasm {
+                        bin::disassemble(&obj_path)?;
+                    }
+                    messages.timing("emit", timer.elapsed());
+
+                    let timer = Instant::now();
+                    let mut objects = vec![obj_path];
+                    // numbered, since C files from different directories
+                    // can share a name
+                    for (i, c) in inputs.c.iter().enumerate() {
+                        let path = temp.file(&format!(
+                            "{}_{}.o",
+                            i,
+                            c.file_stem().unwrap().to_string_lossy()
+                        ));
+                        bin::compile_c(c, &path, &link_options)?;
+                        objects.push(path);
+                    }
+                    objects.extend(inputs.objects.iter().copied().cloned());
+
+                    let out = Artifact::new(&out_path)?;
+                    bin::try_to_bin(&objects, out.path(), &link_options)?;
+                    out.commit()?;
+                    messages.timing("link", timer.elapsed());
+                    messages.artifact("executable", &out_path);
+                    Ok(())
+                })?;
+            }
+            OutputType::Object => {
+                for ((triple, target), path) in
+                    target_outputs(targets, args.output.clone(), "foo.o")?
+                {
+                    check_output(&path)?;
+                    let Some(machine) =
+                        llvm::machine_from_target(target, triple, pie, level)
+                    else {
+                        return Err("failed to build target machine".into());
+                    };
+                    llvm::set_module_target(module, &machine);
+                    llvm::place_build_info(module, triple);
+                    llvm::write_code_to_file(
+                        &machine,
+                        module,
+                        &path,
+                        FileType::Object
+                    )?;
+                    if disasm {
+                        bin::disassemble(&path)?;
+                    }
+                    messages.artifact("object", &path);
+                }
+                messages.timing("emit", timer.elapsed());
+            }
+            OutputType::Assembly => {
+                for ((triple, target), path) in
+                    target_outputs(targets, args.output.clone(), "foo.s")?
+                {
+                    check_output(&path)?;
+                    let Some(machine) =
+                        llvm::machine_from_target(target, triple, pie, level)
+                    else {
+                        return Err("failed to build target machine".into());
+                    };
+                    llvm::set_module_target(module, &machine);
+                    llvm::place_build_info(module, triple);
+                    if args.asm_source_comments || args.with_cpp_line_directives {
+                        llvm::write_annotated_assembly(
+                            &machine,
+                            module,
+                            &path,
+                            inputs.src,
+                            src,
+                            AsmAnnotations {
+                                source_comments: args.asm_source_comments,
+                                line_directives: args.with_cpp_line_directives,
+                            }
+                        )?;
+                    } else {
+                        llvm::write_code_to_file(
+                            &machine,
+                            module,
+                            &path,
+                            FileType::Assembly
+                        )?;
+                    }
+                    messages.artifact("assembly", &path);
+                    asm_paths.push(path);
+                }
+                messages.timing("emit", timer.elapsed());
+            }
+            OutputType::Bitcode => {
+                let path = get_output_path(args.output.clone(), "foo.bc")?;
+                check_output(&path)?;
+                llvm::write_bitcode_to_file(module, &path)?;
+                messages.timing("emit", timer.elapsed());
+                messages.artifact("bitcode", &path);
+            }
+            OutputType::LlvmIR => {
+                if let Some(path) = &args.output {
+                    check_output(path)?;
+                    llvm::write_module_to_file(module, path)?;
+                    messages.artifact("llvm-ir", path);
+                } else {
+                    print_module(module);
+                }
+                messages.timing("emit", timer.elapsed());
+            }
+            OutputType::Jit => {
+                let status = llvm::run_jit(module, &args.entry_symbol, level)?;
+                messages.timing("run", timer.elapsed());
+                if status != 0 {
+                    return Err(format!("the program exited with status {}", status).into());
+                }
+            }
+            OutputType::C => unreachable!("the driver gives `-p c` to the C backend"),
+        }
+
+        if args.emit.contains(&Emit::SourceMap) {
+            // the lines of assembly are those of the file written, if any
+            let asm = match (&targets[..], args.produce) {
+                ([], _) => None,
+                (_, OutputType::Assembly) => {
+                    asm_paths.first().map(std::fs::read_to_string).transpose()?
+                }
+                ([(triple, target), ..], _) => {
+                    let Some(machine) = llvm::machine_from_target(target, triple, pie, level)
+                    else {
+                        return Err("failed to build target machine".into());
+                    };
+                    let buffer = machine.write_to_memory_buffer(module, FileType::Assembly)?;
+                    Some(String::from_utf8_lossy(buffer.as_slice()).into_owned())
+                }
+            };
+            check_output(&sourcemap::map_path(inputs.src))?;
+            sourcemap::write(inputs.src, diags.lines(), ast, symbols, values, asm.as_deref())?;
+            messages.artifact("sourcemap", &sourcemap::map_path(inputs.src));
+        }
+
+        if args.emit.contains(&Emit::SizeReport) {
+            print_size_report(module, targets, pie, level)?;
+        }
+
+        if let Some(remarks) = remarks {
+            if let Some(path) = &args.remarks {
+                check_output(path)?;
+                remarks.write(path, inputs.src, diags.lines())?;
+                messages.artifact("remarks", path);
+            }
+            if stack_sizes {
+                print_stack_sizes(module, targets, &remarks.stack_sizes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints the IR, or the assembly if `asm` is set, of the function `name` by
+/// itself, for `--emit ir-for` and `--emit asm-for`.
+fn print_function(
+    module: &Module,
+    targets: &[(TargetTriple, Target)],
+    name: &str,
+    asm: bool,
+    pie: bool,
+    level: OptimizationLevel
+) -> Result<(), Box<dyn Error>> {
+    // extracting the function runs passes
+    let machine = any_machine(module, targets, pie, level)?;
+    let function = llvm::extract_function(module, name, &machine)?;
+    if asm {
+        let buffer = machine.write_to_memory_buffer(&function, FileType::Assembly)?;
+        print!("{}", String::from_utf8_lossy(buffer.as_slice()));
+    } else {
+        print!("{}", function.to_string());
+    }
+    Ok(())
+}
+
+/// Creates a machine for the first target, or for the module's own triple when
+/// the target wasn't initialized for producing IR, for running passes, which
+/// need one either way.
+fn any_machine(
+    module: &Module,
+    targets: &[BuildTarget],
+    pie: bool,
+    level: OptimizationLevel
+) -> Result<TargetMachine, Box<dyn Error>> {
+    let machine = match targets.first() {
+        Some((triple, target)) => llvm::machine_from_target(target, triple, pie, level),
+        None => {
+            let triple = module.get_triple();
+            llvm::machine_from_target(&llvm::init_target(&triple)?, &triple, pie, level)
+        }
+    };
+    machine.ok_or_else(|| "failed to build target machine".into())
+}
+
+/// Prints the size of each function's stack frame, as reported by LLVM while
+/// generating code for each target.
+fn print_stack_sizes(module: &Module, targets: &[BuildTarget], sizes: &[u64]) {
+    let functions = module.get_functions()
+        .filter(|f| f.count_basic_blocks() > 0)
+        .map(|f| mangle::display(&f.get_name().to_string_lossy()))
+        .collect::<Vec<_>>();
+    if functions.is_empty() {
+        return;
+    }
+
+    for ((triple, _), sizes) in targets.iter().zip(sizes.chunks(functions.len())) {
+        if targets.len() > 1 {
+            println!("{}:", triple.as_str().to_string_lossy());
+        }
+        for (name, size) in functions.iter().zip(sizes) {
+            println!("{:>8} bytes  {}", size, name);
+        }
+    }
+}
+
+/// Prints the size and instruction count of each function's machine code, for
+/// each target, for `--emit size-report`.
+fn print_size_report(
+    module: &Module,
+    targets: &[BuildTarget],
+    pie: bool,
+    level: OptimizationLevel
+) -> Result<(), Box<dyn Error>> {
+    for (triple, target) in targets {
+        let Some(machine) = llvm::machine_from_target(target, triple, pie, level) else {
+            return Err("failed to build target machine".into());
+        };
+        let sizes = llvm::function_sizes(module, &machine)?;
+        if targets.len() > 1 {
+            println!("{}:", triple.as_str().to_string_lossy());
+        }
+        println!("{:>8}  {:>12}  function", "bytes", "instructions");
+        for FunctionSize { name, bytes, instructions } in &sizes {
+            let bytes = bytes.map_or("?".to_owned(), |bytes| bytes.to_string());
+            println!("{:>8}  {:>12}  {}", bytes, instructions, mangle::display(name));
+        }
+        // the total bytes are only known if every function's are
+        let bytes = sizes.iter()
+            .map(|size| size.bytes)
+            .sum::<Option<u64>>()
+            .map_or("?".to_owned(), |bytes| bytes.to_string());
+        let instructions = sizes.iter().map(|size| size.instructions).sum::<usize>();
+        println!("{:>8}  {:>12}  total", bytes, instructions);
+    }
+    Ok(())
+}
+
+/// A triple being built for, along with the target it names.
+type BuildTarget = (TargetTriple, Target);
+
+/// Pairs each target being built for with the path of its output. With a
+/// single target, `output` is the path of the output file; with several, it's
+/// a directory (`out` by default) with a subdirectory for each target, e.g.
+/// `out/x86_64-linux-gnu/foo.o`.
+fn target_outputs<'a>(
+    targets: &'a [BuildTarget],
+    output: Option<PathBuf>,
+    default: &str
+) -> Result<Vec<(&'a BuildTarget, PathBuf)>, Box<dyn Error>> {
+    if let [target] = targets {
+        return Ok(vec![(target, get_output_path(output, default)?)]);
+    }
+
+    let dir = output.unwrap_or_else(|| PathBuf::from("out"));
+    if dir.is_file() {
+        return Err(format!("{:#?} exists and isn't a directory", dir).into());
+    }
+    Ok(targets.iter()
+        .map(|target| {
+            let path = dir
+                .join(target.0.as_str().to_string_lossy().as_ref())
+                .join(default);
+            (target, path)
+        })
+        .collect())
+}
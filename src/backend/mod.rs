@@ -0,0 +1,34 @@
+use std::error::Error;
+
+use crate::{error::Diagnostics, parse::Expr, timings::DeclTimes};
+
+mod c;
+mod interpreter;
+mod llvm;
+
+pub use c::CBackend;
+pub use interpreter::InterpreterBackend;
+pub use llvm::LlvmBackend;
+
+/// Turns a program into the output asked for. The driver parses, checks, and
+/// transforms the program the same way whichever backend `--backend` selects,
+/// then hands it over, calling each of these in turn.
+pub trait Backend<'a> {
+    /// Generates the backend's own form of the program, e.g. an LLVM module.
+    fn generate(&mut self, ast: &'a Expr, diags: &mut Diagnostics) -> Result<(), Box<dyn Error>>;
+
+    /// Gets the time spent generating each declaration, for `--time`. Nothing
+    /// by default, for a backend that doesn't generate anything.
+    fn decl_times(&self) -> DeclTimes {
+        DeclTimes::default()
+    }
+
+    /// Optimizes what was generated. Does nothing by default.
+    fn optimize(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Writes the outputs asked for, or for a backend that doesn't produce
+    /// any, runs the program.
+    fn emit(&mut self, diags: &mut Diagnostics) -> Result<(), Box<dyn Error>>;
+}
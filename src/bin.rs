@@ -1,91 +1,340 @@
-use std::{error::Error, path::PathBuf, process::Command};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
 
-use crate::Linker;
+use crate::{messages::Messages, probes, Linker};
 
 /// List of C compilers/linkers to attempt for linking to an executable.
 /// 
+/// * `cc` - the system's default C compiler driver, or the one named by the
+///   `CC` environment variable, as found by [`system_cc()`]
+/// * `clang` - LLVM's C compiler frontend
 /// * `gcc` - GNU compiler collection
 /// * `ld` - GNU linker; unsure if this would exist in absence of gcc, but can't
 ///   hurt to check
 /// * `lld` - LLVM linker
-/// * `clang` - LLVM's C compiler frontend
 /// * `link` - command for MSVC's linker
-static LINKERS: [&str; 5] = ["clang", "gcc", "link", "ld", "lld"];
+static LINKERS: [&str; 6] = ["cc", "clang", "gcc", "link", "ld", "lld"];
 
 impl Linker {
-    fn to_string(&self) -> &str {
+    fn to_string(self) -> &'static str {
         match self {
-            Linker::Clang => LINKERS[0],
-            Linker::Gcc => LINKERS[1],
-            Linker::Link => LINKERS[2],
-            Linker::Ld => LINKERS[3],
-            Linker::Lld => LINKERS[4],
+            Linker::Cc => LINKERS[0],
+            Linker::Clang => LINKERS[1],
+            Linker::Gcc => LINKERS[2],
+            Linker::Link => LINKERS[3],
+            Linker::Ld => LINKERS[4],
+            Linker::Lld => LINKERS[5],
         }
     }
 }
+
+/// C compilers to attempt for compiling C files passed as inputs.
+static C_COMPILERS: [&str; 3] = ["cc", "clang", "gcc"];
+
+/// Disassemblers to attempt for printing the machine code of object files.
+/// `llvm-objdump` comes first since it understands every target LLVM does.
+static DISASSEMBLERS: [&str; 2] = ["llvm-objdump", "objdump"];
+
+/// Tools to attempt for finding the source line of an address in an
+/// executable with debug info.
+static SYMBOLIZERS: [&str; 2] = ["llvm-addr2line", "addr2line"];
+
+/// How the executable is linked.
+pub struct LinkOptions<'a> {
+    /// The linker, if any, specified by the user via CLI args.
+    pub linker: Option<Linker>,
+    /// Whether to produce a position-independent executable.
+    pub pie: bool,
+    /// The symbols to export from the executable, if any.
+    pub exports: &'a [String],
+    /// Whether to ask the tools for bit-identical output from identical input.
+    pub reproducible: bool,
+    /// Where to write the file listing `exports` for the linker.
+    pub temp_dir: &'a Path,
+    /// Reports the commands that were run.
+    pub messages: Messages,
+}
+
 /// Attempts to use a specified linker, or any known linkers if none was
-/// specified, to produce an executable from a given object or assembly file.
+/// specified, to produce an executable from the given object or assembly files.
 /// 
-/// * `object` - the path to the object or assembly file produced by the compiler
+/// * `objects` - the paths to the object or assembly files to link, starting
+///   with the one produced by the compiler
 /// * `out` - the output path for the executable
-/// * `linker` - the linker, if any, specified by the user via CLI args
 pub fn try_to_bin(
-    object: &PathBuf,
-    out: &PathBuf,
-    linker: Option<Linker>
+    objects: &[PathBuf],
+    out: &Path,
+    options: &LinkOptions
 ) -> Result<(), Box<dyn Error>> {
     let out = out.to_str().unwrap().trim();
     // if the user specified a linker, the program should halt if that linker
     // doesn't work
-    if let Some(linker) = linker {
-        let args = if linker == Linker::Link {
-            [object.to_str().unwrap(), &format!("/OUT:{}", out)]
-        } else {
-            [object.to_str().unwrap(), &format!("-o{}", out)]
-        };
-
-        let res = Command::new(linker.to_string())
-            .args(args)
-            .spawn();
+    if let Some(linker) = options.linker {
+        let res = run_linker(linker.to_string(), objects, out, options)?;
         match res {
-            Ok(mut cmd) => {
-                cmd.wait()?;
-                // clean up intermediary object file
-                std::fs::remove_file(object)?;
-            }
+            Ok(status) => check_status(linker.to_string(), status),
             Err(e) => match e.kind() {
                 std::io::ErrorKind::NotFound => {
-                    return Err(
+                    Err(
                         format!(
                             "command `{}` couldn't be found",
-                            linker.to_string())
+                            program_name(linker.to_string()))
                         .into()
-                    );
+                    )
                 }
                 _ => {
-                    return Err("unknown error occurred calling linker".into());
+                    Err("unknown error occurred calling linker".into())
                 }
             }
         }
-        Ok(())
     // if the user didn't specify a linker, the program should try to find
     // any it knows about
     } else {
-        for linker in LINKERS {
-            let args = if linker == "link" {
-                [object.to_str().unwrap(), &format!("/OUT:{}", out)]
-            } else {
-                [object.to_str().unwrap(), &format!("-o{}", out)]
-            };
-
-            let res = Command::new(linker).args(args).spawn();
-            if let Ok(mut cmd) = res {
-                cmd.wait()?;
-                // clean up intermediary object file
-                std::fs::remove_file(object)?;
-                return Ok(());
+        // the linker found last time is tried first, since the ones before
+        // it couldn't be run
+        let found = probes::linker().filter(|linker| LINKERS.contains(&linker.as_str()));
+        for linker in found.iter().map(String::as_str).chain(LINKERS) {
+            let res = run_linker(linker, objects, out, options)?;
+            if let Ok(status) = res {
+                probes::set_linker(linker);
+                return check_status(linker, status);
             }
         }
         Err("no known linkers were found".into())
     }
-}
\ No newline at end of file
+}
+
+/// Compiles a C source file to an object file with the first C compiler that
+/// can be found, so it can be linked with the program into the executable
+/// described by `options`.
+pub fn compile_c(
+    src: &Path,
+    out: &Path,
+    options: &LinkOptions
+) -> Result<(), Box<dyn Error>> {
+    for compiler in C_COMPILERS {
+        let Some(mut command) = tool_command(compiler) else {
+            continue;
+        };
+        command
+            .arg(if options.pie { "-fPIE" } else { "-fno-pie" })
+            .arg("-c")
+            .arg(src)
+            .arg("-o")
+            .arg(out);
+        if options.reproducible {
+            // keep the working directory out of any paths the compiler records
+            let cwd = std::env::current_dir()?;
+            command.arg(format!("-ffile-prefix-map={}=.", cwd.display()));
+            pin_timestamps(&mut command);
+        }
+        if let Ok(status) = command.status() {
+            options.messages.command(&command);
+            return check_status(compiler, status);
+        }
+    }
+    Err(format!("no known C compilers were found to compile {:#?}", src).into())
+}
+
+/// Prints the disassembly of an object file, labeled with the function each
+/// instruction belongs to, using the first disassembler that can be found.
+pub fn disassemble(object: &Path) -> Result<(), Box<dyn Error>> {
+    for disassembler in DISASSEMBLERS {
+        let res = Command::new(disassembler)
+            .arg("--disassemble")
+            .arg("--no-show-raw-insn")
+            // show what calls and loads refer to, which objects leave unresolved
+            .arg("--reloc")
+            .arg(object)
+            .status();
+        if let Ok(status) = res {
+            return check_status(disassembler, status);
+        }
+    }
+    Err(format!("no known disassemblers were found to disassemble {:#?}", object).into())
+}
+
+/// Finds the source file and line an address in an executable was generated
+/// from, using the first symbolizer that can be found. Returns `None` if none
+/// can be, or if the executable has no debug info for the address.
+pub fn symbolize(exe: &Path, address: u64) -> Option<(PathBuf, u32)> {
+    let output = SYMBOLIZERS.iter().find_map(|symbolizer| {
+        Command::new(symbolizer)
+            .arg("-e")
+            .arg(exe)
+            .arg(format!("{:#x}", address))
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+    })?;
+    // printed as `path:line`, maybe followed by ` (discriminator n)`, or as
+    // `??:0` if the address has no line
+    let output = String::from_utf8(output.stdout).ok()?;
+    let (path, line) = output.lines().next()?.rsplit_once(':')?;
+    let line = line.split_whitespace().next()?.parse().ok().filter(|line| *line != 0)?;
+    Some((PathBuf::from(path), line))
+}
+
+/// Runs `linker` to link `objects` into `out`, writing the file listing the
+/// symbols to export for it to read to the temporary directory, if there are
+/// any.
+///
+/// The outer error is for failing to write that file; the inner one is for
+/// failing to run the linker at all.
+fn run_linker(
+    linker: &str,
+    objects: &[PathBuf],
+    out: &str,
+    options: &LinkOptions
+) -> Result<std::io::Result<ExitStatus>, Box<dyn Error>> {
+    let export_file = if options.exports.is_empty() {
+        None
+    } else {
+        Some(write_export_file(linker, options.exports, options.temp_dir)?)
+    };
+    let Some(mut command) = tool_command(linker) else {
+        return Ok(Err(std::io::ErrorKind::NotFound.into()));
+    };
+    command.args(link_args(linker, objects, out, options, export_file.as_deref()));
+    if options.reproducible {
+        pin_timestamps(&mut command);
+    }
+    let res = command.status();
+    if res.is_ok() {
+        options.messages.command(&command);
+    }
+    Ok(res)
+}
+
+/// Writes the file telling `linker` which symbols to export from the
+/// executable into `dir`: a module-definition file for MSVC's linker, or a
+/// dynamic list for the others.
+fn write_export_file(
+    linker: &str,
+    exports: &[String],
+    dir: &Path
+) -> Result<PathBuf, Box<dyn Error>> {
+    let (path, contents) = if linker == "link" {
+        let names = exports.iter()
+            .map(|name| format!("    {}\n", name))
+            .collect::<String>();
+        (dir.join("exports.def"), format!("EXPORTS\n{}", names))
+    } else {
+        let names = exports.iter()
+            .map(|name| format!("    {};\n", name))
+            .collect::<String>();
+        (dir.join("exports.list"), format!("{{\n{}}};\n", names))
+    };
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Builds the arguments to pass to `linker` to link `objects` into `out` as
+/// described by `options`, exporting the symbols listed in `export_file`, if
+/// given.
+fn link_args(
+    linker: &str,
+    objects: &[PathBuf],
+    out: &str,
+    options: &LinkOptions,
+    export_file: Option<&Path>
+) -> Vec<String> {
+    let pie = options.pie;
+    let mut args = objects.iter()
+        .map(|obj| obj.to_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    if linker == "link" {
+        args.push(format!("/OUT:{}", out));
+        args.push(if pie { "/DYNAMICBASE" } else { "/DYNAMICBASE:NO" }.to_owned());
+        if options.reproducible {
+            // replaces the timestamps in the executable with a hash of it
+            args.push("/Brepro".to_owned());
+        }
+    } else {
+        args.push(format!("-o{}", out));
+        args.push(if pie { "-pie" } else { "-no-pie" }.to_owned());
+        // the prelude's math functions can be lowered to calls into libm,
+        // which MSVC's C runtime already includes
+        args.push("-lm".to_owned());
+    }
+    if let Some(path) = export_file {
+        let path = path.to_str().unwrap();
+        args.push(match linker {
+            "link" => format!("/DEF:{}", path),
+            // the C compilers pass the flag through to the linker
+            "cc" | "clang" | "gcc" => format!("-Wl,--dynamic-list={}", path),
+            _ => format!("--dynamic-list={}", path),
+        });
+    }
+    if options.reproducible && linker != "link" {
+        // a build ID hashed from the contents, rather than a random one
+        args.push(match linker {
+            "cc" | "clang" | "gcc" => "-Wl,--build-id=sha1".to_owned(),
+            _ => "--build-id=sha1".to_owned(),
+        });
+    }
+    args
+}
+
+/// Finds the system's C compiler driver the way the `cc` crate does: the
+/// command in the `CC` environment variable, which can start with a wrapper
+/// like `ccache`, or else `cc`, except on Windows, which has no default.
+///
+/// Returns the program followed by any arguments it's given.
+fn system_cc() -> Option<Vec<String>> {
+    let words = std::env::var("CC")
+        .map(|cc| cc.split_whitespace().map(str::to_owned).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if !words.is_empty() {
+        Some(words)
+    } else if cfg!(windows) {
+        None
+    } else {
+        Some(vec!["cc".to_owned()])
+    }
+}
+
+/// Gets the program run for a tool in [`LINKERS`] or [`C_COMPILERS`], for
+/// messages about it.
+fn program_name(tool: &str) -> String {
+    match tool {
+        "cc" => system_cc()
+            .map(|mut words| words.swap_remove(0))
+            .unwrap_or_else(|| tool.to_owned()),
+        _ => tool.to_owned(),
+    }
+}
+
+/// Creates the command running a tool in [`LINKERS`] or [`C_COMPILERS`], or
+/// `None` if it's `cc` and there's no system C compiler to run.
+fn tool_command(tool: &str) -> Option<Command> {
+    match tool {
+        "cc" => {
+            let cc = system_cc()?;
+            let mut command = Command::new(&cc[0]);
+            command.args(&cc[1..]);
+            Some(command)
+        }
+        _ => Some(Command::new(tool)),
+    }
+}
+
+/// Makes `command` use a fixed timestamp (the Unix epoch) wherever it would
+/// record the current time, unless `SOURCE_DATE_EPOCH` already chooses one.
+fn pin_timestamps(command: &mut Command) {
+    if std::env::var_os("SOURCE_DATE_EPOCH").is_none() {
+        command.env("SOURCE_DATE_EPOCH", "0");
+    }
+}
+
+/// Turns a failing exit status of an external tool into an error.
+fn check_status(tool: &str, status: ExitStatus) -> Result<(), Box<dyn Error>> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{}` failed with {}", tool, status).into())
+    }
+}
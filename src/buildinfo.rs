@@ -0,0 +1,60 @@
+use std::{error::Error, path::Path};
+
+use crate::{llvm, Args};
+
+/// Describes how a program was built, for the build info embedded in the
+/// objects generated from it: the compiler's version, the arguments it was
+/// given, and a hash of the source.
+pub fn describe(args: &Args, src: &str) -> String {
+    let flags = args.command_line.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "compiler: foo_llvm {}\nflags: {}\nsource-hash: fnv1a-64:{:016x}\n",
+        env!("CARGO_PKG_VERSION"),
+        flags,
+        hash(src.as_bytes())
+    )
+}
+
+/// Prints the build info embedded in an object or executable.
+pub fn inspect(path: &Path) -> Result<(), Box<dyn Error>> {
+    let records = llvm::read_build_info(path)?;
+    if records.is_empty() {
+        return Err(
+            format!(
+                "{:#?} has no build info; it wasn't compiled by foo_llvm, or was \
+                stripped",
+                path
+            ).into()
+        );
+    }
+    // one record for each Foo program linked in
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print!("{}", record);
+    }
+    Ok(())
+}
+
+/// Hashes the source with FNV-1a, which stays the same across builds of the
+/// compiler, unlike std's hasher.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Quotes an argument the way a POSIX shell would need it, if it needs it.
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,100 @@
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    error::Diagnostics,
+    llvm,
+    parse::{Cfg, Expr, Spanned},
+};
+
+/// Removes the declarations whose `#[cfg(...)]` attributes don't hold for the
+/// targets being built for, before anything else looks at the program, so a
+/// declaration can be given once for each platform, e.g. an `extern` function
+/// that's named differently by each platform's C library.
+///
+/// Code is only generated once, for every target, so each attribute has to
+/// hold for all of them or none of them. Returns `None` if one doesn't, or if
+/// there are no targets because the output is portable bitcode.
+pub fn filter(root: Expr, triples: &[String], diags: &mut Diagnostics) -> Option<Expr> {
+    let mut filter = Filter { triples, valid: true, diags };
+    let root = filter.decl(root);
+    filter.valid.then_some(root)
+}
+
+/// Removes the declarations left out of the program when it's built for the
+/// machine the compiler runs on, for the commands that don't take a target.
+pub fn filter_host(root: Expr, diags: &mut Diagnostics) -> Expr {
+    let host = llvm::target_triple(None).as_str().to_string_lossy().into_owned();
+    // a single target can't disagree with itself
+    filter(root, &[host], diags).unwrap()
+}
+
+struct Filter<'t, 'd, 'src> {
+    triples: &'t [String],
+    valid: bool,
+    diags: &'d mut Diagnostics<'src>,
+}
+
+impl Filter<'_, '_, '_> {
+    fn decl(&mut self, mut expr: Expr) -> Expr {
+        let (cfg, then) = match &mut expr {
+            Expr::Let { cfg, then, .. }
+            | Expr::Fn { cfg, then, .. }
+            | Expr::Extern { cfg, then, .. } => (cfg, then),
+            _ => return expr,
+        };
+        let keep = cfg.iter().all(|cfg| self.holds(cfg));
+        let placeholder = Expr::Num(0.0, SimpleSpan::from(0..0));
+        let rest = self.decl(std::mem::replace(then, placeholder));
+        if keep {
+            **then = rest;
+            expr
+        } else {
+            rest
+        }
+    }
+
+    /// Checks whether an attribute's condition holds for the targets.
+    fn holds(&mut self, (cfg, span): &Spanned<Cfg>) -> bool {
+        let Some((first, rest)) = self.triples.split_first() else {
+            self.diags.error_at(
+                *span,
+                "`#[cfg]` can't be used in portable bitcode",
+                "the bitcode doesn't have a target to check"
+            );
+            self.valid = false;
+            return true;
+        };
+        let holds = eval(cfg, first);
+        if let Some(other) = rest.iter().find(|triple| eval(cfg, triple) != holds) {
+            let (yes, no) = if holds { (first, other) } else { (other, first) };
+            self.diags.error_at(
+                *span,
+                "`#[cfg]` has to hold for all of the targets or none of them",
+                format!("holds for `{}` but not for `{}`", yes, no)
+            );
+            self.valid = false;
+        }
+        holds
+    }
+}
+
+fn eval(cfg: &Cfg, triple: &str) -> bool {
+    match cfg {
+        Cfg::Target(target) => names(target, triple),
+        Cfg::Not(cfg) => !eval(cfg, triple),
+        Cfg::Any(cfgs) => cfgs.iter().any(|cfg| eval(cfg, triple)),
+        Cfg::All(cfgs) => cfgs.iter().all(|cfg| eval(cfg, triple)),
+    }
+}
+
+/// Checks whether `target` names the triple, or one of its components, e.g.
+/// `windows` or `x86_64` for `x86_64-pc-windows-msvc`. A component can be
+/// followed by a version, so `macosx` names `x86_64-apple-macosx10.15`.
+fn names(target: &str, triple: &str) -> bool {
+    triple == target
+        || triple.split('-').any(|part| {
+            part.strip_prefix(target).is_some_and(|version| {
+                version.is_empty() || version.starts_with(|c: char| c.is_ascii_digit())
+            })
+        })
+}
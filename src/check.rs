@@ -0,0 +1,236 @@
+use std::{error::Error, path::Path};
+
+use chumsky::span::SimpleSpan;
+use inkwell::context::Context;
+use serde::Serialize;
+
+use crate::{
+    cfg,
+    deprecated,
+    error::Diagnostics,
+    include,
+    lines::SourceLines,
+    lint,
+    llvm::{self, CodegenOptions, Instrumentation, LlvmGenerator, PrintFormat},
+    messages::Messages,
+    parse::Expr,
+    sema,
+    FloatModel,
+    FloatWidth,
+    LangVersion,
+    Limits,
+    MessageFormat,
+    Notation,
+    SymbolFormat,
+};
+
+/// Checks a Foo source file for errors without producing any output, then
+/// prints a summary of what the compiler understood: the signature of each
+/// declared function and the type of the final expression. If `symbols` is
+/// given, the functions are listed in that format instead.
+pub fn check(
+    src_path: &Path,
+    limits: &Limits,
+    symbols: Option<SymbolFormat>
+) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+
+    let mut diags = Diagnostics::new(
+        src_path,
+        &src,
+        limits.max_errors,
+        Messages::new(MessageFormat::Human)
+    );
+    if !limits.check_source(&src, &mut diags) {
+        diags.abort();
+    }
+    let features = LangVersion::of(None, &src).features();
+    let ast = include::parse(src_path, &src, features, &mut diags).body;
+    if !limits.check_ast(&ast, &mut diags) {
+        diags.abort();
+    }
+    let ast = cfg::filter_host(ast, &mut diags);
+    sema::check(&ast, &mut diags);
+    lint::check(&ast, &mut diags);
+    deprecated::check(&ast, features, &mut diags);
+    if diags.has_errors() {
+        diags.abort();
+    }
+    diags.print();
+
+    // generate the IR just for the errors it finds, then throw it away
+    let context = Context::create();
+    let module = context.create_module(&src_path.file_stem().unwrap().to_string_lossy());
+    let builder = context.create_builder();
+    let options = CodegenOptions {
+        print_format: PrintFormat {
+            notation: Notation::Fixed,
+            precision: None,
+            newline: true,
+        },
+        entry: None,
+        entry_symbol: Some("main".to_owned()),
+        float_model: FloatModel::Strict,
+        float_width: FloatWidth::F64,
+        stack_probes: false,
+        stack_probe_size: None,
+        exports: vec![],
+        profile: None,
+        readable_names: None,
+    };
+    let instrumentation = Instrumentation::default();
+    let output = LlvmGenerator::generate(
+        &ast,
+        &context,
+        &module,
+        &builder,
+        options,
+        None,
+        instrumentation
+    );
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            diags.semantic_error(e);
+            diags.abort();
+        }
+    };
+    if !llvm::verify_functions(&module, &output.symbols, &mut diags) {
+        diags.abort();
+    }
+
+    match symbols {
+        Some(SymbolFormat::Json) => print_symbols(src_path, &src, diags.lines(), &ast)?,
+        None => print_summary(&ast),
+    }
+    Ok(())
+}
+
+/// Prints the signature of each function declared in a program, and the type of
+/// its final expression. Every value in Foo is an `f64`.
+fn print_summary(root: &Expr) {
+    let mut e = root;
+    loop {
+        match e {
+            Expr::Let { then, .. } => e = then,
+            Expr::Fn { export, name, args, then, .. } => {
+                println!(
+                    "{}fn {}",
+                    if *export { "export " } else { "" },
+                    signature(&name.0, args.iter().map(|(arg, _)| arg))
+                );
+                e = then;
+            }
+            Expr::Extern { name, args, then, .. } => {
+                println!(
+                    "extern fn {}",
+                    signature(&name.0, args.iter().map(|(arg, _)| arg))
+                );
+                e = then;
+            }
+            _ => {
+                println!("result: f64");
+                return;
+            }
+        }
+    }
+}
+
+/// Formats a function's signature, e.g. `add(x: f64, y: f64) -> f64`.
+fn signature<'a>(name: &str, args: impl Iterator<Item = &'a String>) -> String {
+    let args = args
+        .map(|arg| format!("{}: f64", arg))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{}({}) -> f64", name, args)
+}
+
+/// The functions declared in a program, as printed by `check --symbols json`.
+#[derive(Serialize)]
+struct Symbols<'a> {
+    source: &'a Path,
+    functions: Vec<Function<'a>>,
+}
+
+#[derive(Serialize)]
+struct Function<'a> {
+    name: &'a str,
+    /// `fn` for Foo functions, or `extern` for functions declared with
+    /// `extern fn`.
+    kind: &'static str,
+    export: bool,
+    arity: usize,
+    params: Vec<&'a str>,
+    /// The function's signature as the summary shows it, e.g.
+    /// `add(x: f64, y: f64) -> f64`.
+    signature: String,
+    /// The whole declaration, from any annotations to the end of the body.
+    span: Location,
+    /// The function's name in the declaration.
+    name_span: Location,
+}
+
+/// A span of the source, in bytes, with the line and column of its start,
+/// counting from 1.
+#[derive(Serialize)]
+struct Location {
+    start: usize,
+    end: usize,
+    line: u32,
+    column: u32,
+}
+
+/// Prints every function declared in a program as JSON, for editor plugins to
+/// offer completions and outlines with.
+fn print_symbols(
+    src_path: &Path,
+    src: &str,
+    lines: &SourceLines,
+    root: &Expr
+) -> Result<(), Box<dyn Error>> {
+    let location = |span: SimpleSpan| {
+        let (line, column) = lines.line_col(span.start);
+        Location { start: span.start, end: span.end, line, column }
+    };
+
+    let mut functions = vec![];
+    let mut e = root;
+    loop {
+        let (kind, export, name, args, end, then) = match e {
+            Expr::Let { then, .. } => {
+                e = then;
+                continue;
+            }
+            Expr::Fn { export, name, args, body, then, .. } => {
+                ("fn", *export, name, args, body.span().end, then)
+            }
+            Expr::Extern { name, args, then, .. } => {
+                let end = args.last().unwrap_or(name).1.end;
+                ("extern", false, name, args, end, then)
+            }
+            _ => break,
+        };
+        // functions from included files aren't in the source the spans are in
+        if name.1.start >= src.len() {
+            e = then;
+            continue;
+        }
+        functions.push(Function {
+            name: &name.0,
+            kind,
+            export,
+            arity: args.len(),
+            params: args.iter().map(|(arg, _)| arg.as_str()).collect(),
+            signature: signature(&name.0, args.iter().map(|(arg, _)| arg)),
+            span: location(SimpleSpan::new(e.span().start, end)),
+            name_span: location(name.1),
+        });
+        e = then;
+    }
+
+    let symbols = Symbols { source: src_path, functions };
+    println!("{}", serde_json::to_string_pretty(&symbols)?);
+    Ok(())
+}
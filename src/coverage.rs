@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chumsky::span::SimpleSpan;
+use serde::{Deserialize, Serialize};
+
+use crate::{artifact::Artifact, parse::Expr, sources::SourceMap};
+
+/// Maps each coverage counter back to the region of source it counts, written
+/// next to the source file when compiling with `--instrument coverage`.
+#[derive(Serialize, Deserialize)]
+struct CoverageMap {
+    source: PathBuf,
+    /// The regions of the source counted, in the order of their counters.
+    regions: Vec<Region>,
+}
+
+/// A span of the source file, in bytes.
+#[derive(Serialize, Deserialize)]
+struct Region {
+    start: usize,
+    end: usize,
+}
+
+/// Gets the path of the mapping file for a source file, e.g. `test.covmap`.
+pub fn map_path(src: &Path) -> PathBuf {
+    src.with_extension("covmap")
+}
+
+/// Gets the path the instrumented program writes its counters to, e.g.
+/// `test.covdata`.
+pub fn data_path(src: &Path) -> PathBuf {
+    src.with_extension("covdata")
+}
+
+/// Gets the regions counted in a program: every expression that gets evaluated,
+/// as opposed to declared. Counter `n` belongs to the `n`th region.
+///
+/// Regions sharing a span share a counter.
+pub fn regions(root: &Expr) -> Vec<SimpleSpan> {
+    let mut regions = vec![];
+    let mut stack = vec![root];
+
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Fn { body, then, .. } => {
+                stack.push(then);
+                stack.push(body);
+                continue;
+            }
+            Expr::Let { rhs, then, .. } => {
+                stack.push(then);
+                stack.push(rhs);
+                continue;
+            }
+            Expr::Extern { then, .. } => {
+                stack.push(then);
+                continue;
+            }
+            Expr::Num(..) | Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(right);
+                stack.push(left);
+            }
+            Expr::Call(_, args, _) => stack.extend(args.iter().rev()),
+        }
+        let span = expr.span();
+        if !regions.contains(&span) {
+            regions.push(span);
+        }
+    }
+
+    regions
+}
+
+/// Writes the mapping file for the program in `src`.
+pub fn write_map(src: &Path, ast: &Expr) -> Result<(), Box<dyn Error>> {
+    let map = CoverageMap {
+        source: src.to_owned(),
+        regions: regions(ast)
+            .into_iter()
+            .map(|span| Region { start: span.start, end: span.end })
+            .collect(),
+    };
+    let artifact = Artifact::new(&map_path(src))?;
+    serde_json::to_writer_pretty(artifact.create()?, &map)?;
+    artifact.commit()
+}
+
+/// Prints each line of a source file prefixed with the number of times it was
+/// executed in the last run of its instrumented program, gcov-style. A line's
+/// count is the highest count of the regions starting on it, and lines with no
+/// regions are marked `-`.
+pub fn report(src: &Path) -> Result<(), Box<dyn Error>> {
+    let map_path = map_path(src);
+    let Ok(map) = std::fs::read_to_string(&map_path) else {
+        return Err(
+            format!(
+                "couldn't read {:#?}; compile with `--instrument coverage` first",
+                map_path
+            ).into()
+        );
+    };
+    let map: CoverageMap = serde_json::from_str(&map)?;
+
+    let data_path = data_path(src);
+    let Ok(data) = std::fs::read(&data_path) else {
+        return Err(
+            format!(
+                "couldn't read {:#?}; run the instrumented program first",
+                data_path
+            ).into()
+        );
+    };
+    let counts = data.chunks_exact(8)
+        .map(|bytes| u64::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    if counts.len() != map.regions.len() {
+        return Err(
+            format!(
+                "{:#?} doesn't match {:#?}; recompile and run the program again",
+                data_path,
+                map_path
+            ).into()
+        );
+    }
+
+    let src = std::fs::read_to_string(&map.source)?;
+    let sources = SourceMap::new(&map.source, &src);
+
+    let mut line_counts: HashMap<usize, u64> = HashMap::new();
+    for (region, count) in map.regions.iter().zip(counts) {
+        let (_, line, _) = sources.locate(region.start);
+        let entry = line_counts.entry(line as usize).or_default();
+        *entry = (*entry).max(count);
+    }
+
+    for (i, text) in src.lines().enumerate() {
+        let count = match line_counts.get(&(i + 1)) {
+            Some(count) => count.to_string(),
+            None => "-".to_owned(),
+        };
+        println!("{:>9}: {:>4}: {}", count, i + 1, text);
+    }
+    Ok(())
+}
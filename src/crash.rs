@@ -0,0 +1,147 @@
+use std::{
+    error::Error,
+    path::Path,
+    process::{Child, Command, ExitStatus},
+    time::{Duration, Instant},
+};
+
+/// Gets the name of a signal, and what it usually means, for the signals a
+/// program is likely to be killed by.
+#[cfg(unix)]
+pub fn signal_name(signal: i32) -> Option<(&'static str, &'static str)> {
+    Some(match signal {
+        libc::SIGABRT => ("SIGABRT", "aborted"),
+        libc::SIGBUS => ("SIGBUS", "bus error"),
+        libc::SIGFPE => ("SIGFPE", "arithmetic exception"),
+        libc::SIGILL => ("SIGILL", "illegal instruction"),
+        libc::SIGINT => ("SIGINT", "interrupted"),
+        libc::SIGKILL => ("SIGKILL", "killed"),
+        libc::SIGPIPE => ("SIGPIPE", "broken pipe"),
+        libc::SIGSEGV => ("SIGSEGV", "segmentation fault"),
+        libc::SIGTERM => ("SIGTERM", "terminated"),
+        libc::SIGTRAP => ("SIGTRAP", "trap"),
+        libc::SIGXCPU => ("SIGXCPU", "CPU time limit exceeded"),
+        libc::SIGXFSZ => ("SIGXFSZ", "file size limit exceeded"),
+        _ => return None,
+    })
+}
+
+/// Makes the command's program stop for its parent to trace it, so that
+/// [`trace`] can find out where it crashes.
+#[cfg(target_os = "linux")]
+pub fn trace_me(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.pre_exec(|| {
+            libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+            Ok(())
+        });
+    }
+}
+
+/// Waits for a program started with [`trace_me`] to exit, killing it if it's
+/// still running after `limit`, in which case the status is `None`. The
+/// signals it gets are passed on to it, but if one of them is a fault, the
+/// address in the executable of the instruction it faulted at is returned
+/// too, for [`crate::bin::symbolize`].
+#[cfg(target_os = "linux")]
+pub fn trace(
+    child: &Child,
+    exe: &Path,
+    limit: Option<Duration>
+) -> Result<(Option<ExitStatus>, Option<u64>), Box<dyn Error>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = child.id() as libc::pid_t;
+    let start = Instant::now();
+    let mut fault = None;
+    // the first stop is the trap `exec` raises in a traced program
+    let mut started = false;
+    loop {
+        let mut status = 0;
+        let res = unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if res == 0 {
+            if limit.is_some_and(|limit| start.elapsed() >= limit) {
+                unsafe {
+                    libc::kill(pid, libc::SIGKILL);
+                    libc::waitpid(pid, &mut status, 0);
+                }
+                return Ok((None, fault));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        if !libc::WIFSTOPPED(status) {
+            return Ok((Some(ExitStatus::from_raw(status)), fault));
+        }
+
+        let mut signal = libc::WSTOPSIG(status);
+        if signal == libc::SIGTRAP && !started {
+            started = true;
+            signal = 0;
+        } else if matches!(signal, libc::SIGSEGV | libc::SIGBUS | libc::SIGFPE | libc::SIGILL) {
+            fault = fault.or_else(|| fault_address(pid, exe));
+        }
+        unsafe {
+            libc::ptrace(libc::PTRACE_CONT, pid, 0, signal);
+        }
+    }
+}
+
+/// Gets the address in the executable of the instruction a stopped program is
+/// at: its program counter, less where the executable was loaded if it's
+/// position-independent.
+#[cfg(target_os = "linux")]
+fn fault_address(pid: libc::pid_t, exe: &Path) -> Option<u64> {
+    let pc = program_counter(pid)?;
+    // an ELF header's `e_type` is `ET_DYN` for position-independent
+    // executables, which are loaded wherever there's room
+    let header = std::fs::read(exe).ok()?;
+    if header.get(16..18)? != [3, 0] {
+        return Some(pc);
+    }
+    let exe = std::fs::canonicalize(exe).ok()?;
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).ok()?;
+    // each line is `start-end perms offset dev inode path`, and the mapping of
+    // the start of the file is where it was loaded
+    let base = maps.lines().find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (range, offset, path) = (fields.first()?, fields.get(2)?, fields.get(5)?);
+        if Path::new(path) != exe || u64::from_str_radix(offset, 16).ok()? != 0 {
+            return None;
+        }
+        u64::from_str_radix(range.split_once('-')?.0, 16).ok()
+    })?;
+    pc.checked_sub(base)
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn program_counter(pid: libc::pid_t) -> Option<u64> {
+    let mut regs = std::mem::MaybeUninit::<libc::user_regs_struct>::uninit();
+    let mut iov = libc::iovec {
+        iov_base: regs.as_mut_ptr().cast(),
+        iov_len: std::mem::size_of::<libc::user_regs_struct>(),
+    };
+    let res = unsafe {
+        libc::ptrace(libc::PTRACE_GETREGSET, pid, libc::NT_PRSTATUS, &mut iov)
+    };
+    if res < 0 {
+        return None;
+    }
+    let regs = unsafe { regs.assume_init() };
+    #[cfg(target_arch = "x86_64")]
+    return Some(regs.rip);
+    #[cfg(target_arch = "aarch64")]
+    return Some(regs.pc);
+}
+
+/// Reading the registers of other architectures isn't supported, so their
+/// crashes aren't located.
+#[cfg(all(target_os = "linux", not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+fn program_counter(_pid: libc::pid_t) -> Option<u64> {
+    None
+}
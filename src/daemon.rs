@@ -0,0 +1,343 @@
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs::DirBuilder,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::{
+            fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{llvm, messages::{Message, Messages}, probes, run, Args};
+
+/// A compile request sent to the daemon: the command line, and the directory
+/// and environment it was given in. The client's standard streams are sent
+/// along with it, so the compile reads and writes them directly.
+#[derive(Serialize, Deserialize)]
+struct Request {
+    /// The client's [`probes::environment_key`]. A daemon for a different
+    /// compiler, or with different tools on its `PATH`, would compile
+    /// differently, so it refuses the request.
+    key: u64,
+    cwd: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+}
+
+/// Gets the path of the socket the daemon listens on: the one named by
+/// `FOO_LLVM_DAEMON`, or else one in the current user's [`private_dir()`].
+/// `None` if `FOO_LLVM_DAEMON` is set but empty, which turns the daemon off.
+///
+/// The directory is only created if `create` is set, for the daemon; the CLI
+/// looking for a daemon on every run shouldn't leave one behind.
+pub fn socket_path(create: bool) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    match std::env::var_os("FOO_LLVM_DAEMON") {
+        Some(path) if path.is_empty() => Ok(None),
+        Some(path) => Ok(Some(PathBuf::from(path))),
+        None => Ok(Some(private_dir(create)?.join("foo_llvm.sock"))),
+    }
+}
+
+/// Gets a directory only the current user can use, to put the socket in:
+/// `XDG_RUNTIME_DIR`, which is meant for this, or else `foo_llvm-<uid>` in the
+/// temporary directory, which is created if it doesn't exist yet and `create`
+/// is set.
+///
+/// Anyone can create files in the temporary directory, so another user could
+/// create the directory first, or a socket in it to impersonate the daemon.
+/// Either directory has to be a real directory, owned by the user and closed
+/// to everyone else, or it isn't used.
+fn private_dir(create: bool) -> Result<PathBuf, Box<dyn Error>> {
+    let uid = unsafe { libc::getuid() };
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let dir = std::env::temp_dir().join(format!("foo_llvm-{}", uid));
+            if create {
+                match DirBuilder::new().mode(0o700).create(&dir) {
+                    Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            dir
+        }
+    };
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(
+            format!(
+                "{} isn't a directory only the current user can use, so the daemon's \
+                socket can't go in it",
+                dir.display()
+            ).into()
+        );
+    }
+    Ok(dir)
+}
+
+/// Gets the ID of the user running the process at the other end of a socket.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len
+        )
+    };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Gets the ID of the user running the process at the other end of a socket.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<libc::uid_t> {
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Listens on `socket` for compile requests from the CLI, until killed.
+///
+/// The LLVM target for the current machine is initialized and the cached
+/// probe results are loaded once, up front; then each request is compiled in
+/// a fork of the daemon, which starts with all of that already done. Since
+/// it's a separate process, a compile that fails, exits, or crashes does so
+/// without taking the daemon down, and it can change directory and
+/// environment freely. Requests are compiled one at a time.
+///
+/// Nothing a compile does is kept for the next one, so every request parses
+/// and checks its program from scratch: there are no parse or semantic caches,
+/// only the LLVM and probe setup each compile would otherwise repeat.
+pub fn serve(socket: &Path) -> Result<(), Box<dyn Error>> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(
+                format!("a daemon is already listening on {}", socket.display()).into()
+            );
+        }
+        // left behind by a daemon that was killed
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    // only the user the daemon compiles as may ask it to
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+
+    llvm::init_target(&llvm::target_triple(None))?;
+    eprintln!("listening on {}", socket.display());
+
+    for stream in listener.incoming() {
+        let res = stream.map_err(Into::into).and_then(|stream| handle(&stream));
+        if let Err(e) = res {
+            eprintln!("warning: failed to handle a request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Compiles a request in a fork of the daemon, then tells the client the
+/// compile's exit status, or `refused` if the daemon can't compile it.
+fn handle(mut stream: &UnixStream) -> Result<(), Box<dyn Error>> {
+    // the socket's permissions should already keep other users out, but it
+    // can be anywhere `FOO_LLVM_DAEMON` says
+    if peer_uid(stream)? != unsafe { libc::getuid() } {
+        return Err("refused a request from another user".into());
+    }
+    let (request, fds) = receive(stream)?;
+    // another daemon checking whether this one is running closes the
+    // connection without sending anything
+    if request.is_empty() {
+        return Ok(());
+    }
+    let res = (|| {
+        let request: Request = serde_json::from_slice(&request)?;
+        if request.key != probes::environment_key() || fds.len() != 3 {
+            writeln!(stream, "refused")?;
+            return Ok(());
+        }
+        match unsafe { libc::fork() } {
+            -1 => Err(std::io::Error::last_os_error().into()),
+            0 => compile(request, &fds),
+            pid => {
+                let mut status = 0;
+                unsafe {
+                    libc::waitpid(pid, &mut status, 0);
+                }
+                // reported the way a shell reports it
+                let code = if libc::WIFSIGNALED(status) {
+                    128 + libc::WTERMSIG(status)
+                } else {
+                    libc::WEXITSTATUS(status)
+                };
+                writeln!(stream, "{}", code)?;
+                Ok(())
+            }
+        }
+    })();
+    for fd in fds {
+        unsafe {
+            libc::close(fd);
+        }
+    }
+    res
+}
+
+/// Compiles a request in the forked daemon, with the client's streams,
+/// directory, and environment, then exits the way the CLI would have.
+fn compile(request: Request, fds: &[RawFd]) -> ! {
+    for (fd, stream) in fds.iter().zip(0..) {
+        unsafe {
+            libc::dup2(*fd, stream);
+        }
+    }
+    let code = std::panic::catch_unwind(|| {
+        if let Err(e) = std::env::set_current_dir(&request.cwd) {
+            eprintln!("Error: {:?}", e.to_string());
+            return 1;
+        }
+        for (var, _) in std::env::vars_os().collect::<Vec<_>>() {
+            std::env::remove_var(var);
+        }
+        for (var, value) in &request.env {
+            std::env::set_var(var, value);
+        }
+
+        let args = Args::from_command_line(&request.args).unwrap_or_else(|e| e.exit());
+        let messages = Messages::new(args.message_format);
+        if messages.enabled() {
+            yansi::disable();
+        }
+        let res = run(args, messages).map_err(|e| e.to_string());
+        messages.emit(Message::BuildFinished { success: res.is_ok() });
+        match res {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                1
+            }
+        }
+    }).unwrap_or(101);
+    let _ = std::io::stdout().flush();
+    std::process::exit(code);
+}
+
+/// Has the daemon, if one is listening, compile for this process, returning
+/// the exit status to exit with. `None` if there's no daemon, or it refused,
+/// in which case the compile has to be done here instead.
+pub fn request(args: impl Iterator<Item = OsString>) -> Option<i32> {
+    // without a daemon, its directory may not exist yet, which is left that way
+    let mut stream = UnixStream::connect(socket_path(false).ok()??).ok()?;
+    // the request carries the environment and the standard streams, so it's
+    // only sent to a daemon run by the same user, not one another user
+    // listens on the socket with
+    if peer_uid(&stream).ok()? != unsafe { libc::getuid() } {
+        return None;
+    }
+    // the request is JSON, so arguments and variables that aren't UTF-8
+    // can't be sent
+    let args = args.map(OsString::into_string).collect::<Result<Vec<_>, _>>().ok()?;
+    let env = std::env::vars_os()
+        .map(|(var, value)| Some((var.into_string().ok()?, value.into_string().ok()?)))
+        .collect::<Option<Vec<_>>>()?;
+    let request = Request {
+        key: probes::environment_key(),
+        cwd: std::env::current_dir().ok()?,
+        args,
+        env,
+    };
+    let mut json = serde_json::to_vec(&request).ok()?;
+    json.push(b'\n');
+
+    let sent = send(&stream, &json, &[0, 1, 2]).ok()?;
+    stream.write_all(&json[sent..]).ok()?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).ok()?;
+    reply.trim().parse().ok()
+}
+
+/// Sends the start of `data` with the file descriptors `fds`, which the
+/// receiving process gets copies of, returning how many bytes were sent.
+fn send(stream: &UnixStream, data: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+    let fds_len = std::mem::size_of_val(fds);
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len as u32) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len as u32) as _;
+        std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+    }
+    let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, 0) };
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+/// Receives a line sent with [`send`], and the file descriptors sent with
+/// it, which are closed when the daemon runs another program, like the
+/// linker.
+fn receive(stream: &UnixStream) -> Result<(Vec<u8>, Vec<RawFd>), Box<dyn Error>> {
+    let mut data = vec![0u8; 64 * 1024];
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(64) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if received < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut fds = vec![];
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                for i in 0..len / std::mem::size_of::<RawFd>() {
+                    fds.push(data.add(i).read_unaligned());
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    // the rest of a long request follows without the descriptors
+    data.truncate(received as usize);
+    if !data.ends_with(b"\n") {
+        BufReader::new(stream).read_until(b'\n', &mut data)?;
+    }
+    Ok((data, fds))
+}
+
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    error::Diagnostics,
+    parse::{Expr, Features},
+};
+
+/// Builtins that are going away in a later version of the language, each with
+/// the builtin to call instead. None are yet.
+static BUILTINS: &[(&str, &str)] = &[];
+
+/// A use of something deprecated, and how to rewrite it.
+pub struct Deprecation {
+    pub span: SimpleSpan,
+    pub msg: String,
+    pub label: String,
+    /// The span to replace, and its replacement.
+    pub fix: (SimpleSpan, String),
+    /// Whether the code only has to change for the program to move to a later
+    /// version of the language, rather than being deprecated in its own.
+    pub migration: bool,
+}
+
+/// Queues a warning for each use of something deprecated in a program.
+pub fn check(root: &Expr, features: Features, diags: &mut Diagnostics) {
+    for deprecation in find(root, features, diags) {
+        diags.warning_at(deprecation.span, deprecation.msg, deprecation.label);
+    }
+}
+
+/// Finds the calls to deprecated builtins in a program and, if it's written in
+/// an older version of the language, the code the latest version would parse
+/// differently.
+pub fn find(root: &Expr, features: Features, diags: &Diagnostics) -> Vec<Deprecation> {
+    let mut finder = Finder { features, diags, declared: HashSet::new(), found: vec![] };
+    let mut e = root;
+
+    loop {
+        match e {
+            Expr::Let { rhs, then, .. } => {
+                finder.visit(rhs);
+                e = then;
+            }
+            Expr::Fn { name, contracts, body, then, .. } => {
+                finder.declared.insert(&name.0);
+                for (contract, _) in contracts {
+                    finder.visit(&contract.cond);
+                }
+                finder.visit(body);
+                e = then;
+            }
+            Expr::Extern { name, then, .. } => {
+                finder.declared.insert(&name.0);
+                e = then;
+            }
+            expr => {
+                finder.visit(expr);
+                finder.found.sort_by_key(|deprecation| deprecation.span.start);
+                return finder.found;
+            }
+        }
+    }
+}
+
+struct Finder<'e, 'd, 'src> {
+    features: Features,
+    diags: &'d Diagnostics<'src>,
+    /// The functions declared so far, which take the place of builtins with
+    /// their names.
+    declared: HashSet<&'e String>,
+    found: Vec<Deprecation>,
+}
+
+impl Finder<'_, '_, '_> {
+    fn visit(&mut self, root: &Expr) {
+        let mut stack = vec![root];
+
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Num(..) | Expr::Var(..) => (),
+                Expr::Neg(expr, _) => stack.push(expr),
+                Expr::Add(left, right, _)
+                | Expr::Sub(left, right, _)
+                | Expr::Mul(left, right, _)
+                | Expr::Div(left, right, _)
+                | Expr::Cmp(_, left, right, _) => {
+                    if !self.features.optional_semicolons {
+                        self.leading_operator(left.span(), right.span());
+                    }
+                    stack.push(right);
+                    stack.push(left);
+                }
+                Expr::Call((name, span), args, _) => {
+                    let replacement = BUILTINS.iter()
+                        .find(|(builtin, _)| builtin == name && !self.declared.contains(name));
+                    if let Some((_, replacement)) = replacement {
+                        self.found.push(Deprecation {
+                            span: *span,
+                            msg: format!("`{}` is deprecated", name),
+                            label: format!("use `{}` instead", replacement),
+                            fix: (*span, replacement.to_string()),
+                            migration: false,
+                        });
+                    }
+                    stack.extend(args.iter().rev());
+                }
+                // declarations only come before the final expression
+                Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+            }
+        }
+    }
+
+    /// Finds a binary operator starting a line, between its operands. Versions
+    /// from 2 end the declaration at the newline before it instead, so it's
+    /// moved to the end of the line above.
+    fn leading_operator(&mut self, left: SimpleSpan, right: SimpleSpan) {
+        // only the operator, whitespace, and the parentheses around the
+        // operands are between them, and a negation's span starts with the
+        // spaces before its `-`
+        let start = left.end;
+        let right_src = self.diags.source(right);
+        let end = right.start + right_src.len() - right_src.trim_start().len();
+        let between = self.diags.source(SimpleSpan::new(start, end));
+        let Some(op) = between.find(['+', '-', '*', '/', '<', '>', '=', '!']) else {
+            return;
+        };
+        // comparisons can be two characters, e.g. `<=`
+        let two_chars =
+            between[op..].starts_with(['<', '>', '=', '!']) && between[op + 1..].starts_with('=');
+        let len = if two_chars { 2 } else { 1 };
+        let before = &between[..op];
+        if !before.contains('\n') {
+            return;
+        }
+        let closing = before.trim_end();
+        let fixed = format!(
+            "{} {}{}{}",
+            closing,
+            &between[op..op + len],
+            &before[closing.len()..],
+            between[op + len..].trim_start()
+        );
+        let span = SimpleSpan::new(start + op, start + op + len);
+        self.found.push(Deprecation {
+            span,
+            msg: format!("binary operator `{}` starts a line", &between[op..op + len]),
+            label: "version 2 ends the declaration before it; move it to the end of the \
+                line above".to_owned(),
+            fix: (SimpleSpan::new(start, end), fixed),
+            migration: true,
+        });
+    }
+}
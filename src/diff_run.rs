@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::Path,
+};
+
+use chumsky::{span::SimpleSpan, Parser};
+
+use crate::{
+    eval::{self, Interpreter, TracedCall},
+    parse::{parser, Expr},
+    sources::SourceMap,
+    LangVersion,
+};
+
+/// One of the two programs being compared.
+struct Program<'a> {
+    path: &'a Path,
+    root: &'a Expr,
+    sources: SourceMap<'a>,
+    /// The spans of the names of the program's functions, by name.
+    functions: HashMap<&'a str, SimpleSpan>,
+}
+
+impl<'a> Program<'a> {
+    fn new(path: &'a Path, src: &'a str, root: &'a Expr) -> Program<'a> {
+        let mut functions = HashMap::new();
+        let mut e = root;
+        while let Expr::Let { then, .. } | Expr::Fn { then, .. } | Expr::Extern { then, .. } = e {
+            if let Expr::Fn { name, .. } = e {
+                functions.insert(name.0.as_str(), name.1);
+            }
+            e = then;
+        }
+        Program { path, root, sources: SourceMap::new(path, src), functions }
+    }
+
+    /// Evaluates an input in place of the program's final expression, or the
+    /// program's own result, along with the calls made to get it. Each input
+    /// gets an interpreter of its own, since one doesn't evaluate a call that
+    /// passed one of its limits again.
+    fn run(&self, input: Option<&Expr>) -> (Result<f64, String>, Vec<TracedCall<'a>>) {
+        let mut interpreter = Interpreter::new();
+        let mut e = self.root;
+        let result = loop {
+            match e {
+                Expr::Let { name, rhs, then, .. } => {
+                    let value = interpreter.eval(rhs);
+                    interpreter.define_var(&name.0, value);
+                    e = then;
+                }
+                Expr::Fn { name, args, body, then, .. } => {
+                    interpreter.define_fn(&name.0, args, body);
+                    e = then;
+                }
+                Expr::Extern { name, then, .. } => {
+                    interpreter.declare_extern(&name.0);
+                    e = then;
+                }
+                expr => break expr,
+            }
+        };
+
+        // calls made evaluating the `let`s aren't part of the input's
+        interpreter.trace();
+        let value = interpreter.eval(input.unwrap_or(result));
+        let value = match (value, interpreter.take_exceeded().last()) {
+            (Some(value), _) => Ok(value),
+            (None, Some((_, limit))) => Err(format!("gave up: evaluating it {}", limit)),
+            (None, None) => Err("can't evaluate it".to_owned()),
+        };
+        (value, interpreter.take_trace())
+    }
+
+    /// Describes where a function is defined, e.g. `a.foo:3:4`.
+    fn locate(&self, function: &str) -> String {
+        match self.functions.get(function) {
+            Some(span) => {
+                let (path, line, col) = self.sources.locate(span.start);
+                format!("{}:{}:{}", path.display(), line, col)
+            }
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// Runs two Foo programs over the same inputs and reports each input they give
+/// results more than `epsilon` apart for, to check that a refactored program
+/// still computes what it did.
+///
+/// Each line of the `inputs` file is an expression evaluated in place of each
+/// program's final expression, so it can call the functions they declare;
+/// blank lines and lines starting with `#` are skipped. Without `inputs`, the
+/// programs' own results are compared. The programs are run by the
+/// interpreter, which traces the calls each makes, so a divergence is traced
+/// back to the first function to return different results for the same
+/// arguments.
+///
+/// Results are the same if they're equal, both NaN, or within `epsilon` of
+/// each other, either absolutely or relative to the larger of the two.
+pub fn diff_run(
+    a: &Path,
+    b: &Path,
+    inputs: Option<&Path>,
+    epsilon: f64
+) -> Result<(), Box<dyn Error>> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read {:#?}: {}", path, e))
+    };
+    let (src_a, src_b) = (read(a)?, read(b)?);
+    let (root_a, root_b) = (eval::parse(a, &src_a, false), eval::parse(b, &src_b, false));
+    let program_a = Program::new(a, &src_a, &root_a);
+    let program_b = Program::new(b, &src_b, &root_b);
+
+    let inputs = match inputs {
+        Some(path) => {
+            let text = read(path)?;
+            let features = LangVersion::of(None, &src_a).features();
+            let mut inputs = vec![];
+            for (i, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let expr = parser(features)
+                    .parse(line)
+                    .into_result()
+                    .ok()
+                    .filter(|expr| {
+                        !matches!(expr, Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. })
+                    })
+                    .ok_or_else(|| {
+                        format!("{}:{}: `{}` isn't an expression", path.display(), i + 1, line)
+                    })?;
+                inputs.push((line.to_owned(), Some(expr)));
+            }
+            inputs
+        }
+        None => vec![("the program's result".to_owned(), None)],
+    };
+
+    let (mut diverged, mut unevaluated) = (0, 0);
+    for (text, input) in &inputs {
+        let (result_a, trace_a) = program_a.run(input.as_ref());
+        let (result_b, trace_b) = program_b.run(input.as_ref());
+        let (result_a, result_b) = match (result_a, result_b) {
+            (Ok(x), Ok(y)) if same(x, y, epsilon) => continue,
+            (Ok(x), Ok(y)) => (x, y),
+            // a divergence is where only one program gives a result
+            (Err(_), Err(_)) => {
+                unevaluated += 1;
+                println!("`{}`: neither program can evaluate it", text);
+                continue;
+            }
+            (x, y) => {
+                diverged += 1;
+                let describe = |result: Result<f64, String>, path: &Path| match result {
+                    Ok(value) => format!("{} gives {}", path.display(), value),
+                    Err(e) => format!("{} {}", path.display(), e),
+                };
+                println!("`{}`: {}, {}", text, describe(x, a), describe(y, b));
+                continue;
+            }
+        };
+        diverged += 1;
+        println!(
+            "`{}`: {} gives {}, {} gives {} (off by {:e})",
+            text,
+            a.display(),
+            result_a,
+            b.display(),
+            result_b,
+            (result_a - result_b).abs()
+        );
+        if let Some(cause) = first_divergence(&trace_a, &trace_b, epsilon) {
+            println!("  {}", cause.describe(&program_a, &program_b));
+        }
+    }
+
+    let compared = inputs.len() - unevaluated;
+    let plural = |n: usize| if n == 1 { "input" } else { "inputs" };
+    if diverged > 0 {
+        return Err(
+            format!(
+                "{} of {} {} diverged by more than {:e}",
+                diverged,
+                compared,
+                plural(compared),
+                epsilon
+            ).into()
+        );
+    }
+    println!("{} {} agreed within {:e}", compared, plural(compared), epsilon);
+    Ok(())
+}
+
+/// Whether two results are the same, within `epsilon`.
+fn same(x: f64, y: f64, epsilon: f64) -> bool {
+    x == y
+        || (x.is_nan() && y.is_nan())
+        || (x - y).abs() <= epsilon
+        || (x - y).abs() <= epsilon * x.abs().max(y.abs())
+}
+
+/// Where the calls two programs made for an input first part ways.
+enum Divergence<'t, 'a> {
+    /// A function returned different results for the same arguments.
+    Result(&'t TracedCall<'a>, &'t TracedCall<'a>),
+    /// The programs made different calls, from this one on.
+    Calls(Option<&'t TracedCall<'a>>, Option<&'t TracedCall<'a>>),
+}
+
+impl Divergence<'_, '_> {
+    fn describe(&self, program_a: &Program, program_b: &Program) -> String {
+        match self {
+            Divergence::Result(call_a, call_b) => format!(
+                "first differs in `{}` ({} and {}): {} gives {} in the first, and {} in the \
+                second",
+                call_a.name,
+                program_a.locate(call_a.name),
+                program_b.locate(call_b.name),
+                format_call(call_a),
+                call_a.result,
+                call_b.result
+            ),
+            Divergence::Calls(call_a, call_b) => {
+                let describe = |call: &Option<&TracedCall>, program: &Program| match call {
+                    Some(call) => format!("{} ({})", format_call(call), program.locate(call.name)),
+                    None => "nothing more".to_owned(),
+                };
+                format!(
+                    "the calls made first differ: {} in the first, and {} in the second",
+                    describe(call_a, program_a),
+                    describe(call_b, program_b)
+                )
+            }
+        }
+    }
+}
+
+/// Walks the calls two programs made for an input, in the order they
+/// returned, to find the first function to return a different result for the
+/// same arguments. Calls return before the calls made with their results, so
+/// it's the deepest function responsible.
+fn first_divergence<'t, 'a>(
+    trace_a: &'t [TracedCall<'a>],
+    trace_b: &'t [TracedCall<'a>],
+    epsilon: f64
+) -> Option<Divergence<'t, 'a>> {
+    for i in 0..trace_a.len().max(trace_b.len()) {
+        let (call_a, call_b) = (trace_a.get(i), trace_b.get(i));
+        let (Some(a), Some(b)) = (call_a, call_b) else {
+            return Some(Divergence::Calls(call_a, call_b));
+        };
+        let same_call = a.name == b.name
+            && a.args.len() == b.args.len()
+            && a.args.iter().zip(&b.args).all(|(&x, &y)| same(x, y, epsilon));
+        if !same_call {
+            return Some(Divergence::Calls(call_a, call_b));
+        }
+        if !same(a.result, b.result, epsilon) {
+            return Some(Divergence::Result(a, b));
+        }
+    }
+    None
+}
+
+/// Formats a call with its arguments, e.g. `add(1, 2)`.
+fn format_call(call: &TracedCall) -> String {
+    let args = call.args.iter().map(f64::to_string).collect::<Vec<_>>();
+    format!("{}({})", call.name, args.join(", "))
+}
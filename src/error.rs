@@ -1,79 +1,644 @@
-use std::path::PathBuf;
+use std::{error::Error, fmt, ops::Range, path::Path, process};
 
-use chumsky::error::{Rich, RichReason};
-use codesnake::{Block, CodeWidth, Label, LineIndex};
+use chumsky::{error::{Rich, RichPattern, RichReason}, span::SimpleSpan};
+use codesnake::{Block, CodeWidth, Label};
+use inkwell::builder::BuilderError;
 use yansi::Paint;
 
-/// Take the errors output by the Chumsky parser and print them.
-pub fn print_syntax_errors(errs: Vec<Rich<char>>, path: &PathBuf, src: &str) {
-    let idx = LineIndex::new(&src);
-    let errs = build_syntax_errors(errs, path, &idx);
-    errs.iter().for_each(|err| err.print());
+use crate::{
+    messages::{Location, Message, Messages},
+    lines::SourceLines,
+    sources::SourceMap,
+};
+
+/// Collects the diagnostics produced while compiling a source file, and the
+/// files it includes.
+///
+/// Diagnostics point into the files of a [`SourceMap`], which locates them for
+/// their headers, e.g. `test.foo:3:7`, whether they came from the parser or
+/// from codegen. Nothing is rendered until [`print()`] is called, and then
+/// only the diagnostics that fall within the error limit are turned into code
+/// blocks, with the [`LineIndex`] of each file they point into, which is built
+/// the first time one does.
+///
+/// [`print()`]: Self::print()
+pub struct Diagnostics<'src> {
+    sources: SourceMap<'src>,
+    diags: Vec<Diagnostic>,
+    /// The maximum number of errors to render over the whole compile, if any.
+    /// Warnings are always rendered, and don't count toward it.
+    limit: Option<usize>,
+    /// The number of errors rendered so far, by every call to [`print()`].
+    ///
+    /// [`print()`]: Self::print()
+    errors_shown: usize,
+    /// Reports the diagnostics as build messages instead of printing them, if
+    /// enabled.
+    messages: Messages,
+    /// Whether warnings are queued as errors.
+    deny_warnings: bool,
+}
+
+/// A single diagnostic, stored unrendered until it's printed.
+pub struct Diagnostic {
+    /// Whether the diagnostic is a warning, which doesn't stop compilation,
+    /// rather than an error.
+    pub warning: bool,
+    pub msg: String,
+    /// The location in the source the diagnostic points to, if any, along with
+    /// the text of its label.
+    pub label: Option<(Range<usize>, Option<String>)>,
+    /// Other locations related to the diagnostic, with their labels' text.
+    pub related: Vec<(Range<usize>, String)>,
+}
+
+/// An error generating code for a program, pointing to the part of the source
+/// it's about, if there is one, so it can be shown like a syntax error rather
+/// than as a bare message. It's queued with [`Diagnostics::semantic_error()`].
+#[derive(Debug)]
+pub struct CompilerError {
+    pub msg: String,
+    /// The location in the source the error points to, if any, along with the
+    /// text of its label.
+    pub label: Option<(SimpleSpan, String)>,
+    /// Other locations related to the error, with their labels' text.
+    pub related: Vec<(SimpleSpan, String)>,
 }
 
-fn build_syntax_errors<'src>(
-    errs: Vec<Rich<char>>,
-    path: &PathBuf,
-    idx: &'src LineIndex<'src>
-) -> Vec<CompilerErr<'src>> {
-    let mut res = vec![];
+impl CompilerError {
+    /// Creates an error pointing to a location in the source.
+    pub fn at(span: SimpleSpan, msg: impl ToString, label: impl ToString) -> Self {
+        CompilerError {
+            msg: msg.to_string(),
+            label: Some((span, label.to_string())),
+            related: vec![],
+        }
+    }
+
+    /// Labels another location related to the error, which can't overlap
+    /// its own or any other related one.
+    pub fn with_related(mut self, span: SimpleSpan, label: impl ToString) -> Self {
+        self.related.push((span, label.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for CompilerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for CompilerError {}
+
+impl From<String> for CompilerError {
+    fn from(msg: String) -> Self {
+        CompilerError { msg, label: None, related: vec![] }
+    }
+}
+
+impl From<&str> for CompilerError {
+    fn from(msg: &str) -> Self {
+        msg.to_owned().into()
+    }
+}
+
+impl From<BuilderError> for CompilerError {
+    fn from(e: BuilderError) -> Self {
+        e.to_string().into()
+    }
+}
+
+impl From<Box<dyn Error>> for CompilerError {
+    fn from(e: Box<dyn Error>) -> Self {
+        e.to_string().into()
+    }
+}
+
+impl<'src> Diagnostics<'src> {
+    pub fn new(
+        path: &'src Path,
+        src: &'src str,
+        limit: Option<usize>,
+        messages: Messages
+    ) -> Self {
+        Diagnostics {
+            sources: SourceMap::new(path, src),
+            diags: vec![],
+            limit,
+            errors_shown: 0,
+            messages,
+            deny_warnings: false,
+        }
+    }
+
+    /// Queues warnings from now on as errors, for `--deny-warnings`.
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    /// Whether any errors have been queued.
+    pub fn has_errors(&self) -> bool {
+        self.diags.iter().any(|diag| !diag.warning)
+    }
+
+    /// Adds a file included by the one being compiled, returning the offset
+    /// its spans have to be moved by, which places them after every other
+    /// file's.
+    pub fn include(&mut self, path: &Path, src: String) -> usize {
+        self.sources.add(path, src)
+    }
+
+    /// Gets the source text a span covers, in whichever file it's in.
+    pub fn source(&self, span: SimpleSpan) -> &str {
+        self.sources.source(span)
+    }
 
-    for err in errs {
-        let reason = err.reason();
+    /// Gets the file an offset is in, and its line and column there.
+    pub fn locate(&self, offset: usize) -> (&Path, u32, u32) {
+        self.sources.locate(offset)
+    }
+
+    /// Gets the lines of the file being compiled, for locating spans in it.
+    pub fn lines(&self) -> &SourceLines {
+        self.sources.lines()
+    }
+
+    /// Take the errors output by the Chumsky parser and queue them.
+    pub fn syntax_errors(&mut self, errs: Vec<Rich<char>>) {
+        self.syntax_errors_at(errs, 0);
+    }
+
+    /// Take the errors output by the Chumsky parser for a file included at
+    /// `start`, and queue them.
+    pub fn syntax_errors_at(&mut self, errs: Vec<Rich<char>>, start: usize) {
+        // with a delimiter left open, the parser gives up wherever the last
+        // alternative it backtracked to failed, which says little about the
+        // delimiter, so it's reported instead
+        if !errs.is_empty() && self.unclosed_delimiters(start) {
+            return;
+        }
+        for err in errs {
+            let span = SimpleSpan::new(err.span().start + start, err.span().end + start);
+            self.syntax_error(err.reason(), &span);
+        }
+    }
+
+    fn syntax_error(&mut self, reason: &RichReason<char>, span: &SimpleSpan) {
         match reason {
             RichReason::ExpectedFound { expected, found } => {
-                let msg = format!(
-                    "[{:#?}]: invalid syntax, expected {}",
-                    path.file_name().unwrap(),
-                    expected.iter()
-                        .fold(String::new(), |mut acc, e| {
-                            acc.push_str(&e.to_string());
-                            acc
-                        })
+                let msg = match expected_list(expected) {
+                    Some(expected) => format!("invalid syntax, expected {}", expected),
+                    None => "invalid syntax".to_owned(),
+                };
+                let mut label = (
+                    span.into_range(),
+                    Some(match found {
+                        Some(token) => format!("found {}", describe(**token)),
+                        None => "found the end of the file".to_owned(),
+                    })
                 );
-                let label = if let Some(token) = found {
-                    Label::new(err.span().into_range())
-                        .with_text(format!("found {}", token.into_inner()))
-                        .with_style(|s| s.red().to_string())
+                let mut related = vec![];
+                let found = found.map(|token| token.into_inner());
+                if let Some((word, keyword)) = self.misspelled_keyword(span, found) {
+                    let text = format!("did you mean `{}`?", keyword);
+                    if word.start == span.start {
+                        label = (word, Some(text));
+                    } else {
+                        related.push((word, text));
+                    }
+                }
+
+                self.diags.push(Diagnostic {
+                    warning: false,
+                    msg,
+                    label: Some(label),
+                    related,
+                });
+            }
+            RichReason::Custom(msg) => {
+                self.diags.push(Diagnostic {
+                    warning: false,
+                    msg: msg.to_owned(),
+                    label: Some((span.into_range(), Some("here".to_owned()))),
+                    related: vec![],
+                });
+            }
+            // several errors at the same location; custom errors are more
+            // specific than what the other alternatives expected, so prefer them
+            RichReason::Many(reasons) => {
+                let custom = reasons.iter()
+                    .filter(|r| matches!(r, RichReason::Custom(_)))
+                    .collect::<Vec<_>>();
+                let reasons = if custom.is_empty() {
+                    reasons.iter().collect()
                 } else {
-                    Label::new(err.span().into_range())
-                        .with_style(|s| s.red().to_string())
+                    custom
                 };
+                for reason in reasons {
+                    self.syntax_error(reason, span);
+                }
+            }
+        }
+    }
 
-                let block = Block::new(&idx, [label]).unwrap();
-                let block = block.map_code(|c| CodeWidth::new(c, c.len())); 
-
-                res.push(CompilerErr { msg, block });
+    /// Queues an error for the delimiters still open at the end of the file
+    /// at `start`, if any are, returning whether there were. It points to the
+    /// innermost `(` or `[` left open and any around it, and suggests closing
+    /// them all at the end of the code. Delimiters in strings aren't counted.
+    fn unclosed_delimiters(&mut self, start: usize) -> bool {
+        let file = self.sources.file(start);
+        // the suggestion goes right after the last of the code
+        let end = file.start + file.src.trim_end().len();
+        let mut open = vec![];
+        let mut in_string = false;
+        for (i, c) in file.src.char_indices() {
+            match c {
+                '"' => in_string = !in_string,
+                _ if in_string => (),
+                '(' | '[' => open.push((file.start + i, c)),
+                ')' | ']' => { open.pop(); }
+                _ => (),
             }
-            RichReason::Custom(msg) => {
-                let msg = format!(
-                    "[{:#?}]: {}",
-                    path.file_name().unwrap(),
-                    msg
-                );
-                let label = Label::new(err.span().into_range())
-                    .with_text("here".to_owned())
-                    .with_style(|s| s.red().to_string());
-                let block = Block::new(&idx, [label]).unwrap();
-                let block = block.map_code(|c| CodeWidth::new(c, c.len()));
+        }
+        let Some(&(innermost, _)) = open.last() else {
+            return false;
+        };
+        let closing = open.iter()
+            .rev()
+            .map(|(_, c)| if *c == '(' { ')' } else { ']' })
+            .collect::<String>();
+
+        let mut related = open[..open.len() - 1].iter()
+            .map(|(i, _)| (*i..*i + 1, "also unclosed".to_owned()))
+            .collect::<Vec<_>>();
+        related.push((end..end, format!("add `{}` here", closing)));
+        self.diags.push(Diagnostic {
+            warning: false,
+            msg: "unclosed delimiter".to_owned(),
+            label: Some((
+                innermost..innermost + 1,
+                Some("unclosed delimiter opened here".to_owned())
+            )),
+            related,
+        });
+        true
+    }
+
+    /// Finds a word at a syntax error that looks like a misspelled keyword,
+    /// returning its range and the keyword. That's either the word the error
+    /// is at, e.g. `fnn` in `export fnn f() = 1`, or, if a name was found
+    /// where the parser wanted an operator, the word before it, which it took
+    /// for a variable, e.g. `lte` in `lte x = 1`.
+    fn misspelled_keyword(
+        &self,
+        span: &SimpleSpan,
+        found: Option<char>
+    ) -> Option<(Range<usize>, &'static str)> {
+        let file = self.sources.file(span.start);
+        let at = span.start - file.start;
+        let src = file.src;
 
-                res.push(CompilerErr { msg, block });
+        let after = src[at..].len() - src[at..].trim_start_matches(is_ident_char).len();
+        let before = src[..at].trim_end();
+        let word = before.len() - before.trim_end_matches(is_ident_char).len();
+        let words = [
+            Some(at..at + after),
+            found.filter(|c| c.is_alphabetic() || *c == '_')
+                .map(|_| before.len() - word..before.len()),
+        ];
+
+        words.into_iter()
+            .flatten()
+            .filter(|range| range.len() >= 2)
+            .find_map(|range| {
+                let word = &src[range.clone()];
+                if KEYWORDS.contains(&word) || word.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                KEYWORDS.iter()
+                    .find(|keyword| distance(word, keyword) == 1)
+                    .map(|keyword| (range.start + file.start..range.end + file.start, *keyword))
+            })
+    }
+
+    /// Queue an error pointing to a location in the source.
+    pub fn error_at(
+        &mut self,
+        span: SimpleSpan,
+        msg: impl ToString,
+        label: impl ToString
+    ) {
+        self.diags.push(Diagnostic {
+            warning: false,
+            msg: msg.to_string(),
+            label: Some((span.into_range(), Some(label.to_string()))),
+            related: vec![],
+        });
+    }
+
+    /// Queue an error pointing to a location in the source, and labelling
+    /// other locations related to it, like [`warning_with_related()`].
+    ///
+    /// [`warning_with_related()`]: Self::warning_with_related()
+    pub fn error_with_related(
+        &mut self,
+        span: SimpleSpan,
+        msg: impl ToString,
+        label: impl ToString,
+        related: Vec<(SimpleSpan, String)>
+    ) {
+        self.diags.push(Diagnostic {
+            warning: false,
+            msg: msg.to_string(),
+            label: Some((span.into_range(), Some(label.to_string()))),
+            related: related.into_iter()
+                .map(|(span, label)| (span.into_range(), label))
+                .collect(),
+        });
+    }
+
+    /// Queue an error from generating code, pointing to where it is in the
+    /// source if it knows.
+    pub fn semantic_error(&mut self, err: CompilerError) {
+        match err.label {
+            Some((span, label)) => self.error_with_related(span, err.msg, label, err.related),
+            None => self.error(err.msg),
+        }
+    }
+
+    /// Queue an error which has no location in the source attached to it.
+    pub fn error(&mut self, msg: impl ToString) {
+        self.diags.push(Diagnostic {
+            warning: false,
+            msg: msg.to_string(),
+            label: None,
+            related: vec![],
+        });
+    }
+
+    /// Queue a warning pointing to a location in the source.
+    pub fn warning_at(
+        &mut self,
+        span: SimpleSpan,
+        msg: impl ToString,
+        label: impl ToString
+    ) {
+        self.diags.push(Diagnostic {
+            warning: !self.deny_warnings,
+            msg: self.warning_msg(msg),
+            label: Some((span.into_range(), Some(label.to_string()))),
+            related: vec![],
+        });
+    }
+
+    /// Queue a warning pointing to a location in the source, and labelling
+    /// other locations related to it. The related locations can't overlap
+    /// each other or the warning's own.
+    pub fn warning_with_related(
+        &mut self,
+        span: SimpleSpan,
+        msg: impl ToString,
+        label: impl ToString,
+        related: Vec<(SimpleSpan, String)>
+    ) {
+        self.diags.push(Diagnostic {
+            warning: !self.deny_warnings,
+            msg: self.warning_msg(msg),
+            label: Some((span.into_range(), Some(label.to_string()))),
+            related: related.into_iter()
+                .map(|(span, label)| (span.into_range(), label))
+                .collect(),
+        });
+    }
+
+    /// Render and print the queued diagnostics, and clear them so they aren't
+    /// printed again. Errors past the error limit, counting those printed by
+    /// earlier calls, are only counted.
+    pub fn print(&mut self) {
+        let mut hidden = 0;
+        for diag in &self.diags {
+            if !diag.warning {
+                if self.limit.is_some_and(|limit| self.errors_shown >= limit) {
+                    hidden += 1;
+                    continue;
+                }
+                self.errors_shown += 1;
+            }
+            let rendered = self.render(diag);
+            if self.messages.enabled() {
+                self.emit(diag, &rendered);
+            } else {
+                eprint!("{}", rendered);
             }
-            RichReason::Many(_) => todo!(),
         }
+
+        if hidden > 0 {
+            eprintln!(
+                "[{}]: {} more error(s) not shown",
+                self.sources.files()[0].path.display(),
+                hidden
+            );
+        }
+        self.diags = vec![];
+    }
+
+    /// Take the queued diagnostics without printing them, for output that
+    /// shows them alongside the source instead.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diags)
+    }
+
+    /// Gets the message of a warning, noting why it's an error if it's denied.
+    fn warning_msg(&self, msg: impl ToString) -> String {
+        if self.deny_warnings {
+            format!("{} (denied by `--deny-warnings`)", msg.to_string())
+        } else {
+            msg.to_string()
+        }
+    }
+
+    /// Print the queued diagnostics and exit, reporting that the build failed.
+    pub fn abort(&mut self) -> ! {
+        self.print();
+        self.messages.emit(Message::BuildFinished { success: false });
+        process::exit(1);
+    }
+
+    /// Renders a diagnostic as a code block of the file it points to, followed
+    /// by a block for each other file its related locations are in.
+    fn render(&self, diag: &Diagnostic) -> String {
+        let msg = if diag.warning {
+            format!("warning: {}", diag.msg)
+        } else {
+            diag.msg.clone()
+        };
+        let Some((range, text)) = &diag.label else {
+            return format!("[{}]: {}\n", self.sources.files()[0].path.display(), msg);
+        };
+
+        let mut labels = std::iter::once((range, text.as_deref()))
+            .chain(diag.related.iter().map(|(range, text)| (range, Some(text.as_str()))))
+            .map(|(range, text)| (self.sources.index(range.start), range, text))
+            .collect::<Vec<_>>();
+        let primary = labels[0].0;
+        let mut files = labels.iter().map(|(file, _, _)| *file).collect::<Vec<_>>();
+        files.sort_by_key(|&file| (file != primary, file));
+        files.dedup();
+        labels.sort_by_key(|(_, range, _)| range.start);
+
+        let mut rendered = String::new();
+        for file in files {
+            let source = &self.sources.files()[file];
+            let mut first = None;
+            let block_labels = labels.iter()
+                .filter(|(f, _, _)| *f == file)
+                .map(|(_, range, text)| {
+                    first.get_or_insert(range.start);
+                    let label = if diag.warning {
+                        Label::new(source.local((*range).clone()))
+                            .with_style(|s| s.yellow().to_string())
+                    } else {
+                        Label::new(source.local((*range).clone()))
+                            .with_style(|s| s.red().to_string())
+                    };
+                    match text {
+                        Some(text) => label.with_text(text.to_string()),
+                        None => label,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            // the block for the diagnostic's own file is headed by its message
+            let offset = if file == primary { range.start } else { first.unwrap() };
+            let (path, line, col) = self.sources.locate(offset);
+            let header = format!("[{}:{}:{}]", path.display(), line, col);
+            let header = if file == primary { format!("{}: {}", header, msg) } else { header };
+
+            let block = Block::new(source.index(), block_labels).unwrap();
+            let block = block.map_code(|c| CodeWidth::new(c, c.chars().count()));
+            rendered.push_str(&format!(
+                "{}{}\n{}{}\n",
+                block.prologue(),
+                header,
+                block,
+                block.epilogue()
+            ));
+        }
+        rendered
+    }
+
+    fn emit(&self, diag: &Diagnostic, rendered: &str) {
+        let file = match &diag.label {
+            Some((range, _)) => self.locate(range.start).0,
+            None => &self.sources.files()[0].path,
+        };
+        self.messages.emit(Message::Diagnostic {
+            level: if diag.warning { "warning" } else { "error" },
+            message: &diag.msg,
+            file,
+            location: diag.label
+                .as_ref()
+                .map(|(range, text)| self.location(range, text.as_deref())),
+            related: diag.related
+                .iter()
+                .map(|(range, text)| self.location(range, Some(text)))
+                .collect(),
+            rendered,
+        });
+    }
+
+    fn location<'a>(&'a self, range: &Range<usize>, label: Option<&'a str>) -> Location<'a> {
+        let (file, line, column) = self.locate(range.start);
+        let range = self.sources.file(range.start).local(range.clone());
+        Location {
+            file,
+            start: range.start,
+            end: range.end,
+            line,
+            column,
+            label,
+        }
+    }
+}
+
+/// Describes what the parser expected, e.g. `` `)`, `,`, or an operator ``,
+/// or returns `None` if it expected nothing in particular.
+///
+/// Tokens are grouped into what they start, so a digit reads as `a number`
+/// and any of `+-*/<>!` as `an operator` (`=` is left as it is, since it's
+/// expected on its own in declarations); whitespace is left out, since it's
+/// allowed almost anywhere. Punctuation comes first, then labels and groups,
+/// and the end of the file last.
+fn expected_list(expected: &[RichPattern<char>]) -> Option<String> {
+    let mut items = expected.iter()
+        .filter_map(|pattern| match pattern {
+            RichPattern::Token(c) if c.is_whitespace() => None,
+            RichPattern::Token(c) if c.is_ascii_digit() => Some((1, "a number".to_owned())),
+            RichPattern::Token(c) if c.is_alphabetic() || **c == '_' => {
+                Some((1, "a name".to_owned()))
+            }
+            RichPattern::Token(c) if "+-*/<>!".contains(**c) => {
+                Some((1, "an operator".to_owned()))
+            }
+            RichPattern::Token(c) => Some((0, format!("`{}`", **c))),
+            RichPattern::Label(label) => Some((1, label.to_string())),
+            RichPattern::EndOfInput => Some((2, "the end of the file".to_owned())),
+        })
+        .collect::<Vec<_>>();
+    items.sort();
+    items.dedup();
+
+    let items = items.into_iter().map(|(_, item)| item).collect::<Vec<_>>();
+    match items.as_slice() {
+        [] => None,
+        [item] => Some(item.clone()),
+        [a, b] => Some(format!("{} or {}", a, b)),
+        [init @ .., last] => Some(format!("{}, or {}", init.join(", "), last)),
+    }
+}
+
+/// Describes a character the parser found, quoting it unless it's
+/// whitespace, which would be invisible.
+fn describe(c: char) -> String {
+    match c {
+        '\n' => "a line break".to_owned(),
+        c if c.is_whitespace() => "a space".to_owned(),
+        c => format!("`{}`", c),
     }
-    res
 }
 
-struct CompilerErr<'a> {
-    msg: String,
-    block: Block<CodeWidth<&'a str>, String>,
+/// The keywords a word near a syntax error can be suggested as.
+const KEYWORDS: [&str; 7] = ["let", "fn", "extern", "export", "include", "requires", "ensures"];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
-impl<'a> CompilerErr<'a> {
-    pub fn print(&self) {
-        eprintln!("{}{}", self.block.prologue(), self.msg);
-        eprint!("{}", self.block);
-        eprintln!("{}", self.block.epilogue());
+/// Counts the single-character insertions, deletions, substitutions, and swaps
+/// of adjacent characters it takes to turn one word into another, so `lte` is
+/// as close to `let` as `fnn` is to `fn`.
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // d[i][j] is the distance between the first i chars of a and first j of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
     }
-}
\ No newline at end of file
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
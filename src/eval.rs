@@ -0,0 +1,376 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chumsky::{span::SimpleSpan, Parser};
+use inkwell::context::Context;
+
+use crate::{
+    cfg,
+    error::Diagnostics,
+    include,
+    llvm,
+    messages::Messages,
+    parse::{program, Expr, Spanned},
+    prelude,
+    LangVersion,
+    MessageFormat,
+};
+
+/// How deep calls can nest before evaluation gives up. Foo has no conditionals,
+/// so any recursive function recurses forever.
+const MAX_DEPTH: usize = 256;
+
+/// How many expressions can be evaluated before evaluation gives up, so that
+/// evaluating at compile time can't take much longer than compiling.
+const MAX_STEPS: usize = 1_000_000;
+
+/// How long evaluation can take before it gives up, in case the steps are
+/// slow, e.g. calls to expensive builtins.
+const MAX_TIME: Duration = Duration::from_secs(1);
+
+/// How many steps are taken between checks of the time taken.
+const TIME_CHECK_INTERVAL: usize = 1024;
+
+/// The limit evaluation gave up on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Depth,
+    Time,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "exceeded {} steps", MAX_STEPS),
+            Limit::Depth => write!(f, "exceeded {} nested calls", MAX_DEPTH),
+            Limit::Time => write!(f, "took longer than {}s", MAX_TIME.as_secs()),
+        }
+    }
+}
+
+/// A tree-walking interpreter for Foo, used to evaluate expressions at compile
+/// time.
+///
+/// Only expressions made of numbers, known constants, and calls to Foo or
+/// builtin functions can be evaluated; anything else, such as a call to an `extern`
+/// function, makes [`eval()`] return `None`. So do errors, which are left for
+/// codegen to report.
+///
+/// So does evaluation that passes one of the limits on how long it can take.
+/// The call in the top-level scope that was being evaluated is then never
+/// evaluated again, and is reported by [`take_exceeded()`].
+///
+/// [`eval()`]: Self::eval()
+/// [`take_exceeded()`]: Self::take_exceeded()
+pub struct Interpreter<'a> {
+    /// Foo functions' parameters and bodies, by name.
+    functions: HashMap<&'a str, (&'a [Spanned<String>], &'a Expr)>,
+    /// `extern` functions, which shadow builtins of the same name.
+    externs: HashSet<&'a str>,
+    /// Variables whose values are known at compile time.
+    consts: HashMap<&'a str, f64>,
+    steps: Cell<usize>,
+    started: Cell<Instant>,
+    /// The limit the current evaluation passed, if any.
+    limit: Cell<Option<Limit>>,
+    /// The calls in the top-level scope which passed a limit, by span.
+    exceeded: RefCell<HashMap<SimpleSpan, Limit>>,
+    /// The calls which have passed a limit since [`take_exceeded()`] was last
+    /// called.
+    ///
+    /// [`take_exceeded()`]: Self::take_exceeded()
+    new_exceeded: RefCell<Vec<(SimpleSpan, Limit)>>,
+    /// The calls to Foo functions that have returned, if they're being traced.
+    trace: RefCell<Option<Vec<TracedCall<'a>>>>,
+}
+
+/// A call to a Foo function made while evaluating, for [`Interpreter::trace()`].
+#[derive(Clone, Debug)]
+pub struct TracedCall<'a> {
+    pub name: &'a str,
+    pub args: Vec<f64>,
+    pub result: f64,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new() -> Interpreter<'a> {
+        Interpreter {
+            functions: HashMap::new(),
+            externs: HashSet::new(),
+            consts: HashMap::new(),
+            steps: Cell::new(0),
+            started: Cell::new(Instant::now()),
+            limit: Cell::new(None),
+            exceeded: RefCell::new(HashMap::new()),
+            new_exceeded: RefCell::new(vec![]),
+            trace: RefCell::new(None),
+        }
+    }
+
+    /// Starts recording the calls to Foo functions made by the expressions
+    /// evaluated, for [`take_trace()`].
+    ///
+    /// [`take_trace()`]: Self::take_trace()
+    pub fn trace(&mut self) {
+        *self.trace.get_mut() = Some(vec![]);
+    }
+
+    /// Takes the calls recorded since this was last called, in the order they
+    /// returned, so a call comes after the calls made by its arguments and
+    /// body.
+    pub fn take_trace(&self) -> Vec<TracedCall<'a>> {
+        self.trace.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Makes a function callable by the expressions evaluated.
+    pub fn define_fn(
+        &mut self,
+        name: &'a str,
+        args: &'a [Spanned<String>],
+        body: &'a Expr
+    ) {
+        self.functions.insert(name, (args, body));
+    }
+
+    /// Records that a function is `extern`, so calls to it can't be evaluated.
+    pub fn declare_extern(&mut self, name: &'a str) {
+        self.externs.insert(name);
+    }
+
+    /// Records the value of a variable, or that it has none known at compile
+    /// time, replacing any variable it shadows.
+    pub fn define_var(&mut self, name: &'a str, value: Option<f64>) {
+        match value {
+            Some(value) => self.consts.insert(name, value),
+            None => self.consts.remove(name),
+        };
+    }
+
+    /// Evaluates an expression in the program's top-level scope.
+    pub fn eval(&self, expr: &Expr) -> Option<f64> {
+        self.steps.set(0);
+        self.started.set(Instant::now());
+        self.limit.set(None);
+        self.eval_in(expr, &self.consts, 0)
+    }
+
+    /// Takes the spans of the calls which passed a limit since this was last
+    /// called, with the limit each passed. Each call is only reported once.
+    pub fn take_exceeded(&self) -> Vec<(SimpleSpan, Limit)> {
+        std::mem::take(&mut self.new_exceeded.borrow_mut())
+    }
+
+    fn eval_in(
+        &self,
+        expr: &Expr,
+        vars: &HashMap<&str, f64>,
+        depth: usize
+    ) -> Option<f64> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if steps > MAX_STEPS {
+            self.limit.set(Some(Limit::Steps));
+            return None;
+        }
+        if steps.is_multiple_of(TIME_CHECK_INTERVAL) && self.started.get().elapsed() > MAX_TIME {
+            self.limit.set(Some(Limit::Time));
+            return None;
+        }
+
+        match expr {
+            Expr::Num(val, _) => Some(*val),
+            Expr::Var(name, _) => vars.get(name.as_str()).copied(),
+            Expr::Neg(expr, _) => Some(-self.eval_in(expr, vars, depth)?),
+            Expr::Add(left, right, _) => Some(
+                self.eval_in(left, vars, depth)? + self.eval_in(right, vars, depth)?
+            ),
+            Expr::Sub(left, right, _) => Some(
+                self.eval_in(left, vars, depth)? - self.eval_in(right, vars, depth)?
+            ),
+            Expr::Mul(left, right, _) => Some(
+                self.eval_in(left, vars, depth)? * self.eval_in(right, vars, depth)?
+            ),
+            Expr::Div(left, right, _) => Some(
+                self.eval_in(left, vars, depth)? / self.eval_in(right, vars, depth)?
+            ),
+            Expr::Cmp(op, left, right, _) => {
+                let (left, right) =
+                    (self.eval_in(left, vars, depth)?, self.eval_in(right, vars, depth)?);
+                Some(if op.holds(left, right) { 1.0 } else { 0.0 })
+            }
+            Expr::Call((name, _), args, _)
+                if !self.functions.contains_key(name.as_str())
+                    && !self.externs.contains(name.as_str()) =>
+            {
+                let builtin = prelude::builtin(name)?;
+                if args.len() != builtin.params.len() {
+                    return None;
+                }
+                let args = args.iter()
+                    .map(|arg| self.eval_in(arg, vars, depth))
+                    .collect::<Option<Vec<_>>>()?;
+                Some((builtin.eval)(&args))
+            }
+            Expr::Call((name, _), args, span) => {
+                let (&name, &(params, body)) = self.functions.get_key_value(name.as_str())?;
+                if args.len() != params.len() {
+                    return None;
+                }
+                if depth == MAX_DEPTH {
+                    self.limit.set(Some(Limit::Depth));
+                    return None;
+                }
+                if depth == 0 && self.exceeded.borrow().contains_key(span) {
+                    return None;
+                }
+                // function bodies only see their own parameters
+                let mut locals = HashMap::new();
+                let mut values = vec![];
+                for ((param, _), arg) in params.iter().zip(args) {
+                    let value = self.eval_in(arg, vars, depth)?;
+                    locals.insert(param.as_str(), value);
+                    values.push(value);
+                }
+                let value = self.eval_in(body, &locals, depth + 1);
+                if let (Some(trace), Some(result)) = (self.trace.borrow_mut().as_mut(), value) {
+                    trace.push(TracedCall { name, args: values, result });
+                }
+                // an argument passing a limit has already returned, so it's
+                // the call's own body that passed it
+                if let (None, 0, Some(limit)) = (value, depth, self.limit.get()) {
+                    self.exceeded.borrow_mut().insert(*span, limit);
+                    self.new_exceeded.borrow_mut().push((*span, limit));
+                }
+                value
+            }
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => None,
+        }
+    }
+}
+
+/// Evaluates a Foo expression and prints its value, after the declarations in
+/// each of the `includes` files, in order, so that it can call their functions
+/// and use their variables. The expression can start with declarations of its
+/// own, e.g. `let a = 2; fib(a)`.
+///
+/// An included file only needs declarations; if it ends in an expression, the
+/// expression is ignored.
+pub fn evaluate(expr: &str, includes: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut programs = vec![];
+    for path in includes {
+        let Ok(src) = std::fs::read_to_string(path) else {
+            return Err(format!("failed to open {}", path.display()).into());
+        };
+        programs.push(parse(path, &src, true));
+    }
+    programs.push(parse(Path::new("<expr>"), expr, false));
+
+    let mut interpreter = Interpreter::new();
+    let mut value = None;
+    for program in &programs {
+        let mut expr = program;
+        value = loop {
+            match expr {
+                Expr::Let { name, rhs, then, .. } => {
+                    let value = interpreter.eval(rhs);
+                    interpreter.define_var(&name.0, value);
+                    expr = then;
+                }
+                Expr::Fn { name, args, body, then, .. } => {
+                    interpreter.define_fn(&name.0, args, body);
+                    expr = then;
+                }
+                Expr::Extern { name, then, .. } => {
+                    interpreter.declare_extern(&name.0);
+                    expr = then;
+                }
+                expr => break interpreter.eval(expr),
+            }
+        };
+    }
+
+    if let Some((_, limit)) = interpreter.take_exceeded().last() {
+        return Err(format!("evaluating `{}` {}", expr, limit).into());
+    }
+    match value {
+        Some(value) => {
+            println!("{}", value);
+            Ok(())
+        }
+        None => Err(format!(
+            "`{}` couldn't be evaluated: it uses an undefined name, calls an `extern` \
+            function, or doesn't finish",
+            expr
+        ).into()),
+    }
+}
+
+/// Compiles an expression into a function `name` taking `params`, and prints
+/// the IR of the module it's in, for `eval --compile-fn`.
+pub fn compile(expr: &str, name: &str, params: &[String]) -> Result<(), Box<dyn Error>> {
+    let context = Context::create();
+    let params = params.iter().map(String::as_str).collect::<Vec<_>>();
+    let module = llvm::compile_expr_to_fn(&context, name, &params, expr)?;
+    print!("{}", module.print_to_string().to_string_lossy());
+    Ok(())
+}
+
+/// Parses a program and the files it includes, printing their syntax errors
+/// and exiting if they have any, and leaves out the declarations whose
+/// `#[cfg]` doesn't hold for this machine. If `library` is set, the program can
+/// end after its declarations.
+pub fn parse(path: &Path, src: &str, library: bool) -> Expr {
+    let mut diags = Diagnostics::new(path, src, None, Messages::new(MessageFormat::Human));
+    let features = LangVersion::of(None, src).features();
+    let program = match program(features).parse(src).into_result() {
+        Ok(program) => program,
+        // a program has to end in an expression, which a library doesn't need
+        Err(errs) => {
+            let src = format!("{}\n0", src);
+            let program = program(features).parse(&src).into_result().ok().filter(|_| library);
+            program.unwrap_or_else(|| {
+                diags.syntax_errors(errs);
+                diags.abort();
+            })
+        }
+    };
+    let program = include::resolve(path, program, features, &mut diags).unwrap_or_else(|| diags.abort());
+    cfg::filter_host(program.body, &mut diags)
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::Parser;
+
+    use super::Interpreter;
+    use crate::{parse::parser, LangVersion};
+
+    /// `1 + 2^-30` and `1 - 2^-30`, whose product, `1 - 2^-60`, rounds to 1.
+    const A: &str = "(1073741825 / 1073741824)";
+    const B: &str = "(1073741823 / 1073741824)";
+
+    fn eval(src: &str) -> Option<f64> {
+        let expr = parser(LangVersion::LATEST.features())
+            .parse(src)
+            .into_result()
+            .unwrap();
+        Interpreter::new().eval(&expr)
+    }
+
+    #[test]
+    fn separate_mul_and_add_round_twice() {
+        assert_eq!(eval(&format!("{} * {} - 1", A, B)), Some(0.0));
+    }
+
+    #[test]
+    fn fma_rounds_once() {
+        assert_eq!(eval(&format!("fma({}, {}, -1)", A, B)), Some(-(2f64.powi(-60))));
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{bin, crash, messages::Messages, run, temp::TempDir, Args, SandboxLimits};
+
+/// Compiles a Foo source file to an executable in a temporary directory and
+/// runs it, passing its output through. An error is returned if the program
+/// doesn't exit successfully, naming the signal that killed it if one did.
+///
+/// With `debug_info`, the program is compiled with debug info and, on Linux,
+/// traced, so that if it crashes, the line of source it crashed at can be
+/// printed.
+///
+/// With `sandbox`, the program runs under the given limits, for running
+/// programs from people who aren't trusted. See [`SandboxLimits`].
+pub fn run_program(
+    src: &Path,
+    debug_info: bool,
+    sandbox: Option<&SandboxLimits>,
+    messages: Messages
+) -> Result<(), Box<dyn Error>> {
+    // the directory is only kept if compiling fails, not if the program does
+    let (status, fault) = TempDir::scoped(messages, |temp| {
+        let exe = temp.file(if cfg!(windows) { "program.exe" } else { "program" });
+        let mut args = vec![
+            "foo_llvm".as_ref(),
+            src.as_os_str(),
+            "-o".as_ref(),
+            exe.as_os_str(),
+        ];
+        if debug_info {
+            args.push("-g".as_ref());
+        }
+        run(Args::from_command_line(args)?, messages)?;
+
+        let mut command = Command::new(&exe);
+        #[cfg(target_os = "linux")]
+        if debug_info {
+            crash::trace_me(&mut command);
+        }
+        #[cfg(unix)]
+        if let Some(limits) = sandbox {
+            limits.restrict(&mut command)?;
+        }
+        // with `RLIMIT_FSIZE` at zero a sandboxed program couldn't write its
+        // output if it were redirected to a file, so it's passed through a
+        // pipe instead
+        let mut child = if sandbox.is_some() {
+            command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        } else {
+            &mut command
+        }.spawn().map_err(|e| spawn_error(e, sandbox))?;
+        let forwarding = sandbox.is_some().then(|| [
+            forward(child.stdout.take().unwrap(), std::io::stdout()),
+            forward(child.stderr.take().unwrap(), std::io::stderr()),
+        ]);
+
+        let limit = sandbox.map(SandboxLimits::wall_time);
+        #[cfg(target_os = "linux")]
+        let res = if debug_info {
+            crash::trace(&child, &exe, limit).map(|(status, fault)| {
+                (status, fault.and_then(|address| bin::symbolize(&exe, address)))
+            })
+        } else {
+            wait_timeout(&mut child, limit).map(|status| (status, None))
+        };
+        #[cfg(not(target_os = "linux"))]
+        let res = wait_timeout(&mut child, limit).map(|status| (status, None));
+
+        for thread in forwarding.into_iter().flatten() {
+            let _ = thread.join();
+        }
+        res
+    })?;
+
+    if let Some((path, line)) = fault {
+        eprintln!("note: the program crashed at {}:{}", path.display(), line);
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        if let Some(text) = text.lines().nth(line as usize - 1) {
+            eprintln!("{:>5} | {}", line, text);
+        }
+    }
+    match (status, sandbox) {
+        (Some(status), _) => check_status(status, sandbox),
+        (None, Some(limits)) => Err(wall_time_error(limits)),
+        (None, None) => unreachable!("only sandboxed programs are timed"),
+    }
+}
+
+/// Runs a compiled program, under the given limits if there are any, and
+/// returns what it printed to stdout. As with [`run_program()`], an error is
+/// returned if the program doesn't exit successfully.
+pub fn run_captured(
+    exe: &Path,
+    sandbox: Option<&SandboxLimits>
+) -> Result<String, Box<dyn Error>> {
+    let mut command = Command::new(exe);
+    #[cfg(unix)]
+    if let Some(limits) = sandbox {
+        limits.restrict(&mut command)?;
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| spawn_error(e, sandbox))?;
+    // read on a thread of its own, so a program printing more than the pipe
+    // holds isn't blocked while it's waited for
+    let mut stdout = child.stdout.take().unwrap();
+    let reading = std::thread::spawn(move || {
+        let mut out = String::new();
+        let _ = stdout.read_to_string(&mut out);
+        out
+    });
+
+    let status = wait_timeout(&mut child, sandbox.map(SandboxLimits::wall_time))?;
+    let out = reading.join().unwrap_or_default();
+    match (status, sandbox) {
+        (Some(status), _) => check_status(status, sandbox)?,
+        (None, Some(limits)) => return Err(wall_time_error(limits)),
+        (None, None) => unreachable!("only sandboxed programs are timed"),
+    }
+    Ok(out)
+}
+
+/// Explains a failure to start a program, which in a sandbox is most likely a
+/// limit the platform wouldn't apply.
+fn spawn_error(e: std::io::Error, sandbox: Option<&SandboxLimits>) -> Box<dyn Error> {
+    match sandbox {
+        Some(_) => format!("couldn't run the program in the sandbox: {}", e).into(),
+        None => e.into(),
+    }
+}
+
+fn wall_time_error(limits: &SandboxLimits) -> Box<dyn Error> {
+    format!(
+        "the program was killed after running for longer than the wall time limit of {} s",
+        limits.wall_time
+    ).into()
+}
+
+impl SandboxLimits {
+    fn wall_time(&self) -> Duration {
+        Duration::from_secs(self.wall_time)
+    }
+
+    /// Makes the command run its program under the limits. The CPU time and
+    /// memory limits are enforced by the kernel; the program can't create
+    /// processes, write to files, or dump core. On Linux, it also gets a
+    /// network namespace of its own, with no interfaces but loopback, and
+    /// Landlock keeps it from opening any file but itself and the shared
+    /// libraries it's linked with. Elsewhere, it can still use the network
+    /// and read any file its user can.
+    ///
+    /// If a limit can't be applied, e.g. because the kernel doesn't allow
+    /// unprivileged user namespaces or doesn't have Landlock, the program
+    /// isn't started, and spawning it fails. Limits already lower than these
+    /// are kept.
+    #[cfg(unix)]
+    fn restrict(&self, command: &mut Command) -> Result<(), Box<dyn Error>> {
+        use std::os::unix::process::CommandExt;
+
+        let cpu_time = self.cpu_time as libc::rlim_t;
+        let memory = (self.memory as libc::rlim_t).saturating_mul(1024 * 1024);
+        let limits = [
+            // the soft limit sends SIGXCPU, and the hard limit a second later
+            // SIGKILL, in case the program handles SIGXCPU
+            (libc::RLIMIT_CPU, cpu_time, cpu_time + 1),
+            (libc::RLIMIT_AS, memory, memory),
+            (libc::RLIMIT_FSIZE, 0, 0),
+            (libc::RLIMIT_NPROC, 0, 0),
+            (libc::RLIMIT_CORE, 0, 0),
+        ];
+        // the files the program can open are opened here, since the child
+        // can only make system calls
+        #[cfg(target_os = "linux")]
+        let rules = landlock::Rules::for_program(Path::new(command.get_program()))?;
+        let restrict = move || {
+            #[cfg(target_os = "linux")]
+            {
+                check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) })?;
+                rules.restrict_self()?;
+            }
+            for (resource, soft, hard) in limits {
+                let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+                check(unsafe { libc::getrlimit(resource, &mut limit) })?;
+                // without privileges, a hard limit can only be lowered
+                let limit = libc::rlimit {
+                    rlim_cur: soft.min(limit.rlim_max),
+                    rlim_max: hard.min(limit.rlim_max),
+                };
+                check(unsafe { libc::setrlimit(resource, &limit) })?;
+            }
+            Ok(())
+        };
+        unsafe {
+            command.pre_exec(restrict);
+        }
+        Ok(())
+    }
+}
+
+/// Turns the result of a system call into an error if it failed.
+#[cfg(unix)]
+fn check(result: libc::c_int) -> std::io::Result<()> {
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Keeps a sandboxed program from opening files with Landlock, which every
+/// Linux since 5.13 can be built with.
+#[cfg(target_os = "linux")]
+mod landlock {
+    use std::{
+        fs::OpenOptions,
+        os::{fd::{AsRawFd, OwnedFd}, unix::fs::OpenOptionsExt},
+        path::Path,
+    };
+
+    use super::check;
+
+    const EXECUTE: u64 = 1 << 0;
+    const READ_FILE: u64 = 1 << 2;
+    const READ_DIR: u64 = 1 << 3;
+    /// Every access to files in the first version of Landlock, from executing
+    /// them to making symlinks: anything not allowed by a rule is denied.
+    const HANDLED: u64 = (1 << 13) - 1;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    /// Where the dynamic loader finds the C and math libraries, and the cache
+    /// of where they are.
+    const LIBRARIES: [&str; 7] = [
+        "/lib",
+        "/lib32",
+        "/lib64",
+        "/usr/lib",
+        "/usr/lib32",
+        "/usr/lib64",
+        "/etc/ld.so.cache",
+    ];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: libc::c_int,
+    }
+
+    /// The files a program can open, and how.
+    pub struct Rules {
+        paths: Vec<(OwnedFd, u64)>,
+    }
+
+    impl Rules {
+        /// Allows the program to run itself, and the dynamic loader to load
+        /// its libraries, but nothing else. Libraries missing from this system
+        /// are skipped.
+        pub fn for_program(program: &Path) -> std::io::Result<Rules> {
+            let mut paths = vec![(open(program)?, EXECUTE | READ_FILE)];
+            for library in LIBRARIES {
+                let access = if Path::new(library).is_dir() {
+                    EXECUTE | READ_FILE | READ_DIR
+                } else {
+                    READ_FILE
+                };
+                if let Ok(fd) = open(Path::new(library)) {
+                    paths.push((fd, access));
+                }
+            }
+            Ok(Rules { paths })
+        }
+
+        /// Restricts the calling process to the rules. It's called in the
+        /// forked child, so it only makes system calls.
+        pub fn restrict_self(&self) -> std::io::Result<()> {
+            let attr = RulesetAttr { handled_access_fs: HANDLED };
+            let ruleset = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    &attr as *const RulesetAttr,
+                    std::mem::size_of::<RulesetAttr>(),
+                    0
+                )
+            };
+            check(ruleset as libc::c_int)?;
+            for (fd, access) in &self.paths {
+                let rule = PathBeneathAttr { allowed_access: *access, parent_fd: fd.as_raw_fd() };
+                check(unsafe {
+                    libc::syscall(
+                        libc::SYS_landlock_add_rule,
+                        ruleset,
+                        RULE_PATH_BENEATH,
+                        &rule as *const PathBeneathAttr,
+                        0
+                    )
+                } as libc::c_int)?;
+            }
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            check(unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) }
+                as libc::c_int)?;
+            check(unsafe { libc::close(ruleset as libc::c_int) })
+        }
+    }
+
+    /// Opens a path only to refer to it in a rule.
+    fn open(path: &Path) -> std::io::Result<OwnedFd> {
+        Ok(OpenOptions::new().read(true).custom_flags(libc::O_PATH).open(path)?.into())
+    }
+}
+
+/// Copies a child's output to ours as it's written, on a thread of its own so
+/// that neither stream can fill up and block the child while the other is
+/// read.
+fn forward(
+    mut from: impl std::io::Read + Send + 'static,
+    mut to: impl Write + Send + 'static
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut from, &mut to);
+        let _ = to.flush();
+    })
+}
+
+/// Waits for a child to exit, killing it if it's still running after
+/// `limit`, in which case `None` is returned.
+fn wait_timeout(
+    child: &mut Child,
+    limit: Option<Duration>
+) -> Result<Option<ExitStatus>, Box<dyn Error>> {
+    let Some(limit) = limit else {
+        return Ok(Some(child.wait()?));
+    };
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= limit {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Turns a program's exit status into an error if it didn't succeed, saying
+/// which limit it hit if it ran in a sandbox and the status shows one.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn check_status(status: ExitStatus, sandbox: Option<&SandboxLimits>) -> Result<(), Box<dyn Error>> {
+    if status.success() {
+        return Ok(());
+    }
+    if let Some(code) = status.code() {
+        return Err(format!("the program exited with status {}", code).into());
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        let name = match crash::signal_name(signal) {
+            Some((name, meaning)) => format!("{} ({})", name, meaning),
+            None => format!("signal {}", signal),
+        };
+        let limit = sandbox.and_then(|limits| match signal {
+            libc::SIGXCPU | libc::SIGKILL => Some(format!(
+                "it used more than the CPU time limit of {} s",
+                limits.cpu_time
+            )),
+            // failing to allocate memory, including the stack, is how the
+            // memory limit shows itself
+            libc::SIGSEGV | libc::SIGBUS | libc::SIGABRT => Some(format!(
+                "it may have hit the memory limit of {} MiB",
+                limits.memory
+            )),
+            libc::SIGXFSZ => Some("it tried to write to a file".to_owned()),
+            _ => None,
+        });
+        return Err(match limit {
+            Some(limit) => format!("the program was killed by {}: {}", name, limit),
+            None => format!("the program was killed by {}", name),
+        }.into());
+    }
+    Err(format!("the program failed: {}", status).into())
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use chumsky::span::SimpleSpan;
+use inkwell::{context::Context, module::Module, values::AnyValue};
+
+use crate::{
+    cfg,
+    error::Diagnostics,
+    include,
+    llvm::{
+        self, CodegenOptions, Instrumentation, IrValue, LlvmGenerator, PrintFormat,
+        ReadableNames
+    },
+    messages::Messages,
+    parse::Expr,
+    sema,
+    FloatModel,
+    FloatWidth,
+    LangVersion,
+    MessageFormat,
+    Notation,
+};
+
+/// Walks a source file's AST in the order its code is generated, printing each
+/// node with the IR instructions generated for it, which are named after where
+/// in the source they came from, as under `--readable-ir`. The code is
+/// unoptimized, so every operation shows up as it's written, unless the builder
+/// folded it into a constant.
+///
+/// With `interactive`, it pauses after each node for a command: Enter or `n`
+/// for the next node, `c` to print the rest without pausing, or `q` to quit.
+/// The commands are read from `script` if it's given, and echoed, so that a
+/// session can be replayed as a transcript, e.g. for a class.
+pub fn explain(
+    src_path: &Path,
+    interactive: bool,
+    script: Option<&Path>
+) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+    let mut script = match script {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {:#?}: {}", path, e))?;
+            Some(text.lines().map(str::to_owned).collect::<Vec<_>>().into_iter())
+        }
+        None => None,
+    };
+
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let features = LangVersion::of(None, &src).features();
+    let ast = include::parse(src_path, &src, features, &mut diags).body;
+    let ast = cfg::filter_host(ast, &mut diags);
+    sema::check(&ast, &mut diags);
+    if diags.has_errors() {
+        diags.abort();
+    }
+    diags.print();
+
+    let context = Context::create();
+    let module = context.create_module(&src_path.file_stem().unwrap().to_string_lossy());
+    let builder = context.create_builder();
+    let options = CodegenOptions {
+        print_format: PrintFormat {
+            notation: Notation::Fixed,
+            precision: None,
+            newline: true,
+        },
+        entry: None,
+        entry_symbol: Some("main".to_owned()),
+        float_model: FloatModel::Strict,
+        float_width: FloatWidth::F64,
+        stack_probes: false,
+        stack_probe_size: None,
+        exports: vec![],
+        profile: None,
+        readable_names: Some(ReadableNames::new(diags.lines(), &src)),
+    };
+    let instrumentation = Instrumentation::default();
+    let output = LlvmGenerator::generate(
+        &ast,
+        &context,
+        &module,
+        &builder,
+        options,
+        None,
+        instrumentation
+    );
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            diags.semantic_error(e);
+            diags.abort();
+        }
+    };
+    if !llvm::verify_functions(&module, &output.symbols, &mut diags) {
+        diags.abort();
+    }
+    let instructions = instructions(&module);
+
+    let steps = steps(&ast);
+    let mut pausing = interactive || script.is_some();
+    let stdin = std::io::stdin();
+    for (i, expr) in steps.iter().enumerate() {
+        let span = expr.span();
+        // a declaration's span runs on through the declarations after it
+        let end = match expr {
+            Expr::Let { then, .. } | Expr::Fn { then, .. } | Expr::Extern { then, .. } => {
+                then.span().start
+            }
+            _ => span.end,
+        };
+        // and some start with the whitespace before them
+        let text = diags.source(SimpleSpan::new(span.start, end));
+        let start = span.start + text.len() - text.trim_start().len();
+        let text = text.trim();
+        let (path, line, column) = diags.locate(start);
+        let first_line = text.lines().next().unwrap_or_default();
+        println!(
+            "[{}/{}] {} at {}:{}:{}: `{}{}`",
+            i + 1,
+            steps.len(),
+            kind(expr),
+            path.display(),
+            line,
+            column,
+            first_line,
+            if first_line.len() < text.len() { " ..." } else { "" }
+        );
+
+        for line in explanation(expr, &output.values, &instructions, &module, &output.symbols) {
+            println!("    {}", line);
+        }
+
+        if !pausing || i + 1 == steps.len() {
+            continue;
+        }
+        loop {
+            print!("(n)ext, (c)ontinue, (q)uit> ");
+            std::io::stdout().flush()?;
+            let command = match &mut script {
+                Some(script) => {
+                    // the script running out plays the rest through
+                    let command = script.next().unwrap_or_else(|| "c".to_owned());
+                    println!("{}", command);
+                    command
+                }
+                None => {
+                    let mut command = String::new();
+                    if stdin.lock().read_line(&mut command)? == 0 {
+                        println!();
+                        return Ok(());
+                    }
+                    command
+                }
+            };
+            match command.trim() {
+                "" | "n" => break,
+                "c" => {
+                    pausing = false;
+                    break;
+                }
+                "q" => return Ok(()),
+                other => println!("unknown command `{}`", other),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gets the nodes of the AST in the order their code is generated: the
+/// operands of an operation before it, a `let`'s value before the `let`, and a
+/// function before its body.
+fn steps(root: &Expr) -> Vec<&Expr> {
+    let mut steps = vec![];
+    let mut e = root;
+    loop {
+        match e {
+            Expr::Let { rhs, then, .. } => {
+                expr_steps(rhs, &mut steps);
+                steps.push(e);
+                e = then;
+            }
+            Expr::Fn { body, then, .. } => {
+                steps.push(e);
+                expr_steps(body, &mut steps);
+                e = then;
+            }
+            Expr::Extern { then, .. } => {
+                steps.push(e);
+                e = then;
+            }
+            expr => {
+                expr_steps(expr, &mut steps);
+                return steps;
+            }
+        }
+    }
+}
+
+fn expr_steps<'e>(root: &'e Expr, steps: &mut Vec<&'e Expr>) {
+    // each node is pushed back once its operands are, to be taken after them
+    let mut stack = vec![(root, false)];
+    while let Some((expr, operands_done)) = stack.pop() {
+        if operands_done {
+            steps.push(expr);
+            continue;
+        }
+        stack.push((expr, true));
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push((expr, false)),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push((right, false));
+                stack.push((left, false));
+            }
+            Expr::Call(_, args, _) => stack.extend(args.iter().rev().map(|arg| (arg, false))),
+            // declarations only come before the final expression
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+        }
+    }
+}
+
+/// Explains the code generated for a node, a line at a time.
+fn explanation(
+    expr: &Expr,
+    values: &HashMap<SimpleSpan, Vec<IrValue>>,
+    instructions: &HashMap<(String, String), String>,
+    module: &Module,
+    symbols: &HashMap<String, SimpleSpan>
+) -> Vec<String> {
+    let values_of = |expr: &Expr| values.get(&expr.span()).map(Vec::as_slice).unwrap_or_default();
+    let signature = |symbol: &str| module.get_function(symbol).map(|function| {
+        let ir = function.print_to_string().to_string();
+        // just the signature, without the body the nodes after it fill in
+        let signature = ir.lines()
+            .find(|line| line.starts_with("define") || line.starts_with("declare"))
+            .unwrap_or_default();
+        vec![signature.trim_end_matches(" {").to_owned()]
+    });
+
+    match expr {
+        Expr::Fn { name: (_, name_span), .. } => symbols.iter()
+            .find(|(_, span)| *span == name_span)
+            .and_then(|(symbol, _)| signature(symbol))
+            .unwrap_or_default(),
+        // `extern` functions keep their names
+        Expr::Extern { name, .. } => signature(&name.0).unwrap_or_default(),
+        Expr::Let { name, rhs, .. } => match values_of(rhs).last() {
+            Some(value) => vec![format!("`{}` is `%{}` from here on", name.0, value.name)],
+            None => vec![format!(
+                "`{}` is a constant, written into the instructions using it",
+                name.0
+            )],
+        },
+        Expr::Num(..) => vec!["a constant, written into the instructions using it".to_owned()],
+        Expr::Var(name, _) => match values_of(expr).first() {
+            Some(value) => vec![format!("uses `%{}`, the value of `{}`", value.name, name)],
+            None => vec![format!(
+                "`{}` is a constant, written into the instructions using it",
+                name
+            )],
+        },
+        _ => {
+            let lines = values_of(expr).iter()
+                .filter_map(|value| {
+                    instructions.get(&(value.function.clone(), value.name.clone())).cloned()
+                })
+                .collect::<Vec<_>>();
+            if lines.is_empty() {
+                vec!["folded into a constant, as its operands are".to_owned()]
+            } else {
+                lines
+            }
+        }
+    }
+}
+
+/// Gets the text of every named instruction in a module, by its function's
+/// symbol and its name.
+fn instructions(module: &Module) -> HashMap<(String, String), String> {
+    let mut instructions = HashMap::new();
+    for function in module.get_functions() {
+        let symbol = function.get_name().to_string_lossy().into_owned();
+        for block in function.get_basic_blocks() {
+            let mut next = block.get_first_instruction();
+            while let Some(inst) = next {
+                if let Some(name) = inst.get_name().filter(|name| !name.is_empty()) {
+                    instructions.insert(
+                        (symbol.clone(), name.to_string_lossy().into_owned()),
+                        inst.print_to_string().to_string().trim().to_owned()
+                    );
+                }
+                next = inst.get_next_instruction();
+            }
+        }
+    }
+    instructions
+}
+
+fn kind(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Num(..) => "number",
+        Expr::Var(..) => "variable",
+        Expr::Neg(..) => "negation",
+        Expr::Add(..) => "addition",
+        Expr::Sub(..) => "subtraction",
+        Expr::Mul(..) => "multiplication",
+        Expr::Div(..) => "division",
+        Expr::Cmp(..) => "comparison",
+        Expr::Call(..) => "call",
+        Expr::Let { .. } => "let",
+        Expr::Fn { .. } => "fn",
+        Expr::Extern { .. } => "extern",
+    }
+}
@@ -0,0 +1,109 @@
+use std::{error::Error, path::Path};
+
+use chumsky::{span::SimpleSpan, Parser};
+use clap::ValueEnum;
+use similar::TextDiff;
+
+use crate::{
+    artifact,
+    deprecated,
+    error::Diagnostics,
+    messages::Messages,
+    parse::{program, Directive},
+    LangVersion,
+    MessageFormat,
+};
+
+/// Rewrites the calls to deprecated builtins in a source file to call the
+/// builtins replacing them, then writes the file back, or prints the change as
+/// a unified diff if `diff` is set.
+///
+/// If `edition` is set and the program is written in an older version of the
+/// language, it's also migrated to the latest: the code the latest version
+/// parses differently is rewritten, and its `#![version(...)]` directive
+/// updated. Only the simple cases are rewritten, so if the migrated program
+/// still doesn't parse, its errors are printed and the file is left as it is.
+pub fn fix(src_path: &Path, edition: bool, diff: bool) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+    let version = LangVersion::of(None, &src);
+
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let parsed = program(version.features())
+        .parse(&src)
+        .into_result()
+        .unwrap_or_else(|errs| {
+            diags.syntax_errors(errs);
+            diags.abort();
+        });
+
+    let migrate = edition && version < LangVersion::LATEST;
+    let mut edits = deprecated::find(&parsed.body, version.features(), &diags)
+        .into_iter()
+        .filter(|deprecation| migrate || !deprecation.migration)
+        .map(|deprecation| deprecation.fix)
+        .collect::<Vec<_>>();
+    if migrate {
+        let latest = LangVersion::LATEST.to_possible_value().unwrap();
+        let directive = format!("#![version({})]", latest.get_name());
+        let named = parsed.directives.iter()
+            .filter(|(directive, _)| matches!(directive, Directive::Version(_)))
+            .map(|(_, span)| (*span, directive.clone()))
+            .collect::<Vec<_>>();
+        if named.is_empty() {
+            // a file without a directive is read as the default version, so
+            // it needs one to be read as the latest; it's ended with a `;`
+            // rather than a newline to keep the lines where they were
+            edits.push((SimpleSpan::new(0, 0), format!("{}; ", directive)));
+        } else {
+            edits.extend(named);
+        }
+    }
+    if edits.is_empty() {
+        return Ok(());
+    }
+    edits.sort_by_key(|(span, _)| span.start);
+    let fixed = apply(&src, &edits);
+
+    if migrate {
+        if let Err(errs) = program(LangVersion::LATEST.features()).parse(&fixed).into_result() {
+            // moving operators to the line above keeps the number of lines,
+            // so the errors point to the same lines as in the original
+            let mut diags = Diagnostics::new(
+                src_path,
+                &fixed,
+                None,
+                Messages::new(MessageFormat::Human)
+            );
+            diags.syntax_errors(errs);
+            diags.print();
+            return Err(
+                "the rest of the program has to be migrated to the latest version by hand"
+                    .into()
+            );
+        }
+    }
+
+    if diff {
+        let path = src_path.to_string_lossy();
+        print!("{}", TextDiff::from_lines(&src, &fixed).unified_diff().header(&path, &path));
+    } else {
+        artifact::write(src_path, fixed)?;
+    }
+    Ok(())
+}
+
+/// Replaces each of the sorted, non-overlapping spans in `src` with its
+/// replacement.
+fn apply(src: &str, edits: &[(SimpleSpan, String)]) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut last = 0;
+    for (span, replacement) in edits {
+        out.push_str(&src[last..span.start]);
+        out.push_str(replacement);
+        last = span.end;
+    }
+    out.push_str(&src[last..]);
+    out
+}
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, time::Instant};
+
+use chumsky::span::SimpleSpan;
+
+use crate::{error::Diagnostics, eval::Interpreter, parse::Expr, timings::DeclTimes};
+
+/// Propagates the values of `let` bindings known at compile time into the
+/// rest of the program, and folds every expression that can be evaluated at
+/// compile time into a number, e.g. `let a = 2; let b = a * 3; sin(b) + a`
+/// becomes `sin(6) + 2`.
+///
+/// A binding whose value is known is removed, since every use of it has been
+/// replaced. This is only sound because Foo's variables can't be reassigned,
+/// only shadowed: a later `let` of the same name that isn't known at compile
+/// time stops the earlier value from being propagated past it.
+///
+/// Function bodies only see their own parameters, so they're left as they are.
+///
+/// Folding that turns finite numbers into an infinity or a subnormal number
+/// queues a warning pointing to the literals the result came from.
+///
+/// The time spent on each declaration is added to `times`.
+pub fn fold(root: &Expr, diags: &mut Diagnostics, times: &mut DeclTimes) -> Expr {
+    let mut folder = Folder {
+        interpreter: Interpreter::new(),
+        origins: HashMap::new(),
+        diags,
+        times,
+    };
+    folder.decl(root)
+}
+
+struct Folder<'a, 'd, 'src> {
+    interpreter: Interpreter<'a>,
+    /// The spans of the literals each constant variable's value was folded
+    /// from, or `None` if the value isn't finite, so that the warning about it
+    /// isn't repeated for everything computed from it.
+    origins: HashMap<&'a str, Option<Vec<SimpleSpan>>>,
+    diags: &'d mut Diagnostics<'src>,
+    times: &'d mut DeclTimes,
+}
+
+impl<'a> Folder<'a, '_, '_> {
+    fn decl(&mut self, expr: &'a Expr) -> Expr {
+        // each declaration's own time is recorded before going on to the next
+        let started = Instant::now();
+        match expr {
+            Expr::Let { cfg, name, rhs, then, span } => {
+                let value = self.eval(rhs);
+                // fold the initializer while any variable it shadows is still in scope
+                let rhs = match value {
+                    Some(value) => {
+                        let origins = self.check(rhs, value);
+                        self.origins.insert(&name.0, origins);
+                        None
+                    }
+                    None => {
+                        let rhs = self.expr(rhs);
+                        self.origins.remove(name.0.as_str());
+                        Some(rhs)
+                    }
+                };
+                self.interpreter.define_var(&name.0, value);
+                self.times.record(expr, started.elapsed());
+                match rhs {
+                    None => self.decl(then),
+                    Some(rhs) => Expr::Let {
+                        cfg: cfg.clone(),
+                        name: name.clone(),
+                        rhs: Box::new(rhs),
+                        then: Box::new(self.decl(then)),
+                        span: *span,
+                    },
+                }
+            }
+            Expr::Fn { cfg, attrs, export, name, args, contracts, body, then, span } => {
+                self.interpreter.define_fn(&name.0, args, body);
+                self.times.record(expr, started.elapsed());
+                Expr::Fn {
+                    cfg: cfg.clone(),
+                    attrs: attrs.clone(),
+                    export: *export,
+                    name: name.clone(),
+                    args: args.clone(),
+                    contracts: contracts.clone(),
+                    body: body.clone(),
+                    then: Box::new(self.decl(then)),
+                    span: *span,
+                }
+            }
+            Expr::Extern { cfg, name, args, then, span } => {
+                self.interpreter.declare_extern(&name.0);
+                self.times.record(expr, started.elapsed());
+                Expr::Extern {
+                    cfg: cfg.clone(),
+                    name: name.clone(),
+                    args: args.clone(),
+                    then: Box::new(self.decl(then)),
+                    span: *span,
+                }
+            }
+            expr => {
+                let folded = self.expr(expr);
+                self.times.record(expr, started.elapsed());
+                folded
+            }
+        }
+    }
+
+    /// Folds the largest subexpressions of `expr` that can be evaluated.
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        if let Some(value) = self.eval(expr) {
+            self.check(expr, value);
+            return Expr::Num(value, expr.span());
+        }
+
+        let mut binary = |left: &Expr, right: &Expr| {
+            (Box::new(self.expr(left)), Box::new(self.expr(right)))
+        };
+
+        match expr {
+            Expr::Neg(expr, span) => Expr::Neg(Box::new(self.expr(expr)), *span),
+            Expr::Add(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Add(left, right, *span)
+            }
+            Expr::Sub(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Sub(left, right, *span)
+            }
+            Expr::Mul(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Mul(left, right, *span)
+            }
+            Expr::Div(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Div(left, right, *span)
+            }
+            Expr::Cmp(op, left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Cmp(*op, left, right, *span)
+            }
+            Expr::Call(name, args, span) => Expr::Call(
+                name.clone(),
+                args.iter().map(|arg| self.expr(arg)).collect(),
+                *span
+            ),
+            _ => expr.clone(),
+        }
+    }
+
+    /// Evaluates an expression, warning about the calls in it that were given
+    /// up on for taking too long, which are left to be evaluated at runtime.
+    fn eval(&mut self, expr: &Expr) -> Option<f64> {
+        let value = self.interpreter.eval(expr);
+        for (span, limit) in self.interpreter.take_exceeded() {
+            self.diags.warning_at(
+                span,
+                format!("constant evaluation {}", limit),
+                "evaluated at runtime instead"
+            );
+        }
+        value
+    }
+
+    /// Warns if folding `expr` into `value` overflowed to an infinity or
+    /// underflowed to a subnormal number from finite, normal numbers, pointing
+    /// to the subexpression where it happened and the literals that led to it.
+    ///
+    /// Returns the spans of the literals `value` was folded from, or `None` if
+    /// it isn't a finite, normal number (or zero).
+    fn check(&mut self, expr: &Expr, value: f64) -> Option<Vec<SimpleSpan>> {
+        let origins = self.origins(expr);
+        if value.is_normal() || value == 0.0 {
+            return Some(origins.unwrap_or_default());
+        }
+        // already warned about where the variables it uses came from
+        origins.as_ref()?;
+
+        // narrow down to the innermost operation that produced the value
+        let (mut culprit, mut value) = (expr, value);
+        while let Some((operand, result)) = operands(culprit)
+            .into_iter()
+            .filter_map(|operand| Some((operand, self.interpreter.eval(operand)?)))
+            .find(|(_, result)| !result.is_normal() && *result != 0.0)
+        {
+            (culprit, value) = (operand, result);
+        }
+
+        let (msg, label) = if value.is_infinite() {
+            let sign = if value < 0.0 { "negative " } else { "" };
+            (
+                format!("constant expression overflows to {}infinity", sign),
+                format!("evaluates to {}infinity", sign),
+            )
+        } else if value.is_subnormal() {
+            (
+                format!("constant expression underflows to the subnormal number {:e}", value),
+                "loses precision".to_owned(),
+            )
+        } else {
+            return None;
+        };
+        let span = culprit.span();
+        let mut origins = self.origins(culprit).unwrap_or_default();
+        origins.retain(|origin| origin.end <= span.start || origin.start >= span.end);
+        origins.sort_by_key(|origin| origin.start);
+        origins.dedup();
+        let related = origins.into_iter()
+            .map(|origin| (origin, "folded from this literal".to_owned()))
+            .collect();
+        self.diags.warning_with_related(span, msg, label, related);
+        None
+    }
+
+    /// Gets the spans of the literals an expression would be folded from,
+    /// including those of the constant variables it uses, or `None` if any of
+    /// those variables aren't finite.
+    fn origins(&self, expr: &Expr) -> Option<Vec<SimpleSpan>> {
+        let mut origins = vec![];
+        let mut stack = vec![expr];
+
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Num(_, span) => origins.push(*span),
+                Expr::Var(name, _) => {
+                    if let Some(spans) = self.origins.get(name.as_str()) {
+                        origins.extend(spans.as_ref()?);
+                    }
+                }
+                Expr::Neg(expr, _) => stack.push(expr),
+                Expr::Add(left, right, _)
+                | Expr::Sub(left, right, _)
+                | Expr::Mul(left, right, _)
+                | Expr::Div(left, right, _)
+                | Expr::Cmp(_, left, right, _) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+                Expr::Call(_, args, _) => stack.extend(args.iter().rev()),
+                Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+            }
+        }
+        Some(origins)
+    }
+}
+
+/// Gets the operands of an operation, or the arguments of a call.
+fn operands(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Neg(expr, _) => vec![expr],
+        Expr::Add(left, right, _)
+        | Expr::Sub(left, right, _)
+        | Expr::Mul(left, right, _)
+        | Expr::Div(left, right, _)
+        | Expr::Cmp(_, left, right, _) => vec![left, right],
+        Expr::Call(_, args, _) => args.iter().collect(),
+        _ => vec![],
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    error::Error,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use chumsky::Parser;
+use serde::Serialize;
+
+use crate::{
+    artifact,
+    exec,
+    messages::Messages,
+    parse::{program, Expr},
+    temp::TempDir,
+    LangVersion,
+    MessageFormat,
+    ReportFormat,
+    SandboxLimits,
+};
+
+/// The tolerance results are compared within, if the spec doesn't set one.
+const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// What to grade the submissions on: the cases to run, each worth some points.
+struct Spec {
+    tolerance: f64,
+    cases: Vec<Case>,
+}
+
+/// One `[[case]]` of a spec.
+struct Case {
+    name: String,
+    /// The expression to evaluate in place of the program's final expression,
+    /// e.g. `area(2, 3)`, or `None` to check the program's own result.
+    expr: Option<String>,
+    expected: f64,
+    points: f64,
+    /// The tolerance for this case, instead of the spec's.
+    tolerance: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct SubmissionReport {
+    submission: String,
+    score: f64,
+    max_score: f64,
+    compiled: bool,
+    /// The errors and warnings from compiling the submission as it was
+    /// submitted.
+    diagnostics: Vec<ReportDiagnostic>,
+    cases: Vec<CaseReport>,
+}
+
+#[derive(Serialize)]
+struct ReportDiagnostic {
+    level: String,
+    message: String,
+    line: Option<u64>,
+    column: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct CaseReport {
+    name: String,
+    passed: bool,
+    /// The points the case earned: all of its points, or none.
+    points: f64,
+    expected: f64,
+    /// The result the program printed, if it ran.
+    actual: Option<f64>,
+    /// Why the case couldn't run, if it couldn't.
+    error: Option<String>,
+}
+
+/// Grades every Foo source file in the `submissions` directory against the
+/// cases of the spec at `spec_path`, and writes a report of each submission's
+/// score, the diagnostics from compiling it, and the result of each case, to
+/// `output` or stdout.
+///
+/// Each submission is compiled to an executable, then, for each case, its
+/// final expression is swapped for the case's, so the case can call the
+/// functions the submission declares, and the result it prints is compared to
+/// the one expected, within the case's tolerance. A case without an expression
+/// checks the program's own result. With `sandbox`, the programs run under the
+/// given limits, as they do for `run --sandbox`. The report is never written
+/// over the spec or a submission, and only over another file with `force`.
+pub fn grade(
+    spec_path: &Path,
+    submissions: &Path,
+    format: ReportFormat,
+    output: Option<&Path>,
+    force: bool,
+    sandbox: Option<&SandboxLimits>
+) -> Result<(), Box<dyn Error>> {
+    let Ok(text) = std::fs::read_to_string(spec_path) else {
+        return Err(format!("failed to open {:#?}", spec_path).into());
+    };
+    let spec = parse_spec(&text)
+        .map_err(|e| format!("{}:{}", spec_path.display(), e))?;
+
+    if !submissions.is_dir() {
+        return Err(format!("{:#?} isn't a directory of submissions", submissions).into());
+    }
+    let mut paths = std::fs::read_dir(submissions)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "foo"));
+    paths.sort();
+    // checked before grading, which can take a while
+    if let Some(path) = output {
+        let inputs = std::iter::once(spec_path).chain(paths.iter().map(PathBuf::as_path));
+        artifact::check_output(path, inputs, force)?;
+    }
+
+    let reports = TempDir::scoped(Messages::new(MessageFormat::Human), |temp| {
+        paths.iter()
+            .map(|path| grade_submission(&spec, path, temp, sandbox))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let report = match format {
+        ReportFormat::Json => serde_json::to_string_pretty(&reports)? + "\n",
+        ReportFormat::Csv => csv(&spec, &reports),
+    };
+    match output {
+        Some(path) => artifact::write(path, report)?,
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+fn grade_submission(
+    spec: &Spec,
+    path: &Path,
+    temp: &TempDir,
+    sandbox: Option<&SandboxLimits>
+) -> Result<SubmissionReport, Box<dyn Error>> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {:#?}: {}", path, e))?;
+    let exe = temp.file(if cfg!(windows) { "submission.exe" } else { "submission" });
+    let (compiled, diagnostics) = compile(path, &exe)?;
+
+    // the span of the final expression, which the cases replace
+    let result_span = program(LangVersion::of(None, &src).features())
+        .parse(&src)
+        .into_result()
+        .ok()
+        .map(|parsed| {
+            let mut e = &parsed.body;
+            while let Expr::Let { then, .. }
+                | Expr::Fn { then, .. }
+                | Expr::Extern { then, .. } = e
+            {
+                e = then;
+            }
+            e.span()
+        });
+
+    let mut cases = vec![];
+    for case in &spec.cases {
+        let actual = match (&case.expr, result_span) {
+            _ if !compiled => Err("the submission doesn't compile".into()),
+            (None, _) => run(&exe, sandbox),
+            (Some(expr), Some(span)) => {
+                let case_src = format!("{}{}{}", &src[..span.start], expr, &src[span.end..]);
+                let case_path = temp.file("case.foo");
+                std::fs::write(&case_path, case_src)?;
+                let case_exe = temp.file(if cfg!(windows) { "case.exe" } else { "case" });
+                match compile(&case_path, &case_exe)? {
+                    (true, _) => run(&case_exe, sandbox),
+                    (false, diagnostics) => Err(diagnostics.into_iter()
+                        .find(|diag| diag.level == "error")
+                        .map_or("the case doesn't compile".to_owned(), |diag| diag.message)),
+                }
+            }
+            (Some(_), None) => Err("the submission doesn't parse".into()),
+        };
+
+        let tolerance = case.tolerance.unwrap_or(spec.tolerance);
+        let passed = actual.as_ref().is_ok_and(|&actual| {
+            actual == case.expected
+                || (actual.is_nan() && case.expected.is_nan())
+                || (actual - case.expected).abs() <= tolerance
+        });
+        cases.push(CaseReport {
+            name: case.name.clone(),
+            passed,
+            points: if passed { case.points } else { 0.0 },
+            expected: case.expected,
+            actual: actual.as_ref().ok().copied(),
+            error: actual.err(),
+        });
+    }
+
+    Ok(SubmissionReport {
+        submission: path.file_name().unwrap().to_string_lossy().into_owned(),
+        score: cases.iter().map(|case| case.points).sum(),
+        max_score: spec.cases.iter().map(|case| case.points).sum(),
+        compiled,
+        diagnostics,
+        cases,
+    })
+}
+
+/// Compiles a source file to an executable in a compiler process of its own,
+/// since one compiling a program with errors exits once they're printed.
+/// Returns whether it compiled, and its errors and warnings. Results are
+/// printed with every digit needed to read them back exactly.
+fn compile(
+    src: &Path,
+    exe: &Path
+) -> Result<(bool, Vec<ReportDiagnostic>), Box<dyn Error>> {
+    let output = Command::new(std::env::current_exe()?)
+        .arg(src)
+        .arg("-o")
+        .arg(exe)
+        .args(["--message-format", "json", "--print-format", "general", "--precision", "17"])
+        // the temporary directory's executables are reused between submissions
+        .arg("--force")
+        .stdin(Stdio::null())
+        .output()?;
+
+    let mut diagnostics = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if message["reason"] != "diagnostic" {
+            continue;
+        }
+        diagnostics.push(ReportDiagnostic {
+            level: message["level"].as_str().unwrap_or("error").to_owned(),
+            message: message["message"].as_str().unwrap_or_default().to_owned(),
+            line: message["location"]["line"].as_u64(),
+            column: message["location"]["column"].as_u64(),
+        });
+    }
+    // failures after the source was checked, like the linker's, aren't
+    // diagnostics, and are only printed
+    let success = output.status.success();
+    if !success && !diagnostics.iter().any(|diag| diag.level == "error") {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        diagnostics.push(ReportDiagnostic {
+            level: "error".to_owned(),
+            message: stderr.trim().trim_start_matches("Error: ").trim_matches('"').to_owned(),
+            line: None,
+            column: None,
+        });
+    }
+    Ok((success, diagnostics))
+}
+
+/// Runs a compiled submission and reads the result it printed.
+fn run(exe: &Path, sandbox: Option<&SandboxLimits>) -> Result<f64, String> {
+    let out = exec::run_captured(exe, sandbox).map_err(|e| e.to_string())?;
+    out.trim()
+        .parse()
+        .map_err(|_| format!("the program printed `{}`, which isn't a number", out.trim()))
+}
+
+/// Writes the reports as CSV, a row for each submission with the points it
+/// earned for each case, and its diagnostics joined into the last column.
+fn csv(spec: &Spec, reports: &[SubmissionReport]) -> String {
+    let mut out = String::new();
+    let header = ["submission", "score", "max_score", "compiled"].into_iter()
+        .map(csv_field)
+        .chain(spec.cases.iter().map(|case| csv_field(&case.name)))
+        .chain([csv_field("diagnostics")])
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "{}", header.join(","));
+
+    for report in reports {
+        let diagnostics = report.diagnostics.iter()
+            .map(|diag| match (diag.line, diag.column) {
+                (Some(line), Some(column)) => {
+                    format!("{}:{}: {}: {}", line, column, diag.level, diag.message)
+                }
+                _ => format!("{}: {}", diag.level, diag.message),
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let row = [
+            csv_field(&report.submission),
+            report.score.to_string(),
+            report.max_score.to_string(),
+            report.compiled.to_string(),
+        ].into_iter()
+            .chain(report.cases.iter().map(|case| case.points.to_string()))
+            .chain([csv_field(&diagnostics)])
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "{}", row.join(","));
+    }
+    out
+}
+
+/// Quotes a CSV field if it has to be.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Reads a spec, written in the part of TOML it needs: `key = value` pairs,
+/// at the top level or in `[[case]]` tables, whose values are strings or
+/// numbers, and `#` comments. Errors start with the line they're on.
+///
+/// ```toml
+/// tolerance = 1e-6
+///
+/// [[case]]
+/// name = "area of a 2 by 3 rectangle"
+/// expr = "area(2, 3)"
+/// expected = 6
+/// points = 2
+/// ```
+fn parse_spec(text: &str) -> Result<Spec, String> {
+    let mut spec = Spec { tolerance: DEFAULT_TOLERANCE, cases: vec![] };
+    // the line each case starts on and its keys, read once the whole table
+    // has been
+    let mut tables = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let n = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if line.replace(' ', "") != "[[case]]" {
+                return Err(format!("{}: expected `[[case]]`, the only table a spec has", n));
+            }
+            tables.push((n, vec![]));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{}: expected `key = value`", n));
+        };
+        let key = key.trim().to_owned();
+        let value = parse_value(value.trim()).map_err(|e| format!("{}: {}", n, e))?;
+        match tables.last_mut() {
+            Some((_, keys)) => keys.push((n, key, value)),
+            None if key == "tolerance" => spec.tolerance = value.number(n, &key)?,
+            None => return Err(format!("{}: unknown key `{}`", n, key)),
+        }
+    }
+
+    for (table, keys) in tables {
+        let mut case = Case {
+            name: format!("case {}", spec.cases.len() + 1),
+            expr: None,
+            expected: f64::NAN,
+            points: 1.0,
+            tolerance: None,
+        };
+        let mut expected = false;
+        for (n, key, value) in keys {
+            match key.as_str() {
+                "name" => case.name = value.string(n, &key)?,
+                "expr" => case.expr = Some(value.string(n, &key)?),
+                "expected" => {
+                    case.expected = value.number(n, &key)?;
+                    expected = true;
+                }
+                "points" => case.points = value.number(n, &key)?,
+                "tolerance" => case.tolerance = Some(value.number(n, &key)?),
+                _ => return Err(format!("{}: unknown key `{}`", n, key)),
+            }
+        }
+        if !expected {
+            return Err(format!("{}: the case has no `expected` result", table));
+        }
+        spec.cases.push(case);
+    }
+    Ok(spec)
+}
+
+enum Value {
+    String(String),
+    Number(f64),
+}
+
+impl Value {
+    fn string(self, line: usize, key: &str) -> Result<String, String> {
+        match self {
+            Value::String(string) => Ok(string),
+            Value::Number(_) => Err(format!("{}: `{}` has to be a string", line, key)),
+        }
+    }
+
+    fn number(self, line: usize, key: &str) -> Result<f64, String> {
+        match self {
+            Value::Number(number) => Ok(number),
+            Value::String(_) => Err(format!("{}: `{}` has to be a number", line, key)),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        return literal.strip_suffix('\'')
+            .map(|literal| Value::String(literal.to_owned()))
+            .ok_or_else(|| "unterminated string".to_owned());
+    }
+    let Some(basic) = value.strip_prefix('"') else {
+        // TOML's `inf` and `nan` are written the way Rust parses them
+        return value.replace('_', "")
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("expected a string or a number, found `{}`", value));
+    };
+
+    let mut string = String::new();
+    let mut chars = basic.chars();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_owned()),
+            Some('"') => break,
+            Some('\\') => string.push(match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                other => {
+                    return Err(format!(
+                        "unsupported escape `\\{}`",
+                        other.map(String::from).unwrap_or_default()
+                    ));
+                }
+            }),
+            Some(c) => string.push(c),
+        }
+    }
+    if !chars.as_str().trim().is_empty() {
+        return Err(format!("unexpected `{}` after the string", chars.as_str().trim()));
+    }
+    Ok(Value::String(string))
+}
+
+/// Cuts a `#` comment off a line, unless the `#` is in a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => (),
+        }
+        escaped = false;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_spec, parse_value, strip_comment, Spec, Value, DEFAULT_TOLERANCE};
+
+    fn spec(text: &str) -> Spec {
+        parse_spec(text).unwrap_or_else(|e| panic!("the spec didn't parse: {}", e))
+    }
+
+    fn error(text: &str) -> String {
+        match parse_spec(text) {
+            Ok(_) => panic!("the spec parsed"),
+            Err(e) => e,
+        }
+    }
+
+    fn string(value: &str) -> String {
+        match parse_value(value) {
+            Ok(Value::String(string)) => string,
+            Ok(Value::Number(number)) => panic!("parsed the number {}", number),
+            Err(e) => panic!("didn't parse: {}", e),
+        }
+    }
+
+    fn number(value: &str) -> f64 {
+        match parse_value(value) {
+            Ok(Value::Number(number)) => number,
+            Ok(Value::String(string)) => panic!("parsed the string {:?}", string),
+            Err(e) => panic!("didn't parse: {}", e),
+        }
+    }
+
+    fn value_error(value: &str) -> String {
+        match parse_value(value) {
+            Ok(_) => panic!("parsed"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn reads_cases() {
+        let spec = spec(
+            "tolerance = 0.5\n\
+            \n\
+            [[case]]\n\
+            name = \"area\"\n\
+            expr = \"area(2, 3)\"\n\
+            expected = 6\n\
+            points = 2\n\
+            tolerance = 1e-3\n\
+            \n\
+            [ [ case ] ]\n\
+            expected = -1\n"
+        );
+        assert_eq!(spec.tolerance, 0.5);
+        assert_eq!(spec.cases.len(), 2);
+
+        let case = &spec.cases[0];
+        assert_eq!(case.name, "area");
+        assert_eq!(case.expr.as_deref(), Some("area(2, 3)"));
+        assert_eq!(case.expected, 6.0);
+        assert_eq!(case.points, 2.0);
+        assert_eq!(case.tolerance, Some(1e-3));
+
+        // the rest are defaults
+        let case = &spec.cases[1];
+        assert_eq!(case.name, "case 2");
+        assert_eq!(case.expr, None);
+        assert_eq!(case.expected, -1.0);
+        assert_eq!(case.points, 1.0);
+        assert_eq!(case.tolerance, None);
+    }
+
+    #[test]
+    fn defaults_the_tolerance() {
+        assert_eq!(spec("").tolerance, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn skips_comments() {
+        let spec = spec(
+            "# a spec\n\
+            [[case]] # the first\n\
+            name = \"a # b\" # not part of the name\n\
+            expected = 1 # one\n"
+        );
+        assert_eq!(spec.cases[0].name, "a # b");
+        assert_eq!(spec.cases[0].expected, 1.0);
+    }
+
+    #[test]
+    fn reads_strings() {
+        assert_eq!(string("\"basic\""), "basic");
+        assert_eq!(string("'literal \\n'"), "literal \\n");
+        assert_eq!(string("\"\""), "");
+    }
+
+    #[test]
+    fn reads_escapes() {
+        assert_eq!(string(r#""a\"b\\c\nd\te""#), "a\"b\\c\nd\te");
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(number("42"), 42.0);
+        assert_eq!(number("-1.5"), -1.5);
+        assert_eq!(number("1e-6"), 1e-6);
+        assert_eq!(number("1_000"), 1000.0);
+        assert_eq!(number("inf"), f64::INFINITY);
+        assert!(number("nan").is_nan());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert_eq!(value_error("\"open"), "unterminated string");
+        assert_eq!(value_error("\"ends in an escape\\"), "unsupported escape `\\`");
+        assert_eq!(value_error("'open"), "unterminated string");
+        assert_eq!(value_error(r#""\x""#), "unsupported escape `\\x`");
+        assert_eq!(value_error("\"a\" b"), "unexpected `b` after the string");
+        assert_eq!(value_error("six"), "expected a string or a number, found `six`");
+    }
+
+    #[test]
+    fn strips_comments_outside_strings() {
+        assert_eq!(strip_comment("a = 1 # comment"), "a = 1 ");
+        assert_eq!(strip_comment("# comment"), "");
+        assert_eq!(strip_comment("a = \"#\" # comment"), "a = \"#\" ");
+        assert_eq!(strip_comment("a = '#' # comment"), "a = '#' ");
+        assert_eq!(strip_comment(r##"a = "\"#" # comment"##), r##"a = "\"#" "##);
+        assert_eq!(strip_comment(r#"a = "\\" # comment"#), r#"a = "\\" "#);
+        // literal strings have no escapes
+        assert_eq!(strip_comment(r"a = '\' # comment"), r"a = '\' ");
+        assert_eq!(strip_comment("no comment"), "no comment");
+    }
+
+    #[test]
+    fn reports_errors_with_their_lines() {
+        assert_eq!(
+            error("[case]\n"),
+            "1: expected `[[case]]`, the only table a spec has"
+        );
+        assert_eq!(error("\n[[case]]\nexpected\n"), "3: expected `key = value`");
+        assert_eq!(error("points = 1\n"), "1: unknown key `points`");
+        assert_eq!(error("[[case]]\nexpected = 1\nweight = 2\n"), "3: unknown key `weight`");
+        assert_eq!(error("tolerance = \"small\"\n"), "1: `tolerance` has to be a number");
+        assert_eq!(error("[[case]]\nexpected = 1\nname = 2\n"), "3: `name` has to be a string");
+        assert_eq!(
+            error("[[case]]\nexpected = \"6\"\n"),
+            "2: `expected` has to be a number"
+        );
+        assert_eq!(error("[[case]]\nname = \"open\n"), "2: unterminated string");
+        assert_eq!(
+            error("[[case]]\nexpected = 1\n\n[[case]]\nname = \"x\"\n"),
+            "4: the case has no `expected` result"
+        );
+    }
+}
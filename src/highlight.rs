@@ -0,0 +1,271 @@
+use std::{error::Error, fmt::Write, ops::Range, path::Path};
+
+use chumsky::Parser;
+
+use crate::{
+    artifact,
+    deprecated,
+    error::{Diagnostic, Diagnostics},
+    fold,
+    lint,
+    messages::Messages,
+    parse::program,
+    sema,
+    timings::DeclTimes,
+    LangVersion,
+    MessageFormat,
+};
+
+const STYLE: &str = "\
+pre.foo { background: #fafafa; padding: 1em; line-height: 1.4; }
+.foo .kw { color: #8959a8; font-weight: bold; }
+.foo .attr { color: #c82829; }
+.foo .num { color: #f5871f; }
+.foo .fn { color: #4271ae; }
+.foo .var { color: #3e999f; }
+.foo .op, .foo .punct { color: #666; }
+.foo .error { text-decoration: underline wavy red; }
+.foo .warning { text-decoration: underline wavy #eab700; }
+.foo .marker.error::after { content: \"\\25C2\"; color: red; }
+.foo .marker.warning::after { content: \"\\25C2\"; color: #eab700; }
+ul.foo-diagnostics { font-family: monospace; }
+";
+
+/// The kinds of token the source is highlighted as, named by their classes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Keyword,
+    Attr,
+    Num,
+    /// The name of a function where it's declared or called.
+    Fn,
+    Var,
+    Op,
+    Punct,
+    /// Whitespace, and anything the highlighter doesn't recognize.
+    Plain,
+}
+
+impl Token {
+    fn class(self) -> Option<&'static str> {
+        match self {
+            Token::Keyword => Some("kw"),
+            Token::Attr => Some("attr"),
+            Token::Num => Some("num"),
+            Token::Fn => Some("fn"),
+            Token::Var => Some("var"),
+            Token::Op => Some("op"),
+            Token::Punct => Some("punct"),
+            Token::Plain => None,
+        }
+    }
+}
+
+/// Writes a Foo source file as a standalone HTML page, with its tokens
+/// highlighted by class and the errors and warnings the compiler finds in it
+/// underlined, their messages shown as tooltips and listed below the source.
+///
+/// The source doesn't have to parse: it's tokenized separately from the
+/// parser, so a program with syntax errors is still highlighted.
+///
+/// The page is written to `output`, or next to the source with an .html
+/// extension. Like the compiler's outputs, it's never written over the source,
+/// and only over another file with `force`.
+pub fn highlight(
+    src_path: &Path,
+    output: Option<&Path>,
+    force: bool
+) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+    let output = output.map(Path::to_path_buf).unwrap_or(src_path.with_extension("html"));
+    artifact::check_output(&output, [src_path], force)?;
+
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let features = LangVersion::of(None, &src).features();
+    match program(features).parse(&src).into_result().map(|program| program.body) {
+        Ok(ast) => {
+            sema::check(&ast, &mut diags);
+            lint::check(&ast, &mut diags);
+            deprecated::check(&ast, features, &mut diags);
+            fold::fold(&ast, &mut diags, &mut DeclTimes::default());
+        }
+        Err(errs) => diags.syntax_errors(errs),
+    }
+    let lines = diags.lines().clone();
+    let mut diags = diags.take()
+        .into_iter()
+        .filter(|diag| diag.label.is_some())
+        .collect::<Vec<_>>();
+    diags.sort_by_key(|diag| diag.label.as_ref().unwrap().0.start);
+
+    let mut html = String::new();
+    let title = escape(&src_path.display().to_string());
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>", title, STYLE)?;
+    writeln!(html, "<pre class=\"foo\">{}</pre>", code(&src, &diags))?;
+    if !diags.is_empty() {
+        writeln!(html, "<ul class=\"foo-diagnostics\">")?;
+        for diag in &diags {
+            let (range, _) = diag.label.as_ref().unwrap();
+            let (line, col) = lines.line_col(range.start);
+            writeln!(
+                html,
+                "<li class=\"{}\">{}:{}: {}</li>",
+                severity(diag),
+                line,
+                col,
+                escape(&tooltip(diag))
+            )?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+    writeln!(html, "</body>\n</html>")?;
+
+    artifact::write(&output, html)?;
+    Ok(())
+}
+
+/// Renders the highlighted source, splitting it wherever a token or a
+/// diagnostic's span starts or ends, so each piece has one class and is
+/// underlined by every diagnostic covering it.
+fn code(src: &str, diags: &[Diagnostic]) -> String {
+    let tokens = tokenize(src);
+    let spans = diags.iter()
+        .map(|diag| diag.label.as_ref().unwrap().0.clone())
+        .collect::<Vec<_>>();
+    let mut bounds = tokens.iter()
+        .flat_map(|(_, range)| [range.start, range.end])
+        .chain(spans.iter().flat_map(|range| [range.start, range.end]))
+        .collect::<Vec<_>>();
+    bounds.sort();
+    bounds.dedup();
+
+    let mut html = String::new();
+    for (start, end) in bounds.iter().zip(&bounds[1..]).map(|(&s, &e)| (s, e)) {
+        // diagnostics at a single position, e.g. the end of the file, can't be
+        // underlined, so they're marked instead
+        for (diag, span) in diags.iter().zip(&spans) {
+            if span.is_empty() && span.start == start {
+                let _ = write!(
+                    html,
+                    "<span class=\"marker {}\" title=\"{}\"></span>",
+                    severity(diag),
+                    escape(&tooltip(diag))
+                );
+            }
+        }
+        let mut piece = escape(&src[start..end]);
+        for (diag, span) in diags.iter().zip(&spans) {
+            if span.start <= start && end <= span.end {
+                piece = format!(
+                    "<span class=\"{}\" title=\"{}\">{}</span>",
+                    severity(diag),
+                    escape(&tooltip(diag)),
+                    piece
+                );
+            }
+        }
+        let token = tokens.iter()
+            .find(|(_, range)| range.start <= start && end <= range.end)
+            .map_or(Token::Plain, |(token, _)| *token);
+        match token.class() {
+            Some(class) => {
+                let _ = write!(html, "<span class=\"{}\">{}</span>", class, piece);
+            }
+            None => html.push_str(&piece),
+        }
+    }
+    for (diag, span) in diags.iter().zip(&spans) {
+        if span.is_empty() && span.start >= src.len() {
+            let _ = write!(
+                html,
+                "<span class=\"marker {}\" title=\"{}\"></span>",
+                severity(diag),
+                escape(&tooltip(diag))
+            );
+        }
+    }
+    html
+}
+
+/// Splits the source into the tokens it's highlighted as.
+fn tokenize(src: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokens: Vec<(Token, Range<usize>)> = vec![];
+    let mut chars = src.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        let mut take_while = |pred: fn(char) -> bool| {
+            while let Some(&(i, c)) = chars.peek().filter(|(_, c)| pred(*c)) {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            end
+        };
+        let token = if c.is_ascii_digit() {
+            take_while(|c| c.is_ascii_digit());
+            Token::Num
+        } else if c == '@' {
+            take_while(is_ident_continue);
+            Token::Attr
+        } else if c == '#' {
+            // a directive, up to its closing bracket
+            take_while(|c| c != ']' && c != '\n');
+            if let Some((i, _)) = chars.next_if(|(_, c)| *c == ']') {
+                end = i + 1;
+            }
+            Token::Attr
+        } else if c.is_alphabetic() || c == '_' {
+            end = take_while(is_ident_continue);
+            match &src[start..end] {
+                "let" | "fn" | "extern" | "export" | "include" | "requires" | "ensures" => {
+                    Token::Keyword
+                }
+                _ if src[end..].trim_start().starts_with('(') => Token::Fn,
+                _ if tokens.iter()
+                    .rev()
+                    .find(|(token, _)| *token != Token::Plain)
+                    .is_some_and(|(token, range)| {
+                        *token == Token::Keyword && &src[range.clone()] == "fn"
+                    }) => Token::Fn,
+                _ => Token::Var,
+            }
+        } else if "+-*/=".contains(c) {
+            Token::Op
+        } else if "(),;".contains(c) {
+            Token::Punct
+        } else {
+            Token::Plain
+        };
+        tokens.push((token, start..end));
+    }
+    tokens
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn severity(diag: &Diagnostic) -> &'static str {
+    if diag.warning { "warning" } else { "error" }
+}
+
+/// Gets the text shown for a diagnostic, with its label's, e.g.
+/// `warning: integer literal can't be represented exactly (rounded to ...)`.
+fn tooltip(diag: &Diagnostic) -> String {
+    let label = diag.label.as_ref().and_then(|(_, text)| text.as_deref());
+    match label {
+        Some(label) => format!("{}: {} ({})", severity(diag), diag.msg, label),
+        None => format!("{}: {}", severity(diag), diag.msg),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
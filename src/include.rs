@@ -0,0 +1,229 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use chumsky::{span::SimpleSpan, Parser};
+
+use crate::{
+    error::Diagnostics,
+    parse::{program, Expr, Features, Program, Spanned},
+};
+
+/// Parses the file being compiled and every file it includes, printing their
+/// syntax errors and exiting if they have any. The declarations of included
+/// files are spliced in front of the program's own, in the order they're
+/// included, so the program can use them as if they'd been written at its top.
+/// Included files are parsed in the same version of the language as the
+/// program.
+pub fn parse(path: &Path, src: &str, features: Features, diags: &mut Diagnostics) -> Program {
+    let program = program(features)
+        .parse(src)
+        .into_result()
+        .unwrap_or_else(|errs| {
+            diags.syntax_errors(errs);
+            diags.abort();
+        });
+    resolve(path, program, features, diags).unwrap_or_else(|| diags.abort())
+}
+
+/// Splices the declarations of the files a program includes in front of its
+/// own. Each included file's own includes come before it, and a file included
+/// more than once is only spliced in the first time, so one declaring what
+/// several others use can be included by all of them. Included files are found
+/// relative to the file including them, and have to end after their
+/// declarations, without a final expression.
+///
+/// Returns `None` if a file can't be read or parsed, or includes itself.
+pub fn resolve(
+    path: &Path,
+    mut program: Program,
+    features: Features,
+    diags: &mut Diagnostics
+) -> Option<Program> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    let mut resolver = Resolver {
+        features,
+        stack: vec![(canonical.clone(), path.to_owned())],
+        seen: HashSet::from([canonical]),
+        decls: vec![],
+        valid: true,
+        diags,
+    };
+    for include in &program.includes {
+        resolver.include(path, include);
+    }
+    if !resolver.valid {
+        return None;
+    }
+
+    program.body = resolver.decls
+        .into_iter()
+        .rev()
+        .fold(program.body, |rest, decls| splice(decls, rest));
+    Some(program)
+}
+
+struct Resolver<'d, 'src> {
+    features: Features,
+    /// The files being included, each by the one before it, by their
+    /// canonical paths and the paths they were found at.
+    stack: Vec<(PathBuf, PathBuf)>,
+    seen: HashSet<PathBuf>,
+    /// The declarations of each included file, in the order they're spliced.
+    decls: Vec<Expr>,
+    valid: bool,
+    diags: &'d mut Diagnostics<'src>,
+}
+
+impl Resolver<'_, '_> {
+    fn include(&mut self, from: &Path, (name, span): &Spanned<String>) {
+        let path = from.parent().unwrap_or(Path::new("")).join(name);
+        let Ok(canonical) = path.canonicalize() else {
+            self.error(*span, format!("couldn't find `{}`", name));
+            return;
+        };
+        if let Some(i) = self.stack.iter().position(|(file, _)| *file == canonical) {
+            let cycle = self.stack[i..].iter()
+                .map(|(_, path)| path.display().to_string())
+                .chain([path.display().to_string()])
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.error(*span, format!("include cycle: {}", cycle));
+            return;
+        }
+        if !self.seen.insert(canonical.clone()) {
+            return;
+        }
+        let Ok(src) = std::fs::read_to_string(&path) else {
+            self.error(*span, format!("couldn't read `{}`", name));
+            return;
+        };
+
+        let start = self.diags.include(&path, src.clone());
+        let errs = match program(self.features).parse(&src).into_result() {
+            Ok(_) => {
+                self.error(
+                    *span,
+                    format!("`{}` ends in an expression, so it can't be included", name)
+                );
+                return;
+            }
+            Err(errs) => errs,
+        };
+        // a program has to end in an expression, which an included file can't
+        let library = format!("{}\n0", src);
+        let Ok(mut included) = program(self.features).parse(&library).into_result() else {
+            self.diags.syntax_errors_at(errs, start);
+            self.valid = false;
+            return;
+        };
+        for (_, span) in &included.directives {
+            self.diags.warning_at(
+                shift(*span, start),
+                "directives only apply in the file being compiled",
+                "ignored"
+            );
+        }
+
+        self.stack.push((canonical, path.clone()));
+        for (name, span) in &included.includes {
+            self.include(&path, &(name.clone(), shift(*span, start)));
+        }
+        self.stack.pop();
+
+        shift_expr(&mut included.body, start);
+        self.decls.push(included.body);
+    }
+
+    fn error(&mut self, span: SimpleSpan, msg: String) {
+        self.diags.error_at(span, msg, "included here");
+        self.valid = false;
+    }
+}
+
+/// Replaces the final expression after a chain of declarations with `rest`.
+fn splice(mut decls: Expr, rest: Expr) -> Expr {
+    let mut expr = &mut decls;
+    while matches!(expr, Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. }) {
+        expr = match expr {
+            Expr::Let { then, .. } | Expr::Fn { then, .. } | Expr::Extern { then, .. } => then,
+            _ => unreachable!(),
+        };
+    }
+    *expr = rest;
+    decls
+}
+
+fn shift(span: SimpleSpan, by: usize) -> SimpleSpan {
+    SimpleSpan::new(span.start + by, span.end + by)
+}
+
+/// Moves every span in an included file's AST by the offset its file starts
+/// at, so diagnostics can tell which file they're in.
+fn shift_expr(root: &mut Expr, by: usize) {
+    let mut stack = vec![root];
+
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Num(_, span) | Expr::Var(_, span) => *span = shift(*span, by),
+            Expr::Neg(expr, span) => {
+                *span = shift(*span, by);
+                stack.push(expr);
+            }
+            Expr::Add(left, right, span)
+            | Expr::Sub(left, right, span)
+            | Expr::Mul(left, right, span)
+            | Expr::Div(left, right, span)
+            | Expr::Cmp(_, left, right, span) => {
+                *span = shift(*span, by);
+                stack.push(left);
+                stack.push(right);
+            }
+            Expr::Call((_, name_span), args, span) => {
+                *name_span = shift(*name_span, by);
+                *span = shift(*span, by);
+                stack.extend(args.iter_mut());
+            }
+            Expr::Let { cfg, name, rhs, then, span } => {
+                for (_, span) in cfg.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                name.1 = shift(name.1, by);
+                *span = shift(*span, by);
+                stack.push(rhs);
+                stack.push(then);
+            }
+            Expr::Fn { cfg, attrs, name, args, contracts, body, then, span, .. } => {
+                for (_, span) in cfg.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                for (_, span) in attrs.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                for (_, span) in args.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                for (contract, span) in contracts.iter_mut() {
+                    *span = shift(*span, by);
+                    stack.push(&mut contract.cond);
+                }
+                name.1 = shift(name.1, by);
+                *span = shift(*span, by);
+                stack.push(body);
+                stack.push(then);
+            }
+            Expr::Extern { cfg, name, args, then, span } => {
+                for (_, span) in cfg.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                for (_, span) in args.iter_mut() {
+                    *span = shift(*span, by);
+                }
+                name.1 = shift(name.1, by);
+                *span = shift(*span, by);
+                stack.push(then);
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parse::{Contract, Expr, FnAttr};
+
+/// Inlines calls to small Foo functions in the AST before codegen, so that
+/// even unoptimized output, and compile-time evaluation, doesn't pay for
+/// calling them.
+///
+/// A function is small if its body has at most `threshold` expressions, or if
+/// it's annotated with `@inline`; `@noinline` functions, and functions with
+/// contracts, whose checks would be skipped, are never inlined.
+///
+/// A function can only be called after its definition, so walking the program
+/// in order visits the call graph bottom-up: every call to a function is
+/// considered after the function's own calls have been inlined into it. A
+/// function's calls to itself are never inlined, since it isn't known yet
+/// inside its own body.
+pub fn inline(root: Expr, threshold: usize) -> Expr {
+    let mut inliner = Inliner {
+        threshold,
+        functions: HashMap::new(),
+        impure: HashSet::new(),
+    };
+    inliner.decl(root)
+}
+
+struct Inliner {
+    threshold: usize,
+    /// The parameters and bodies of the functions that can be inlined, by name.
+    functions: HashMap<String, (Vec<String>, Expr)>,
+    /// Functions which may have side effects: `extern` functions, and Foo
+    /// functions which call them.
+    impure: HashSet<String>,
+}
+
+impl Inliner {
+    fn decl(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Let { cfg, name, rhs, then, span } => Expr::Let {
+                cfg,
+                name,
+                rhs: Box::new(self.expr(*rhs)),
+                then: Box::new(self.decl(*then)),
+                span,
+            },
+            Expr::Fn { cfg, attrs, export, name, args, contracts, body, then, span } => {
+                let contracts = contracts.into_iter()
+                    .map(|(contract, span)| {
+                        let cond = self.expr(contract.cond);
+                        (Contract { cond, ..contract }, span)
+                    })
+                    .collect::<Vec<_>>();
+                let body = self.expr(*body);
+                self.shadow_builtin(&name.0);
+                if !self.is_pure(&body) {
+                    self.impure.insert(name.0.clone());
+                }
+                let has_attr = |attr| attrs.iter().any(|(a, _)| *a == attr);
+                if contracts.is_empty()
+                    && !has_attr(FnAttr::NoInline)
+                    && (has_attr(FnAttr::Inline) || size(&body) <= self.threshold)
+                {
+                    let params = args.iter().map(|(arg, _)| arg.clone()).collect();
+                    self.functions.insert(name.0.clone(), (params, body.clone()));
+                }
+                Expr::Fn {
+                    cfg,
+                    attrs,
+                    export,
+                    name,
+                    args,
+                    contracts,
+                    body: Box::new(body),
+                    then: Box::new(self.decl(*then)),
+                    span,
+                }
+            }
+            Expr::Extern { cfg, name, args, then, span } => {
+                self.shadow_builtin(&name.0);
+                self.impure.insert(name.0.clone());
+                Expr::Extern { cfg, name, args, then: Box::new(self.decl(*then)), span }
+            }
+            expr => self.expr(expr),
+        }
+    }
+
+    fn expr(&self, expr: Expr) -> Expr {
+        let binary = |left: Box<Expr>, right: Box<Expr>| {
+            (Box::new(self.expr(*left)), Box::new(self.expr(*right)))
+        };
+
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => expr,
+            Expr::Neg(expr, span) => Expr::Neg(Box::new(self.expr(*expr)), span),
+            Expr::Add(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Add(left, right, span)
+            }
+            Expr::Sub(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Sub(left, right, span)
+            }
+            Expr::Mul(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Mul(left, right, span)
+            }
+            Expr::Div(left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Div(left, right, span)
+            }
+            Expr::Cmp(op, left, right, span) => {
+                let (left, right) = binary(left, right);
+                Expr::Cmp(op, left, right, span)
+            }
+            Expr::Call(name, args, span) => {
+                let args = args.into_iter().map(|arg| self.expr(arg)).collect::<Vec<_>>();
+                match self.inlined(&name.0, &args) {
+                    Some(body) => body,
+                    None => Expr::Call(name, args, span),
+                }
+            }
+            // declarations only appear at the top level
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => expr,
+        }
+    }
+
+    /// Stops inlining the functions which call a builtin that a function now
+    /// being declared takes the place of, since their bodies still mean the
+    /// builtin.
+    fn shadow_builtin(&mut self, name: &str) {
+        self.functions.retain(|_, (_, body)| !calls(body, name));
+    }
+
+    /// Gets the body of the function `name` with `args` substituted for its
+    /// parameters, if the call can be inlined.
+    ///
+    /// Inlining moves each argument to where its parameter is used, so it's
+    /// only done if that can't change what the program does or make it do more
+    /// work: an argument used more than once has to be a number or variable,
+    /// and one used once can't call anything with side effects, which could be
+    /// reordered or dropped.
+    fn inlined(&self, name: &str, args: &[Expr]) -> Option<Expr> {
+        let (params, body) = self.functions.get(name)?;
+        if params.len() != args.len() {
+            return None;
+        }
+        for (param, arg) in params.iter().zip(args) {
+            let trivial = matches!(arg, Expr::Num(..) | Expr::Var(..));
+            if !trivial && (uses(body, param) > 1 || !self.is_pure(arg)) {
+                return None;
+            }
+        }
+
+        let params = params.iter()
+            .map(String::as_str)
+            .zip(args)
+            .collect::<HashMap<_, _>>();
+        Some(substitute(body, &params))
+    }
+
+    /// Checks that an expression doesn't call anything with side effects.
+    fn is_pure(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => true,
+            Expr::Neg(expr, _) => self.is_pure(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => self.is_pure(left) && self.is_pure(right),
+            Expr::Call((name, _), args, _) => {
+                !self.impure.contains(name) && args.iter().all(|arg| self.is_pure(arg))
+            }
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => false,
+        }
+    }
+}
+
+/// Counts the expressions in a function body.
+fn size(expr: &Expr) -> usize {
+    match expr {
+        Expr::Neg(expr, _) => 1 + size(expr),
+        Expr::Add(left, right, _)
+        | Expr::Sub(left, right, _)
+        | Expr::Mul(left, right, _)
+        | Expr::Div(left, right, _)
+        | Expr::Cmp(_, left, right, _) => 1 + size(left) + size(right),
+        Expr::Call(_, args, _) => 1 + args.iter().map(size).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+/// Checks whether a function body calls the function `name`.
+fn calls(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Neg(expr, _) => calls(expr, name),
+        Expr::Add(left, right, _)
+        | Expr::Sub(left, right, _)
+        | Expr::Mul(left, right, _)
+        | Expr::Div(left, right, _)
+        | Expr::Cmp(_, left, right, _) => calls(left, name) || calls(right, name),
+        Expr::Call((callee, _), args, _) => {
+            callee == name || args.iter().any(|arg| calls(arg, name))
+        }
+        _ => false,
+    }
+}
+
+/// Counts the uses of a parameter in a function body.
+fn uses(expr: &Expr, param: &str) -> usize {
+    match expr {
+        Expr::Var(name, _) => usize::from(name == param),
+        Expr::Neg(expr, _) => uses(expr, param),
+        Expr::Add(left, right, _)
+        | Expr::Sub(left, right, _)
+        | Expr::Mul(left, right, _)
+        | Expr::Div(left, right, _)
+        | Expr::Cmp(_, left, right, _) => uses(left, param) + uses(right, param),
+        Expr::Call(_, args, _) => args.iter().map(|arg| uses(arg, param)).sum(),
+        _ => 0,
+    }
+}
+
+/// Copies a function body, replacing its parameters with the arguments they're
+/// bound to. Function bodies only see their own parameters, so every variable
+/// in one is a parameter.
+fn substitute(expr: &Expr, args: &HashMap<&str, &Expr>) -> Expr {
+    let binary = |left: &Expr, right: &Expr| {
+        (Box::new(substitute(left, args)), Box::new(substitute(right, args)))
+    };
+
+    match expr {
+        Expr::Var(name, span) => match args.get(name.as_str()) {
+            Some(arg) => (*arg).clone(),
+            None => Expr::Var(name.clone(), *span),
+        },
+        Expr::Neg(expr, span) => Expr::Neg(Box::new(substitute(expr, args)), *span),
+        Expr::Add(left, right, span) => {
+            let (left, right) = binary(left, right);
+            Expr::Add(left, right, *span)
+        }
+        Expr::Sub(left, right, span) => {
+            let (left, right) = binary(left, right);
+            Expr::Sub(left, right, *span)
+        }
+        Expr::Mul(left, right, span) => {
+            let (left, right) = binary(left, right);
+            Expr::Mul(left, right, *span)
+        }
+        Expr::Div(left, right, span) => {
+            let (left, right) = binary(left, right);
+            Expr::Div(left, right, *span)
+        }
+        Expr::Cmp(op, left, right, span) => {
+            let (left, right) = binary(left, right);
+            Expr::Cmp(*op, left, right, *span)
+        }
+        Expr::Call(name, call_args, span) => Expr::Call(
+            name.clone(),
+            call_args.iter().map(|arg| substitute(arg, args)).collect(),
+            *span
+        ),
+        _ => expr.clone(),
+    }
+}
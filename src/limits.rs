@@ -0,0 +1,146 @@
+use chumsky::span::SimpleSpan;
+
+use crate::{error::Diagnostics, parse::Expr, Limits};
+
+/// The most parameters a function can be declared with.
+pub const MAX_PARAMS: usize = 255;
+
+/// The most parameters a Foo function takes as parameters of its own in the
+/// generated code. One declared with more takes a pointer to an array of its
+/// arguments instead, so calls to it don't pass a long tail of them on the
+/// stack by rules that differ between targets.
+pub const MAX_DIRECT_PARAMS: usize = 16;
+
+impl Limits {
+    /// Checks that the source doesn't nest deeper than `--max-ast-depth` before
+    /// it's parsed, since the parser recurses into every declaration and
+    /// parenthesis it enters. Without parsing, the depth at each point is the
+    /// number of `let` and `fn` keywords before it plus the number of
+    /// parentheses open around it.
+    ///
+    /// Returns whether the source is within the limit, queueing an error at
+    /// the point it's exceeded if not.
+    pub fn check_source(&self, src: &str, diags: &mut Diagnostics) -> bool {
+        let mut decls = 0;
+        let mut parens = 0_usize;
+        let mut word = String::new();
+
+        for (i, c) in src.char_indices() {
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            if word == "let" || word == "fn" {
+                decls += 1;
+                if decls + parens > self.max_ast_depth {
+                    self.depth_error(SimpleSpan::new(i - word.len(), i), diags);
+                    return false;
+                }
+            }
+            word.clear();
+
+            match c {
+                '(' => {
+                    parens += 1;
+                    if decls + parens > self.max_ast_depth {
+                        self.depth_error(SimpleSpan::new(i, i + 1), diags);
+                        return false;
+                    }
+                }
+                ')' => parens = parens.saturating_sub(1),
+                _ => (),
+            }
+        }
+        true
+    }
+
+    /// Checks that the parsed program is within `--max-ast-depth` and
+    /// `--max-function-count`, and that no function has more than
+    /// [`MAX_PARAMS`] parameters.
+    ///
+    /// Returns whether it is, queueing an error at the first expression or
+    /// function over the limit if not.
+    pub fn check_ast(&self, root: &Expr, diags: &mut Diagnostics) -> bool {
+        let mut functions = 0;
+        let mut stack = vec![(root, 1)];
+
+        while let Some((expr, depth)) = stack.pop() {
+            if depth > self.max_ast_depth {
+                self.depth_error(expr.span(), diags);
+                return false;
+            }
+
+            match expr {
+                Expr::Fn { name: (name, span), args, .. }
+                | Expr::Extern { name: (name, span), args, .. } => {
+                    if args.len() > MAX_PARAMS {
+                        diags.error_at(
+                            *span,
+                            format!(
+                                "function `{}` has {} parameters, more than the {} a function can have",
+                                name,
+                                args.len(),
+                                MAX_PARAMS
+                            ),
+                            "declared here"
+                        );
+                        return false;
+                    }
+                    functions += 1;
+                    if let Some(max) = self.max_function_count.filter(|&max| functions > max) {
+                        diags.error_at(
+                            *span,
+                            format!(
+                                "program declares more than {} functions (see `--max-function-count`)",
+                                max
+                            ),
+                            "limit reached here"
+                        );
+                        return false;
+                    }
+                }
+                _ => (),
+            }
+
+            match expr {
+                Expr::Num(..) | Expr::Var(..) => (),
+                Expr::Neg(expr, _) => stack.push((expr, depth + 1)),
+                Expr::Add(left, right, _)
+                | Expr::Sub(left, right, _)
+                | Expr::Mul(left, right, _)
+                | Expr::Div(left, right, _)
+                | Expr::Cmp(_, left, right, _) => {
+                    stack.push((right, depth + 1));
+                    stack.push((left, depth + 1));
+                }
+                Expr::Call(_, args, _) => {
+                    stack.extend(args.iter().rev().map(|arg| (arg, depth + 1)));
+                }
+                Expr::Let { rhs, then, .. } => {
+                    stack.push((then, depth + 1));
+                    stack.push((rhs, depth + 1));
+                }
+                Expr::Fn { contracts, body, then, .. } => {
+                    stack.push((then, depth + 1));
+                    stack.push((body, depth + 1));
+                    stack.extend(
+                        contracts.iter().rev().map(|(contract, _)| (&contract.cond, depth + 1))
+                    );
+                }
+                Expr::Extern { then, .. } => stack.push((then, depth + 1)),
+            }
+        }
+        true
+    }
+
+    fn depth_error(&self, span: SimpleSpan, diags: &mut Diagnostics) {
+        diags.error_at(
+            span,
+            format!(
+                "program nests more than {} levels deep (see `--max-ast-depth`)",
+                self.max_ast_depth
+            ),
+            "limit reached here"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use std::rc::Rc;
+
+/// The byte offsets each line in a source file starts at, for turning spans
+/// into line and column numbers. It's built once per file, by its
+/// [`SourceFile`], and clones share the offsets.
+///
+/// [`SourceFile`]: crate::sources::SourceFile
+#[derive(Clone, Debug)]
+pub struct SourceLines(Rc<[usize]>);
+
+impl SourceLines {
+    pub fn new(src: &str) -> SourceLines {
+        let starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceLines(starts)
+    }
+
+    /// Gets the 1-based line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (u32, u32) {
+        let line = self.0.partition_point(|&start| start <= offset);
+        let col = offset - self.0[line - 1] + 1;
+        (line as u32, col as u32)
+    }
+
+    /// Gets the byte offset of a 1-based line and column, if the line exists.
+    pub fn offset(&self, line: usize, col: usize) -> Option<usize> {
+        Some(self.0.get(line - 1)? + col - 1)
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use unicode_security::{skeleton, MixedScript};
+
+use crate::{
+    error::Diagnostics,
+    parse::{Expr, Spanned},
+    prelude,
+};
+
+/// Queues warnings about code which compiles, but probably doesn't mean what
+/// it looks like it means.
+pub fn check(root: &Expr, diags: &mut Diagnostics) {
+    let mut idents = vec![];
+    let mut stack = vec![root];
+
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Num(val, span) => {
+                // formatting with a precision prints the value's exact digits
+                if format!("{:.0}", val) != diags.source(*span) {
+                    diags.warning_at(
+                        *span,
+                        "integer literal can't be represented exactly",
+                        format!("rounded to {:.0}", val)
+                    );
+                }
+            }
+            Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(right);
+                stack.push(left);
+            }
+            Expr::Call(_, args, _) => stack.extend(args.iter().rev()),
+            Expr::Let { name, rhs, then, .. } => {
+                idents.push(name);
+                stack.push(then);
+                stack.push(rhs);
+            }
+            Expr::Fn { name, args, contracts, body, then, .. } => {
+                idents.push(name);
+                idents.extend(args);
+                stack.push(then);
+                stack.push(body);
+                stack.extend(contracts.iter().rev().map(|(contract, _)| &contract.cond));
+            }
+            Expr::Extern { name, args, then, .. } => {
+                idents.push(name);
+                idents.extend(args);
+                stack.push(then);
+            }
+        }
+    }
+
+    confusables(&idents, diags);
+    unused_results(root, diags);
+}
+
+/// Warns about declarations using names that already mean something else, for
+/// `--check-reserved`: those of builtin functions, which the declaration takes
+/// the place of; `main`, the entry function's symbol; and `result` for a
+/// parameter, which `ensures` clauses can't see past the function's result.
+pub fn reserved(root: &Expr, diags: &mut Diagnostics) {
+    let mut e = root;
+    loop {
+        let (name, params, then) = match e {
+            Expr::Let { name, then, .. } => (name, &[][..], then),
+            Expr::Fn { name, args, then, .. } | Expr::Extern { name, args, then, .. } => {
+                (name, &args[..], then)
+            }
+            _ => return,
+        };
+        let (ident, span) = name;
+        if prelude::builtin(ident).is_some() {
+            diags.warning_at(
+                *span,
+                format!("`{}` is the name of a builtin function", ident),
+                "this takes the builtin's place"
+            );
+        } else if ident == "main" {
+            diags.warning_at(
+                *span,
+                "`main` is the name of the program's entry function",
+                "declared here"
+            );
+        }
+        for (param, span) in params {
+            if param == "result" {
+                diags.warning_at(
+                    *span,
+                    "parameter named `result`",
+                    "`ensures` clauses see the function's result instead"
+                );
+            }
+        }
+        e = then;
+    }
+}
+
+/// Warns about declared identifiers mixing characters from different scripts,
+/// and about ones which look the same as another but aren't, e.g. `a` and the
+/// Cyrillic `а`.
+fn confusables(idents: &[&Spanned<String>], diags: &mut Diagnostics) {
+    let mut skeletons: HashMap<String, &str> = HashMap::new();
+
+    for (name, span) in idents {
+        if !name.is_single_script() {
+            diags.warning_at(
+                *span,
+                format!("identifier `{}` mixes characters from several scripts", name),
+                "declared here"
+            );
+        }
+        let other = skeletons.entry(skeleton(name).collect()).or_insert(name);
+        if other != name {
+            diags.warning_at(
+                *span,
+                format!("identifier `{}` is easily confused with `{}`", name, other),
+                "declared here"
+            );
+        }
+    }
+}
+
+/// Warns about `let` declarations which are never used, when computing their
+/// value only calls functions without effects, e.g. `let x = fib(10);`, since
+/// the calls do nothing. `extern` functions, and the functions calling them,
+/// are assumed to have effects, so `let x = putchar(65);` is fine.
+fn unused_results(root: &Expr, diags: &mut Diagnostics) {
+    // whether each function declared so far has effects
+    let mut effects: HashMap<&str, bool> = HashMap::new();
+    let mut e = root;
+
+    loop {
+        match e {
+            Expr::Extern { name, then, .. } => {
+                effects.insert(&name.0, true);
+                e = then;
+            }
+            Expr::Fn { name, body, then, .. } => {
+                // calls to the function itself add no effects of their own
+                let impure = calls(body)
+                    .iter()
+                    .any(|(callee, _)| effects.get(callee.as_str()).copied().unwrap_or(false));
+                effects.insert(&name.0, impure);
+                e = then;
+            }
+            Expr::Let { name: (name, span), rhs, then, .. } => {
+                let calls = calls(rhs);
+                let pure = calls.iter()
+                    .all(|(callee, _)| !effects.get(callee.as_str()).copied().unwrap_or(false));
+                if let Some((callee, _)) = calls.first()
+                    .filter(|_| pure && !used(name, then))
+                {
+                    diags.warning_at(
+                        *span,
+                        format!("the result of calling `{}` is never used", callee),
+                        format!("`{}` is never used, and `{}` has no effects", name, callee)
+                    );
+                }
+                e = then;
+            }
+            _ => break,
+        }
+    }
+}
+
+/// Gets the functions an expression calls, in the order they appear.
+fn calls(root: &Expr) -> Vec<&Spanned<String>> {
+    let mut calls = vec![];
+    let mut stack = vec![root];
+
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(right);
+                stack.push(left);
+            }
+            Expr::Call(name, args, _) => {
+                calls.push(name);
+                stack.extend(args.iter().rev());
+            }
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+        }
+    }
+    calls
+}
+
+/// Checks whether a variable is used in the declarations after it, or the
+/// final expression, before another `let` shadows it. Functions can't see
+/// variables, so their bodies are skipped.
+fn used(var: &str, mut e: &Expr) -> bool {
+    let mentions = |root: &Expr| {
+        let mut stack = vec![root];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Var(name, _) if name == var => return true,
+                Expr::Num(..) | Expr::Var(..) => (),
+                Expr::Neg(expr, _) => stack.push(expr),
+                Expr::Add(left, right, _)
+                | Expr::Sub(left, right, _)
+                | Expr::Mul(left, right, _)
+                | Expr::Div(left, right, _)
+                | Expr::Cmp(_, left, right, _) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+                Expr::Call(_, args, _) => stack.extend(args.iter()),
+                Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+            }
+        }
+        false
+    };
+
+    loop {
+        match e {
+            Expr::Let { name, rhs, then, .. } => {
+                if mentions(rhs) {
+                    return true;
+                }
+                if name.0 == var {
+                    return false;
+                }
+                e = then;
+            }
+            Expr::Fn { then, .. } | Expr::Extern { then, .. } => e = then,
+            _ => return mentions(e),
+        }
+    }
+}
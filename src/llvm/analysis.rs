@@ -0,0 +1,286 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use inkwell::basic_block::BasicBlock;
+use inkwell::module::Module;
+use inkwell::values::{FunctionValue, InstructionOpcode};
+
+use crate::artifact;
+
+/// The control flow graph of a function, with its dominator tree and natural
+/// loops, as LLVM's `DominatorTree` and `LoopInfo` analyses would find them.
+/// LLVM's C API exposes neither, so they're computed here from the IR.
+struct FunctionAnalysis<'ctx> {
+    blocks: Vec<BasicBlock<'ctx>>,
+    /// The successors of each block, with the label of the edge for a
+    /// conditional branch.
+    successors: Vec<Vec<(usize, Option<&'static str>)>>,
+    /// Each reachable block's immediate dominator. The entry block is its own.
+    idom: Vec<Option<usize>>,
+    /// The loops, each as its header and the blocks in it.
+    loops: Vec<(usize, Vec<usize>)>,
+}
+
+/// Gets the path of the analysis graph for a source file, e.g.
+/// `test.analysis.dot`.
+pub fn analysis_dot_path(src: &Path) -> PathBuf {
+    src.with_extension("analysis.dot")
+}
+
+/// Writes a GraphViz graph of each function defined in `module` to `path`, for
+/// `--emit analysis-dot`: its basic blocks and the branches between them, the
+/// dominator tree as dashed edges, and each block annotated with its immediate
+/// dominator and the loops it's in, with back edges in red.
+pub fn write_analysis_dot(module: &Module, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut dot = String::from(
+        "digraph analysis {\n    node [shape=box, fontname=monospace];\n"
+    );
+    for (i, function) in module.get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .enumerate()
+    {
+        write_function(&mut dot, i, function, &analyze(function));
+    }
+    dot.push_str("}\n");
+    artifact::write(path, dot)
+}
+
+fn analyze(function: FunctionValue) -> FunctionAnalysis {
+    let blocks = function.get_basic_blocks();
+    let index = |block: BasicBlock| blocks.iter().position(|b| *b == block);
+    let successors = blocks.iter()
+        .map(|block| {
+            let Some(terminator) = block.get_terminator() else {
+                return vec![];
+            };
+            // a conditional branch's operands are the condition, then the
+            // blocks branched to when it's false and when it's true
+            let conditional = terminator.get_opcode() == InstructionOpcode::Br
+                && terminator.get_num_operands() == 3;
+            (0..terminator.get_num_operands())
+                .filter_map(|i| {
+                    let succ = terminator.get_operand(i)?.right()?;
+                    let label = match (conditional, i) {
+                        (true, 1) => Some("false"),
+                        (true, 2) => Some("true"),
+                        _ => None,
+                    };
+                    Some((index(succ)?, label))
+                })
+                .collect()
+        })
+        .collect::<Vec<Vec<_>>>();
+
+    // blocks in reverse postorder, so each block comes after its dominators
+    let mut postorder = vec![];
+    let mut visited = vec![false; blocks.len()];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        match successors[block].get(next) {
+            Some(&(succ, _)) => {
+                stack.push((block, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            }
+            None => postorder.push(block),
+        }
+    }
+    let mut order = vec![usize::MAX; blocks.len()];
+    for (n, &block) in postorder.iter().enumerate() {
+        order[block] = n;
+    }
+    let mut predecessors = vec![vec![]; blocks.len()];
+    for (block, succs) in successors.iter().enumerate() {
+        for &(succ, _) in succs {
+            predecessors[succ].push(block);
+        }
+    }
+
+    // Cooper, Harvey, and Kennedy's "A Simple, Fast Dominance Algorithm"
+    let mut idom = vec![None; blocks.len()];
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in postorder.iter().rev().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for &pred in &predecessors[block] {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(mut other) => {
+                        let mut pred = pred;
+                        while pred != other {
+                            while order[pred] < order[other] {
+                                pred = idom[pred].unwrap();
+                            }
+                            while order[other] < order[pred] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        pred
+                    }
+                });
+            }
+            if new_idom.is_some() && idom[block] != new_idom {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    let dominates = |a: usize, mut b: usize| loop {
+        if a == b {
+            return true;
+        }
+        match idom[b] {
+            Some(parent) if parent != b => b = parent,
+            _ => return false,
+        }
+    };
+
+    // a back edge goes to a block that dominates where it comes from, and the
+    // loop it closes is every block that reaches it without passing through
+    // the header
+    let mut loops: Vec<(usize, Vec<usize>)> = vec![];
+    for (block, succs) in successors.iter().enumerate() {
+        for &(header, _) in succs {
+            if idom[block].is_none() || !dominates(header, block) {
+                continue;
+            }
+            let position = match loops.iter().position(|(h, _)| *h == header) {
+                Some(position) => position,
+                None => {
+                    loops.push((header, vec![header]));
+                    loops.len() - 1
+                }
+            };
+            let body = &mut loops[position].1;
+            let mut work = vec![block];
+            while let Some(block) = work.pop() {
+                if !body.contains(&block) {
+                    body.push(block);
+                    work.extend(
+                        predecessors[block].iter().filter(|pred| idom[**pred].is_some())
+                    );
+                }
+            }
+        }
+    }
+
+    FunctionAnalysis { blocks, successors, idom, loops }
+}
+
+fn write_function(
+    dot: &mut String,
+    n: usize,
+    function: FunctionValue,
+    analysis: &FunctionAnalysis
+) {
+    let FunctionAnalysis { blocks, successors, idom, loops } = analysis;
+    let name = function.get_name().to_string_lossy();
+    let block_name = |block: usize| match blocks[block].get_name().to_str() {
+        Ok(name) if !name.is_empty() => format!("%{}", name),
+        _ => format!("%{}", block),
+    };
+    let _ = writeln!(dot, "    subgraph cluster_{} {{", n);
+    let _ = writeln!(
+        dot,
+        "        label=\"{} ({} block{}, {} loop{})\";",
+        escape(&name),
+        blocks.len(),
+        if blocks.len() == 1 { "" } else { "s" },
+        loops.len(),
+        if loops.len() == 1 { "" } else { "s" }
+    );
+
+    for (block, bb) in blocks.iter().enumerate() {
+        let mut label = block_name(block);
+        let mut instructions = 0;
+        let mut instruction = bb.get_first_instruction();
+        while let Some(i) = instruction {
+            instructions += 1;
+            instruction = i.get_next_instruction();
+        }
+        let _ = write!(
+            label,
+            "\\l{} instruction{}",
+            instructions,
+            if instructions == 1 { "" } else { "s" }
+        );
+        match idom[block] {
+            None => label.push_str("\\lunreachable"),
+            Some(parent) if parent == block => label.push_str("\\lentry"),
+            Some(parent) => { let _ = write!(label, "\\lidom: {}", block_name(parent)); }
+        }
+        let depth = loops.iter().filter(|(_, body)| body.contains(&block)).count();
+        if loops.iter().any(|(header, _)| *header == block) {
+            let _ = write!(label, "\\lloop header, depth {}", depth);
+        } else if let Some((header, _)) = loops.iter()
+            .filter(|(_, body)| body.contains(&block))
+            // the innermost loop is the smallest
+            .min_by_key(|(_, body)| body.len())
+        {
+            let _ = write!(label, "\\lin loop of {}, depth {}", block_name(*header), depth);
+        }
+        let _ = writeln!(
+            dot,
+            "        n{}_{} [label=\"{}\\l\"{}];",
+            n,
+            block,
+            escape(&label),
+            if depth > 0 { ", style=filled, fillcolor=lightyellow" } else { "" }
+        );
+    }
+
+    for (block, succs) in successors.iter().enumerate() {
+        for &(succ, label) in succs {
+            let back = loops.iter()
+                .any(|(header, body)| *header == succ && body.contains(&block));
+            let mut attrs = vec![];
+            if let Some(label) = label {
+                attrs.push(format!("label={}", label));
+            }
+            if back {
+                attrs.push("color=red".to_owned());
+                attrs.push("xlabel=\"back edge\"".to_owned());
+            }
+            let _ = writeln!(
+                dot,
+                "        n{0}_{1} -> n{0}_{2}{3};",
+                n,
+                block,
+                succ,
+                if attrs.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", attrs.join(", "))
+                }
+            );
+        }
+    }
+    // the dominator tree, drawn over the control flow without moving it
+    for (block, parent) in idom.iter().enumerate() {
+        if let Some(parent) = parent.filter(|parent| *parent != block) {
+            let _ = writeln!(
+                dot,
+                "        n{0}_{1} -> n{0}_{2} [style=dashed, color=blue, constraint=false];",
+                n,
+                parent,
+                block
+            );
+        }
+    }
+    dot.push_str("    }\n");
+}
+
+/// Escapes a string for a quoted GraphViz ID, keeping the `\l` line breaks
+/// already in it.
+fn escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
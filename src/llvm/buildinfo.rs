@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::path::Path;
+
+use inkwell::context::Context;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::TargetTriple;
+use inkwell::AddressSpace;
+
+/// The global holding the build info.
+const GLOBAL: &str = "foo.buildinfo";
+
+/// The name of the section holding the build info, on ELF and COFF. It's kept
+/// to 8 characters, the longest a section of a PE executable can be named.
+const SECTION: &str = ".fooinfo";
+
+/// The segment and name of the section holding the build info, on Mach-O.
+const MACHO_SECTION: &str = "__DATA,__fooinfo";
+
+/// Embeds `info` in its own section of the objects generated from `module`,
+/// for `foo_llvm inspect` to read back, placed for the object format of
+/// `triple`.
+pub fn add_build_info<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    info: &str,
+    triple: &TargetTriple
+) {
+    // ended with a NUL, so the records of several modules linked together
+    // can be told apart
+    let value = context.const_string(info.as_bytes(), true);
+    let global = module.add_global(value.get_type(), None, GLOBAL);
+    global.set_initializer(&value);
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+    global.set_alignment(1);
+
+    // nothing refers to it, so it's marked as used to keep it
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let used = ptr_type.const_array(&[global.as_pointer_value()]);
+    let used_global = module.add_global(used.get_type(), None, "llvm.used");
+    used_global.set_initializer(&used);
+    used_global.set_linkage(Linkage::Appending);
+    used_global.set_section(Some("llvm.metadata"));
+
+    place_build_info(module, triple);
+}
+
+/// Moves the build info to the section for the object format of `triple`, as
+/// the module is compiled for each target in turn.
+pub fn place_build_info(module: &Module, triple: &TargetTriple) {
+    let Some(global) = module.get_global(GLOBAL) else {
+        return;
+    };
+    let triple = triple.as_str().to_string_lossy();
+    let macho = triple.contains("apple") || triple.contains("darwin");
+    global.set_section(Some(if macho { MACHO_SECTION } else { SECTION }));
+}
+
+/// Reads the build info embedded in an object or executable, one record for
+/// each module it was compiled from, or none if it has no build info.
+pub fn read_build_info(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let buffer = MemoryBuffer::create_from_file(path)
+        .map_err(|e| format!("failed to read {:#?}: {}", path, e.to_string_lossy()))?;
+    let object = buffer.create_object_file()
+        .map_err(|()| format!("{:#?} isn't an object file or executable", path))?;
+    // Mach-O sections are named without their segment
+    let name = MACHO_SECTION.split_once(',').unwrap().1;
+    Ok(object.get_sections()
+        .filter(|section| {
+            section.get_name().is_some_and(|n| n.to_bytes() == SECTION.as_bytes()
+                || n.to_bytes() == name.as_bytes())
+        })
+        .flat_map(|section| {
+            section.get_contents()
+                .split(|byte| *byte == 0)
+                .filter(|record| !record.is_empty())
+                .map(|record| String::from_utf8_lossy(record).into_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
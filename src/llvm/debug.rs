@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use chumsky::span::SimpleSpan;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DIScope, DIType,
+    DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::targets::{FileType, TargetMachine};
+use inkwell::values::FunctionValue;
+use inkwell::AddressSpace;
+
+use crate::artifact;
+use crate::lines::SourceLines;
+use crate::FloatWidth;
+
+/// DWARF's type encoding for floating point numbers (`DW_ATE_float`).
+const DW_ATE_FLOAT: u32 = 0x04;
+/// DWARF's type encoding for signed integers (`DW_ATE_signed`).
+const DW_ATE_SIGNED: u32 = 0x05;
+
+/// Generates DWARF debug info for a module, so that instructions can be traced
+/// back to the line and column of the Foo source they came from.
+pub struct DebugInfo<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    /// The debug info type for Foo's numbers.
+    float_type: DIType<'ctx>,
+    /// The debug info type for the entry function's exit status.
+    int_type: DIType<'ctx>,
+    /// The debug info type for the array of arguments a function with many
+    /// parameters takes.
+    args_type: DIType<'ctx>,
+    lines: SourceLines,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    /// Sets up debug info for `module`, which is generated from the Foo source
+    /// file at `path` and computes with floats of `float_width`. If
+    /// `reproducible` is set, the source's directory is recorded as given
+    /// instead of as an absolute path.
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        path: &Path,
+        lines: &SourceLines,
+        float_width: FloatWidth,
+        reproducible: bool
+    ) -> DebugInfo<'ctx> {
+        // without this flag, LLVM strips the debug info from the module
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(3, false)
+        );
+
+        let filename = path.file_name().unwrap().to_string_lossy();
+        // reproducible builds record the directory relative to where the
+        // compiler was run, so they don't depend on where the source is
+        let directory = if reproducible {
+            path.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(|dir| dir.to_string_lossy().into_owned())
+        } else {
+            std::fs::canonicalize(path)
+                .ok()
+                .and_then(|path| Some(path.parent()?.to_string_lossy().into_owned()))
+        };
+        let directory = directory.unwrap_or_else(|| ".".to_owned());
+
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            "foo_llvm",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            ""
+        );
+        let float_type = builder
+            .create_basic_type(
+                float_width.c_type(),
+                float_width.bits() as u64,
+                DW_ATE_FLOAT,
+                DIFlags::PUBLIC
+            )
+            .unwrap()
+            .as_type();
+        let int_type = builder
+            .create_basic_type("int", 32, DW_ATE_SIGNED, DIFlags::PUBLIC)
+            .unwrap()
+            .as_type();
+        // the pointer's size is left for the target to fill in
+        let args_type = builder
+            .create_pointer_type(
+                &format!("const {} *", float_width.c_type()),
+                float_type,
+                0,
+                0,
+                AddressSpace::default()
+            )
+            .as_type();
+
+        DebugInfo {
+            context,
+            builder,
+            unit,
+            float_type,
+            int_type,
+            args_type,
+            lines: lines.clone(),
+        }
+    }
+
+    /// Attaches debug info to a function, returning the scope its instructions'
+    /// locations belong to.
+    ///
+    /// * `name` - The function's name in the Foo source.
+    /// * `span` - The span of the function's definition.
+    pub fn function(
+        &self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        span: SimpleSpan
+    ) -> DIScope<'ctx> {
+        let (line, _) = self.lines.line_col(span.start);
+        let file = self.unit.get_file();
+
+        let params = function.get_param_iter()
+            .map(|param| if param.is_pointer_value() { self.args_type } else { self.float_type })
+            .collect::<Vec<_>>();
+        let return_type = function.get_type()
+            .get_return_type()
+            .map(|ty| if ty.is_float_type() { self.float_type } else { self.int_type });
+        let ty = self.builder.create_subroutine_type(
+            file,
+            return_type,
+            &params,
+            DIFlags::PUBLIC
+        );
+
+        let linkage_name = function.get_name().to_string_lossy();
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            name,
+            Some(&linkage_name),
+            file,
+            line,
+            ty,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false
+        );
+        function.set_subprogram(subprogram);
+        subprogram.as_debug_info_scope()
+    }
+
+    /// Sets the location of the instructions `builder` builds from here on to
+    /// the start of `span`.
+    pub fn set_location(
+        &self,
+        builder: &Builder<'ctx>,
+        scope: DIScope<'ctx>,
+        span: SimpleSpan
+    ) {
+        let (line, col) = self.lines.line_col(span.start);
+        let location = self.builder.create_debug_location(
+            self.context,
+            line,
+            col,
+            scope,
+            None
+        );
+        builder.set_current_debug_location(location);
+    }
+
+    /// Resolves the debug info once the module is finished. Must be called
+    /// before the module is verified or emitted.
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
+
+/// What [`write_annotated_assembly`] adds to the assembly it writes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsmAnnotations {
+    /// A comment quoting each line of the Foo source before the instructions
+    /// generated from it.
+    pub source_comments: bool,
+    /// C preprocessor line markers, e.g. `# 3 "/src/test.foo"`, so that the
+    /// line each instruction is on is its line in the Foo source to an
+    /// assembler that reads them, like GNU `as` and LLVM's. Its diagnostics
+    /// then point into the source, and it can generate line tables from them
+    /// if the `.loc` directives are stripped.
+    pub line_directives: bool,
+}
+
+/// Writes the assembly for `module` to `path`, with the annotations given.
+///
+/// The annotations are placed using the `.loc` directives LLVM emits from the
+/// module's debug info, so the module needs to have been generated with a
+/// [`DebugInfo`].
+pub fn write_annotated_assembly(
+    machine: &TargetMachine,
+    module: &Module,
+    path: &Path,
+    src_path: &Path,
+    src: &str,
+    annotations: AsmAnnotations
+) -> Result<(), Box<dyn Error>> {
+    let buffer = machine.write_to_memory_buffer(module, FileType::Assembly)?;
+    let asm = String::from_utf8_lossy(buffer.as_slice());
+
+    let src_name = src_path.file_name().unwrap().to_string_lossy();
+    let src_lines = src.lines().collect::<Vec<_>>();
+    let comment = comment_prefix(machine);
+    // the whole path, so the markers still point to the source wherever the
+    // assembly is assembled
+    let marker_path = std::fs::canonicalize(src_path)
+        .unwrap_or_else(|_| src_path.to_owned())
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+
+    let mut last_line = None;
+    // the line of source the next line written is on, as far as an assembler
+    // reading the markers is concerned
+    let mut marked_line = None;
+    let mut out = String::with_capacity(asm.len());
+
+    for (line, location) in asm.lines().zip(asm_locations(&asm, &src_name)) {
+        if let Some((src_line, _)) = location {
+            if annotations.source_comments && last_line != Some(src_line) {
+                if let Some(text) = src_lines.get(src_line as usize - 1) {
+                    out.push_str(&format!(
+                        "\t{} {}:{}: {}\n",
+                        comment,
+                        src_name,
+                        src_line,
+                        text.trim()
+                    ));
+                    marked_line = marked_line.map(|n| n + 1);
+                }
+            }
+            last_line = Some(src_line);
+        }
+        if annotations.line_directives && is_instruction(line) {
+            if let Some(src_line) = last_line.filter(|&n| marked_line != Some(n)) {
+                out.push_str(&format!("# {} \"{}\"\n", src_line, marker_path));
+                marked_line = Some(src_line);
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+        marked_line = marked_line.map(|n| n + 1);
+    }
+
+    artifact::write(path, out)
+}
+
+/// Whether a line of assembly is an instruction, rather than a directive,
+/// label, comment, or blank.
+fn is_instruction(line: &str) -> bool {
+    match line.split_whitespace().next() {
+        Some(word) => !(word.starts_with(['.', '#', '/', '@', ';']) || word.ends_with(':')),
+        None => false,
+    }
+}
+
+/// Finds the `.loc` directives in assembly generated from a module with
+/// [`DebugInfo`] that point into the source file `src_name`. For each line of
+/// `asm`, gets the line and column of the source it points to if it's one of
+/// those directives.
+pub fn asm_locations(asm: &str, src_name: &str) -> Vec<Option<(u32, u32)>> {
+    // maps the file numbers in `.file` directives to file names
+    let mut files = HashMap::new();
+
+    asm.lines().map(|line| {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some(".file") => {
+                // the name may or may not be joined with the directory
+                let name = line.rsplit('"')
+                    .nth(1)
+                    .and_then(|name| Path::new(name).file_name())
+                    .map(|name| name.to_string_lossy().into_owned());
+                if let (Some(num), Some(name)) = (words.next(), name) {
+                    files.insert(num.to_owned(), name);
+                }
+                None
+            }
+            Some(".loc") => {
+                let file = words.next().and_then(|num| files.get(num))?;
+                let line = words.next().and_then(|num| num.parse().ok())?;
+                let col = words.next().and_then(|num| num.parse().ok())?;
+                (file == src_name && line > 0).then_some((line, col))
+            }
+            _ => None,
+        }
+    }).collect()
+}
+
+/// Gets the string that starts a line comment in the target's assembly syntax.
+pub(super) fn comment_prefix(machine: &TargetMachine) -> &'static str {
+    let triple = machine.get_triple();
+    let triple = triple.as_str().to_string_lossy();
+    if triple.starts_with("aarch64") || triple.starts_with("arm64") {
+        "//"
+    } else if triple.starts_with("arm") || triple.starts_with("thumb") {
+        "@"
+    } else {
+        "#"
+    }
+}
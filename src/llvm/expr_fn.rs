@@ -0,0 +1,97 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use chumsky::Parser;
+use inkwell::context::Context;
+use inkwell::module::Module;
+
+use super::{CodegenOptions, Instrumentation, LlvmGenerator, PrintFormat};
+use crate::error::Diagnostics;
+use crate::messages::Messages;
+use crate::parse::{parser, Expr};
+use crate::{FloatModel, FloatWidth, LangVersion, MessageFormat, Notation};
+
+/// Compiles a Foo expression into a module defining exactly one function,
+/// `name`, which takes `params` and returns the expression's value. It's
+/// exported under `name` itself, rather than a mangled symbol, so it can be
+/// looked up and called from outside Foo, e.g. by a JIT evaluating a formula
+/// typed into a plotting tool.
+///
+/// The function is generated by [`LlvmGenerator::add_expr_fn()`], as the
+/// REPL's expressions are, and its C signature is `double name(double, ...)`
+/// with a `double` per parameter; more than [`MAX_DIRECT_PARAMS`] are refused.
+///
+/// [`MAX_DIRECT_PARAMS`]: crate::limits::MAX_DIRECT_PARAMS
+///
+/// The expression can use its parameters and the builtins, and nothing else:
+/// there are no other Foo functions for it to call. Errors generating it,
+/// including any LLVM's verifier finds, are reported with the column of the
+/// expression they point to.
+pub fn compile_expr_to_fn<'ctx>(
+    context: &'ctx Context,
+    name: &str,
+    params: &[&str],
+    expr_src: &str
+) -> Result<Module<'ctx>, Box<dyn Error>> {
+    let features = LangVersion::of(None, expr_src).features();
+    let body = parser(features)
+        .parse(expr_src)
+        .into_result()
+        .map_err(|errs| {
+            let errs = errs.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            format!("`{}` isn't a valid expression: {}", expr_src, errs.join("; "))
+        })?;
+    if matches!(body, Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. }) {
+        return Err(
+            format!(
+                "`{}` starts with declarations, but only an expression can be compiled",
+                expr_src
+            ).into()
+        );
+    }
+
+    let module = context.create_module(name);
+    let builder = context.create_builder();
+    let options = CodegenOptions {
+        print_format: PrintFormat {
+            notation: Notation::Fixed,
+            precision: None,
+            newline: true,
+        },
+        entry: None,
+        entry_symbol: None,
+        float_model: FloatModel::Strict,
+        float_width: FloatWidth::F64,
+        stack_probes: false,
+        stack_probe_size: None,
+        exports: vec![],
+        profile: None,
+        readable_names: None,
+    };
+    let mut generator = LlvmGenerator::incremental(
+        context,
+        &module,
+        &builder,
+        options,
+        Instrumentation::default()
+    );
+    if let Err(e) = generator.add_expr_fn(name, params, &body, &HashMap::new()) {
+        let mut diags = Diagnostics::new(
+            Path::new("<expr>"),
+            expr_src,
+            None,
+            Messages::new(MessageFormat::Human)
+        );
+        diags.semantic_error(e);
+        let errs = diags.take()
+            .into_iter()
+            .map(|diag| match diag.label {
+                Some((range, _)) => {
+                    format!("{} at column {}", diag.msg, diags.locate(range.start).2)
+                }
+                None => diag.msg,
+            })
+            .collect::<Vec<_>>();
+        return Err(format!("`{}` can't be compiled: {}", expr_src, errs.join("; ")).into());
+    }
+    Ok(module)
+}
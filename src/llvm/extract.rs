@@ -0,0 +1,40 @@
+use std::error::Error;
+
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::TargetMachine;
+
+use super::run_passes;
+use crate::mangle::demangle;
+
+/// Copies the function `name` out of `module` into a scratch module, so it can
+/// be printed by itself. The functions it calls are reduced to declarations,
+/// and everything else it doesn't use is dropped.
+///
+/// `name` is looked up as a Foo function first, then as a symbol, so `main`,
+/// exported functions, and functions from linked IR can be found too.
+pub fn extract_function<'ctx>(
+    module: &Module<'ctx>,
+    name: &str,
+    machine: &TargetMachine
+) -> Result<Module<'ctx>, Box<dyn Error>> {
+    let scratch = module.clone();
+    let Some(function) = scratch.get_functions()
+        .find(|f| demangle(&f.get_name().to_string_lossy()).is_some_and(|d| d.name == name))
+        .or_else(|| scratch.get_function(name))
+        .filter(|function| function.count_basic_blocks() > 0)
+    else {
+        return Err(format!("function `{}` isn't defined in the module", name).into());
+    };
+
+    // marking the other definitions as available elsewhere lets LLVM reduce
+    // them to declarations; the function itself is made external so it isn't
+    // dropped along with everything unused
+    for other in scratch.get_functions() {
+        if other != function && other.count_basic_blocks() > 0 {
+            other.set_linkage(Linkage::AvailableExternally);
+        }
+    }
+    function.set_linkage(Linkage::External);
+    run_passes(&scratch, machine, "elim-avail-extern,globaldce")?;
+    Ok(scratch)
+}
@@ -0,0 +1,584 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use chumsky::span::SimpleSpan;
+use inkwell::builder::{Builder, BuilderError};
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::values::{FloatValue, FunctionValue, GlobalValue};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate};
+
+use super::runtime::{self, Runtime};
+use crate::coverage;
+use crate::lines::SourceLines;
+use crate::parse::Expr;
+use crate::profile;
+use crate::Instrument;
+
+/// The runtime checks the generated code is instrumented with, none by default.
+#[derive(Default)]
+pub struct Instrumentation<'ctx> {
+    /// Tracks where NaN and infinite values first appear, if requested.
+    pub nan: Option<NanTracker<'ctx>>,
+    /// Counts how many times each expression is evaluated, if requested.
+    pub coverage: Option<CoverageCounters<'ctx>>,
+    /// Counts how many times each function is called, if requested.
+    pub profile: Option<ProfileCounters<'ctx>>,
+    /// Checks functions' contracts, if requested.
+    pub contracts: Option<ContractChecks<'ctx>>,
+    /// Checks for division by zero, if requested.
+    pub div_zero: Option<DivZeroChecks<'ctx>>,
+}
+
+impl<'ctx> Instrumentation<'ctx> {
+    /// Sets up the requested kinds of instrumentation for `module`, which is
+    /// generated from the program `ast` in the Foo source file at `path`.
+    pub fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        kinds: &[Instrument],
+        path: &Path,
+        lines: &SourceLines,
+        ast: &Expr
+    ) -> Instrumentation<'ctx> {
+        let nan = kinds.contains(&Instrument::Nan)
+            .then(|| NanTracker::new(context, module, path, lines));
+        let coverage = kinds.contains(&Instrument::Coverage)
+            .then(|| CoverageCounters::new(context, module, path, ast));
+        let profile = kinds.contains(&Instrument::Profile)
+            .then(|| ProfileCounters::new(context, module, path, ast));
+        let contracts = kinds.contains(&Instrument::Contracts)
+            .then_some(ContractChecks { context });
+        let div_zero = kinds.contains(&Instrument::DivZero)
+            .then(|| DivZeroChecks::new(context, path, lines));
+        Instrumentation { nan, coverage, profile, contracts, div_zero }
+    }
+}
+
+/// Instruments a module so that, when the program exits, it reports the first
+/// operation that produced a NaN and the first that produced an infinity.
+///
+/// Every operation checked is given a site number, and its source location is
+/// recorded in a table of sites emitted into the module. At runtime, a global
+/// for each kind of value holds the number of the first site to produce one
+/// (or 0 if none has yet).
+pub struct NanTracker<'ctx> {
+    context: &'ctx Context,
+    /// The site that first produced a NaN.
+    nan_site: GlobalValue<'ctx>,
+    /// The site that first produced an infinity.
+    inf_site: GlobalValue<'ctx>,
+    /// Descriptions of the sites checked so far, e.g. `` `/` at test.foo:3:7``.
+    sites: RefCell<Vec<String>>,
+    src_name: String,
+    lines: SourceLines,
+}
+
+impl<'ctx> NanTracker<'ctx> {
+    fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        path: &Path,
+        lines: &SourceLines
+    ) -> NanTracker<'ctx> {
+        let site_global = |name| {
+            let global = module.add_global(context.i32_type(), None, name);
+            global.set_initializer(&context.i32_type().const_zero());
+            global.set_linkage(Linkage::Private);
+            global
+        };
+
+        NanTracker {
+            context,
+            nan_site: site_global("foo.nan.site"),
+            inf_site: site_global("foo.inf.site"),
+            sites: RefCell::new(vec![]),
+            src_name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            lines: lines.clone(),
+        }
+    }
+
+    /// Builds a check recording `value`'s site if it's the first NaN or
+    /// infinity to appear.
+    ///
+    /// * `op` - Describes the operation which produced `value`, e.g. `` `+` ``.
+    pub fn check(
+        &self,
+        builder: &Builder<'ctx>,
+        value: FloatValue<'ctx>,
+        span: SimpleSpan,
+        op: &str
+    ) -> Result<(), BuilderError> {
+        let mut sites = self.sites.borrow_mut();
+        let (line, col) = self.lines.line_col(span.start);
+        sites.push(format!("{} at {}:{}:{}", op, self.src_name, line, col));
+        let site = self.context.i32_type().const_int(sites.len() as u64, false);
+
+        let float_type = value.get_type();
+        let is_nan = builder.build_float_compare(
+            FloatPredicate::UNO,
+            value,
+            value,
+            "isnan"
+        )?;
+        let is_pos_inf = builder.build_float_compare(
+            FloatPredicate::OEQ,
+            value,
+            float_type.const_float(f64::INFINITY),
+            "isposinf"
+        )?;
+        let is_neg_inf = builder.build_float_compare(
+            FloatPredicate::OEQ,
+            value,
+            float_type.const_float(f64::NEG_INFINITY),
+            "isneginf"
+        )?;
+        let is_inf = builder.build_or(is_pos_inf, is_neg_inf, "isinf")?;
+
+        for (global, found) in [(self.nan_site, is_nan), (self.inf_site, is_inf)] {
+            let first = builder
+                .build_load(self.context.i32_type(), global.as_pointer_value(), "first")?
+                .into_int_value();
+            let unset = builder.build_int_compare(
+                IntPredicate::EQ,
+                first,
+                self.context.i32_type().const_zero(),
+                "unset"
+            )?;
+            let record = builder.build_and(unset, found, "record")?;
+            let first = builder.build_select(record, site, first, "first")?;
+            builder.build_store(global.as_pointer_value(), first)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the report of where NaN and infinity first appeared, to be run
+    /// at the end of `main`. Must be called after every check has been built.
+    pub fn report(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        printf: FunctionValue<'ctx>
+    ) -> Result<(), BuilderError> {
+        let sites = self.sites.borrow();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+
+        // the site table; site `n` is at index `n - 1`
+        let descriptions = sites.iter()
+            .enumerate()
+            .map(|(i, site)| -> Result<_, BuilderError> {
+                Ok(builder
+                    .build_global_string_ptr(site, &format!("foo.site.{}", i + 1))?
+                    .as_pointer_value())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let table_type = ptr_type.array_type(sites.len() as u32);
+        let table = module.add_global(table_type, None, "foo.sites");
+        table.set_initializer(&ptr_type.const_array(&descriptions));
+        table.set_linkage(Linkage::Private);
+        table.set_constant(true);
+
+        let main = builder.get_insert_block().unwrap().get_parent().unwrap();
+        for (global, kind) in [(self.nan_site, "NaN"), (self.inf_site, "infinity")] {
+            let report = self.context.append_basic_block(main, "report");
+            let next = self.context.append_basic_block(main, "report_next");
+
+            let first = builder
+                .build_load(self.context.i32_type(), global.as_pointer_value(), "first")?
+                .into_int_value();
+            let found = builder.build_int_compare(
+                IntPredicate::NE,
+                first,
+                self.context.i32_type().const_zero(),
+                "found"
+            )?;
+            builder.build_conditional_branch(found, report, next)?;
+
+            builder.position_at_end(report);
+            let index = builder.build_int_sub(
+                first,
+                self.context.i32_type().const_int(1, false),
+                "index"
+            )?;
+            let site = unsafe {
+                builder.build_gep(
+                    table_type,
+                    table.as_pointer_value(),
+                    &[self.context.i32_type().const_zero(), index],
+                    "site"
+                )?
+            };
+            let site = builder.build_load(ptr_type, site, "site")?;
+            let format = builder.build_global_string_ptr(
+                &format!("{} first produced by %s\n", kind),
+                "nanfmt"
+            )?;
+            builder.build_call(
+                printf,
+                &[format.as_pointer_value().into(), site.into()],
+                "calltmp"
+            )?;
+            builder.build_unconditional_branch(next)?;
+
+            builder.position_at_end(next);
+        }
+        Ok(())
+    }
+}
+
+/// Checks the `requires` and `ensures` clauses of functions as they're called
+/// and return. A clause that doesn't hold is printed, e.g. `contract violated:
+/// `requires x` of `f``, and the program exits with status 1.
+pub struct ContractChecks<'ctx> {
+    context: &'ctx Context,
+}
+
+impl<'ctx> ContractChecks<'ctx> {
+    /// Builds a check that `value`, the condition of the clause `text` of the
+    /// function `name`, isn't 0 or NaN.
+    pub fn check(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        value: FloatValue<'ctx>,
+        name: &str,
+        text: &str
+    ) -> Result<(), BuilderError> {
+        let holds = builder.build_float_compare(
+            FloatPredicate::ONE,
+            value,
+            value.get_type().const_zero(),
+            "holds"
+        )?;
+        let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+        let fail = self.context.append_basic_block(function, "contract_fail");
+        let next = self.context.append_basic_block(function, "contract_next");
+        builder.build_conditional_branch(holds, next, fail)?;
+
+        builder.position_at_end(fail);
+        // the clause is passed as an argument, so it's printed as written
+        let format = builder.build_global_string_ptr("contract violated: %s\n", "contractfmt")?;
+        let clause = builder.build_global_string_ptr(
+            &format!("`{}` of `{}`", text, name),
+            "contract"
+        )?;
+        builder.build_call(
+            Runtime::Printf.get(self.context, module),
+            &[format.as_pointer_value().into(), clause.as_pointer_value().into()],
+            "calltmp"
+        )?;
+        builder.build_call(
+            Runtime::Exit.get(self.context, module),
+            &[self.context.i32_type().const_int(1, false).into()],
+            ""
+        )?;
+        builder.build_unreachable()?;
+
+        builder.position_at_end(next);
+        Ok(())
+    }
+}
+
+/// Checks each division's divisor before dividing. If it's zero, the program
+/// prints where, e.g. `division by zero at test.foo:3:7`, and exits with status
+/// 1, instead of going on with an infinity or NaN.
+pub struct DivZeroChecks<'ctx> {
+    context: &'ctx Context,
+    src_name: String,
+    lines: SourceLines,
+}
+
+impl<'ctx> DivZeroChecks<'ctx> {
+    fn new(context: &'ctx Context, path: &Path, lines: &SourceLines) -> DivZeroChecks<'ctx> {
+        DivZeroChecks {
+            context,
+            src_name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            lines: lines.clone(),
+        }
+    }
+
+    /// Builds a check that `divisor`, of the division at `span`, isn't zero.
+    pub fn check(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>,
+        divisor: FloatValue<'ctx>,
+        span: SimpleSpan
+    ) -> Result<(), BuilderError> {
+        // a NaN divisor isn't zero, and is left for `--instrument nan`
+        let zero = builder.build_float_compare(
+            FloatPredicate::OEQ,
+            divisor,
+            divisor.get_type().const_zero(),
+            "divzero"
+        )?;
+        let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+        let fail = self.context.append_basic_block(function, "divzero_fail");
+        let next = self.context.append_basic_block(function, "divzero_next");
+        builder.build_conditional_branch(zero, fail, next)?;
+
+        builder.position_at_end(fail);
+        let (line, col) = self.lines.line_col(span.start);
+        let message = builder.build_global_string_ptr(
+            &format!("division by zero at {}:{}:{}\n", self.src_name, line, col),
+            "divzeromsg"
+        )?;
+        // passed as an argument, so a `%` in the file's name is printed as is
+        let format = builder.build_global_string_ptr("%s", "divzerofmt")?;
+        builder.build_call(
+            Runtime::Printf.get(self.context, module),
+            &[format.as_pointer_value().into(), message.as_pointer_value().into()],
+            "calltmp"
+        )?;
+        builder.build_call(
+            Runtime::Exit.get(self.context, module),
+            &[self.context.i32_type().const_int(1, false).into()],
+            ""
+        )?;
+        builder.build_unreachable()?;
+
+        builder.position_at_end(next);
+        Ok(())
+    }
+}
+
+/// Instruments a module with a counter for each region of the source listed in
+/// its coverage map, which is written out to the coverage data file when the
+/// program exits.
+pub struct CoverageCounters<'ctx> {
+    context: &'ctx Context,
+    /// The array of counters, one per region.
+    counters: GlobalValue<'ctx>,
+    /// The index of each region's counter, by the region's start and end.
+    indices: HashMap<(usize, usize), u64>,
+    /// Where the counters are written, next to the source file.
+    data_path: String,
+}
+
+impl<'ctx> CoverageCounters<'ctx> {
+    fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        path: &Path,
+        ast: &Expr
+    ) -> CoverageCounters<'ctx> {
+        let indices = coverage::regions(ast)
+            .into_iter()
+            .enumerate()
+            .map(|(i, span)| ((span.start, span.end), i as u64))
+            .collect::<HashMap<_, _>>();
+
+        let counters_type = context.i64_type().array_type(indices.len() as u32);
+        let counters = module.add_global(counters_type, None, "foo.cov.counters");
+        counters.set_initializer(&counters_type.const_zero());
+        counters.set_linkage(Linkage::Private);
+
+        // the program may not be run from the directory it was compiled in
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let data_path = coverage::data_path(&path).to_string_lossy().into_owned();
+
+        CoverageCounters { context, counters, indices, data_path }
+    }
+
+    /// Builds an increment of the counter for the region at `span`.
+    pub fn count(
+        &self,
+        builder: &Builder<'ctx>,
+        span: SimpleSpan
+    ) -> Result<(), BuilderError> {
+        let Some(index) = self.indices.get(&(span.start, span.end)) else {
+            return Ok(());
+        };
+        let i64_type = self.context.i64_type();
+        let counter = unsafe {
+            builder.build_gep(
+                i64_type.array_type(self.indices.len() as u32),
+                self.counters.as_pointer_value(),
+                &[i64_type.const_zero(), i64_type.const_int(*index, false)],
+                "counter"
+            )?
+        };
+        let count = builder
+            .build_load(i64_type, counter, "count")?
+            .into_int_value();
+        let count = builder.build_int_add(count, i64_type.const_int(1, false), "count")?;
+        builder.build_store(counter, count)?;
+        Ok(())
+    }
+
+    /// Builds the code writing the counters to the coverage data file, to be
+    /// run at the end of `main`. If the file can't be opened, nothing is
+    /// written.
+    pub fn write_out(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>
+    ) -> Result<(), BuilderError> {
+        let size_type = runtime::size_type(self.context, module);
+        let fopen = Runtime::Fopen.get(self.context, module);
+        let fwrite = Runtime::Fwrite.get(self.context, module);
+        let fclose = Runtime::Fclose.get(self.context, module);
+
+        let main = builder.get_insert_block().unwrap().get_parent().unwrap();
+        let write = self.context.append_basic_block(main, "cov_write");
+        let done = self.context.append_basic_block(main, "cov_done");
+
+        let path = builder.build_global_string_ptr(&self.data_path, "covpath")?;
+        let mode = builder.build_global_string_ptr("wb", "covmode")?;
+        let file = builder
+            .build_call(
+                fopen,
+                &[path.as_pointer_value().into(), mode.as_pointer_value().into()],
+                "file"
+            )?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let failed = builder.build_is_null(file, "failed")?;
+        builder.build_conditional_branch(failed, done, write)?;
+
+        builder.position_at_end(write);
+        builder.build_call(
+            fwrite,
+            &[
+                self.counters.as_pointer_value().into(),
+                size_type.const_int(8, false).into(),
+                size_type.const_int(self.indices.len() as u64, false).into(),
+                file.into(),
+            ],
+            "written"
+        )?;
+        builder.build_call(fclose, &[file.into()], "closed")?;
+        builder.build_unconditional_branch(done)?;
+
+        builder.position_at_end(done);
+        Ok(())
+    }
+}
+
+/// Instruments a module with a counter for each Foo function, counting the
+/// calls to it, which is written to the profile when the program exits.
+///
+/// The counters are stored after the program's checksum, in the same array, so
+/// the whole profile is written at once.
+pub struct ProfileCounters<'ctx> {
+    context: &'ctx Context,
+    /// The checksum followed by the array of counters, one per function.
+    counters: GlobalValue<'ctx>,
+    /// The index of each function's counter in `counters`, by name.
+    indices: HashMap<String, u64>,
+    /// Where the profile is written, next to the source file.
+    data_path: String,
+}
+
+impl<'ctx> ProfileCounters<'ctx> {
+    fn new(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        path: &Path,
+        ast: &Expr
+    ) -> ProfileCounters<'ctx> {
+        let indices = profile::functions(ast)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.to_owned(), i as u64 + 1))
+            .collect::<HashMap<_, _>>();
+
+        let i64_type = context.i64_type();
+        let initial = std::iter::once(i64_type.const_int(profile::checksum(ast), false))
+            .chain(indices.iter().map(|_| i64_type.const_zero()))
+            .collect::<Vec<_>>();
+        let counters_type = i64_type.array_type(initial.len() as u32);
+        let counters = module.add_global(counters_type, None, "foo.prof.counters");
+        counters.set_initializer(&i64_type.const_array(&initial));
+        counters.set_linkage(Linkage::Private);
+
+        // the program may not be run from the directory it was compiled in
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let data_path = profile::data_path(&path).to_string_lossy().into_owned();
+
+        ProfileCounters { context, counters, indices, data_path }
+    }
+
+    fn len(&self) -> u64 {
+        self.indices.len() as u64 + 1
+    }
+
+    /// Builds an increment of the counter for the function `name`, to be run
+    /// on entry to it.
+    pub fn count(
+        &self,
+        builder: &Builder<'ctx>,
+        name: &str
+    ) -> Result<(), BuilderError> {
+        let Some(index) = self.indices.get(name) else {
+            return Ok(());
+        };
+        let i64_type = self.context.i64_type();
+        let counter = unsafe {
+            builder.build_gep(
+                i64_type.array_type(self.len() as u32),
+                self.counters.as_pointer_value(),
+                &[i64_type.const_zero(), i64_type.const_int(*index, false)],
+                "counter"
+            )?
+        };
+        let count = builder
+            .build_load(i64_type, counter, "count")?
+            .into_int_value();
+        let count = builder.build_int_add(count, i64_type.const_int(1, false), "count")?;
+        builder.build_store(counter, count)?;
+        Ok(())
+    }
+
+    /// Builds the code writing the profile, to be run at the end of `main`. If
+    /// the file can't be opened, nothing is written.
+    pub fn write_out(
+        &self,
+        builder: &Builder<'ctx>,
+        module: &Module<'ctx>
+    ) -> Result<(), BuilderError> {
+        let size_type = runtime::size_type(self.context, module);
+        let fopen = Runtime::Fopen.get(self.context, module);
+        let fwrite = Runtime::Fwrite.get(self.context, module);
+        let fclose = Runtime::Fclose.get(self.context, module);
+
+        let main = builder.get_insert_block().unwrap().get_parent().unwrap();
+        let write = self.context.append_basic_block(main, "prof_write");
+        let done = self.context.append_basic_block(main, "prof_done");
+
+        let path = builder.build_global_string_ptr(&self.data_path, "profpath")?;
+        let mode = builder.build_global_string_ptr("wb", "profmode")?;
+        let file = builder
+            .build_call(
+                fopen,
+                &[path.as_pointer_value().into(), mode.as_pointer_value().into()],
+                "file"
+            )?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let failed = builder.build_is_null(file, "failed")?;
+        builder.build_conditional_branch(failed, done, write)?;
+
+        builder.position_at_end(write);
+        builder.build_call(
+            fwrite,
+            &[
+                self.counters.as_pointer_value().into(),
+                size_type.const_int(8, false).into(),
+                size_type.const_int(self.len(), false).into(),
+                file.into(),
+            ],
+            "written"
+        )?;
+        builder.build_call(fclose, &[file.into()], "closed")?;
+        builder.build_unconditional_branch(done)?;
+
+        builder.position_at_end(done);
+        Ok(())
+    }
+}
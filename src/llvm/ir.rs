@@ -1,23 +1,43 @@
 use super::*;
 
+use inkwell::attributes::{Attribute, AttributeLoc};
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::types::BasicMetadataTypeEnum;
-use inkwell::values::FloatValue;
-use inkwell::AddressSpace;
+use inkwell::debug_info::DIScope;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::targets::TargetTriple;
+use inkwell::types::{BasicMetadataTypeEnum, FloatType};
+use inkwell::values::{BasicMetadataValueEnum, FloatValue, FunctionValue, PointerValue};
+use inkwell::{AddressSpace, FloatPredicate};
 
-use crate::parse::Expr;
+use chumsky::span::SimpleSpan;
+use serde::Serialize;
 
-use std::error::Error;
+use super::runtime::Runtime;
+use crate::error::CompilerError;
+use crate::limits::MAX_DIRECT_PARAMS;
+use crate::mangle::{self, mangle};
+use crate::lines::SourceLines;
+use crate::parse::{CmpOp, Contract, ContractKind, Expr, FnAttr, Spanned};
+use crate::prelude;
+use crate::profile::Profile;
+use crate::sema;
+use crate::timings::DeclTimes;
+use crate::{FloatModel, FloatWidth, Notation};
+
+use std::cell::RefCell;
+use std::time::Instant;
 use std::collections::HashMap;
 
 /// Used to traverse the program AST and generate the LLVM IR.
 /// 
 /// This struct itself shouldn't be needed externally; only its public non-method
-/// [`generate()`] function. The [`new()`] function as well as all of the
-/// struct's methods are only used internally.
+/// [`generate()`] function, or [`incremental()`] to generate a program a piece
+/// at a time. The [`new()`] function as well as the rest of the struct's
+/// methods are only used internally.
 /// 
 /// [`generate()`]:     Self::generate()
+/// [`incremental()`]:  Self::incremental()
 /// [`new()`]:          Self::new()
 pub struct LlvmGenerator<'a, 'ctx> {
     /// The LLVM Context for the program's module. Used to manage types and
@@ -27,6 +47,210 @@ pub struct LlvmGenerator<'a, 'ctx> {
     module: &'a Module<'ctx>,
     /// Handles building of code blocks, functions, and calls.
     builder: &'a Builder<'ctx>,
+    /// Settings from the command line that affect the generated code.
+    options: CodegenOptions,
+    /// Functions that can be called from Foo code, by their name in the source
+    /// (as opposed to their symbol name).
+    functions: HashMap<String, FunctionValue<'ctx>>,
+    /// The number of parameters of each function that takes its arguments in
+    /// an array, by name.
+    arg_arrays: HashMap<String, usize>,
+    /// Where in the source each symbol defined in the module came from.
+    symbols: SymbolSpans,
+    /// The symbols of the functions exported from the program.
+    exports: Vec<String>,
+    /// Generates debug info for the module, if it was requested.
+    debug: Option<DebugInfo<'ctx>>,
+    /// The debug info scope of the function currently being built, if any.
+    scope: Option<DIScope<'ctx>>,
+    /// Runtime checks to build into the generated code.
+    instrumentation: Instrumentation<'ctx>,
+    /// The named IR values generated for each span of the source.
+    values: RefCell<ValueSpans>,
+    /// The time spent generating each declaration.
+    decl_times: DeclTimes,
+}
+
+/// Maps the names of symbols defined in the generated module to the spans of
+/// the source code that defined them.
+pub type SymbolSpans = HashMap<String, SimpleSpan>;
+
+/// Maps spans of the source code to the named IR values generated for them.
+pub type ValueSpans = HashMap<SimpleSpan, Vec<IrValue>>;
+
+/// A named value in the generated IR.
+#[derive(Clone, Debug, Serialize)]
+pub struct IrValue {
+    /// The symbol of the function the value is in, e.g. `foo.test.add.2`.
+    pub function: String,
+    /// The value's name, e.g. `addtmp`.
+    pub name: String,
+}
+
+/// What's known about the generated module beyond its contents.
+pub struct CodegenOutput {
+    /// Where in the source each symbol defined in the module came from.
+    pub symbols: SymbolSpans,
+    /// The named IR values generated for each span of the source.
+    pub values: ValueSpans,
+    /// The symbols of the functions exported from the program. If there are
+    /// any, every other function has internal linkage.
+    pub exports: Vec<String>,
+    /// The time spent generating each declaration, for `--time`.
+    pub decl_times: DeclTimes,
+}
+
+/// Checks whether a symbol is one the generated code relies on, which `extern`
+/// declarations and exported functions can't take. `main` is reserved even when
+/// the entry function has another name or isn't generated, since it's then
+/// likely to be defined by C code the program is linked with.
+fn is_reserved(symbol: &str, options: &CodegenOptions) -> bool {
+    symbol == "main"
+        || options.entry_symbol.as_deref() == Some(symbol)
+        || Runtime::ALL.iter().any(|function| function.name() == symbol)
+}
+
+/// The fast-math flags set on floating point operations under the relaxed float
+/// model. Everything is allowed except assuming there are no NaNs or infinities,
+/// which would make the operations that produce them undefined.
+const RELAXED_FAST_MATH: u32 = inkwell::llvm_sys::LLVMFastMathAllowReassoc
+    | inkwell::llvm_sys::LLVMFastMathAllowContract
+    | inkwell::llvm_sys::LLVMFastMathAllowReciprocal
+    | inkwell::llvm_sys::LLVMFastMathNoSignedZeros;
+
+/// Options controlling how the IR is generated.
+#[derive(Clone, Debug)]
+pub struct CodegenOptions {
+    /// How the program's result gets printed.
+    pub print_format: PrintFormat,
+    /// The user function `main` calls to get the program's result, in place of
+    /// evaluating the final expression.
+    pub entry: Option<String>,
+    /// The symbol of the entry function generated to evaluate the program's
+    /// final expression and print it, usually `main`, or `None` to generate
+    /// only the program's functions.
+    pub entry_symbol: Option<String>,
+    /// Whether floating point operations must follow IEEE 754 exactly.
+    pub float_model: FloatModel,
+    /// The width of the floats the program computes with.
+    pub float_width: FloatWidth,
+    /// Whether functions probe each page of large stack frames as they
+    /// allocate them, so overflowing the stack can't skip the guard page.
+    pub stack_probes: bool,
+    /// The size in bytes of the pages probed, if not the target's default.
+    pub stack_probe_size: Option<u32>,
+    /// Functions to export, in addition to those declared with `export`.
+    pub exports: Vec<String>,
+    /// The profile of earlier runs of the program, guiding how its functions
+    /// are optimized, if one was given.
+    pub profile: Option<Profile>,
+    /// Names the values generated after where in the source they come from,
+    /// for `--readable-ir`, instead of after what they are, e.g. `addtmp`.
+    pub readable_names: Option<ReadableNames>,
+}
+
+/// Names IR values after the source they're generated for, e.g. `%add.3.14`
+/// for an addition starting on line 3, column 14.
+#[derive(Clone, Debug)]
+pub struct ReadableNames {
+    lines: SourceLines,
+    /// The file being compiled. The code of included files comes after it,
+    /// and isn't located.
+    src: String,
+}
+
+impl ReadableNames {
+    pub fn new(lines: &SourceLines, src: &str) -> ReadableNames {
+        ReadableNames { lines: lines.clone(), src: src.to_owned() }
+    }
+
+    /// Names a value after what produced it and where the code of `span`
+    /// starts, past any whitespace it starts with, or gets `None` if the span
+    /// isn't in the file being compiled.
+    fn name(&self, what: &str, span: SimpleSpan) -> Option<String> {
+        let code = self.src.get(span.into_range())?;
+        (!code.is_empty()).then(|| {
+            let start = span.end - code.trim_start().len();
+            let (line, col) = self.lines.line_col(start);
+            format!("{}.{}.{}", what, line, col)
+        })
+    }
+}
+
+/// Describes the `printf` format used to print the program's result.
+#[derive(Clone, Copy, Debug)]
+pub struct PrintFormat {
+    pub notation: Notation,
+    /// Precision given to the conversion, if not the C default.
+    pub precision: Option<usize>,
+    /// Whether the result is followed by a newline.
+    pub newline: bool,
+}
+
+impl PrintFormat {
+    /// Builds the `printf` format string for a float of the given width, e.g.
+    /// `%.2f\n`.
+    pub fn to_format_string(self, width: FloatWidth) -> String {
+        let mut fmt = String::from("%");
+        if let Some(precision) = self.precision {
+            fmt.push_str(&format!(".{}", precision));
+        }
+        // `long double`s need a length modifier, while `float`s are passed to
+        // `printf` as `double`s
+        if width == FloatWidth::F80 {
+            fmt.push('L');
+        }
+        fmt.push(match self.notation {
+            Notation::Fixed => 'f',
+            Notation::General => 'g',
+            Notation::Scientific => 'e',
+        });
+        if self.newline {
+            fmt.push('\n');
+        }
+        fmt
+    }
+}
+
+impl FloatWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            FloatWidth::F32 => 32,
+            FloatWidth::F64 => 64,
+            FloatWidth::F80 => 80,
+        }
+    }
+
+    /// Gets the LLVM type of floats of this width.
+    pub fn float_type(self, context: &Context) -> FloatType<'_> {
+        match self {
+            FloatWidth::F32 => context.f32_type(),
+            FloatWidth::F64 => context.f64_type(),
+            FloatWidth::F80 => context.x86_f80_type(),
+        }
+    }
+
+    /// Gets the name of the C type of floats of this width.
+    pub fn c_type(self) -> &'static str {
+        match self {
+            FloatWidth::F32 => "float",
+            FloatWidth::F64 => "double",
+            FloatWidth::F80 => "long double",
+        }
+    }
+
+    /// Checks whether the target has floats of this width, and they can be
+    /// printed with `printf`: 80-bit floats are only C's `long double` on x86
+    /// targets, and not even there under MSVC, where it's the same as `double`.
+    pub fn supported_on(self, triple: &TargetTriple) -> bool {
+        if self != FloatWidth::F80 {
+            return true;
+        }
+        let triple = triple.as_str().to_string_lossy();
+        let arch = triple.split('-').next().unwrap_or_default();
+        let x86 = arch == "x86_64" || (arch.starts_with('i') && arch.ends_with("86"));
+        x86 && !triple.ends_with("msvc")
+    }
 }
 
 impl<'a, 'ctx> LlvmGenerator<'a, 'ctx> {
@@ -37,136 +261,569 @@ impl<'a, 'ctx> LlvmGenerator<'a, 'ctx> {
     /// 
     /// * `context` - The LLVM Context for the program.
     /// * `module_name` - For IR readability.
+    /// * `options` - Settings affecting the generated code.
+    /// * `debug` - Generates debug info for the module, if requested.
+    /// * `instrumentation` - Runtime checks to build into the code.
     fn new(
         context: &'ctx Context,
         module: &'a Module<'ctx>,
         builder: &'a Builder<'ctx>,
+        options: CodegenOptions,
+        debug: Option<DebugInfo<'ctx>>,
+        instrumentation: Instrumentation<'ctx>,
     ) -> LlvmGenerator<'a, 'ctx> {
         LlvmGenerator {
             context,
             module,
             builder,
+            options,
+            functions: HashMap::new(),
+            arg_arrays: HashMap::new(),
+            symbols: HashMap::new(),
+            exports: vec![],
+            debug,
+            scope: None,
+            instrumentation,
+            values: RefCell::new(HashMap::new()),
+            decl_times: DeclTimes::default(),
         }
     }
 
     /// This is the primary function called to execute the IR generation process.
     /// 
-    /// Loops through each [`Fn`] or [`Let`] and their `then` values until
-    /// reaching the final expression for evaluation.
+    /// Loops through each [`Fn`], [`Extern`], or [`Let`] and their `then`
+    /// values until reaching the final expression for evaluation.
     /// 
     /// * `root` - The root node of the AST.
     /// 
     /// [`Fn`]:     Expr::Fn
+    /// [`Extern`]: Expr::Extern
     /// [`Let`]:    Expr::Let
-    fn run(&self, root: &Expr) -> Result<(), Box<dyn Error>> {
+    fn run(&mut self, root: &Expr) -> Result<(), CompilerError> {
         let mut vars = HashMap::new();
+        let mut built = vec![];
         let mut e = root;
 
-        let main = self.module.add_function(
-            "main",
-            self.context.void_type().fn_type(&[], false),
-            None
-        );
-        let main_block = self.context.append_basic_block(main, "main_enter");
-        self.builder.position_at_end(main_block);
+        if let Some(symbol) = self.options.entry_symbol
+            .as_deref()
+            .filter(|symbol| Runtime::ALL.iter().any(|function| function.name() == *symbol))
+        {
+            return Err(
+                format!("entry symbol `{}` conflicts with a runtime symbol", symbol).into()
+            );
+        }
+        // without an entry function, the top-level `let`s and the final
+        // expression have nowhere to run, so only functions are generated
+        let entry_symbol = self.options.entry_symbol.clone();
+        let main = entry_symbol.as_deref().map(|symbol| {
+            let main = self.module.add_function(
+                symbol,
+                self.context.i32_type().fn_type(&[], false),
+                None
+            );
+            self.add_stack_probes(main);
+            let block = self.context.append_basic_block(main, "main_enter");
+            self.builder.position_at_end(block);
+            (symbol, main, block)
+        });
+        let main_scope = self.debug
+            .as_ref()
+            .zip(main)
+            .map(|(debug, (symbol, main, _))| debug.function(main, symbol, root.span()));
+        self.scope = main_scope;
 
         loop { // loop through Fn and Let until `e` is some other expression type
+            self.set_location(e.span());
+            let (decl, started) = (e, Instant::now());
             match e {
-                // If anyone reading is confused: the `name` field is a tuple of
-                // both a string and a locational span; the `name` identifier is
-                // being shadowed here to refer to only the string.
-                Expr::Fn { name: (name, _), args, body, then, .. } => {
-                    // `args` also gets mapped to a span-less variant:
-                    let args = args.iter().map(|(name, _)| name);
-
-                    if let Some(_) = self.module.get_function(&name) {
-                        return Err(format!("function `{}` already exists", name).into());
-                    } else {
-                        // create function and add it to the module
-                        let arg_types = std::iter::repeat(self.context.f64_type())
-                            .take(args.len())
-                            .map(|t| t.into())
-                            .collect::<Vec<BasicMetadataTypeEnum>>();
-                        let r#fn = self.module.add_function(
-                            &name,
-                            self.context
-                                .f64_type()
-                                .fn_type(
-                                    &arg_types,
-                                    false
-                                ),
-                            None
-                        );
-                        // set param names
-                        r#fn.get_param_iter()
-                            .zip(args)
-                            .for_each(|(param, arg)| {
-                                param.set_name(&arg);
-                            }
-                        );
-                        // generate function body
-                        let block = self.context.append_basic_block(
-                            r#fn, 
-                            &format!("{}_enter", name)
-                        );
+                Expr::Fn { name: (name, _), then, .. } => {
+                    let r#fn = self.visit_fn(e)?;
+                    // kept to be made private once the exports are known
+                    built.push((name, r#fn));
+                    self.decl_times.record(decl, started.elapsed());
+                    e = then;
+                    if let Some((_, _, block)) = main {
                         self.builder.position_at_end(block);
-    
-                        let mut fn_vars = HashMap::new();
-                        r#fn.get_param_iter().for_each(|param| {
-                            fn_vars.insert(
-                                param.get_name().to_str().unwrap().to_owned(),
-                                param.into_float_value()
-                            );
-                        });
-    
-                        self.builder.build_return(Some(&self.visit_expr(body, &fn_vars)?))?;
-                        
-                        if r#fn.verify(true) {
-                            e = &then;
-                            self.builder.position_at_end(main_block);
-                        } else {
-                            return Err(format!("function `{}` not built properly", name).into());
-                        }
                     }
+                    self.scope = main_scope;
                 }
-                Expr::Let { name: (name, _), rhs, then, .. } => {
-                    vars.insert(name.to_owned(), self.visit_expr(rhs, &vars)?);
-                    e = &then;
+                Expr::Extern { then, .. } => {
+                    self.visit_extern(e)?;
+                    self.decl_times.record(decl, started.elapsed());
+                    e = then;
+                }
+                Expr::Let { name, rhs, then, .. } => {
+                    if main.is_some() {
+                        let value = self.visit_expr(rhs, &vars)?;
+                        self.name_variable(name, rhs, value);
+                        vars.insert(name.0.to_owned(), value);
+                    }
+                    self.decl_times.record(decl, started.elapsed());
+                    e = then;
                 }
                 _ => {
-                    let exp = self.visit_expr(e, &vars)?;
-                    
-                    // call printf from libc
-                    let printf = self.module.add_function(
-                        "printf",
-                        self.context
-                            .i32_type()
-                            .fn_type(
-                                &[
-                                    BasicMetadataTypeEnum::PointerType(
-                                        self.context.ptr_type(AddressSpace::default())
-                                    ),
-                                ],
-                                true
-                            ),
-                        None
-                    );
-                    let format = self.builder.build_global_string_ptr("%f\n", "fmtstr")?;
-                    self.builder.build_call(
-                        printf,
-                        &[
-                            format.as_pointer_value().into(),
-                            exp.into()
-                        ],
-                        "calltmp"
-                    )?;
+                    let Some((symbol, _, _)) = main else {
+                        break;
+                    };
+                    self.symbols.insert(symbol.to_owned(), e.span());
+                    let exp = match &self.options.entry {
+                        Some(entry) => self.visit_entry(entry)?,
+                        None => self.visit_expr(e, &vars)?,
+                    };
+                    self.build_print(exp)?;
+                    if let Some(nan) = &self.instrumentation.nan {
+                        nan.report(self.builder, self.module, self.printf())?;
+                    }
+                    if let Some(coverage) = &self.instrumentation.coverage {
+                        coverage.write_out(self.builder, self.module)?;
+                    }
+                    if let Some(profile) = &self.instrumentation.profile {
+                        profile.write_out(self.builder, self.module)?;
+                    }
+                    // the program exits successfully once it's printed its result
+                    self.builder.build_return(Some(&self.context.i32_type().const_zero()))?;
+                    self.decl_times.record(decl, started.elapsed());
                     break;
                 }
             }
         }
-        self.builder.build_return(None)?;
-        main.verify(true);
+        if let Some(debug) = &self.debug {
+            debug.finalize();
+        }
+        if let Some(name) = self.options.exports
+            .iter()
+            .find(|name| !self.exports.contains(name))
+        {
+            return Err(format!("exported function `{}` not found", name).into());
+        }
+        // once anything is exported, the rest of the functions are kept private
+        if !self.exports.is_empty() {
+            for (name, r#fn) in &built {
+                if !self.exports.contains(name) {
+                    r#fn.set_linkage(Linkage::Internal);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates a function declared with `fn`, leaving the builder at the end
+    /// of its body. It's verified along with the rest of the module.
+    fn visit_fn(&mut self, decl: &Expr) -> Result<FunctionValue<'ctx>, CompilerError> {
+        // If anyone reading is confused: the `name` field is a tuple of both a
+        // string and a locational span; the `name` identifier is being
+        // shadowed here to refer to only the string.
+        let Expr::Fn {
+            attrs,
+            export,
+            name: (name, name_span),
+            args,
+            contracts,
+            body,
+            span,
+            ..
+        } = decl
+        else {
+            unreachable!("only `fn` declarations are generated as functions");
+        };
+        // `args` also gets mapped to a span-less variant:
+        let args = args.iter().map(|(name, _)| name);
+        if self.functions.contains_key(name) {
+            return Err(
+                CompilerError::at(
+                    *name_span,
+                    format!("function `{}` already exists", name),
+                    "declared again here"
+                )
+            );
+        }
+        // exported functions keep their own names, so they can be called from
+        // outside of Foo
+        let exported = *export || self.options.exports.contains(name);
+        if exported && is_reserved(name, &self.options) {
+            return Err(
+                CompilerError::at(
+                    *name_span,
+                    format!("exported function `{}` conflicts with a runtime symbol", name),
+                    "exported here"
+                )
+            );
+        }
+        let symbol = if exported {
+            self.exports.push(name.to_owned());
+            name.to_owned()
+        } else {
+            mangle(
+                &self.module.get_name().to_string_lossy(),
+                name,
+                args.len()
+            )
+        };
+
+        // create function and add it to the module; past `MAX_DIRECT_PARAMS`,
+        // the arguments are passed in an array instead
+        let packed = args.len() > MAX_DIRECT_PARAMS;
+        let arg_types = if packed {
+            vec![self.context.ptr_type(AddressSpace::default()).into()]
+        } else {
+            std::iter::repeat_n(
+                    self.float_type(),
+                    args.len()
+                )
+                .map(|t| t.into())
+                .collect::<Vec<BasicMetadataTypeEnum>>()
+        };
+        let r#fn = self.module.add_function(
+            &symbol,
+            self.float_type()
+                .fn_type(
+                    &arg_types,
+                    false
+                ),
+            None
+        );
+        self.functions.insert(name.to_owned(), r#fn);
+        if packed {
+            self.arg_arrays.insert(name.to_owned(), args.len());
+            self.add_arg_array_attributes(r#fn);
+        }
+        if self.instrumentation.contracts.is_none() {
+            self.add_contract_metadata(&symbol, contracts)?;
+        }
+        self.symbols.insert(symbol, *name_span);
+        self.add_attributes(r#fn, name, attrs)?;
+        self.add_profile(r#fn, name, attrs);
+        self.add_stack_probes(r#fn);
+        // set param names
+        if packed {
+            r#fn.get_nth_param(0).unwrap().set_name("args");
+        } else {
+            r#fn.get_param_iter()
+                .zip(args.clone())
+                .for_each(|(param, arg)| {
+                    param.set_name(arg);
+                }
+            );
+        }
+        // generate function body
+        let block = self.context.append_basic_block(
+            r#fn, 
+            &format!("{}_enter", name)
+        );
+        self.builder.position_at_end(block);
+        self.scope = self.debug
+            .as_ref()
+            .map(|debug| debug.function(r#fn, name, *span));
+        self.set_location(*span);
+        if let Some(profile) = &self.instrumentation.profile {
+            profile.count(self.builder, name)?;
+        }
+
+        let mut fn_vars = HashMap::new();
+        if packed {
+            let array = r#fn.get_nth_param(0).unwrap().into_pointer_value();
+            for (i, arg) in args.enumerate() {
+                let ptr = self.arg_ptr(array, i)?;
+                let value = self.builder
+                    .build_load(self.float_type(), ptr, arg)?
+                    .into_float_value();
+                fn_vars.insert(arg.to_owned(), value);
+            }
+        } else {
+            r#fn.get_param_iter().for_each(|param| {
+                fn_vars.insert(
+                    param.get_name().to_str().unwrap().to_owned(),
+                    param.into_float_value()
+                );
+            });
+        }
 
+        for (contract, _) in contracts {
+            if contract.kind == ContractKind::Requires {
+                self.check_contract(name, contract, &fn_vars)?;
+            }
+        }
+        let result = self.visit_expr(body, &fn_vars)?;
+        if contracts.iter().any(|(c, _)| c.kind == ContractKind::Ensures) {
+            let mut returned = fn_vars.clone();
+            returned.insert("result".to_owned(), result);
+            for (contract, _) in contracts {
+                if contract.kind == ContractKind::Ensures {
+                    self.check_contract(name, contract, &returned)?;
+                }
+            }
+        }
+        self.builder.build_return(Some(&result))?;
+        Ok(r#fn)
+    }
+
+    /// Declares a function defined outside of Foo, declared with `extern`.
+    fn visit_extern(&mut self, decl: &Expr) -> Result<FunctionValue<'ctx>, CompilerError> {
+        let Expr::Extern { name: (name, name_span), args, .. } = decl else {
+            unreachable!("only `extern` declarations are declared as external functions");
+        };
+        if self.functions.contains_key(name) {
+            return Err(
+                CompilerError::at(
+                    *name_span,
+                    format!("function `{}` already exists", name),
+                    "declared again here"
+                )
+            );
+        }
+        if is_reserved(name, &self.options) {
+            return Err(
+                CompilerError::at(
+                    *name_span,
+                    format!("extern function `{}` conflicts with a runtime symbol", name),
+                    "declared here"
+                )
+            );
+        }
+        let arg_types = std::iter::repeat_n(
+                self.float_type(),
+                args.len()
+            )
+            .map(|t| t.into())
+            .collect::<Vec<BasicMetadataTypeEnum>>();
+        // the symbol is left unmangled so the linker can find the definition
+        let r#fn = self.module.add_function(
+            name,
+            self.float_type().fn_type(&arg_types, false),
+            None
+        );
+        self.functions.insert(name.to_owned(), r#fn);
+        Ok(r#fn)
+    }
+
+    /// Calls the user-chosen entry function to get the program's result. The
+    /// function must exist and take no arguments.
+    fn visit_entry(&self, entry: &str) -> Result<FloatValue<'ctx>, CompilerError> {
+        let Some(r#fn) = self.functions.get(entry) else {
+            return Err(format!("entry function `{}` not found", entry).into());
+        };
+        if r#fn.count_params() != 0 {
+            return Err(
+                format!("entry function `{}` must not take any arguments", entry)
+                    .into()
+            );
+        }
+        match self.builder
+            .build_call(*r#fn, &[], "calltmp")?
+            .try_as_basic_value()
+            .left()
+        {
+            Some(val) => Ok(val.into_float_value()),
+            None => Err("failed to build function call".into()),
+        }
+    }
+
+    /// Builds the check of one of a function's contracts, if contracts are
+    /// being checked, evaluating its condition with `vars`.
+    fn check_contract(
+        &self,
+        name: &str,
+        contract: &Contract,
+        vars: &HashMap<String, FloatValue<'ctx>>
+    ) -> Result<(), CompilerError> {
+        let Some(checks) = &self.instrumentation.contracts else {
+            return Ok(());
+        };
+        let value = self.visit_expr(&contract.cond, vars)?;
+        checks.check(self.builder, self.module, value, name, &contract.text)?;
+        Ok(())
+    }
+
+    /// Records a function's contracts, when they aren't checked, in the
+    /// module's `foo.contracts` named metadata, as a node of the function's
+    /// symbol followed by its clauses as written, e.g.
+    /// `!{!"foo.test.f.1", !"requires x"}`.
+    fn add_contract_metadata(
+        &self,
+        symbol: &str,
+        contracts: &[Spanned<Contract>]
+    ) -> Result<(), CompilerError> {
+        if contracts.is_empty() {
+            return Ok(());
+        }
+        let values = std::iter::once(symbol)
+            .chain(contracts.iter().map(|(contract, _)| contract.text.as_str()))
+            .map(|text| self.context.metadata_string(text).into())
+            .collect::<Vec<BasicMetadataValueEnum>>();
+        let node = self.context.metadata_node(&values);
+        self.module.add_global_metadata("foo.contracts", &node)?;
+        Ok(())
+    }
+
+    /// Translates a function's annotations to LLVM function attributes.
+    fn add_attributes(
+        &self,
+        r#fn: FunctionValue<'ctx>,
+        name: &str,
+        attrs: &[(FnAttr, SimpleSpan)]
+    ) -> Result<(), CompilerError> {
+        // already reported by `sema::check`, but not everything generating
+        // code runs it
+        if let Some((first, second)) = sema::inline_conflict(attrs) {
+            return Err(
+                CompilerError::at(
+                    second,
+                    format!("function `{}` can't be both `@inline` and `@noinline`", name),
+                    "conflicting annotation"
+                ).with_related(first, "conflicts with this one")
+            );
+        }
+
+        for (attr, _) in attrs {
+            let kind = match attr {
+                FnAttr::Inline => "alwaysinline",
+                FnAttr::NoInline => "noinline",
+                FnAttr::Cold => "cold",
+            };
+            let attribute = self.context.create_enum_attribute(
+                Attribute::get_named_enum_kind_id(kind),
+                0
+            );
+            r#fn.add_attribute(AttributeLoc::Function, attribute);
+        }
+        Ok(())
+    }
+
+    /// Gives a function the number of calls to it in the profile, if one was
+    /// given, as its entry count, and marks it `cold` if it was never called or
+    /// `hot` if it was called more than most, for the optimizations and the
+    /// code generator to use. The profile doesn't override `@cold`.
+    fn add_profile(
+        &self,
+        r#fn: FunctionValue<'ctx>,
+        name: &str,
+        attrs: &[(FnAttr, SimpleSpan)]
+    ) {
+        let Some(profile) = &self.options.profile else {
+            return;
+        };
+        let Some(count) = profile.count(name) else {
+            return;
+        };
+
+        let entry_count = self.context.metadata_node(&[
+            self.context.metadata_string("function_entry_count").into(),
+            self.context.i64_type().const_int(count, false).into(),
+        ]);
+        r#fn.as_global_value()
+            .set_metadata(entry_count, self.context.get_kind_id("prof"));
+
+        let kind = if count == 0 {
+            "cold"
+        } else if profile.is_hot(name) && !attrs.iter().any(|(a, _)| *a == FnAttr::Cold) {
+            "hot"
+        } else {
+            return;
+        };
+        let attribute = self.context.create_enum_attribute(
+            Attribute::get_named_enum_kind_id(kind),
+            0
+        );
+        r#fn.add_attribute(AttributeLoc::Function, attribute);
+    }
+
+    /// Sets the stack probing attributes on a function, if stack probes were
+    /// requested.
+    fn add_stack_probes(&self, r#fn: FunctionValue<'ctx>) {
+        if !self.options.stack_probes {
+            return;
+        }
+        r#fn.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_string_attribute("probe-stack", "inline-asm")
+        );
+        if let Some(size) = self.options.stack_probe_size {
+            r#fn.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_string_attribute(
+                    "stack-probe-size",
+                    &size.to_string()
+                )
+            );
+        }
+    }
+
+    /// Gets the name of the value built for `span`: the readable name for
+    /// `what` produced it, under `--readable-ir`, or else `tmp`.
+    fn value_name(&self, what: &str, span: SimpleSpan, tmp: &str) -> String {
+        self.options.readable_names
+            .as_ref()
+            .and_then(|names| names.name(what, span))
+            .unwrap_or_else(|| tmp.to_owned())
+    }
+
+    /// Names the value of a `let` after its variable, under `--readable-ir`,
+    /// unless it's a constant or another variable's value.
+    fn name_variable(&self, (name, span): &Spanned<String>, rhs: &Expr, value: FloatValue<'ctx>) {
+        if matches!(rhs, Expr::Var(..)) || value.as_instruction().is_none() {
+            return;
+        }
+        let Some(readable) = self.options.readable_names
+            .as_ref()
+            .and_then(|names| names.name(name, *span))
+        else {
+            return;
+        };
+        let old = value.get_name().to_string_lossy().into_owned();
+        value.set_name(&readable);
+        // the value was already recorded under its old name
+        let new = value.get_name().to_string_lossy().into_owned();
+        if let Some(values) = self.values.borrow_mut().get_mut(&rhs.span()) {
+            for value in values.iter_mut().filter(|value| value.name == old) {
+                value.name = new.clone();
+            }
+        }
+    }
+
+    /// Gets the LLVM type of the program's floats.
+    fn float_type(&self) -> FloatType<'ctx> {
+        self.options.float_width.float_type(self.context)
+    }
+
+    /// Sets the debug location of the instructions built from here on to the
+    /// start of `span`, if debug info is being generated.
+    fn set_location(&self, span: SimpleSpan) {
+        if let (Some(debug), Some(scope)) = (&self.debug, self.scope) {
+            debug.set_location(self.builder, scope, span);
+        }
+    }
+
+    /// Gets `printf` from libc, declaring it if it hasn't been already.
+    fn printf(&self) -> FunctionValue<'ctx> {
+        Runtime::Printf.get(self.context, self.module)
+    }
+
+    /// Calls `printf` to print `value` according to the generator's
+    /// [`PrintFormat`].
+    fn build_print(&self, value: FloatValue<'ctx>) -> Result<(), CompilerError> {
+        let printf = self.printf();
+        let format = self.builder.build_global_string_ptr(
+            &self.options.print_format.to_format_string(self.options.float_width),
+            "fmtstr"
+        )?;
+        // variadic arguments are promoted from `float` to `double`
+        let value = match self.options.float_width {
+            FloatWidth::F32 => self.builder.build_float_ext(
+                value,
+                self.context.f64_type(),
+                "exttmp"
+            )?,
+            _ => value,
+        };
+        self.builder.build_call(
+            printf,
+            &[
+                format.as_pointer_value().into(),
+                value.into()
+            ],
+            "calltmp"
+        )?;
         Ok(())
     }
 
@@ -183,46 +840,217 @@ impl<'a, 'ctx> LlvmGenerator<'a, 'ctx> {
         &self,
         expr: &Expr,
         vars: &HashMap<String, FloatValue<'ctx>>
-    ) -> Result<FloatValue<'ctx>, Box<dyn Error>> {
+    ) -> Result<FloatValue<'ctx>, CompilerError> {
+        let value = self.build_expr(expr, vars)?;
+        self.record_value(expr.span(), value);
+        Ok(value)
+    }
+
+    /// Builds the code for an expression, for [`visit_expr()`].
+    ///
+    /// [`visit_expr()`]:   Self::visit_expr()
+    fn build_expr(
+        &self,
+        expr: &Expr,
+        vars: &HashMap<String, FloatValue<'ctx>>
+    ) -> Result<FloatValue<'ctx>, CompilerError> {
+        self.count(expr.span())?;
+
         match expr {
-            Expr::Add(left, right, _) => {
+            Expr::Add(left, right, span) => {
+                if let Some(value) = self.visit_fmuladd(left, right, false, *span, vars)? {
+                    return Ok(value);
+                }
+                let left = self.visit_expr(left, vars)?;
+                let right = self.visit_expr(right, vars)?;
+                self.set_location(*span);
+
+                let value = self.builder.build_float_add(
+                    left,
+                    right,
+                    &self.value_name("add", *span, "addtmp")
+                )?;
+                self.apply_float_model(value);
+                self.check_value(value, *span, "`+`")?;
+                Ok(value)
+            }
+            Expr::Sub(left, right, span) => {
+                if let Some(value) = self.visit_fmuladd(left, right, true, *span, vars)? {
+                    return Ok(value);
+                }
                 let left = self.visit_expr(left, vars)?;
                 let right = self.visit_expr(right, vars)?;
+                self.set_location(*span);
 
-                Ok(self.builder.build_float_add(left, right, "addtmp")?)
+                let value = self.builder.build_float_sub(
+                    left,
+                    right,
+                    &self.value_name("sub", *span, "subtmp")
+                )?;
+                self.apply_float_model(value);
+                self.check_value(value, *span, "`-`")?;
+                Ok(value)
             }
-            Expr::Sub(left, right, _) => {
+            Expr::Mul(left, right, span) => {
                 let left = self.visit_expr(left, vars)?;
                 let right = self.visit_expr(right, vars)?;
+                self.set_location(*span);
 
-                Ok(self.builder.build_float_sub(left, right, "subtmp")?)
+                let value = self.builder.build_float_mul(
+                    left,
+                    right,
+                    &self.value_name("mul", *span, "multmp")
+                )?;
+                self.apply_float_model(value);
+                self.check_value(value, *span, "`*`")?;
+                Ok(value)
             }
-            Expr::Mul(left, right, _) => {
+            Expr::Div(left, right, span) => {
                 let left = self.visit_expr(left, vars)?;
                 let right = self.visit_expr(right, vars)?;
+                self.set_location(*span);
 
-                Ok(self.builder.build_float_mul(left, right, "multmp")?)
+                if let Some(checks) = &self.instrumentation.div_zero {
+                    checks.check(self.builder, self.module, right, *span)?;
+                }
+                let value = self.builder.build_float_div(
+                    left,
+                    right,
+                    &self.value_name("div", *span, "divtmp")
+                )?;
+                self.apply_float_model(value);
+                self.check_value(value, *span, "`/`")?;
+                Ok(value)
             }
-            Expr::Div(left, right, _) => {
+            Expr::Cmp(op, left, right, span) => {
                 let left = self.visit_expr(left, vars)?;
                 let right = self.visit_expr(right, vars)?;
+                self.set_location(*span);
 
-                Ok(self.builder.build_float_div(left, right, "divtmp")?)
+                // comparisons with NaN are unordered, and only `!=` holds
+                let predicate = match op {
+                    CmpOp::Lt => FloatPredicate::OLT,
+                    CmpOp::Gt => FloatPredicate::OGT,
+                    CmpOp::Le => FloatPredicate::OLE,
+                    CmpOp::Ge => FloatPredicate::OGE,
+                    CmpOp::Eq => FloatPredicate::OEQ,
+                    CmpOp::Ne => FloatPredicate::UNE,
+                };
+                let holds = self.builder.build_float_compare(
+                    predicate,
+                    left,
+                    right,
+                    &self.value_name("cmp", *span, "cmptmp")
+                )?;
+                // the `i1` is 1 or 0 as a float
+                Ok(self.builder.build_unsigned_int_to_float(
+                    holds,
+                    self.float_type(),
+                    &self.value_name("bool", *span, "booltmp")
+                )?)
             }
-            Expr::Num(val, _) => Ok(self.context.f64_type().const_float(*val)),
-            Expr::Var(name, _) => match vars.get(name) {
+            Expr::Num(val, _) => Ok(self.float_type().const_float(*val)),
+            Expr::Var(name, span) => match vars.get(name) {
                 Some (val) => Ok(val.to_owned()),
-                None => Err(format!("variable `{}` not found in scope", name).into()),
+                None => Err(
+                    CompilerError::at(
+                        *span,
+                        format!("variable `{}` not found in scope", name),
+                        "not declared before this"
+                    )
+                ),
             }
-            Expr::Neg(expr, _) => {
+            Expr::Neg(expr, span) => {
                 let expr = self.visit_expr(expr, vars)?;
-                Ok(self.builder.build_float_neg(expr, "negtmp")?)
+                self.set_location(*span);
+                let value = self.builder
+                    .build_float_neg(expr, &self.value_name("neg", *span, "negtmp"))?;
+                self.apply_float_model(value);
+                self.check_value(value, *span, "negation")?;
+                Ok(value)
+            }
+            Expr::Call((name, name_span), args, span) => {
+                self.visit_call(name, *name_span, args, *span, vars)
             }
-            Expr::Call((name, _), args, _) => self.visit_call(name, args, vars),
             _ => panic!()
         }
     }
 
+    /// Under the relaxed float model, builds `a * b + c`, `c + a * b`,
+    /// `a * b - c`, or `c - a * b` as a call to `llvm.fmuladd`, which LLVM may
+    /// turn into a fused multiply-add. Returns `None` if the addition or
+    /// subtraction isn't one of those, or can't be contracted.
+    fn visit_fmuladd(
+        &self,
+        left: &Expr,
+        right: &Expr,
+        subtract: bool,
+        span: SimpleSpan,
+        vars: &HashMap<String, FloatValue<'ctx>>
+    ) -> Result<Option<FloatValue<'ctx>>, CompilerError> {
+        if self.options.float_model != FloatModel::Relaxed {
+            return Ok(None);
+        }
+        let negate = |value| -> Result<_, CompilerError> {
+            let value = self.builder
+                .build_float_neg(value, &self.value_name("neg", span, "negtmp"))?;
+            self.apply_float_model(value);
+            Ok(value)
+        };
+        // operands are still visited in source order
+        let args = match (left, right) {
+            (Expr::Mul(a, b, mul_span), c) => {
+                self.count(*mul_span)?;
+                let a = self.visit_expr(a, vars)?;
+                let b = self.visit_expr(b, vars)?;
+                let c = self.visit_expr(c, vars)?;
+                [a, b, if subtract { negate(c)? } else { c }]
+            }
+            (c, Expr::Mul(a, b, mul_span)) => {
+                let c = self.visit_expr(c, vars)?;
+                self.count(*mul_span)?;
+                let a = self.visit_expr(a, vars)?;
+                let b = self.visit_expr(b, vars)?;
+                [if subtract { negate(a)? } else { a }, b, c]
+            }
+            _ => return Ok(None),
+        };
+        self.set_location(span);
+
+        let value = self.build_intrinsic(
+            "llvm.fmuladd",
+            &args,
+            &self.value_name("fma", span, "fmatmp")
+        )?;
+        self.apply_float_model(value);
+        self.check_value(value, span, if subtract { "`-`" } else { "`+`" })?;
+        Ok(Some(value))
+    }
+
+    /// Calls one of LLVM's floating point intrinsics, for the program's width
+    /// of float.
+    fn build_intrinsic(
+        &self,
+        name: &str,
+        args: &[FloatValue<'ctx>],
+        tmp: &str
+    ) -> Result<FloatValue<'ctx>, CompilerError> {
+        let Some(declaration) = Intrinsic::find(name)
+            .and_then(|i| i.get_declaration(self.module, &[self.float_type().into()]))
+        else {
+            return Err(format!("intrinsic `{}` not found", name).into());
+        };
+        let args = args.iter().map(|&arg| arg.into()).collect::<Vec<_>>();
+        match self.builder
+            .build_call(declaration, &args, tmp)?
+            .try_as_basic_value()
+            .left()
+        {
+            Some(val) => Ok(val.into_float_value()),
+            None => Err(format!("failed to build call to `{}`", name).into()),
+        }
+    }
+
     /// Helper function for [`visit_expr()`]. Checks that a function call is
     /// valid and, if so, grabs the return value from the call.
     /// 
@@ -230,43 +1058,495 @@ impl<'a, 'ctx> LlvmGenerator<'a, 'ctx> {
     fn visit_call(
         &self,
         name: &String,
+        name_span: SimpleSpan,
         args: &Vec<Expr>,
+        span: SimpleSpan,
         vars: &HashMap<String, FloatValue<'ctx>>
-    ) -> Result<FloatValue<'ctx>, Box<dyn Error>> {
-        match self.module.get_function(name) {
-            None => Err(format!("function `{}` not found in scope", name).into()),
+    ) -> Result<FloatValue<'ctx>, CompilerError> {
+        match self.functions.get(name) {
+            // builtins can be shadowed by functions of the same name
+            None => {
+                let Some(builtin) = prelude::builtin(name) else {
+                    return Err(
+                        CompilerError::at(
+                            name_span,
+                            format!("function `{}` not found in scope", name),
+                            "not declared before this"
+                        )
+                    );
+                };
+                if args.len() != builtin.params.len() {
+                    return Err(
+                        CompilerError::at(
+                            span,
+                            format!(
+                                "`{}` takes {} argument{}",
+                                name,
+                                builtin.params.len(),
+                                if builtin.params.len() == 1 { "" } else { "s" }
+                            ),
+                            format!("given {}", args.len())
+                        )
+                    );
+                }
+                let mut argsv = vec![];
+                for arg in args {
+                    argsv.push(self.visit_expr(arg, vars)?);
+                }
+                self.set_location(span);
+                let value = self.build_intrinsic(
+                    builtin.intrinsic,
+                    &argsv,
+                    &self.value_name(name, span, "calltmp")
+                )?;
+                self.check_value(value, span, &format!("call to `{}`", name))?;
+                Ok(value)
+            }
             Some(r#fn) => {
-                if args.len() != r#fn.get_params().len() {
-                    return Err("arguments to function call are incorrect".into());
+                let arity = self.arg_arrays
+                    .get(name)
+                    .copied()
+                    .unwrap_or(r#fn.count_params() as usize);
+                if args.len() != arity {
+                    return Err(
+                        CompilerError::at(
+                            span,
+                            format!(
+                                "`{}` takes {} argument{}",
+                                name,
+                                arity,
+                                if arity == 1 { "" } else { "s" }
+                            ),
+                            format!("given {}", args.len())
+                        )
+                    );
                 }
                 let mut argsv = vec![];
                 for arg in args {
                     argsv.push(self.visit_expr(arg, vars)?.into());
                 }
+                self.set_location(span);
+                if self.arg_arrays.contains_key(name) {
+                    argsv = vec![self.build_arg_array(&argsv)?.into()];
+                }
                 match self.builder
-                    .build_call(r#fn, &argsv, "calltmp")?
+                    .build_call(*r#fn, &argsv, &self.value_name(name, span, "calltmp"))?
                     .try_as_basic_value()
                     .left()
                 {
-                    Some(val) => Ok(val.into_float_value()),
+                    Some(val) => {
+                        let val = val.into_float_value();
+                        self.check_value(val, span, &format!("call to `{}`", name))?;
+                        Ok(val)
+                    }
                     None => Err("failed to build function call".into()),
                 }
             }
         }
     }
 
+    /// Stores the arguments to a function taking them in an array in a new
+    /// array on the stack of the current function, returning a pointer to it.
+    /// The array is allocated in the function's entry block, so that it's
+    /// allocated once however often the call runs.
+    fn build_arg_array(
+        &self,
+        args: &[BasicMetadataValueEnum<'ctx>]
+    ) -> Result<PointerValue<'ctx>, CompilerError> {
+        let Some(function) = self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+        else {
+            return Err("failed to build function call".into());
+        };
+        let entry = function.get_first_basic_block().unwrap();
+        let entry_builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => entry_builder.position_before(&first),
+            None => entry_builder.position_at_end(entry),
+        }
+        let array = entry_builder.build_array_alloca(
+            self.float_type(),
+            self.context.i32_type().const_int(args.len() as u64, false),
+            "argstmp"
+        )?;
+
+        for (i, arg) in args.iter().enumerate() {
+            let ptr = self.arg_ptr(array, i)?;
+            self.builder.build_store(ptr, arg.into_float_value())?;
+        }
+        Ok(array)
+    }
+
+    /// Builds a pointer to the `i`th element of an array of arguments.
+    fn arg_ptr(
+        &self,
+        array: PointerValue<'ctx>,
+        i: usize
+    ) -> Result<PointerValue<'ctx>, CompilerError> {
+        let index = self.context.i64_type().const_int(i as u64, false);
+        // in bounds, since the array has an element for each parameter
+        let ptr = unsafe {
+            self.builder.build_in_bounds_gep(self.float_type(), array, &[index], "argptr")?
+        };
+        Ok(ptr)
+    }
+
+    /// Marks the array a function takes its arguments in as only read, and not
+    /// kept past the call, so calls to it can be optimized as if the arguments
+    /// were passed directly.
+    fn add_arg_array_attributes(&self, r#fn: FunctionValue<'ctx>) {
+        for kind in ["nocapture", "readonly", "noundef"] {
+            let attribute = self.context.create_enum_attribute(
+                Attribute::get_named_enum_kind_id(kind),
+                0
+            );
+            r#fn.add_attribute(AttributeLoc::Param(0), attribute);
+        }
+    }
+
+    /// Marks a floating point operation as free to be reassociated, contracted,
+    /// etc. under the relaxed float model. Operations the builder folded into
+    /// constants were already folded by IEEE rules, and are left alone.
+    fn apply_float_model(&self, value: FloatValue<'ctx>) {
+        if self.options.float_model == FloatModel::Relaxed {
+            if let Some(inst) = value.as_instruction() {
+                inst.set_fast_math_flags(RELAXED_FAST_MATH);
+            }
+        }
+    }
+
+    /// Records the value generated for the expression at `span`, if it has a
+    /// name; constants the builder folded don't.
+    fn record_value(&self, span: SimpleSpan, value: FloatValue<'ctx>) {
+        let name = value.get_name().to_string_lossy();
+        let function = self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent());
+        if let (false, Some(function)) = (name.is_empty(), function) {
+            self.values.borrow_mut().entry(span).or_default().push(IrValue {
+                function: function.get_name().to_string_lossy().into_owned(),
+                name: name.into_owned(),
+            });
+        }
+    }
+
+    /// Builds an increment of the coverage counter for the region at `span`, if
+    /// the code is being instrumented with them.
+    fn count(&self, span: SimpleSpan) -> Result<(), CompilerError> {
+        if let Some(coverage) = &self.instrumentation.coverage {
+            coverage.count(self.builder, span)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the NaN/infinity check for a computed value, if the code is being
+    /// instrumented with them.
+    fn check_value(
+        &self,
+        value: FloatValue<'ctx>,
+        span: SimpleSpan,
+        op: &str
+    ) -> Result<(), CompilerError> {
+        if let Some(nan) = &self.instrumentation.nan {
+            nan.check(self.builder, value, span, op)?;
+        }
+        Ok(())
+    }
+
     /// This is the function called externally to input the AST [`Expr`] along
     /// with the LLVM `Context`, `Module`, and `Builder` and generate the IR.
     /// 
-    /// If there are no errors, this function doesn't return anything useful.
-    /// Instead, the module can be used to do further actions with the IR.
+    /// An error points to the part of the source it's about where there is
+    /// one, to be queued with [`Diagnostics::semantic_error()`].
+    ///
+    /// If there are no errors, the module can be used to do further actions
+    /// with the IR, once it's been checked with [`verify_functions()`], which
+    /// can only be done after the debug info is finalized here. The spans of
+    /// the symbols defined in it are returned for diagnostics which come up
+    /// later, e.g. while verifying or linking, along with the symbols exported.
+    ///
+    /// [`verify_functions()`]: super::verify_functions()
+    /// [`Diagnostics::semantic_error()`]: crate::error::Diagnostics::semantic_error()
     pub fn generate(
         ast: &Expr,
         context: &'ctx Context,
         module: &'a Module<'ctx>,
-        builder: &'a Builder<'ctx>
-    ) -> Result<(), Box<dyn Error>> {
-        let generator = LlvmGenerator::new(context, module, builder);
-        generator.run(ast)
+        builder: &'a Builder<'ctx>,
+        options: CodegenOptions,
+        debug: Option<DebugInfo<'ctx>>,
+        instrumentation: Instrumentation<'ctx>,
+    ) -> Result<CodegenOutput, CompilerError> {
+        let mut generator = LlvmGenerator::new(
+            context,
+            module,
+            builder,
+            options,
+            debug,
+            instrumentation
+        );
+        generator.run(ast)?;
+        Ok(CodegenOutput {
+            symbols: generator.symbols,
+            values: generator.values.into_inner(),
+            exports: generator.exports,
+            decl_times: generator.decl_times,
+        })
+    }
+
+    /// Creates a generator for a program entered a piece at a time, as in the
+    /// REPL, rather than generated from a whole AST by [`generate()`]. Each
+    /// declaration is added to the module with [`add_decl()`] as it comes, and
+    /// each expression with [`add_expr_fn()`]; there's no entry function or
+    /// debug info.
+    ///
+    /// [`generate()`]:     Self::generate()
+    /// [`add_decl()`]:     Self::add_decl()
+    /// [`add_expr_fn()`]:  Self::add_expr_fn()
+    pub fn incremental(
+        context: &'ctx Context,
+        module: &'a Module<'ctx>,
+        builder: &'a Builder<'ctx>,
+        options: CodegenOptions,
+        instrumentation: Instrumentation<'ctx>,
+    ) -> LlvmGenerator<'a, 'ctx> {
+        LlvmGenerator::new(context, module, builder, options, None, instrumentation)
+    }
+
+    /// Adds the function declared by `decl`, a `fn` or `extern` declaration,
+    /// to the module, leaving out the declarations after it. If it can't be
+    /// generated, nothing of it is left behind, so it can be declared again.
+    pub fn add_decl(&mut self, decl: &Expr) -> Result<FunctionValue<'ctx>, CompilerError> {
+        let (Expr::Fn { name: (name, _), .. } | Expr::Extern { name: (name, _), .. }) = decl
+        else {
+            unreachable!("only `fn` and `extern` declarations declare functions");
+        };
+        let existed = self.functions.contains_key(name);
+        let added = match decl {
+            Expr::Fn { .. } => self.visit_fn(decl),
+            _ => self.visit_extern(decl),
+        };
+        let err = match added {
+            Ok(r#fn) if r#fn.verify(false) => return Ok(r#fn),
+            Ok(r#fn) => format!(
+                "function `{}` was generated incorrectly; this is a bug in the compiler",
+                mangle::display(&r#fn.get_name().to_string_lossy())
+            ).into(),
+            Err(e) => e,
+        };
+        // the function is added before its body is generated, so it can call
+        // itself
+        if !existed {
+            if let Some(r#fn) = self.functions.remove(name) {
+                let symbol = r#fn.get_name().to_string_lossy().into_owned();
+                self.symbols.remove(&symbol);
+                self.exports.retain(|export| *export != symbol);
+                self.arg_arrays.remove(name);
+                unsafe { r#fn.delete() };
+            }
+        }
+        Err(err)
+    }
+
+    /// Adds a function `symbol` to the module which takes `params` and
+    /// returns the value of `expr`, where `vars` are the values of the other
+    /// variables it can use. This is how both the REPL and
+    /// [`compile_expr_to_fn()`] generate expressions as functions. If it can't
+    /// be generated, it's left out.
+    ///
+    /// Each parameter is a `double` of its own, as in a `fn` with up to
+    /// [`MAX_DIRECT_PARAMS`] parameters; the array a `fn` with more takes its
+    /// arguments in isn't offered, so more than that are refused.
+    ///
+    /// [`compile_expr_to_fn()`]: super::compile_expr_to_fn()
+    pub fn add_expr_fn(
+        &mut self,
+        symbol: &str,
+        params: &[&str],
+        expr: &Expr,
+        vars: &HashMap<String, f64>
+    ) -> Result<FunctionValue<'ctx>, CompilerError> {
+        if params.len() > MAX_DIRECT_PARAMS {
+            return Err(format!(
+                "an expression can be compiled with at most {} parameters, not {}",
+                MAX_DIRECT_PARAMS,
+                params.len()
+            ).into());
+        }
+        let param_types = vec![self.float_type().into(); params.len()];
+        let r#fn = self.module.add_function(
+            symbol,
+            self.float_type().fn_type(&param_types, false),
+            None
+        );
+        let block = self.context.append_basic_block(r#fn, "entry");
+        self.builder.position_at_end(block);
+        self.scope = None;
+
+        let mut vars = vars.iter()
+            .map(|(name, value)| (name.to_owned(), self.float_type().const_float(*value)))
+            .collect::<HashMap<_, _>>();
+        for (param, value) in params.iter().zip(r#fn.get_param_iter()) {
+            value.set_name(param);
+            vars.insert(param.to_string(), value.into_float_value());
+        }
+        let built = self.visit_expr(expr, &vars)
+            .and_then(|value| Ok(self.builder.build_return(Some(&value))?));
+        match built {
+            Ok(_) if r#fn.verify(false) => Ok(r#fn),
+            result => {
+                unsafe { r#fn.delete() };
+                Err(result.err().unwrap_or_else(|| {
+                    CompilerError::at(
+                        expr.span(),
+                        "the expression was generated incorrectly; this is a bug in the \
+                        compiler",
+                        "LLVM's verifier rejects the code generated for this"
+                    )
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chumsky::Parser;
+
+    use super::*;
+    use crate::{parse::parser, LangVersion};
+
+    /// Generates a program under a float model, returning its IR.
+    fn generate_ir(src: &str, float_model: FloatModel) -> String {
+        let ast = parser(LangVersion::LATEST.features())
+            .parse(src)
+            .into_result()
+            .unwrap();
+        let context = Context::create();
+        let module = context.create_module("test");
+        let builder = context.create_builder();
+        let options = CodegenOptions {
+            print_format: PrintFormat {
+                notation: Notation::Fixed,
+                precision: None,
+                newline: true,
+            },
+            entry: None,
+            entry_symbol: Some("main".to_owned()),
+            float_model,
+            float_width: FloatWidth::F64,
+            stack_probes: false,
+            stack_probe_size: None,
+            exports: vec![],
+            profile: None,
+            readable_names: None,
+        };
+        let instrumentation = Instrumentation::default();
+        LlvmGenerator::generate(&ast, &context, &module, &builder, options, None, instrumentation)
+            .unwrap();
+        module.print_to_string().to_string()
+    }
+
+    /// Gets the lines of IR with an instruction or call to `op` in them.
+    fn lines_with<'ir>(ir: &'ir str, op: &str) -> Vec<&'ir str> {
+        ir.lines().filter(|line| line.contains(op) && line.contains(" = ")).collect()
+    }
+
+    const MUL_ADD: &str = "fn f a b c = a * b + c;\nf(2, 3, 4)";
+
+    #[test]
+    fn strict_keeps_mul_and_add_separate() {
+        let ir = generate_ir(MUL_ADD, FloatModel::Strict);
+        assert!(!ir.contains("llvm.fmuladd"));
+        assert_eq!(lines_with(&ir, "fmul double").len(), 1);
+        assert_eq!(lines_with(&ir, "fadd double").len(), 1);
+    }
+
+    #[test]
+    fn relaxed_contracts_mul_and_add() {
+        let ir = generate_ir(MUL_ADD, FloatModel::Relaxed);
+        assert_eq!(lines_with(&ir, "@llvm.fmuladd.f64(").len(), 1);
+        assert!(lines_with(&ir, "fmul double").is_empty());
+        assert!(lines_with(&ir, "fadd double").is_empty());
+    }
+
+    #[test]
+    fn relaxed_contracts_subtraction_by_negating() {
+        let ir = generate_ir("fn f a b c = c - a * b;\nf(2, 3, 4)", FloatModel::Relaxed);
+        assert_eq!(lines_with(&ir, "fneg").len(), 1);
+        assert_eq!(lines_with(&ir, "@llvm.fmuladd.f64(").len(), 1);
+    }
+
+    #[test]
+    fn strict_sets_no_fast_math_flags() {
+        let ir = generate_ir("fn f a b = a / b;\nf(1, 2)", FloatModel::Strict);
+        assert_eq!(lines_with(&ir, "fdiv double").len(), 1);
+    }
+
+    #[test]
+    fn relaxed_sets_fast_math_flags() {
+        let ir = generate_ir("fn f a b = a / b;\nf(1, 2)", FloatModel::Relaxed);
+        assert_eq!(lines_with(&ir, "fdiv reassoc nsz arcp contract double").len(), 1);
     }
-}
\ No newline at end of file
+
+    /// Parses an expression and writes it back with every operation in
+    /// parentheses, to show how it was grouped.
+    fn grouping(src: &str) -> String {
+        fn write(expr: &Expr) -> String {
+            let binary = |op: &str, left: &Expr, right: &Expr| {
+                format!("({} {} {})", write(left), op, write(right))
+            };
+            match expr {
+                Expr::Num(n, _) => n.to_string(),
+                Expr::Var(name, _) => name.clone(),
+                Expr::Neg(operand, _) => format!("-{}", write(operand)),
+                Expr::Add(left, right, _) => binary("+", left, right),
+                Expr::Sub(left, right, _) => binary("-", left, right),
+                Expr::Mul(left, right, _) => binary("*", left, right),
+                Expr::Div(left, right, _) => binary("/", left, right),
+                Expr::Cmp(op, left, right, _) => binary(op.symbol(), left, right),
+                _ => panic!("only operators are written"),
+            }
+        }
+        write(&parser(LangVersion::LATEST.features()).parse(src).into_result().unwrap())
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_sums() {
+        assert_eq!(grouping("a + 1 < b - 2"), "((a + 1) < (b - 2))");
+        assert_eq!(grouping("a * 2 == b / 2"), "((a * 2) == (b / 2))");
+        assert_eq!(grouping("-a != b"), "(-a != b)");
+    }
+
+    #[test]
+    fn comparisons_associate_left() {
+        assert_eq!(grouping("1 < 2 < 3"), "((1 < 2) < 3)");
+        assert_eq!(grouping("a == b != c"), "((a == b) != c)");
+    }
+
+    #[test]
+    fn two_character_comparisons_are_tried_first() {
+        assert_eq!(grouping("a <= b"), "(a <= b)");
+        assert_eq!(grouping("a >= b"), "(a >= b)");
+        assert_eq!(grouping("a <= b >= c"), "((a <= b) >= c)");
+        // `<` then `=` isn't `<=`
+        assert!(parser(LangVersion::LATEST.features()).parse("a < = b").into_result().is_err());
+    }
+
+    #[test]
+    fn only_not_equal_holds_for_nan() {
+        let ir = generate_ir(
+            "fn lt a b = a < b;\nfn gt a b = a > b;\nfn le a b = a <= b;\n\
+            fn ge a b = a >= b;\nfn eq a b = a == b;\nfn ne a b = a != b;\n0",
+            FloatModel::Strict
+        );
+        for predicate in ["olt", "ogt", "ole", "oge", "oeq", "une"] {
+            assert_eq!(lines_with(&ir, &format!("fcmp {} double", predicate)).len(), 1);
+        }
+        for op in [CmpOp::Lt, CmpOp::Gt, CmpOp::Le, CmpOp::Ge, CmpOp::Eq] {
+            assert!(!op.holds(f64::NAN, 1.0) && !op.holds(f64::NAN, f64::NAN), "{:?}", op);
+        }
+        assert!(CmpOp::Ne.holds(f64::NAN, 1.0) && CmpOp::Ne.holds(f64::NAN, f64::NAN));
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::support;
+use inkwell::OptimizationLevel;
+
+use crate::mangle;
+
+/// Compiles a module in memory with LLVM's JIT and runs its `entry_symbol`
+/// function as the program's `main`, in this process, returning the status it
+/// exits with. Nothing is written and nothing is linked, so it's quicker than
+/// building an executable to run, and doesn't need a linker.
+///
+/// The functions the module declares are looked up in this process, which has
+/// the C library, so the builtins and the runtime can be called, but not
+/// `extern` functions defined in C files. A module that declares one this
+/// process doesn't have is rejected before anything runs, since LLVM would
+/// abort the compiler if the program called it.
+pub fn run_jit(
+    module: &Module,
+    entry_symbol: &str,
+    level: OptimizationLevel
+) -> Result<i32, Box<dyn Error>> {
+    let (jit_module, engine) = jit(module, level)?;
+    let Some(main) = jit_module.get_function(entry_symbol) else {
+        return Err(format!("the program has no `{}` function to run", entry_symbol).into());
+    };
+    let status = unsafe { engine.run_function_as_main(main, &[]) };
+    flush_stdio();
+    Ok(status)
+}
+
+/// Compiles a module in memory like [`run_jit()`], and calls its function
+/// `symbol`, which takes no arguments and returns a `double`, for the REPL.
+pub fn call_jit(module: &Module, symbol: &str) -> Result<f64, Box<dyn Error>> {
+    let (_jit_module, engine) = jit(module, OptimizationLevel::Default)?;
+    let value = unsafe {
+        let r#fn = engine.get_function::<unsafe extern "C" fn() -> f64>(symbol)
+            .map_err(|e| format!("failed to find `{}` in the JIT: {}", symbol, e))?;
+        r#fn.call()
+    };
+    flush_stdio();
+    Ok(value)
+}
+
+/// Whether the JIT can find `symbol` in this process, for an `extern` function
+/// to call.
+pub fn jit_can_find(symbol: &str) -> bool {
+    support::load_visible_symbols();
+    support::search_for_address_of_symbol(symbol).is_some()
+}
+
+/// Creates a JIT generating code at `level` for a copy of a module, after
+/// checking that it can find every function the module declares. The engine
+/// takes the module it runs, and frees it along with itself, so it's given the
+/// copy, which is returned with it.
+fn jit<'ctx>(
+    module: &Module<'ctx>,
+    level: OptimizationLevel
+) -> Result<(Module<'ctx>, ExecutionEngine<'ctx>), Box<dyn Error>> {
+    let missing = module.get_functions()
+        .filter(|f| f.count_basic_blocks() == 0)
+        .map(|f| f.get_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with("llvm.") && !jit_can_find(name))
+        .map(|name| format!("`{}`", mangle::display(&name)))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(
+            format!(
+                "the JIT can't find {}, which the program calls; build an executable to link \
+                the files defining them",
+                missing.join(", ")
+            ).into()
+        );
+    }
+
+    let jit_module = module.clone();
+    let engine = jit_module
+        .create_jit_execution_engine(level)
+        .map_err(|e| format!("failed to create the JIT: {}", e.to_string_lossy()))?;
+    Ok((jit_module, engine))
+}
+
+/// Flushes C's `stdio`, which the program printed with, and which is otherwise
+/// only flushed at exit.
+fn flush_stdio() {
+    unsafe {
+        libc::fflush(std::ptr::null_mut());
+    }
+}
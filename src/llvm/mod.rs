@@ -1,26 +1,173 @@
+use std::collections::HashSet;
 use std::error::Error;
-use std::fs::File;
-use std::io::Write;
+use std::path::Path;
 
-use inkwell::module::Module;
+use inkwell::context::Context;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{Linkage, Module};
 
+use crate::artifact::Artifact;
+
+mod analysis;
+mod buildinfo;
+mod debug;
+mod expr_fn;
+mod extract;
+mod instrument;
 mod ir;
+mod jit;
+mod passes;
+mod peephole;
+mod remarks;
+mod runtime;
+mod size;
 mod target;
+mod verify;
 
+pub use analysis::analysis_dot_path;
+pub use analysis::write_analysis_dot;
+pub use buildinfo::add_build_info;
+pub use buildinfo::place_build_info;
+pub use buildinfo::read_build_info;
+pub use debug::asm_locations;
+pub use debug::write_annotated_assembly;
+pub use debug::AsmAnnotations;
+pub use debug::DebugInfo;
+pub use expr_fn::compile_expr_to_fn;
+pub use extract::extract_function;
+pub use instrument::Instrumentation;
+pub use ir::CodegenOptions;
+pub use ir::CodegenOutput;
+pub use ir::IrValue;
 pub use ir::LlvmGenerator;
+pub use ir::PrintFormat;
+pub use ir::ReadableNames;
+pub use jit::call_jit;
+pub use jit::jit_can_find;
+pub use jit::run_jit;
+pub use passes::codegen_level;
+pub use passes::run_passes;
+pub use remarks::RemarkCollector;
+pub use size::function_sizes;
+pub use size::FunctionSize;
 pub use target::init_target;
 pub use target::machine_from_target;
+pub use target::set_module_target;
+pub use target::target_triple;
+pub use target::test_target_triple;
 pub use target::write_code_to_file;
+pub use verify::verify_functions;
 
 /// Prints an LLVM module's contents to stderr.
 pub fn print_module(module: &Module) {
     module.print_to_stderr();
 }
 
-pub fn write_module_to_file(
-    module: &Module,
-    file: &mut File
-) -> Result<(), Box<dyn Error>> {
-    let module = module.to_string();
-    Ok(file.write_all(module.as_bytes())?)
-}
\ No newline at end of file
+/// Writes an LLVM module's IR to a file, which is only put in place once it's
+/// complete.
+///
+/// LLVM prints the module straight into the file, a function at a time
+/// through a buffered stream, rather than into one string holding the whole
+/// IR, so writing the IR of a program with tens of thousands of functions
+/// doesn't take as much memory again as the module itself.
+pub fn write_module_to_file(module: &Module, path: &Path) -> Result<(), Box<dyn Error>> {
+    let artifact = Artifact::new(path)?;
+    module.print_to_file(artifact.path())
+        .map_err(|e| format!("failed to write `{}`: {}", path.display(), e.to_string_lossy()))?;
+    artifact.commit()
+}
+
+/// Writes an LLVM module's bitcode to a file, which is only put in place once
+/// it's complete.
+pub fn write_bitcode_to_file(module: &Module, path: &Path) -> Result<(), Box<dyn Error>> {
+    let artifact = Artifact::new(path)?;
+    if !module.write_bitcode_to_path(artifact.path()) {
+        return Err(format!("failed to write bitcode to `{}`", path.display()).into());
+    }
+    artifact.commit()
+}
+
+/// Loads an existing LLVM module from a file, so it can be linked with the
+/// module generated from Foo code.
+///
+/// Files ending in `.bc` are read as bitcode; anything else is parsed as
+/// textual IR.
+pub fn load_module<'ctx>(
+    context: &'ctx Context,
+    path: &Path
+) -> Result<Module<'ctx>, Box<dyn Error>> {
+    if path.extension().is_some_and(|ext| ext == "bc") {
+        Ok(Module::parse_bitcode_from_path(path, context)?)
+    } else {
+        let buffer = MemoryBuffer::create_from_file(path)?;
+        Ok(context.create_module_from_ir(buffer)?)
+    }
+}
+
+/// A symbol defined both in the module being linked into and in one of the
+/// modules being linked.
+pub struct DuplicateSymbol {
+    pub symbol: String,
+    /// The name of the module being linked in that also defines the symbol.
+    pub module: String,
+}
+
+pub enum LinkError {
+    /// Symbols defined more than once. Every duplicate found across all of the
+    /// modules is listed, rather than only the first.
+    Duplicates(Vec<DuplicateSymbol>),
+    /// Any other error LLVM reported while linking.
+    Llvm(String),
+}
+
+/// Merges `others` into `module` one after another, in the style of
+/// `llvm-link`.
+///
+/// Each module's definitions are checked against what's already in `module`
+/// before linking it, so duplicate symbols can be reported individually instead
+/// of as LLVM's single error string.
+pub fn link_modules<'ctx>(
+    module: &Module<'ctx>,
+    others: Vec<Module<'ctx>>
+) -> Result<(), LinkError> {
+    let mut duplicates = vec![];
+
+    for other in others {
+        let defined = defined_symbols(module);
+        let mut other_dups = defined_symbols(&other)
+            .into_iter()
+            .filter(|symbol| defined.contains(symbol))
+            .map(|symbol| DuplicateSymbol {
+                symbol,
+                module: other.get_name().to_string_lossy().into_owned(),
+            })
+            .collect::<Vec<_>>();
+
+        if other_dups.is_empty() {
+            let name = other.get_name().to_string_lossy().into_owned();
+            module.link_in_module(other).map_err(|e| LinkError::Llvm(
+                format!("failed to link `{}`: {}", name, e.to_string_lossy())
+            ))?;
+        } else {
+            other_dups.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+            duplicates.append(&mut other_dups);
+        }
+    }
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(LinkError::Duplicates(duplicates))
+    }
+}
+
+/// Gets the names of the externally visible functions and globals a module
+/// defines (as opposed to only declares).
+fn defined_symbols(module: &Module) -> HashSet<String> {
+    module.get_functions()
+        .map(|f| f.as_global_value())
+        .chain(module.get_globals())
+        .filter(|g| !g.is_declaration() && g.get_linkage() == Linkage::External)
+        .map(|g| g.get_name().to_string_lossy().into_owned())
+        .collect()
+}
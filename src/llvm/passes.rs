@@ -0,0 +1,95 @@
+use std::error::Error;
+
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use inkwell::OptimizationLevel;
+
+use super::peephole::{peephole, PEEPHOLE};
+use crate::OptLevel;
+
+impl OptLevel {
+    /// Gets the pass pipeline for the level: the peephole pass, then LLVM's
+    /// default pipeline for the level, e.g. `foo-peephole,default<O2>`.
+    pub fn pipeline(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "foo-peephole,default<O0>",
+            OptLevel::O1 => "foo-peephole,default<O1>",
+            OptLevel::O2 => "foo-peephole,default<O2>",
+            OptLevel::O3 => "foo-peephole,default<O3>",
+            OptLevel::Os => "foo-peephole,default<Os>",
+            OptLevel::Oz => "foo-peephole,default<Oz>",
+        }
+    }
+}
+
+/// Gets the level the code generator optimizes at for a `-O` level, if one
+/// was given. LLVM's code generator has no size levels, so those get its
+/// default, as does leaving `-O` out.
+pub fn codegen_level(opt_level: Option<OptLevel>) -> OptimizationLevel {
+    match opt_level {
+        Some(OptLevel::O0) => OptimizationLevel::None,
+        Some(OptLevel::O1) => OptimizationLevel::Less,
+        Some(OptLevel::O2 | OptLevel::Os | OptLevel::Oz) | None => OptimizationLevel::Default,
+        Some(OptLevel::O3) => OptimizationLevel::Aggressive,
+    }
+}
+
+/// Runs a pass pipeline over a module. `passes` is written the way `opt`'s
+/// `-passes` option takes it, e.g. `default<O2>` or `instcombine,gvn`, and can
+/// also name the compiler's own `foo-peephole` pass at its top level. The
+/// passes between each run of it are handed to LLVM together.
+pub fn run_passes(
+    module: &Module,
+    machine: &TargetMachine,
+    passes: &str
+) -> Result<(), Box<dyn Error>> {
+    let mut llvm_passes = vec![];
+    for pass in split_pipeline(passes) {
+        if pass.trim() == PEEPHOLE {
+            run_llvm_passes(module, machine, &llvm_passes.join(","))?;
+            llvm_passes.clear();
+            peephole(module);
+        } else {
+            llvm_passes.push(pass);
+        }
+    }
+    run_llvm_passes(module, machine, &llvm_passes.join(","))
+}
+
+fn run_llvm_passes(
+    module: &Module,
+    machine: &TargetMachine,
+    passes: &str
+) -> Result<(), Box<dyn Error>> {
+    if passes.is_empty() {
+        return Ok(());
+    }
+    module
+        .run_passes(passes, machine, PassBuilderOptions::create())
+        .map_err(|e| {
+            format!("failed to run passes `{}`: {}", passes, e.to_string_lossy())
+                .into()
+        })
+}
+
+/// Splits a pipeline at the commas between its top-level passes, leaving the
+/// ones nested in e.g. `function(instcombine,gvn)` alone.
+fn split_pipeline(passes: &str) -> Vec<&str> {
+    let mut split = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in passes.char_indices() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(&passes[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    split.push(&passes[start..]);
+    split
+}
@@ -0,0 +1,103 @@
+use inkwell::module::Module;
+use inkwell::values::{AnyValue, BasicValueEnum, FloatValue, InstructionOpcode, InstructionValue};
+
+/// The name the peephole pass goes by in a pass pipeline.
+pub const PEEPHOLE: &str = "foo-peephole";
+
+/// Removes the arithmetic that doesn't change its operand, which the code
+/// generator leaves in as it's written: `x * 1`, `1 * x`, `x / 1`, `x - 0`,
+/// and `-(-x)`. `x + 0` and `0 + x` are only removed when the addition may
+/// ignore the sign of zero, since `-0 + 0` is `+0`. Each of these gives exactly
+/// the value it replaces under IEEE 754, so it's safe at any level.
+pub fn peephole(module: &Module) {
+    for function in module.get_functions() {
+        for block in function.get_basic_blocks() {
+            let mut next = block.get_first_instruction();
+            while let Some(inst) = next {
+                next = inst.get_next_instruction();
+                let Some(operand) = identity_operand(inst) else {
+                    continue;
+                };
+                // the inner negation of `-(-x)` goes too, unless something
+                // else uses it
+                let inner = match inst.get_opcode() {
+                    InstructionOpcode::FNeg => operand_instruction(inst),
+                    _ => None,
+                };
+                inst.as_any_value_enum()
+                    .into_float_value()
+                    .replace_all_uses_with(operand);
+                inst.erase_from_basic_block();
+                if let Some(inner) = inner.filter(|inner| inner.get_first_use().is_none()) {
+                    inner.erase_from_basic_block();
+                }
+            }
+        }
+    }
+}
+
+/// Gets the operand an instruction is the same as, if it is.
+fn identity_operand(inst: InstructionValue) -> Option<FloatValue> {
+    let operand = |i| match inst.get_operand(i)?.left()? {
+        BasicValueEnum::FloatValue(value) => Some(value),
+        _ => None,
+    };
+    // whether an operand is a constant equal to `value`, including its sign
+    let is = |value: FloatValue, constant: f64| value.get_constant().is_some_and(|(c, _)| {
+        c == constant && c.is_sign_negative() == constant.is_sign_negative()
+    });
+
+    match inst.get_opcode() {
+        InstructionOpcode::FMul => {
+            let (left, right) = (operand(0)?, operand(1)?);
+            if is(right, 1.0) {
+                Some(left)
+            } else if is(left, 1.0) {
+                Some(right)
+            } else {
+                None
+            }
+        }
+        InstructionOpcode::FDiv => {
+            let left = operand(0)?;
+            is(operand(1)?, 1.0).then_some(left)
+        }
+        InstructionOpcode::FSub => {
+            let left = operand(0)?;
+            is(operand(1)?, 0.0).then_some(left)
+        }
+        InstructionOpcode::FAdd => {
+            let (left, right) = (operand(0)?, operand(1)?);
+            let no_signed_zeros = inst.get_fast_math_flags()
+                .is_some_and(|flags| flags & inkwell::llvm_sys::LLVMFastMathNoSignedZeros != 0);
+            // `x + -0` is `x` even for `x = -0`
+            let zero = |value| is(value, -0.0) || (no_signed_zeros && is(value, 0.0));
+            if zero(right) {
+                Some(left)
+            } else if zero(left) {
+                Some(right)
+            } else {
+                None
+            }
+        }
+        InstructionOpcode::FNeg => {
+            let inner = operand_instruction(inst)?;
+            if inner.get_opcode() != InstructionOpcode::FNeg {
+                return None;
+            }
+            match inner.get_operand(0)?.left()? {
+                BasicValueEnum::FloatValue(value) => Some(value),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Gets the instruction producing an instruction's first operand, if it is one.
+fn operand_instruction(inst: InstructionValue) -> Option<InstructionValue> {
+    match inst.get_operand(0)?.left()? {
+        BasicValueEnum::FloatValue(value) => value.as_instruction(),
+        _ => None,
+    }
+}
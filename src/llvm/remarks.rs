@@ -0,0 +1,199 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_void, CStr, CString};
+use std::path::Path;
+
+use inkwell::context::Context;
+use inkwell::llvm_sys::core::{
+    LLVMContextSetDiagnosticHandler, LLVMDisposeMessage,
+    LLVMGetDiagInfoDescription, LLVMGetDiagInfoSeverity
+};
+use inkwell::llvm_sys::prelude::LLVMDiagnosticInfoRef;
+use inkwell::llvm_sys::support::LLVMParseCommandLineOptions;
+use inkwell::llvm_sys::LLVMDiagnosticSeverity;
+use serde::Serialize;
+
+use crate::artifact::Artifact;
+use crate::lines::SourceLines;
+
+/// Collects the optimization remarks LLVM reports while optimizing and
+/// generating code for a module.
+///
+/// LLVM's C API doesn't expose whether a remark is about an optimization that
+/// was applied, one that was missed, or an analysis, so only each remark's
+/// location and message are recorded.
+pub struct RemarkCollector<'ctx> {
+    context: &'ctx Context,
+    /// Descriptions of the remarks reported so far. Boxed so its address stays
+    /// the same while LLVM holds a pointer to it.
+    descriptions: Box<RefCell<Vec<String>>>,
+}
+
+/// A remark, as written to the remarks file.
+#[derive(Serialize)]
+struct Remark {
+    message: String,
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+    /// The byte offset in the Foo source the remark points to, if it points to
+    /// the Foo source.
+    offset: Option<usize>,
+}
+
+impl<'ctx> RemarkCollector<'ctx> {
+    /// Enables remarks and starts collecting the ones reported within
+    /// `context`. If `all` isn't set, only the stack size remarks needed for
+    /// [`stack_sizes()`] are enabled.
+    ///
+    /// LLVM's command line options can only be set once per process, so this
+    /// should only be called once.
+    ///
+    /// [`stack_sizes()`]: Self::stack_sizes()
+    pub fn new(context: &'ctx Context, all: bool) -> RemarkCollector<'ctx> {
+        let args = if all {
+            vec![
+                "foo_llvm",
+                "-pass-remarks=.*",
+                "-pass-remarks-missed=.*",
+                "-pass-remarks-analysis=.*",
+            ]
+        } else {
+            vec!["foo_llvm", "-pass-remarks-analysis=prologepilog"]
+        };
+        let args = args.into_iter()
+            .map(|arg| CString::new(arg).unwrap())
+            .collect::<Vec<_>>();
+        let argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+
+        let descriptions = Box::new(RefCell::new(vec![]));
+        unsafe {
+            LLVMParseCommandLineOptions(
+                argv.len() as i32,
+                argv.as_ptr(),
+                std::ptr::null()
+            );
+            LLVMContextSetDiagnosticHandler(
+                context.raw(),
+                Some(handle_diagnostic),
+                descriptions.as_ptr() as *mut c_void
+            );
+        }
+
+        RemarkCollector { context, descriptions }
+    }
+
+    /// Gets the size in bytes of each stack frame LLVM reported laying out, in
+    /// the order the functions were generated: the order they're defined in
+    /// the module, for each time code was generated for it.
+    ///
+    /// The remarks' descriptions don't include the function they're about,
+    /// which is why the order matters.
+    pub fn stack_sizes(&self) -> Vec<u64> {
+        self.descriptions.borrow()
+            .iter()
+            .filter_map(|desc| {
+                desc.rsplit_once(": ")?
+                    .1
+                    .strip_suffix(" stack bytes in function")?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Writes the remarks collected so far to `path` as a JSON array, mapping
+    /// the ones located in the Foo source back to offsets in it.
+    pub fn write(
+        &self,
+        path: &Path,
+        src_path: &Path,
+        lines: &SourceLines
+    ) -> Result<(), Box<dyn Error>> {
+        let src_name = src_path.file_name().unwrap().to_string_lossy();
+
+        let remarks = self.descriptions.borrow()
+            .iter()
+            .map(|desc| {
+                // descriptions look like `file:line:col: message`, with a file
+                // of `<unknown>` if the remark doesn't have a location
+                let mut parts = desc.splitn(4, ':');
+                let (Some(file), Some(line), Some(col), Some(message)) =
+                    (parts.next(), parts.next(), parts.next(), parts.next())
+                else {
+                    return Remark {
+                        message: desc.to_owned(),
+                        file: None,
+                        line: None,
+                        column: None,
+                        offset: None,
+                    };
+                };
+                let line = line.parse().ok().filter(|line| *line > 0);
+                let column = col.parse().ok().filter(|col| *col > 0);
+                let file = (file != "<unknown>").then(|| file.to_owned());
+                // a remark without a column points to the start of its line
+                let offset = match (&file, line) {
+                    (Some(file), Some(line)) if *file == src_name => {
+                        lines.offset(line, column.unwrap_or(1))
+                    }
+                    _ => None,
+                };
+
+                Remark {
+                    message: message.trim().to_owned(),
+                    file,
+                    line,
+                    column,
+                    offset,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let artifact = Artifact::new(path)?;
+        serde_json::to_writer_pretty(artifact.create()?, &remarks)?;
+        artifact.commit()
+    }
+}
+
+impl Drop for RemarkCollector<'_> {
+    fn drop(&mut self) {
+        // LLVM mustn't be left with a pointer to `descriptions`
+        unsafe {
+            LLVMContextSetDiagnosticHandler(
+                self.context.raw(),
+                None,
+                std::ptr::null_mut()
+            );
+        }
+    }
+}
+
+/// The diagnostic handler given to LLVM. Remarks are added to the list of
+/// descriptions; anything else is printed the way LLVM would have without a
+/// handler set.
+extern "C" fn handle_diagnostic(
+    info: LLVMDiagnosticInfoRef,
+    descriptions: *mut c_void
+) {
+    let (severity, description) = unsafe {
+        let ptr = LLVMGetDiagInfoDescription(info);
+        let description = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        LLVMDisposeMessage(ptr);
+        (LLVMGetDiagInfoSeverity(info), description)
+    };
+
+    match severity {
+        LLVMDiagnosticSeverity::LLVMDSRemark => unsafe {
+            (*(descriptions as *mut Vec<String>)).push(description);
+        }
+        LLVMDiagnosticSeverity::LLVMDSError => {
+            eprintln!("error: {}", description);
+            std::process::exit(1);
+        }
+        LLVMDiagnosticSeverity::LLVMDSWarning => {
+            eprintln!("warning: {}", description);
+        }
+        LLVMDiagnosticSeverity::LLVMDSNote => eprintln!("note: {}", description),
+    }
+}
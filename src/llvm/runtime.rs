@@ -0,0 +1,80 @@
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::targets::TargetData;
+use inkwell::types::IntType;
+use inkwell::values::FunctionValue;
+use inkwell::AddressSpace;
+
+/// The functions from the C runtime the generated code calls, which programs
+/// are always linked with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Runtime {
+    /// Prints the program's result.
+    Printf,
+    /// Opens the coverage data file.
+    Fopen,
+    /// Writes the coverage counters.
+    Fwrite,
+    /// Closes the coverage data file.
+    Fclose,
+    /// Ends the program when a contract doesn't hold.
+    Exit,
+}
+
+impl Runtime {
+    /// Every runtime function, whose names can't be taken by functions which
+    /// keep their own names, such as `extern` or exported ones.
+    pub const ALL: [Runtime; 5] = [
+        Runtime::Printf,
+        Runtime::Fopen,
+        Runtime::Fwrite,
+        Runtime::Fclose,
+        Runtime::Exit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Runtime::Printf => "printf",
+            Runtime::Fopen => "fopen",
+            Runtime::Fwrite => "fwrite",
+            Runtime::Fclose => "fclose",
+            Runtime::Exit => "exit",
+        }
+    }
+
+    /// Gets the function from `module`, declaring it if it hasn't been already.
+    pub fn get<'ctx>(
+        self,
+        context: &'ctx Context,
+        module: &Module<'ctx>
+    ) -> FunctionValue<'ctx> {
+        if let Some(function) = module.get_function(self.name()) {
+            return function;
+        }
+
+        let ptr_type = context.ptr_type(AddressSpace::default());
+        let ty = match self {
+            Runtime::Printf => context.i32_type().fn_type(&[ptr_type.into()], true),
+            Runtime::Fopen => {
+                ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false)
+            }
+            Runtime::Fwrite => {
+                let size_type = size_type(context, module);
+                size_type.fn_type(
+                    &[ptr_type.into(), size_type.into(), size_type.into(), ptr_type.into()],
+                    false
+                )
+            }
+            Runtime::Fclose => context.i32_type().fn_type(&[ptr_type.into()], false),
+            Runtime::Exit => context.void_type().fn_type(&[context.i32_type().into()], false),
+        };
+        module.add_function(self.name(), ty, None)
+    }
+}
+
+/// Gets C's `size_t` for the module's target.
+pub fn size_type<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> IntType<'ctx> {
+    let data_layout = module.get_data_layout();
+    let target_data = TargetData::create(data_layout.as_str().to_str().unwrap());
+    context.ptr_sized_int_type(&target_data, None)
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use inkwell::module::Module;
+use inkwell::targets::{FileType, TargetMachine};
+
+use super::debug::comment_prefix;
+
+/// How much machine code was generated for a function.
+pub struct FunctionSize {
+    pub name: String,
+    /// The size of the function's code in bytes, from the object's symbol
+    /// table. Only ELF objects record it, so it's unknown for other formats.
+    pub bytes: Option<u64>,
+    /// The number of instructions in the function's assembly.
+    pub instructions: usize,
+}
+
+/// Generates an object and the assembly for `module`, and gets the size of
+/// each function defined in it, in the module's order.
+pub fn function_sizes(
+    module: &Module,
+    machine: &TargetMachine
+) -> Result<Vec<FunctionSize>, Box<dyn Error>> {
+    let functions = module.get_functions()
+        .filter(|f| f.count_basic_blocks() > 0)
+        .map(|f| f.get_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    let object = machine.write_to_memory_buffer(module, FileType::Object)?;
+    // only ELF records the sizes of symbols; LLVM gives Mach-O and COFF
+    // symbols' values instead
+    let elf = object.as_slice().starts_with(b"\x7fELF");
+    let object = object.create_object_file()
+        .map_err(|()| "failed to read the object generated")?;
+    let symbol_sizes = object.get_symbols()
+        .filter_map(|symbol| {
+            let name = symbol.get_name()?.to_string_lossy().into_owned();
+            Some((name, symbol.size()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let asm = machine.write_to_memory_buffer(module, FileType::Assembly)?;
+    let instructions = count_instructions(
+        &String::from_utf8_lossy(asm.as_slice()),
+        &functions,
+        comment_prefix(machine)
+    );
+
+    Ok(functions.into_iter()
+        .map(|name| {
+            // Mach-O symbols start with an underscore
+            let bytes = symbol_sizes.get(&name)
+                .or_else(|| symbol_sizes.get(&format!("_{}", name)))
+                .copied()
+                .filter(|size| *size > 0 && elf);
+            let instructions = instructions.get(&name).copied().unwrap_or(0);
+            FunctionSize { name, bytes, instructions }
+        })
+        .collect())
+}
+
+/// Counts the instructions between each function's label and the end of the
+/// function in `asm`, skipping labels, directives, and comments.
+fn count_instructions(
+    asm: &str,
+    functions: &[String],
+    comment: &str
+) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    let mut current = None;
+    for line in asm.lines() {
+        let line = line.split(comment).next().unwrap_or_default().trim();
+        if let Some(label) = line.strip_suffix(':') {
+            // names that aren't plain identifiers are quoted
+            let label = label.trim_matches('"');
+            let function = functions.iter()
+                .find(|f| *f == label)
+                // Mach-O symbols start with an underscore
+                .or_else(|| functions.iter().find(|f| label.strip_prefix('_') == Some(f)));
+            if let Some(function) = function {
+                current = Some(function.clone());
+                counts.insert(function.clone(), 0);
+            } else if label.contains("func_end") {
+                current = None;
+            }
+        } else if line.is_empty() || line.starts_with('.') {
+            if matches!(line, ".cfi_endproc" | ".seh_endproc") {
+                current = None;
+            }
+        } else if let Some(function) = &current {
+            *counts.get_mut(function).unwrap() += 1;
+        }
+    }
+    counts
+}
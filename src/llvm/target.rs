@@ -1,4 +1,4 @@
-use std::{error::Error, path::PathBuf};
+use std::{error::Error, path::Path};
 
 use inkwell::{
     module::Module,
@@ -9,40 +9,114 @@ use inkwell::{
     OptimizationLevel
 };
 
-pub fn init_target(triple: &Option<String>) -> Result<Target, Box<dyn Error>> {
-    // initialize targets
-    Target::initialize_all(&InitializationConfig::default());
+use crate::{artifact::Artifact, probes};
 
-    // set triple (e.g. x86_64-linux-gnu)
-    let triple = if let Some(t) = triple {
+/// Gets the triple to build for from the one given (e.g. x86_64-linux-gnu), or
+/// the current machine's if none was.
+pub fn target_triple(triple: Option<&str>) -> TargetTriple {
+    if let Some(t) = triple {
         TargetTriple::create(t)
     } else {
         // detect default triple for the current machine
-        TargetMachine::get_default_triple()
-    };
+        TargetTriple::create(&probes::default_triple(|| {
+            TargetMachine::get_default_triple().as_str().to_string_lossy().into_owned()
+        }))
+    }
+}
+
+/// The triple `--test-target` builds for, whatever machine the compiler runs
+/// on.
+const TEST_TRIPLE: &str = "x86_64-unknown-linux-gnu";
+
+/// Gets the triple `--test-target` pins builds to. Machines are always created
+/// with the target's generic CPU and no extra features, and modules take their
+/// data layout from the machine, so the triple pins down everything about the
+/// target that the IR and assembly depend on.
+pub fn test_target_triple() -> TargetTriple {
+    TargetTriple::create(TEST_TRIPLE)
+}
+
+/// Finds the LLVM target for a triple. A triple that resolved to a target
+/// before only has that target initialized; otherwise every target is, and
+/// the one it resolves to is remembered for next time.
+pub fn init_target(triple: &TargetTriple) -> Result<Target, Box<dyn Error>> {
+    let config = InitializationConfig::default();
+    let name = triple.as_str().to_string_lossy();
+    if let Some(initialize) = probes::target(&name).and_then(|target| initializer(&target)) {
+        initialize(&config);
+        if let Ok(target) = Target::from_triple(triple) {
+            return Ok(target);
+        }
+    }
 
-    match Target::from_triple(&triple) {
-        Ok(target) => Ok(target),
+    // initialize targets
+    Target::initialize_all(&config);
+
+    match Target::from_triple(triple) {
+        Ok(target) => {
+            probes::set_target(&name, &target.get_name().to_string_lossy());
+            Ok(target)
+        }
         Err(e) => Err(Box::new(e)),
     }
 }
 
-pub fn machine_from_target(target: &Target) -> Option<TargetMachine> {
+/// Gets the function initializing only the LLVM target with the given name,
+/// e.g. `x86-64`, for the targets Foo is commonly built for.
+fn initializer(name: &str) -> Option<fn(&InitializationConfig)> {
+    match name {
+        "x86" | "x86-64" => Some(Target::initialize_x86),
+        "aarch64" | "aarch64_32" | "aarch64_be" | "arm64" | "arm64_32" => {
+            Some(Target::initialize_aarch64)
+        }
+        "arm" | "armeb" | "thumb" | "thumbeb" => Some(Target::initialize_arm),
+        "riscv32" | "riscv64" => Some(Target::initialize_riscv),
+        "wasm32" | "wasm64" => Some(Target::initialize_webassembly),
+        "mips" | "mipsel" | "mips64" | "mips64el" => Some(Target::initialize_mips),
+        "ppc32" | "ppc32le" | "ppc64" | "ppc64le" => Some(Target::initialize_power_pc),
+        "systemz" => Some(Target::initialize_system_z),
+        _ => None,
+    }
+}
+
+/// Creates a machine for the target. `pie` chooses between position-independent
+/// and statically positioned code, which has to match what the linker is told
+/// to produce, and `level` is how hard the code generator optimizes.
+pub fn machine_from_target(
+    target: &Target,
+    triple: &TargetTriple,
+    pie: bool,
+    level: OptimizationLevel
+) -> Option<TargetMachine> {
+    let reloc_mode = if pie { RelocMode::PIC } else { RelocMode::Static };
     target.create_target_machine(
-        &TargetMachine::get_default_triple(),
-        "generic",
+        triple,
+        // leaving the CPU empty picks the target's generic CPU, which not
+        // every target calls "generic"
+        "",
         "",
-        OptimizationLevel::Default,
-        RelocMode::PIC,
+        level,
+        reloc_mode,
         CodeModel::Default,
     )
 }
 
+/// Sets the module's triple and data layout to the machine's, so the IR
+/// describes the target the code is generated for.
+pub fn set_module_target(module: &Module, machine: &TargetMachine) {
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+}
+
+/// Writes the object or assembly code generated for a module to a file, which
+/// is only put in place once it's complete.
 pub fn write_code_to_file(
     machine: &TargetMachine,
     module: &Module,
-    path: &PathBuf,
+    path: &Path,
     file_type: FileType
 ) -> Result<(), Box<dyn Error>> {
-    Ok(machine.write_to_file(module, file_type, path)?)
+    let artifact = Artifact::new(path)?;
+    machine.write_to_file(module, file_type, artifact.path())?;
+    artifact.commit()
 }
\ No newline at end of file
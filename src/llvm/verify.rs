@@ -0,0 +1,39 @@
+use inkwell::module::Module;
+
+use super::ir::SymbolSpans;
+use crate::error::Diagnostics;
+use crate::mangle;
+
+/// Verifies each function defined in a generated module, queueing an error for
+/// every one LLVM's verifier rejects, at the source it was generated from,
+/// rather than stopping at the first. Returns whether they're all valid.
+///
+/// The verifier can only describe the problems of a whole module, so its
+/// report is queued once after them, as an error of its own.
+pub fn verify_functions(module: &Module, symbols: &SymbolSpans, diags: &mut Diagnostics) -> bool {
+    let mut valid = true;
+    for function in module.get_functions().filter(|f| f.count_basic_blocks() > 0) {
+        if function.verify(false) {
+            continue;
+        }
+        valid = false;
+        let symbol = function.get_name().to_string_lossy();
+        let msg = format!(
+            "function `{}` was generated incorrectly; this is a bug in the compiler",
+            mangle::display(&symbol)
+        );
+        match symbols.get(symbol.as_ref()) {
+            Some(span) => {
+                diags.error_at(*span, msg, "LLVM's verifier rejects the code generated for this")
+            }
+            None => diags.error(msg),
+        }
+    }
+    if !valid {
+        if let Err(report) = module.verify() {
+            let report = report.to_string_lossy();
+            diags.error(format!("LLVM's verifier reported:\n{}", report.trim_end()));
+        }
+    }
+    valid
+}
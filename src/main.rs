@@ -1,22 +1,84 @@
-use std::{error::Error, fs::File, path::PathBuf, process};
+use std::{
+    error::Error,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
 
+use backend::{Backend, CBackend, InterpreterBackend, LlvmBackend};
 use chumsky::Parser;
 use clap::ValueEnum;
-use inkwell::targets::FileType;
-use llvm::{print_module, LlvmGenerator};
-use parse::parser;
+use error::Diagnostics;
+use messages::{Message, Messages};
+use parse::{Directive, Features, Program, Spanned};
+use timings::DeclTimes;
 
+#[cfg(feature = "alloc-stats")]
+mod alloc;
+mod artifact;
+mod backend;
 mod bin;
+mod buildinfo;
+mod cfg;
+mod check;
+mod coverage;
+mod crash;
+#[cfg(unix)]
+mod daemon;
+mod deprecated;
+mod diff_run;
 mod error;
+mod eval;
+mod exec;
+mod explain;
+mod fix;
+mod fold;
+mod grade;
+mod highlight;
+mod include;
+mod inline;
+mod lines;
+mod limits;
+mod lint;
 mod llvm;
+mod mangle;
+mod messages;
+mod minify;
 mod parse;
+mod prelude;
+mod probes;
+mod profile;
+mod query;
+mod rename;
+mod repl;
+mod sema;
+mod sourcemap;
+mod sources;
+mod stats;
+mod stress;
+mod symbols;
+mod temp;
+mod timings;
+mod tools;
 
 /// Example LLVM-based compiler for a simple language
 #[derive(clap::Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Source file to compile
-    src: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Source files to compile: a Foo program, along with any LLVM IR (.ll)
+    /// or bitcode (.bc) files to link into it, and any C (.c) or object (.o,
+    /// .obj) files to link into the executable
+    #[arg(required = true)]
+    src: Vec<PathBuf>,
     /// Path of file to output
     #[arg(short, long)]
     output: Option<PathBuf>,
@@ -24,15 +86,549 @@ struct Args {
     #[arg(short, long, value_enum, default_value = "executable")]
     produce: OutputType,
     /// Target triple of the intended target machine to build for,
-    /// in form <arch><sub_arch>-<vendor>-<sys>-<env>, e.g. x86_64-linux-gnu
+    /// in form <arch><sub_arch>-<vendor>-<sys>-<env>, e.g. x86_64-linux-gnu.
+    /// Can be given more than once when producing objects or assembly, in
+    /// which case the output path is a directory (`out` by default) with a
+    /// subdirectory for each target.
     #[arg(short, long)]
-    target: Option<String>,
+    target: Vec<String>,
+    /// Leave the target triple out of the bitcode produced, so it can be
+    /// compiled for any target later with `foo_llvm llc --target`
+    #[arg(long, conflicts_with = "target")]
+    bitcode_portable: bool,
+    /// Build for a fixed target (x86_64-unknown-linux-gnu, with its generic
+    /// CPU and no extra features) instead of the machine the compiler runs
+    /// on or any the source names, so golden IR and assembly come out the
+    /// same on every machine (implies --reproducible)
+    #[arg(long, conflicts_with_all = ["target", "bitcode_portable"])]
+    test_target: bool,
+    /// The backend that turns the checked program into output
+    #[arg(long, value_enum, default_value = "llvm")]
+    backend: BackendKind,
+    /// The optimization level: the module is run through LLVM's pipeline for
+    /// it, and machine code is generated at it. Without it, no passes are run
+    #[arg(short = 'O', value_enum)]
+    opt_level: Option<OptLevel>,
     /// Specify a specific linker to use, if producing an executable. If a
     /// specific linker is chosen, the program will return an error if that
     /// linker isn't found. If this option is omitted, the program will try
     /// all options.
     #[arg(short, long)]
     linker: Option<Linker>,
+    #[command(flatten)]
+    limits: Limits,
+    /// The printf notation used to print the program's result
+    #[arg(long, value_enum, default_value = "fixed")]
+    print_format: Notation,
+    /// Number of digits printed after the decimal point (or, for `general`,
+    /// significant digits) in the program's result
+    #[arg(long)]
+    precision: Option<usize>,
+    /// Don't print a newline after the program's result
+    #[arg(long)]
+    no_newline: bool,
+    /// Name of a function taking no arguments to call as the program's entry
+    /// point, instead of evaluating the final expression
+    #[arg(long, conflicts_with = "no_main")]
+    entry: Option<String>,
+    /// Symbol of the entry function generated to evaluate the program and
+    /// print its result
+    #[arg(long, value_name = "SYMBOL", default_value = "main")]
+    entry_symbol: String,
+    /// Don't generate an entry function, compiling only the program's
+    /// functions, e.g. for a library or when C code linked in defines `main`
+    #[arg(long, conflicts_with = "entry_symbol")]
+    no_main: bool,
+    /// Whether floating point operations must follow IEEE 754 exactly (the
+    /// default), or may be reassociated, contracted, etc. for speed
+    #[arg(long, value_enum)]
+    float_model: Option<FloatModel>,
+    /// The width in bits of the floating point numbers the program computes
+    /// with, which `extern` functions take and return (64 by default)
+    #[arg(long, value_enum, value_name = "BITS")]
+    float_width: Option<FloatWidth>,
+    /// The version of the language the program is written in, overriding its
+    /// `#![version(...)]` directive (the latest by default)
+    #[arg(long, value_enum, value_name = "VERSION")]
+    lang_version: Option<LangVersion>,
+    /// Don't evaluate or propagate constants at compile time, even when they
+    /// only use numbers and Foo functions
+    #[arg(long)]
+    no_const_eval: bool,
+    /// Count the calls to each Foo function, writing them to a .profraw file
+    /// next to the source when the program exits, for `--profile-use`
+    #[arg(long, conflicts_with = "profile_use")]
+    profile_generate: bool,
+    /// Optimize using a profile written by a program compiled with
+    /// `--profile-generate`
+    #[arg(long, value_name = "FILE")]
+    profile_use: Option<PathBuf>,
+    /// Inline calls to Foo functions whose bodies have at most this many
+    /// expressions before generating code (or any size, for `@inline` ones)
+    #[arg(long, value_name = "SIZE")]
+    fe_inline_threshold: Option<usize>,
+    /// Generate DWARF debug info mapping the output back to the source
+    #[arg(short = 'g', long)]
+    debug_info: bool,
+    /// When producing assembly, add comments quoting the line of source each
+    /// group of instructions came from (implies --debug-info)
+    #[arg(long)]
+    asm_source_comments: bool,
+    /// When producing assembly, add C preprocessor line markers pointing each
+    /// instruction at the line of source it came from, alongside the `.file`
+    /// and `.loc` directives of the debug info, for assembling it with another
+    /// toolchain; when producing LLVM IR, keep the locations in it (implies
+    /// --debug-info)
+    #[arg(long)]
+    with_cpp_line_directives: bool,
+    /// Instrument the generated code with runtime checks (can be given more
+    /// than once)
+    #[arg(long, value_enum)]
+    instrument: Vec<Instrument>,
+    /// Treat warnings as errors
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Warn about declarations named like a builtin function or `main`, and
+    /// parameters named `result`
+    #[arg(long)]
+    check_reserved: bool,
+    /// Fail loudly on sloppy programs: implies --deny-warnings,
+    /// --check-reserved, --float-model strict, and --instrument nan and
+    /// div-zero
+    #[arg(long)]
+    strict: bool,
+    /// Produce a position-independent executable (the default)
+    #[arg(long, overrides_with = "no_pie")]
+    pie: bool,
+    /// Produce a statically positioned executable, generating code with static
+    /// relocations and telling the linker not to produce a PIE
+    #[arg(long, overrides_with = "pie")]
+    no_pie: bool,
+    /// File listing functions to export from the program, one per line, in
+    /// addition to those declared with `export`
+    #[arg(long, value_name = "FILE")]
+    export_list: Option<PathBuf>,
+    /// Make functions probe each page of large stack frames as they allocate
+    /// them, so a stack overflow always hits the guard page
+    #[arg(long)]
+    stack_probes: bool,
+    /// The size in bytes of the pages probed by --stack-probes, if not the
+    /// target's default
+    #[arg(long, requires = "stack_probes")]
+    stack_probe_size: Option<u32>,
+    /// Extra reports to print while compiling (can be given more than once):
+    /// `stack-sizes`, the size of each function's stack frame; `disasm`, the
+    /// disassembly of the object produced; `sourcemap`, a JSON file next to
+    /// the source mapping each AST node to the IR and assembly generated for
+    /// it (implies --debug-info); or `ir-for=<fn>` and `asm-for=<fn>`, the IR
+    /// or assembly of just the function `fn`
+    #[arg(long, value_name = "REPORT")]
+    emit: Vec<Emit>,
+    /// Write the optimization remarks LLVM reports while generating code to
+    /// this file as JSON (implies --debug-info, so remarks have locations)
+    #[arg(long, value_name = "FILE")]
+    remarks: Option<PathBuf>,
+    /// Name of the LLVM module generated; defaults to the source file's name
+    /// without its extension
+    #[arg(long)]
+    module_name: Option<String>,
+    /// Name the values in the generated IR after the source that produced
+    /// them and where it is, e.g. `%add.3.14`, instead of `%addtmp`
+    #[arg(long)]
+    readable_ir: bool,
+    /// Produce bit-identical output from identical input, wherever it's
+    /// compiled: paths are recorded as given rather than absolute, and the
+    /// external tools are asked not to record timestamps or random IDs
+    #[arg(long)]
+    reproducible: bool,
+    /// Overwrite output files that already exist, instead of refusing to
+    #[arg(long)]
+    force: bool,
+    /// Find the default target, the LLVM targets, and the linker again
+    /// instead of using the results cached from earlier runs
+    #[arg(long)]
+    refresh_probes: bool,
+    /// How to report errors and the build's progress
+    #[arg(long, value_enum, default_value = "human")]
+    message_format: MessageFormat,
+    /// Report how long each declaration took to parse, check, and generate
+    /// code for, slowest first
+    #[arg(long)]
+    time: bool,
+    /// The arguments the compiler was given, for the build info embedded in
+    /// objects.
+    #[arg(skip)]
+    command_line: Vec<String>,
+}
+
+impl Args {
+    /// Parses the arguments the compiler was given, starting with the
+    /// program's name, and keeps them.
+    fn from_command_line<I, T>(command_line: I) -> Result<Args, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        use clap::Parser;
+        let command_line = command_line.into_iter().map(Into::into).collect::<Vec<_>>();
+        let mut args = Args::try_parse_from(&command_line)?;
+        args.command_line = command_line.iter()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        args.expand_strict()?;
+        // golden output can't depend on where the source is either
+        if args.test_target {
+            args.reproducible = true;
+        }
+        Ok(args)
+    }
+
+    /// Expands `--strict` into the options it stands for, which the rest of
+    /// the compiler checks for by themselves.
+    fn expand_strict(&mut self) -> Result<(), clap::Error> {
+        if !self.strict {
+            return Ok(());
+        }
+        if self.float_model == Some(FloatModel::Relaxed) {
+            use clap::CommandFactory;
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--strict` requires `--float-model strict`, so it can't be used with \
+                `--float-model relaxed`"
+            ));
+        }
+        self.deny_warnings = true;
+        self.check_reserved = true;
+        self.float_model = Some(FloatModel::Strict);
+        for kind in [Instrument::Nan, Instrument::DivZero] {
+            if !self.instrument.contains(&kind) {
+                self.instrument.push(kind);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Check a Foo source file for errors without producing any output, and
+    /// summarize the functions it declares and the type of its result
+    Check {
+        /// The source file to check
+        src: PathBuf,
+        #[command(flatten)]
+        limits: Limits,
+        /// Print every function the program declares, with its parameters
+        /// and location, in this format instead of the summary
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        symbols: Option<SymbolFormat>,
+    },
+    /// Work with the coverage data of programs compiled with
+    /// `--instrument coverage`
+    Cov {
+        #[command(subcommand)]
+        command: CovCommand,
+    },
+    /// Keep the compiler running in the background, compiling for the CLI
+    /// with LLVM already initialized, until killed
+    #[cfg(unix)]
+    Daemon {
+        /// Path of the socket to listen on; defaults to `FOO_LLVM_DAEMON`, or
+        /// else a socket in the temporary directory named for the user
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Print the Foo functions that mangled symbols name, e.g.
+    /// `foo.test.add.2` as `test::add/2`
+    Demangle {
+        /// The symbols to demangle; without any, the symbols in the text on
+        /// stdin are demangled instead, e.g. the output of `nm`
+        symbols: Vec<String>,
+    },
+    /// Run two Foo programs over the same inputs with the interpreter and
+    /// report where their results diverge
+    DiffRun {
+        /// The first program
+        a: PathBuf,
+        /// The second program
+        b: PathBuf,
+        /// File of expressions, one per line, to evaluate in place of each
+        /// program's final expression; without it, the programs' own results
+        /// are compared
+        #[arg(long, value_name = "FILE")]
+        inputs: Option<PathBuf>,
+        /// How far apart results can be, absolutely or relative to the larger
+        /// one, and still be the same
+        #[arg(long, default_value_t = 1e-9)]
+        epsilon: f64,
+    },
+    /// Evaluate a Foo expression and print its value
+    Eval {
+        /// The expression, which can start with declarations, e.g.
+        /// `let a = 2; fib(a)`
+        expr: String,
+        /// A source file whose declarations the expression can use; can be
+        /// given more than once
+        #[arg(long, value_name = "FILE")]
+        include: Vec<PathBuf>,
+        /// Instead of evaluating the expression, compile it into a function
+        /// with this name, exported under it, and print the LLVM IR of the
+        /// module defining it
+        #[arg(long, value_name = "NAME", conflicts_with = "include")]
+        compile_fn: Option<String>,
+        /// A parameter of the function given to `--compile-fn`, which the
+        /// expression can use; can be given more than once, in order
+        #[arg(long = "param", value_name = "NAME", requires = "compile_fn")]
+        params: Vec<String>,
+    },
+    /// Walk through a Foo source file node by node, printing the IR each
+    /// node generates
+    Explain {
+        /// The source file to explain
+        src: PathBuf,
+        /// Pause after each node until Enter is pressed
+        #[arg(long)]
+        interactive: bool,
+        /// Read the commands given at each pause from a file instead of
+        /// stdin, echoing them, to print a transcript of a session
+        #[arg(long, value_name = "FILE")]
+        script: Option<PathBuf>,
+    },
+    /// Rewrite the deprecated code in a Foo source file the way its warnings
+    /// suggest
+    Fix {
+        /// The source file to rewrite
+        src: PathBuf,
+        /// Also migrate the program to the latest version of the language, if
+        /// it's written in an older one
+        #[arg(long)]
+        edition: bool,
+        /// Print the change as a unified diff instead of rewriting the file
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Compile every Foo source file in a directory of submissions, run the
+    /// cases of a spec against each, and report their scores
+    Grade {
+        /// The spec, a TOML file listing the cases to run, each with the
+        /// result expected and the points it's worth
+        #[arg(long, value_name = "FILE")]
+        spec: PathBuf,
+        /// The directory of submissions to grade
+        submissions: PathBuf,
+        /// The format of the report
+        #[arg(long, value_enum, default_value = "json")]
+        format: ReportFormat,
+        /// Path of the report to write, instead of printing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output if it already exists, instead of refusing to
+        #[arg(long)]
+        force: bool,
+        /// Run the submissions under resource limits, killing any that hit
+        /// one
+        #[arg(long)]
+        sandbox: bool,
+        #[command(flatten)]
+        limits: SandboxLimits,
+    },
+    /// Write a Foo source file as an HTML page, syntax highlighted and with
+    /// its errors and warnings marked
+    Highlight {
+        /// The source file to highlight
+        src: PathBuf,
+        /// Path of the HTML file to output. Defaults to the source with a
+        /// .html extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output if it already exists, instead of refusing to
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the build info embedded in an object or executable: the version
+    /// of the compiler that built it, its arguments, and a hash of the source
+    Inspect {
+        /// The object or executable to inspect
+        artifact: PathBuf,
+    },
+    /// Optimize an LLVM IR (.ll) or bitcode (.bc) file, like LLVM's `opt`
+    Opt {
+        /// The IR or bitcode file to optimize
+        input: PathBuf,
+        /// Path of file to output: IR if it ends in .ll, bitcode otherwise. If
+        /// omitted, the IR is written to stderr
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// The optimization level, choosing LLVM's default pipeline for it
+        #[arg(short = 'O', value_enum, default_value = "2")]
+        opt_level: OptLevel,
+        /// A pass pipeline to run instead of the default one, written the way
+        /// `opt -passes` takes it, e.g. `instcombine,gvn`
+        #[arg(long, conflicts_with = "opt_level")]
+        passes: Option<String>,
+        /// Target triple to optimize for; defaults to the file's own target,
+        /// or else the current machine
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Print a unified diff of the IR of the function FN before and after
+        /// the pipeline runs
+        #[arg(long, value_name = "FN")]
+        show_opt_diff: Option<String>,
+    },
+    /// Compile an LLVM IR (.ll) or bitcode (.bc) file to assembly or an object
+    /// file, like LLVM's `llc`
+    Llc {
+        /// The IR or bitcode file to compile
+        input: PathBuf,
+        /// Path of file to output: assembly if it ends in .s, an object file
+        /// otherwise. Defaults to the input with a .o extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Target triple to compile for; defaults to the file's own target,
+        /// or else the current machine
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Produce position-dependent code, for executables linked with
+        /// `--no-pie`
+        #[arg(long)]
+        no_pie: bool,
+        /// The level the code generator optimizes at; LLVM's default if
+        /// omitted. No passes are run; use `opt` for those
+        #[arg(short = 'O', value_enum)]
+        opt_level: Option<OptLevel>,
+    },
+    /// Shorten the names in a Foo source file and strip its whitespace,
+    /// keeping the names of exported and extern functions
+    Minify {
+        /// The source file to minify
+        src: PathBuf,
+        /// Path of the minified file to write, instead of printing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output if it already exists, instead of refusing to
+        #[arg(long)]
+        force: bool,
+    },
+    /// Work with the profiles of programs compiled with `--profile-generate`
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Find where names in a Foo source file are declared and used
+    Query {
+        #[command(subcommand)]
+        command: QueryCommand,
+    },
+    /// Rename a variable, parameter, or function, along with every reference
+    /// to it, in a Foo source file
+    Rename {
+        /// The source file to rewrite
+        src: PathBuf,
+        /// The byte offset of the name to rename, at its declaration or any
+        /// reference to it
+        #[arg(long, value_name = "OFFSET")]
+        at: usize,
+        /// The new name
+        #[arg(long, value_name = "NAME")]
+        to: String,
+        /// Print the change as a unified diff instead of rewriting the file
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Start an interactive session, reading declarations and expressions a
+    /// line at a time and printing the value of each expression, run with
+    /// LLVM's JIT
+    Repl,
+    /// Compile a Foo source file and run the program, passing its output
+    /// through
+    Run {
+        /// The source file to run
+        src: PathBuf,
+        /// Compile the program with debug info, so that if it crashes, the
+        /// line it crashed at is printed (on Linux)
+        #[arg(short = 'g', long)]
+        debug_info: bool,
+        /// Run the program under resource limits, killing it if it hits one,
+        /// for programs from people who aren't trusted
+        #[arg(long)]
+        sandbox: bool,
+        #[command(flatten)]
+        limits: SandboxLimits,
+        /// Run the program in-process with LLVM's JIT, like `-p jit`, instead
+        /// of building an executable to run
+        #[arg(long, conflicts_with_all = ["debug_info", "sandbox"])]
+        jit: bool,
+    },
+    /// Print metrics of a Foo source file's AST: how many expressions of each
+    /// kind it has, how deep it nests, and how big its functions are
+    Stats {
+        /// The source file to measure
+        src: PathBuf,
+    },
+    /// Generate random valid programs and time compiling each of them, as a
+    /// workload for measuring the compiler's performance
+    Stress {
+        /// How many programs to generate
+        #[arg(long, value_name = "N")]
+        generate: usize,
+        /// What the programs are made of
+        #[arg(long, value_enum, default_value = "mixed")]
+        shape: StressShape,
+        /// How many declarations, or levels of nesting, each program has
+        #[arg(long, default_value_t = 1000)]
+        size: usize,
+        /// Seed for generating the programs; the same seed generates the same
+        /// programs
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Directory to keep the generated programs in, instead of a temporary
+        /// one
+        #[arg(long, value_name = "DIR")]
+        keep: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CovCommand {
+    /// Print each line of a source file with the number of times it was
+    /// executed in the last run of its program
+    Report {
+        /// The Foo source file the program was compiled from
+        src: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ProfileCommand {
+    /// Print the Foo functions from the most to the least called in the
+    /// profile, with their call counts and locations
+    Report {
+        /// The Foo source file the program was compiled from
+        src: PathBuf,
+        /// The profile to read; defaults to the one the program writes next
+        /// to the source
+        #[arg(long, value_name = "FILE")]
+        profile: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum QueryCommand {
+    /// Print the location of every declaration of a name
+    Defs {
+        /// The source file to search
+        src: PathBuf,
+        /// The variable, parameter, or function name
+        name: String,
+    },
+    /// Print the location of every reference to a declared name
+    Refs {
+        /// The source file to search
+        src: PathBuf,
+        /// The variable, parameter, or function name
+        name: String,
+    },
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -48,10 +644,231 @@ enum OutputType {
     /// Output LLVM IR (to stderr; specify an output path to write to a file, 
     /// typically .ll)
     LlvmIR,
+    /// Output C source (.c), generated by the C backend
+    C,
+    /// Run the program in-process with LLVM's JIT, instead of producing
+    /// output
+    Jit,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum BackendKind {
+    /// Generate code with LLVM, for any of the output types.
+    Llvm,
+    /// Run the program with the interpreter used for compile-time evaluation,
+    /// printing its result instead of producing output.
+    Interpreter,
+    /// Generate readable C, compiled by the system's C compiler unless `-p c`
+    /// asks for the source.
+    C,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum OptLevel {
+    /// No optimization.
+    #[value(name = "0")]
+    O0,
+    /// Quick optimizations that don't hurt debugging much.
+    #[value(name = "1")]
+    O1,
+    /// Most optimizations.
+    #[value(name = "2")]
+    O2,
+    /// Every optimization, including ones that make code bigger.
+    #[value(name = "3")]
+    O3,
+    /// Optimize for size.
+    #[value(name = "s")]
+    Os,
+    /// Optimize aggressively for size.
+    #[value(name = "z")]
+    Oz,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Notation {
+    /// Fixed-point notation, e.g. `3.000000` (`%f`).
+    Fixed,
+    /// The shorter of fixed-point or scientific notation, without trailing
+    /// zeroes, e.g. `3` (`%g`).
+    General,
+    /// Scientific notation, e.g. `3.000000e+00` (`%e`).
+    Scientific,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum MessageFormat {
+    /// Print diagnostics for a person to read.
+    Human,
+    /// Print diagnostics, outputs written, commands run, and timings to stdout
+    /// as newline-delimited JSON.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum StressShape {
+    /// One expression, nested as deep as the size.
+    Nesting,
+    /// As many functions as the size, each calling earlier ones.
+    Functions,
+    /// A chain of as many `let`s as the size, each using earlier ones.
+    Lets,
+    /// Half functions and half `let`s, with moderately nested expressions.
+    Mixed,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SymbolFormat {
+    /// A JSON object listing the functions, for editor plugins.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ReportFormat {
+    /// A JSON array with an object for each submission, including the result
+    /// of each case.
+    Json,
+    /// A CSV table with a row for each submission and a column for each case.
+    Csv,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum FloatWidth {
+    /// C's `float`.
+    #[value(name = "32")]
+    F32,
+    /// C's `double`.
+    #[value(name = "64")]
+    F64,
+    /// The x87 extended precision format, C's `long double` on x86 targets
+    /// other than MSVC.
+    #[value(name = "80")]
+    F80,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum LangVersion {
+    /// Every declaration ends with a `;`.
+    #[value(name = "1")]
+    V1,
+    /// Declarations can end at the end of their line instead.
+    #[value(name = "2")]
+    V2,
+}
+
+impl LangVersion {
+    const LATEST: LangVersion = LangVersion::V2;
+    /// The version of files that don't name one, which is the first, so that
+    /// programs written before there were versions keep compiling.
+    const DEFAULT: LangVersion = LangVersion::V1;
+
+    /// Gets the version a source file is written in: the one given on the
+    /// command line, if any, or else the one its `#![version(...)]` directive
+    /// names, or else the default. The directives are read on their own, since
+    /// the version decides how the rest of the file is parsed; an invalid one
+    /// is reported when the whole file is.
+    fn of(given: Option<LangVersion>, src: &str) -> LangVersion {
+        given.unwrap_or_else(|| {
+            parse::directives()
+                .lazy()
+                .parse(src)
+                .into_output()
+                .unwrap_or_default()
+                .iter()
+                .rev()
+                .find_map(|(directive, _)| match directive {
+                    Directive::Version(version) => LangVersion::from_str(version, false).ok(),
+                    _ => None,
+                })
+                .unwrap_or(LangVersion::DEFAULT)
+        })
+    }
+
+    fn features(self) -> Features {
+        Features {
+            optional_semicolons: self >= LangVersion::V2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum FloatModel {
+    /// Every operation is rounded exactly as IEEE 754 specifies, in the order
+    /// the source gives.
+    Strict,
+    /// Operations may be reassociated, contracted into fused multiply-adds,
+    /// turned into multiplications by reciprocals, and may ignore the sign of
+    /// zero.
+    Relaxed,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Instrument {
+    /// Report the first operations to produce a NaN and an infinity when the
+    /// program exits.
+    Nan,
+    /// Count how many times each expression is evaluated, for `cov report`.
+    Coverage,
+    /// Check functions' `requires` and `ensures` clauses when they're called
+    /// and return, exiting with the clause that failed if one doesn't hold.
+    Contracts,
+    /// Exit with the location of the first division by zero, as soon as it
+    /// happens.
+    DivZero,
+    /// Count how many times each function is called, for `--profile-use`.
+    /// Enabled with `--profile-generate`.
+    #[value(skip)]
+    Profile,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Emit {
+    /// The size of each function's stack frame, as laid out by the code
+    /// generator.
+    StackSizes,
+    /// The disassembly of the object file produced, by function.
+    Disasm,
+    /// The IR of a single function, given by its name in Foo.
+    IrFor(String),
+    /// The assembly of a single function, given by its name in Foo.
+    AsmFor(String),
+    /// A map from each node of the AST to the IR values and lines of assembly
+    /// generated for it.
+    SourceMap,
+    /// A GraphViz graph of each function's basic blocks, annotated with its
+    /// dominator tree and loops.
+    AnalysisDot,
+    /// The size in bytes and number of instructions of each function's
+    /// machine code.
+    SizeReport,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "stack-sizes" => Ok(Emit::StackSizes),
+            None if s == "disasm" => Ok(Emit::Disasm),
+            None if s == "sourcemap" => Ok(Emit::SourceMap),
+            None if s == "analysis-dot" => Ok(Emit::AnalysisDot),
+            None if s == "size-report" => Ok(Emit::SizeReport),
+            Some(("ir-for", name)) => Ok(Emit::IrFor(name.to_owned())),
+            Some(("asm-for", name)) => Ok(Emit::AsmFor(name.to_owned())),
+            _ => Err(
+                "expected `stack-sizes`, `disasm`, `sourcemap`, `analysis-dot`, \
+                `size-report`, `ir-for=<fn>`, or `asm-for=<fn>`"
+                    .to_owned()
+            ),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Linker {
+    /// The system's C compiler driver: the one named by the `CC` environment
+    /// variable, or else `cc`.
+    Cc,
     /// LLVM's C-compiler frontend.
     Clang,
     /// The GNU Compiler Collection.
@@ -64,123 +881,433 @@ enum Linker {
     Lld,
 }
 
+/// Limits on the programs the compiler accepts, so that adversarial or
+/// generated inputs fail with a diagnostic instead of exhausting the compiler's
+/// stack or memory.
+#[derive(clap::Args, Clone, Copy, Debug)]
+struct Limits {
+    /// Maximum number of errors to display before giving up on printing
+    /// the rest.
+    #[arg(long)]
+    max_errors: Option<usize>,
+    /// Maximum depth the program can nest to. Every declaration, parenthesized
+    /// expression, operator, and call is a level deeper than the one before it
+    #[arg(long, default_value_t = 2000)]
+    max_ast_depth: usize,
+    /// Maximum number of functions the program can define or declare
+    #[arg(long)]
+    max_function_count: Option<usize>,
+}
+
+/// Limits on a program run with `run --sandbox`. Past the CPU time limit the
+/// kernel kills the program, and past the wall time limit `run` does; past the
+/// memory limit, allocating fails, which in a Foo program means a crash.
+#[derive(clap::Args, Clone, Copy, Debug)]
+struct SandboxLimits {
+    /// Maximum CPU time the program can use, in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 5, requires = "sandbox")]
+    cpu_time: u64,
+    /// Maximum time the program can run for, in seconds, including any time
+    /// it spends waiting
+    #[arg(long, value_name = "SECS", default_value_t = 10, requires = "sandbox")]
+    wall_time: u64,
+    /// Maximum memory the program can map, in MiB
+    #[arg(long, value_name = "MIB", default_value_t = 512, requires = "sandbox")]
+    memory: u64,
+}
+
+/// The stack size of the thread the compiler runs on, in bytes.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = {
-        use clap::Parser;
-        Args::parse()
-    };
+    let args = Args::from_command_line(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    // a daemon, if one is running, compiles faster than starting afresh
+    #[cfg(unix)]
+    if !matches!(args.command, Some(Command::Daemon { .. })) {
+        if let Some(code) = daemon::request(std::env::args_os()) {
+            std::process::exit(code);
+        }
+    }
+    let messages = Messages::new(args.message_format);
+    if messages.enabled() {
+        // rendered diagnostics are embedded in the messages as plain text
+        yansi::disable();
+    }
+
+    // the parser and code generator recurse into every nested expression and
+    // declaration, so the compiler runs on a thread with room for programs
+    // nested as deeply as `--max-ast-depth` allows
+    let res = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(args, messages).map_err(|e| e.to_string()))?
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    messages.emit(Message::BuildFinished { success: res.is_ok() });
+    Ok(res?)
+}
 
-    let Ok(src) = std::fs::read_to_string(args.src.clone()) else {
+fn run(args: Args, messages: Messages) -> Result<(), Box<dyn Error>> {
+    match &args.command {
+        Some(Command::Cov { command: CovCommand::Report { src } }) => {
+            return coverage::report(src);
+        }
+        Some(Command::Check { src, limits, symbols }) => {
+            return check::check(src, limits, *symbols);
+        }
+        #[cfg(unix)]
+        Some(Command::Daemon { socket }) => {
+            let socket = match socket {
+                Some(socket) => Some(socket.clone()),
+                None => daemon::socket_path(true)?,
+            };
+            let Some(socket) = socket else {
+                return Err("no socket to listen on, since FOO_LLVM_DAEMON is empty".into());
+            };
+            return daemon::serve(&socket);
+        }
+        Some(Command::Demangle { symbols }) => {
+            return mangle::demangle_command(symbols);
+        }
+        Some(Command::DiffRun { a, b, inputs, epsilon }) => {
+            return diff_run::diff_run(a, b, inputs.as_deref(), *epsilon);
+        }
+        Some(Command::Eval { expr, compile_fn: Some(name), params, .. }) => {
+            return eval::compile(expr, name, params);
+        }
+        Some(Command::Eval { expr, include, compile_fn: None, .. }) => {
+            return eval::evaluate(expr, include);
+        }
+        Some(Command::Explain { src, interactive, script }) => {
+            return explain::explain(src, *interactive, script.as_deref());
+        }
+        Some(Command::Fix { src, edition, diff }) => {
+            return fix::fix(src, *edition, *diff);
+        }
+        Some(Command::Grade { spec, submissions, format, output, force, sandbox, limits }) => {
+            return grade::grade(
+                spec,
+                submissions,
+                *format,
+                output.as_deref(),
+                *force,
+                sandbox.then_some(limits)
+            );
+        }
+        Some(Command::Highlight { src, output, force }) => {
+            return highlight::highlight(src, output.as_deref(), *force);
+        }
+        Some(Command::Inspect { artifact }) => {
+            return buildinfo::inspect(artifact);
+        }
+        Some(Command::Opt { input, output, opt_level, passes, target, show_opt_diff }) => {
+            return tools::opt(
+                input,
+                output.as_deref(),
+                passes.as_deref().unwrap_or(opt_level.pipeline()),
+                target.as_deref(),
+                show_opt_diff.as_deref()
+            );
+        }
+        Some(Command::Llc { input, output, target, no_pie, opt_level }) => {
+            return tools::llc(
+                input,
+                output.as_deref(),
+                target.as_deref(),
+                !no_pie,
+                llvm::codegen_level(*opt_level)
+            );
+        }
+        Some(Command::Minify { src, output, force }) => {
+            return minify::minify(src, output.as_deref(), *force);
+        }
+        Some(Command::Profile { command: ProfileCommand::Report { src, profile } }) => {
+            return profile::report(src, profile.as_deref());
+        }
+        Some(Command::Query { command: QueryCommand::Defs { src, name } }) => {
+            return query::defs(src, name);
+        }
+        Some(Command::Query { command: QueryCommand::Refs { src, name } }) => {
+            return query::refs(src, name);
+        }
+        Some(Command::Rename { src, at, to, diff }) => {
+            return rename::rename(src, *at, to, *diff);
+        }
+        Some(Command::Repl) => {
+            return repl::repl();
+        }
+        Some(Command::Run { src, jit: true, .. }) => {
+            let args = ["foo_llvm".as_ref(), src.as_os_str(), "-p".as_ref(), "jit".as_ref()];
+            return run(Args::from_command_line(args)?, messages);
+        }
+        Some(Command::Run { src, debug_info, sandbox, limits, .. }) => {
+            return exec::run_program(src, *debug_info, sandbox.then_some(limits), messages);
+        }
+        Some(Command::Stats { src }) => {
+            return stats::stats(src);
+        }
+        Some(Command::Stress { generate, shape, size, seed, keep }) => {
+            return stress::stress(*generate, *shape, *size, *seed, keep.as_deref());
+        }
+        None => (),
+    }
+
+    if args.refresh_probes {
+        probes::refresh();
+    }
+    let inputs = sort_inputs(&args.src)?;
+    if args.produce != OutputType::Executable
+        && !(inputs.c.is_empty() && inputs.objects.is_empty())
+    {
+        return Err(
+            "C and object files can only be linked when producing an executable"
+                .into()
+        );
+    }
+
+    let Ok(src) = std::fs::read_to_string(inputs.src) else {
         return Err("failed to open file".into());
     };
 
-    let ast = parser()
-        .parse(&src)
-        .into_result()
-        .unwrap_or_else(|errs| {
-            error::print_syntax_errors(errs, &args.src, &src);
-            std::process::exit(1);
-        });
+    let mut diags = Diagnostics::new(
+        inputs.src,
+        &src,
+        args.limits.max_errors,
+        messages
+    );
+    if args.deny_warnings {
+        diags.deny_warnings();
+    }
 
-    let target = match llvm::init_target(&args.target) {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
-        }
+    let timer = Instant::now();
+
+    if !args.limits.check_source(&src, &mut diags) {
+        diags.abort();
+    }
+    let features = LangVersion::of(args.lang_version, &src).features();
+    let Program { directives, includes, body: ast } =
+        include::parse(inputs.src, &src, features, &mut diags);
+    let Some(directed) = source_options(&directives, &mut diags) else {
+        diags.abort();
+    };
+    if !args.limits.check_ast(&ast, &mut diags) {
+        diags.abort();
+    }
+    // options given on the command line take precedence over the source's
+    let float_model = args.float_model.or(directed.float_model).unwrap_or(FloatModel::Strict);
+    let float_width = args.float_width.or(directed.float_width).unwrap_or(FloatWidth::F64);
+    // portable bitcode leaves the target out, as if none was given
+    let target = if args.target.is_empty() && !args.bitcode_portable {
+        &directed.target
+    } else {
+        &args.target
+    };
+    let triples = if args.test_target {
+        vec![llvm::test_target_triple()]
+    } else if target.is_empty() {
+        vec![llvm::target_triple(None)]
+    } else {
+        target.iter().map(|t| llvm::target_triple(Some(t))).collect()
+    };
+    // portable bitcode has no target for `#[cfg]` to check
+    let cfg_triples = if args.bitcode_portable {
+        vec![]
+    } else {
+        triples.iter().map(|t| t.as_str().to_string_lossy().into_owned()).collect()
+    };
+    let Some(ast) = cfg::filter(ast, &cfg_triples, &mut diags) else {
+        diags.abort();
     };
 
-    let context = inkwell::context::Context::create();
-    let module = context.create_module("foo");
-    let builder = context.create_builder();
-
-    // best practice: optionally set the data layout for the module based
-    // on target machine
-    if let Some(machine) = llvm::machine_from_target(&target) {
-        module.set_data_layout(&machine.get_target_data().get_data_layout());
-    }
-
-    match LlvmGenerator::generate(&ast, &context, &module, &builder) {
-        Ok(_) => {
-            match args.produce {
-                OutputType::Executable => {
-                    let obj_path = PathBuf::from("foo.o");
-                    // use scope to drop file after ensuring it exists
-                    { let _ = open_file(&obj_path)?; }
-                    let Some(machine) = llvm::machine_from_target(&target) else {
-                        return Err("failed to build target machine".into())
-                    };
-                    llvm::write_code_to_file(
-                        &machine,
-                        &module,
-                        &obj_path,
-                        FileType::Object
-                    )?;
-
-                    let out_path = get_output_path(args.output, "foo")?;
-
-                    bin::try_to_bin(&obj_path, &out_path, args.linker)?;
-                }
-                OutputType::Object => {
-                    let path = get_output_path(args.output, "foo.o")?;
-                    // use scope to drop file after ensuring it exists
-                    { let _ = open_file(&path)?; }
-                    let Some(machine) = llvm::machine_from_target(&target) else {
-                        return Err("failed to build target machine".into());
-                    };
-                    llvm::write_code_to_file(
-                        &machine,
-                        &module,
-                        &path,
-                        FileType::Object
-                    )?;
-                }
-                OutputType::Assembly => {
-                    let path = get_output_path(args.output, "foo.s")?;
-                    // use scope to drop file after ensuring it exists
-                    { let _ = open_file(&path)?; }
-                    let Some(machine) = llvm::machine_from_target(&target) else {
-                        return Err("failed to build target machine".into());
-                    };
-                    llvm::write_code_to_file(
-                        &machine,
-                        &module,
-                        &path,
-                        FileType::Assembly
-                    )?;
-                }
-                OutputType::Bitcode => {
-                    let path = get_output_path(args.output, "foo.bc")?;
-                    // use scope to drop file after ensuring it exists
-                    { let _ = open_file(&path)?; }
-                    module.write_bitcode_to_path(&path);
-                }
-                OutputType::LlvmIR => {
-                    if let Some(path) = args.output {
-                        let mut file = open_file(&path)?;
-                        llvm::write_module_to_file(&module, &mut file)?;
-                    } else {
-                        print_module(&module);
-                    }
-                }
-            }
-        }
-        Err(e) => eprintln!("{}", e),
+    sema::check(&ast, &mut diags);
+    lint::check(&ast, &mut diags);
+    deprecated::check(&ast, features, &mut diags);
+    if args.check_reserved {
+        lint::reserved(&ast, &mut diags);
+    }
+    // warnings are errors under `--deny-warnings`
+    if diags.has_errors() {
+        diags.abort();
     }
+    diags.print();
+    messages.timing("parse", timer.elapsed());
+    // parsing the declarations again for their times isn't counted in the
+    // phase's
+    let timed = args.time.then(|| {
+        let parse_times = timings::parse_times(&ast, features, &diags);
+        (ast.clone(), parse_times)
+    });
+    let mut sema_times = DeclTimes::default();
 
+    let mut instrument = args.instrument.clone();
+    if args.profile_generate {
+        instrument.push(Instrument::Profile);
+    }
+    // a profile counts every call in the source, which inlining would hide
+    let ast = match args.fe_inline_threshold {
+        Some(threshold) if !args.profile_generate => inline::inline(ast, threshold),
+        _ => ast,
+    };
+    // evaluating at compile time would skip the instrumentation's checks, and
+    // only gets the same results as the program when it computes in `double`s
+    let ast = if !args.no_const_eval
+        && instrument.is_empty()
+        && float_width == FloatWidth::F64
+    {
+        fold::fold(&ast, &mut diags, &mut sema_times)
+    } else {
+        ast
+    };
+    if diags.has_errors() {
+        diags.abort();
+    }
+    diags.print();
+
+    let context;
+    // C source can only come from the C backend
+    let kind = match (args.backend, args.produce) {
+        (BackendKind::Llvm, OutputType::C) => BackendKind::C,
+        (kind, _) => kind,
+    };
+    let mut backend: Box<dyn Backend> = match kind {
+        BackendKind::Llvm => {
+            context = inkwell::context::Context::create();
+            Box::new(LlvmBackend::new(
+                &context,
+                &args,
+                &inputs,
+                &src,
+                triples,
+                float_model,
+                float_width,
+                &instrument,
+                !includes.is_empty(),
+                messages,
+                &mut diags
+            )?)
+        }
+        BackendKind::Interpreter => Box::new(InterpreterBackend::new(&args, &inputs)?),
+        BackendKind::C => {
+            Box::new(CBackend::new(&args, &inputs, float_model, float_width, messages)?)
+        }
+    };
+
+    let timer = Instant::now();
+    backend.generate(&ast, &mut diags)?;
+    messages.timing("codegen", timer.elapsed());
+    if let Some((decls, parse_times)) = &timed {
+        timings::report(decls, parse_times, &sema_times, &backend.decl_times(), &diags, messages);
+    }
+    backend.optimize()?;
+    backend.emit(&mut diags)?;
     Ok(())
 }
 
-fn open_file(path: &PathBuf) -> Result<File, Box<dyn Error>> {
-    if path.exists() && !path.is_file() {
-        return Err("output path isn't a file name".into());
+/// The input files given on the command line, sorted by type.
+struct Inputs<'a> {
+    /// The Foo source file.
+    src: &'a PathBuf,
+    /// LLVM IR and bitcode files to link into the generated module.
+    ir: Vec<&'a PathBuf>,
+    /// C files to compile and link into the executable.
+    c: Vec<&'a PathBuf>,
+    /// Object files to link into the executable.
+    objects: Vec<&'a PathBuf>,
+}
+
+impl Inputs<'_> {
+    /// Checks that an output can be written to `path`: that it isn't one of
+    /// the inputs, and that nothing is there already, unless `force` is given.
+    fn check_output(&self, path: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+        let inputs = std::iter::once(self.src)
+            .chain(self.ir.iter().copied())
+            .chain(self.c.iter().copied())
+            .chain(self.objects.iter().copied())
+            .map(PathBuf::as_path);
+        artifact::check_output(path, inputs, force)
+    }
+}
+
+/// Sorts the input files by their extensions. Exactly one Foo source is
+/// required.
+fn sort_inputs(inputs: &[PathBuf]) -> Result<Inputs<'_>, Box<dyn Error>> {
+    let mut src = vec![];
+    let mut ir = vec![];
+    let mut c = vec![];
+    let mut objects = vec![];
+
+    for path in inputs {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ll" | "bc") => ir.push(path),
+            Some("c") => c.push(path),
+            Some("o" | "obj") => objects.push(path),
+            _ => src.push(path),
+        }
     }
 
-    if let Some(dir) = path.parent() {
-        std::fs::create_dir_all(dir)?;
+    match src[..] {
+        [src] => Ok(Inputs { src, ir, c, objects }),
+        [] => Err("no Foo source file given".into()),
+        _ => Err("only one Foo source file can be compiled at a time".into()),
     }
+}
 
-    Ok(File::create(&path)?)
+/// Reads the names in an export list file, skipping blank lines and comments
+/// starting with `#`.
+fn read_export_list(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let Ok(list) = std::fs::read_to_string(path) else {
+        return Err(format!("failed to open export list {:#?}", path).into());
+    };
+    Ok(list.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect())
+}
+
+/// The options set by a source file's directives.
+#[derive(Default)]
+struct SourceOptions {
+    target: Vec<String>,
+    float_model: Option<FloatModel>,
+    float_width: Option<FloatWidth>,
+}
+
+/// Gets the options a source file's directives set, or `None` if any of them
+/// is invalid.
+fn source_options(
+    directives: &[Spanned<Directive>],
+    diags: &mut Diagnostics
+) -> Option<SourceOptions> {
+    let mut valid = true;
+    let mut options = SourceOptions::default();
+
+    for (directive, span) in directives {
+        match directive {
+            Directive::Target(triple) => options.target.push(triple.clone()),
+            Directive::FastMath => options.float_model = Some(FloatModel::Relaxed),
+            Directive::FloatWidth(bits) => match FloatWidth::from_str(bits, false) {
+                Ok(width) => options.float_width = Some(width),
+                Err(_) => {
+                    diags.error_at(
+                        *span,
+                        format!("{}-bit floats aren't supported", bits),
+                        "expected 32, 64, or 80"
+                    );
+                    valid = false;
+                }
+            },
+            // already read before the file was parsed
+            Directive::Version(version) => if LangVersion::from_str(version, false).is_err() {
+                diags.error_at(
+                    *span,
+                    format!("there's no version {} of the language", version),
+                    "expected 1 or 2"
+                );
+                valid = false;
+            },
+        }
+    }
+    valid.then_some(options)
 }
 
 fn get_output_path(
@@ -0,0 +1,212 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{BufRead, Write},
+};
+
+/// The prefix of every mangled symbol. `.` can't appear in a C identifier, so
+/// the symbols can't collide with `main` or anything in the C runtime.
+const PREFIX: &str = "foo.";
+
+/// A Foo function's symbol, taken apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Demangled {
+    /// The name of the module the function is defined in: the source file's
+    /// stem, or the name given to `--module-name`.
+    pub module: String,
+    pub name: String,
+    pub arity: usize,
+}
+
+impl fmt::Display for Demangled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}/{}", self.module, self.name, self.arity)
+    }
+}
+
+/// Gets the symbol for a function defined in Foo: `foo.`, then its module, its
+/// name, and its number of parameters, separated by `.`, e.g. `foo.test.add.2`
+/// for `fn add x y` in `test.foo`. Any byte of the module or name other than an
+/// ASCII letter, digit, or `_` is written as `$` and its two hex digits, so
+/// neither can contain a `.`, and the symbol can be taken apart again.
+///
+/// The module and arity are there so that functions with the same name can be
+/// told apart by the linker once they can come from other modules or be
+/// overloaded.
+pub fn mangle(module: &str, name: &str, arity: usize) -> String {
+    format!("{}{}.{}.{}", PREFIX, escape(module), escape(name), arity)
+}
+
+/// Takes apart a symbol made by [`mangle`], or returns `None` if it isn't one.
+pub fn demangle(symbol: &str) -> Option<Demangled> {
+    let mut parts = symbol.strip_prefix(PREFIX)?.split('.');
+    let (Some(module), Some(name), Some(arity), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    // the arity is written without leading zeros, so each symbol has one
+    // spelling
+    if arity.is_empty() || (arity.starts_with('0') && arity != "0") {
+        return None;
+    }
+    Some(Demangled {
+        module: unescape(module)?,
+        name: unescape(name)?,
+        arity: arity.parse().ok()?,
+    })
+}
+
+/// Gets the name to show a symbol by in a diagnostic or report: demangled if
+/// it's a Foo function's, or else as it is.
+pub fn display(symbol: &str) -> String {
+    match demangle(symbol) {
+        Some(demangled) => demangled.to_string(),
+        None => symbol.to_owned(),
+    }
+}
+
+/// Prints each symbol demangled, or as it is if it isn't a Foo function's.
+/// Without any symbols, it reads text from stdin instead, and prints it with
+/// any symbols in it demangled, e.g. the output of `nm`.
+pub fn demangle_command(symbols: &[String]) -> Result<(), Box<dyn Error>> {
+    if !symbols.is_empty() {
+        for symbol in symbols {
+            println!("{}", display(symbol));
+        }
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        writeln!(stdout, "{}", demangle_text(&line?))?;
+    }
+    Ok(())
+}
+
+/// Demangles every symbol in a line of text.
+fn demangle_text(line: &str) -> String {
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$';
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(PREFIX) {
+        // a symbol can't start in the middle of a word
+        let starts_word = rest[..start].chars().next_back().is_none_or(|c| !is_symbol_char(c));
+        let len = rest[start..].find(|c| !is_symbol_char(c)).unwrap_or(rest.len() - start);
+        let word = &rest[start..start + len];
+        out.push_str(&rest[..start]);
+        match demangle(word).filter(|_| starts_word) {
+            Some(demangled) => out.push_str(&demangled.to_string()),
+            None => out.push_str(word),
+        }
+        rest = &rest[start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape(component: &str) -> String {
+    let mut escaped = String::new();
+    for byte in component.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'_' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("${:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn unescape(component: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = component.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte == b'$' {
+            let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &after[2..];
+        } else if byte.is_ascii_alphanumeric() || byte == b'_' {
+            bytes.push(byte);
+            rest = after;
+        } else {
+            return None;
+        }
+    }
+    if bytes.is_empty() {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{demangle, demangle_text, mangle, Demangled};
+
+    fn demangled(module: &str, name: &str, arity: usize) -> Demangled {
+        Demangled { module: module.to_owned(), name: name.to_owned(), arity }
+    }
+
+    #[test]
+    fn round_trips() {
+        for (module, name, arity) in [("test", "add", 2), ("a_b", "f_1", 0), ("m", "g", 255)] {
+            let symbol = mangle(module, name, arity);
+            assert_eq!(demangle(&symbol), Some(demangled(module, name, arity)));
+        }
+        assert_eq!(mangle("test", "add", 2), "foo.test.add.2");
+    }
+
+    #[test]
+    fn escapes_other_bytes() {
+        assert_eq!(mangle("my-lib", "f", 1), "foo.my$2dlib.f.1");
+        assert_eq!(mangle("a.b", "f", 1), "foo.a$2eb.f.1");
+        assert_eq!(demangle("foo.my$2dlib.f.1"), Some(demangled("my-lib", "f", 1)));
+        assert_eq!(demangle("foo.a$2eb.f.1"), Some(demangled("a.b", "f", 1)));
+    }
+
+    #[test]
+    fn escapes_non_ascii_names_byte_by_byte() {
+        assert_eq!(mangle("test", "área", 1), "foo.test.$c3$a1rea.1");
+        assert_eq!(demangle("foo.test.$c3$a1rea.1"), Some(demangled("test", "área", 1)));
+        assert_eq!(demangle(&mangle("数学", "π", 0)), Some(demangled("数学", "π", 0)));
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        // cut short, not hex, and not UTF-8
+        assert_eq!(demangle("foo.test.f$2.1"), None);
+        assert_eq!(demangle("foo.test.f$zz.1"), None);
+        assert_eq!(demangle("foo.test.$ff.1"), None);
+    }
+
+    #[test]
+    fn rejects_leading_zero_arity() {
+        assert_eq!(demangle("foo.test.f.02"), None);
+        assert_eq!(demangle("foo.test.f.00"), None);
+        assert_eq!(demangle("foo.test.f.0"), Some(demangled("test", "f", 0)));
+    }
+
+    #[test]
+    fn rejects_other_symbols() {
+        let symbols = ["main", "foo.test.f", "foo.test.f.1.2", "foo..f.1", "foo.test.f.", "bar.t.f.1"];
+        for symbol in symbols {
+            assert_eq!(demangle(symbol), None, "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn demangles_whole_words_in_text() {
+        assert_eq!(
+            demangle_text("0000000000001130 T foo.test.add.2"),
+            "0000000000001130 T test::add/2"
+        );
+        assert_eq!(
+            demangle_text("call foo.a.f.1, foo.b.g.0"),
+            "call a::f/1, b::g/0"
+        );
+        // inside a longer symbol, or not a whole symbol
+        assert_eq!(demangle_text("_foo.test.add.2"), "_foo.test.add.2");
+        assert_eq!(demangle_text("x$foo.test.add.2"), "x$foo.test.add.2");
+        assert_eq!(demangle_text("foo.test.add.2.3 foo."), "foo.test.add.2.3 foo.");
+        assert_eq!(demangle_text("(foo.test.add.2)"), "(test::add/2)");
+    }
+}
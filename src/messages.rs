@@ -0,0 +1,160 @@
+use std::{path::Path, process::Command, time::Duration};
+
+use serde::Serialize;
+
+use crate::MessageFormat;
+
+/// Reports the build's progress as newline-delimited JSON on stdout under
+/// `--message-format json`, so that wrappers and editors can follow it without
+/// scraping the human-readable output. Under the default format, nothing is
+/// reported.
+#[derive(Clone, Copy, Debug)]
+pub struct Messages {
+    json: bool,
+}
+
+/// A single build event, serialized as one line of JSON tagged with its
+/// `reason`, e.g. `{"reason":"artifact","kind":"object","path":"foo.o"}`.
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Message<'a> {
+    /// An error or warning about the program.
+    Diagnostic {
+        level: &'a str,
+        message: &'a str,
+        file: &'a Path,
+        /// Where in the source the diagnostic points, if anywhere.
+        location: Option<Location<'a>>,
+        /// Further locations the diagnostic points to.
+        related: Vec<Location<'a>>,
+        /// The diagnostic as it would have been printed for a human.
+        rendered: &'a str,
+    },
+    /// An output file was written.
+    Artifact {
+        /// What was written, e.g. `executable` or `llvm-ir`.
+        kind: &'a str,
+        path: &'a Path,
+    },
+    /// A step of the build failed, and its intermediate files were kept for
+    /// inspection.
+    KeptIntermediates {
+        path: &'a Path,
+    },
+    /// An external tool, such as the linker, was run.
+    Command {
+        program: String,
+        args: Vec<String>,
+    },
+    /// A phase of the build finished.
+    Timing {
+        /// The phase, e.g. `parse` or `link`.
+        phase: &'a str,
+        seconds: f64,
+        /// The most bytes the compiler had allocated at once during the phase,
+        /// with the `alloc-stats` feature.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        peak_bytes: Option<usize>,
+        /// The allocations the compiler made during the phase, with the
+        /// `alloc-stats` feature.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        allocations: Option<u64>,
+    },
+    /// How long a declaration, or the final expression, took in each phase,
+    /// under `--time`. These come slowest first.
+    DeclTiming {
+        /// `let`, `fn`, `extern fn`, or `final expression`.
+        kind: &'a str,
+        /// The declaration's name, or `null` for the final expression.
+        name: Option<&'a str>,
+        file: &'a Path,
+        line: u32,
+        column: u32,
+        /// The seconds taken to parse it, to check and evaluate it at compile
+        /// time, and to generate code for it.
+        parse: f64,
+        sema: f64,
+        codegen: f64,
+    },
+    /// The build is over, whether it succeeded or not. Always the last message.
+    BuildFinished {
+        success: bool,
+    },
+}
+
+/// The location a [`Message::Diagnostic`] points to.
+#[derive(Serialize)]
+pub struct Location<'a> {
+    /// The file the location is in, which for related locations can differ
+    /// from the diagnostic's.
+    pub file: &'a Path,
+    /// The span of the file, in bytes.
+    pub start: usize,
+    pub end: usize,
+    /// The line and column of `start`, counting from 1.
+    pub line: u32,
+    pub column: u32,
+    pub label: Option<&'a str>,
+}
+
+impl Messages {
+    pub fn new(format: MessageFormat) -> Messages {
+        Messages { json: format == MessageFormat::Json }
+    }
+
+    /// Whether messages are being reported, in which case diagnostics go to
+    /// stdout as messages rather than to stderr.
+    pub fn enabled(self) -> bool {
+        self.json
+    }
+
+    pub fn emit(self, message: Message) {
+        if self.json {
+            println!("{}", serde_json::to_string(&message).unwrap());
+        }
+    }
+
+    pub fn artifact(self, kind: &str, path: &Path) {
+        self.emit(Message::Artifact { kind, path });
+    }
+
+    /// Reports where a failed step's intermediate files were kept, as a
+    /// message or else a note on stderr.
+    pub fn kept_intermediates(self, path: &Path) {
+        if self.json {
+            self.emit(Message::KeptIntermediates { path });
+        } else {
+            eprintln!("note: intermediate files were kept in {}", path.display());
+        }
+    }
+
+    /// Reports that `command` is about to be run.
+    pub fn command(self, command: &Command) {
+        self.emit(Message::Command {
+            program: command.get_program().to_string_lossy().into_owned(),
+            args: command.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        });
+    }
+
+    /// Reports that a phase of the build finished, with the memory it used if
+    /// allocations are being counted. A phase is counted from when the one
+    /// before it finished.
+    pub fn timing(self, phase: &str, duration: Duration) {
+        #[cfg(feature = "alloc-stats")]
+        let (peak_bytes, allocations) = {
+            let (peak, allocations) = crate::alloc::take();
+            (Some(peak), Some(allocations))
+        };
+        #[cfg(not(feature = "alloc-stats"))]
+        let (peak_bytes, allocations) = (None, None);
+
+        self.emit(Message::Timing {
+            phase,
+            seconds: duration.as_secs_f64(),
+            peak_bytes,
+            allocations,
+        });
+    }
+}
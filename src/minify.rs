@@ -0,0 +1,267 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+};
+
+use chumsky::{span::SimpleSpan, Parser};
+
+use crate::{
+    artifact,
+    error::Diagnostics,
+    messages::Messages,
+    parse::{program, Cfg, ContractKind, Directive, Expr, FnAttr, Spanned},
+    prelude,
+    symbols::{SymbolKind, SymbolTable},
+    LangVersion,
+    MessageFormat,
+};
+
+/// Names that can't be given to a renamed declaration: the keywords, `result`,
+/// which means a function's result in an `ensures` clause, and `main`, the
+/// entry function's symbol.
+const RESERVED: [&str; 9] = [
+    "let", "fn", "extern", "export", "requires", "ensures", "include", "result", "main",
+];
+
+/// Rewrites a source file as compactly as it can be written: each variable,
+/// parameter, and function gets the shortest name free, the most used ones
+/// first, and the program is printed on one line, after its directives and
+/// includes. Exported and `extern` functions keep their names, since they're
+/// the symbols the program links with, as do names that don't resolve to a
+/// declaration in the file, like builtins and the functions of included files.
+///
+/// The result is written to `output`, or printed if there isn't one. It's
+/// never written over the source, and only over another file with `force`.
+pub fn minify(src_path: &Path, output: Option<&Path>, force: bool) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+    if let Some(path) = output {
+        artifact::check_output(path, [src_path], force)?;
+    }
+
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let parsed = program(LangVersion::of(None, &src).features())
+        .parse(&src)
+        .into_result()
+        .unwrap_or_else(|errs| {
+            diags.syntax_errors(errs);
+            diags.abort();
+        });
+
+    let table = SymbolTable::build(&parsed.body);
+    let exported = exported(&parsed.body);
+    let (kept, mut renamed): (Vec<_>, Vec<_>) = table.symbols.iter()
+        .partition(|symbol| symbol.kind == SymbolKind::Extern || exported.contains(&symbol.decl));
+    let mut taken = RESERVED.iter()
+        .map(|name| name.to_string())
+        .chain(prelude::BUILTINS.iter().map(|builtin| builtin.name.to_owned()))
+        .chain(kept.iter().map(|symbol| symbol.name.clone()))
+        .chain(table.unresolved.iter().map(|(name, _)| name.clone()))
+        .collect::<HashSet<_>>();
+
+    // the shortest names go to the names written the most
+    renamed.sort_by_key(|symbol| std::cmp::Reverse(symbol.refs.len()));
+    let mut names = HashMap::new();
+    let mut next = 0;
+    for symbol in renamed {
+        let name = loop {
+            let name = short_name(next);
+            next += 1;
+            if taken.insert(name.clone()) {
+                break name;
+            }
+        };
+        for span in symbol.refs.iter().chain([&symbol.decl]) {
+            names.insert(span.start, name.clone());
+        }
+    }
+
+    let mut out = String::new();
+    for (directive, _) in &parsed.directives {
+        out.push_str(&match directive {
+            Directive::Target(triple) => format!("#![target(\"{}\")]", triple),
+            Directive::FastMath => "#![fast-math]".to_owned(),
+            Directive::FloatWidth(bits) => format!("#![float-width({})]", bits),
+            Directive::Version(version) => format!("#![version({})]", version),
+        });
+        out.push('\n');
+    }
+    for (path, _) in &parsed.includes {
+        out.push_str(&format!("include \"{}\"\n", path));
+    }
+    let printer = Printer { src: &src, names };
+    printer.decls(&parsed.body, &mut out);
+    out.push('\n');
+
+    match output {
+        Some(path) => artifact::write(path, out)?,
+        None => print!("{}", out),
+    }
+    Ok(())
+}
+
+/// Gets the spans of the names of the exported functions.
+fn exported(root: &Expr) -> HashSet<SimpleSpan> {
+    let mut exported = HashSet::new();
+    let mut e = root;
+    loop {
+        e = match e {
+            Expr::Fn { export, name, then, .. } => {
+                if *export {
+                    exported.insert(name.1);
+                }
+                then
+            }
+            Expr::Let { then, .. } | Expr::Extern { then, .. } => then,
+            _ => return exported,
+        };
+    }
+}
+
+/// Gets the `n`th name in the order `a`, ..., `z`, `aa`, `ab`, ...
+fn short_name(mut n: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+struct Printer<'a> {
+    src: &'a str,
+    /// The new names of the renamed declarations and references, by the start
+    /// of their spans.
+    names: HashMap<usize, String>,
+}
+
+impl Printer<'_> {
+    fn name<'n>(&'n self, (name, span): &'n Spanned<String>) -> &'n str {
+        self.names.get(&span.start).map_or(name, String::as_str)
+    }
+
+    /// Prints a chain of declarations and the final expression, each
+    /// declaration ended with a `;`, which every version of the language
+    /// accepts.
+    fn decls(&self, root: &Expr, out: &mut String) {
+        let mut e = root;
+        loop {
+            e = match e {
+                Expr::Let { cfg, name, rhs, then, .. } => {
+                    self.cfgs(cfg.iter().map(|(cfg, _)| cfg), out);
+                    out.push_str(&format!("let {}={};", self.name(name), self.expr(rhs, 0)));
+                    then
+                }
+                Expr::Fn { cfg, attrs, export, name, args, contracts, body, then, .. } => {
+                    self.cfgs(cfg.iter().map(|(cfg, _)| cfg), out);
+                    for (attr, _) in attrs {
+                        out.push_str(match attr {
+                            FnAttr::Inline => "@inline ",
+                            FnAttr::NoInline => "@noinline ",
+                            FnAttr::Cold => "@cold ",
+                        });
+                    }
+                    if *export {
+                        out.push_str("export ");
+                    }
+                    out.push_str("fn ");
+                    out.push_str(self.name(name));
+                    for arg in args {
+                        out.push(' ');
+                        out.push_str(self.name(arg));
+                    }
+                    for (contract, _) in contracts {
+                        out.push_str(match contract.kind {
+                            ContractKind::Requires => " requires ",
+                            ContractKind::Ensures => " ensures ",
+                        });
+                        out.push_str(&self.expr(&contract.cond, 0));
+                    }
+                    out.push_str(&format!("={};", self.expr(body, 0)));
+                    then
+                }
+                Expr::Extern { cfg, name, args, then, .. } => {
+                    self.cfgs(cfg.iter().map(|(cfg, _)| cfg), out);
+                    out.push_str("extern fn ");
+                    out.push_str(self.name(name));
+                    for arg in args {
+                        out.push(' ');
+                        out.push_str(self.name(arg));
+                    }
+                    out.push(';');
+                    then
+                }
+                expr => {
+                    out.push_str(&self.expr(expr, 0));
+                    return;
+                }
+            };
+        }
+    }
+
+    fn cfgs<'c>(&self, cfgs: impl Iterator<Item = &'c Cfg>, out: &mut String) {
+        for cfg in cfgs {
+            out.push_str(&format!("#[cfg({})]", cfg_string(cfg)));
+        }
+    }
+
+    /// Prints an expression, parenthesized if it binds less tightly than
+    /// `precedence` (0 for none, 1 for comparisons, 2 for `+` and `-`, 3 for
+    /// `*` and `/`, and 4 for negation).
+    fn expr(&self, expr: &Expr, precedence: u8) -> String {
+        let (code, own) = match expr {
+            Expr::Num(_, span) => (self.src[span.into_range()].to_owned(), 5),
+            Expr::Var(name, span) => {
+                (self.names.get(&span.start).unwrap_or(name).clone(), 5)
+            }
+            Expr::Call(name, args, _) => {
+                let args = args.iter()
+                    .map(|arg| self.expr(arg, 0))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                (format!("{}({})", self.name(name), args), 5)
+            }
+            Expr::Neg(expr, _) => (format!("-{}", self.expr(expr, 4)), 4),
+            // each operator is left associative, so a right operand of the
+            // same precedence is parenthesized
+            Expr::Cmp(op, left, right, _) => (
+                format!("{}{}{}", self.expr(left, 1), op.symbol(), self.expr(right, 2)),
+                1
+            ),
+            Expr::Add(left, right, _) => {
+                (format!("{}+{}", self.expr(left, 2), self.expr(right, 3)), 2)
+            }
+            Expr::Sub(left, right, _) => {
+                (format!("{}-{}", self.expr(left, 2), self.expr(right, 3)), 2)
+            }
+            Expr::Mul(left, right, _) => {
+                (format!("{}*{}", self.expr(left, 3), self.expr(right, 4)), 3)
+            }
+            Expr::Div(left, right, _) => {
+                (format!("{}/{}", self.expr(left, 3), self.expr(right, 4)), 3)
+            }
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => unreachable!(),
+        };
+        if own < precedence {
+            format!("({})", code)
+        } else {
+            code
+        }
+    }
+}
+
+fn cfg_string(cfg: &Cfg) -> String {
+    let list = |cfgs: &[Cfg]| cfgs.iter().map(cfg_string).collect::<Vec<_>>().join(",");
+    match cfg {
+        Cfg::Target(target) => format!("target=\"{}\"", target),
+        Cfg::Not(cfg) => format!("not({})", cfg_string(cfg)),
+        Cfg::Any(cfgs) => format!("any({})", list(cfgs)),
+        Cfg::All(cfgs) => format!("all({})", list(cfgs)),
+    }
+}
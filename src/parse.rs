@@ -1,4 +1,5 @@
 use chumsky::{extra::Err, prelude::*};
+use unicode_normalization::UnicodeNormalization;
 
 // The following `parser()` function, aside from some tweaks for personal use
 // case, is derived primarily from Chumsky's `foo` example. Chumsky's repository
@@ -26,158 +27,577 @@ use chumsky::{extra::Err, prelude::*};
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-type Spanned<T> = (T, SimpleSpan);
+pub type Spanned<T> = (T, SimpleSpan);
 
-pub fn parser<'src>() -> impl Parser<'src, &'src str, Expr, Err<Rich<'src, char>>> {
-    let ident = text::ascii::ident()
-        .padded()
-        .map_with(|ident: &str, extra| (ident.to_owned(), extra.span()));
+pub fn parser<'src>(
+    features: Features
+) -> impl Parser<'src, &'src str, Expr, Err<Rich<'src, char>>> {
+    // Declarations can be ended by a newline instead of a `;`, so only
+    // operands skip newlines before themselves, and no token skips them after.
+    // A line ending in an operator is waiting on its right operand, so it
+    // continues onto the next. Without that, every token skips newlines like
+    // any other whitespace.
+    let (space, end) = if features.optional_semicolons {
+        (
+            text::inline_whitespace().boxed(),
+            text::inline_whitespace()
+                .ignore_then(just(';').ignored().or(text::newline()))
+                .boxed(),
+        )
+    } else {
+        (
+            text::whitespace().boxed(),
+            text::whitespace().ignore_then(just(';')).ignored().boxed(),
+        )
+    };
+
+    // identifiers are compared in NFC, so that the same name typed with
+    // precomposed or combining characters is the same identifier
+    let ident = space.clone()
+        .ignore_then(
+            text::unicode::ident()
+                .map_with(|ident: &str, extra| (ident.nfc().collect(), extra.span()))
+        )
+        .then_ignore(space.clone());
 
     let expr = recursive(|expr| {
-        let int = text::int(10).map_with(|s: &str, extra|
-            Expr::Num(s.parse().unwrap(), Some(extra.span()))
-        );
+        let int = text::int(10).try_map(|s: &str, span| match s.parse::<f64>() {
+            Ok(val) if val.is_finite() => Ok(Expr::Num(val, span)),
+            _ => Err(Rich::custom(span, "integer literal is too large to represent")),
+        });
 
         let call =
-            ident
+            ident.clone()
             .then(
                 expr.clone()
                     .separated_by(just(','))
                     .allow_trailing()
                     .collect::<Vec<_>>()
-                    .delimited_by(just('('), just(')')),
+                    .delimited_by(just('('), text::whitespace().then(just(')'))),
             )
             .map_with(|(f, args), extra|
-                Expr::Call(f, args, Some(extra.span()))
+                Expr::Call(f, args, extra.span())
             );
 
-        let atom =
-            int
-            .or(expr.delimited_by(just('('), just(')')))
-            .or(call)
-            .or(
-                ident.map(|(ident, span)| Expr::Var(ident, Some(span)))
+        let atom = text::whitespace()
+            .ignore_then(
+                int
+                .or(expr.delimited_by(just('('), text::whitespace().then(just(')'))))
+                .or(call)
+                .or(
+                    ident.clone().map(|(ident, span)| Expr::Var(ident, span))
+                )
             )
-            .padded();
+            .then_ignore(space.clone());
 
-        let op = |c| just(c).padded();
+        // binary operators have to be on the same line as their left operand
+        // if newlines end declarations; it has already skipped the spaces
+        // before them
+        let op = |c| just(c);
 
-        let unary = op('-')
+        // each fold gets the span from its operator to the end of its operand
+        let unary = just('-')
+            .padded()
             .repeated() // <- allow any number of consecutive negative signs
-            .foldr(atom, |_op, rhs| Expr::Neg(Box::new(rhs), None))
-            .map_with(|mut expr, extra| { expr.set_span(extra.span()); expr });
+            .foldr_with(atom, |_op, rhs, extra| Expr::Neg(Box::new(rhs), extra.span()));
 
-        let product = unary.clone().foldl(
+        // each fold gets the span from the start of the leftmost operand
+        let product = unary.clone().foldl_with(
             choice(( // tuple structs are implicitly functions
                 op('*').to(Expr::Mul as fn(_, _, _) -> _),
                 op('/').to(Expr::Div as fn(_, _, _) -> _),
             ))
             .then(unary)
             .repeated(),
-            |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs), None),
-        )
-            .map_with(|mut expr, extra| { expr.set_span(extra.span()); expr });
+            |lhs, (op, rhs), extra| op(Box::new(lhs), Box::new(rhs), extra.span()),
+        );
 
-        let sum = product.clone().foldl(
+        let sum = product.clone().foldl_with(
             choice((
                 op('+').to(Expr::Add as fn(_, _, _) -> _),
                 op('-').to(Expr::Sub as fn(_, _, _) -> _),
             ))
             .then(product)
             .repeated(),
-            |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs), None),
-        )
-            .map_with(|mut expr, extra| { expr.set_span(extra.span()); expr });
+            |lhs, (op, rhs), extra| op(Box::new(lhs), Box::new(rhs), extra.span()),
+        );
+
+        // the two-character operators have to be tried before their prefixes
+        let cmp = sum.clone().foldl_with(
+            choice((
+                just("<=").to(CmpOp::Le),
+                just(">=").to(CmpOp::Ge),
+                just("==").to(CmpOp::Eq),
+                just("!=").to(CmpOp::Ne),
+                op('<').to(CmpOp::Lt),
+                op('>').to(CmpOp::Gt),
+            ))
+            .then(sum)
+            .repeated(),
+            |lhs, (op, rhs), extra| Expr::Cmp(op, Box::new(lhs), Box::new(rhs), extra.span()),
+        );
 
-        sum
+        cmp
     });
 
+    let cfg = recursive(|cfg| {
+        let list = cfg.clone()
+            .separated_by(just(','))
+            .allow_trailing()
+            .collect::<Vec<_>>()
+            .delimited_by(just('('), text::whitespace().then(just(')')));
+
+        let target = text::ascii::ident()
+            .then_ignore(just('=').padded())
+            .then(
+                none_of("\"\n")
+                    .repeated()
+                    .to_slice()
+                    .delimited_by(just('"'), just('"'))
+            )
+            // an unknown key is reported without failing the parse, which
+            // would only say what can't come after `#[cfg(`
+            .validate(|(key, value): (&str, &str), extra, emitter| {
+                if key != "target" {
+                    emitter.emit(Rich::custom(
+                        extra.span(),
+                        format!("unknown `cfg` key `{}`; expected `target`", key)
+                    ));
+                }
+                Cfg::Target(value.to_owned())
+            });
+
+        choice((
+            text::ascii::keyword("not")
+                .ignore_then(cfg.delimited_by(just('('), just(')')))
+                .map(|cfg| Cfg::Not(Box::new(cfg))),
+            text::ascii::keyword("any").ignore_then(list.clone()).map(Cfg::Any),
+            text::ascii::keyword("all").ignore_then(list).map(Cfg::All),
+            target,
+        ))
+        .padded()
+    });
+
+    // `#[cfg(...)]` attributes can be placed before any declaration
+    let cfgs = just("#[cfg")
+        .ignore_then(cfg.delimited_by(just('('), just(')')))
+        .then_ignore(just(']'))
+        .map_with(|cfg, extra| (cfg, extra.span()))
+        .padded()
+        .repeated()
+        .collect::<Vec<_>>();
+
     let decl = recursive(|decl| {
-        let r#let = text::ascii::keyword("let")
-            .ignore_then(ident)
+        let r#let = cfgs.clone()
+            .then_ignore(text::ascii::keyword("let"))
+            .then(ident.clone())
             .then_ignore(just('='))
             .then(expr.clone())
-            .then_ignore(just(';'))
+            .then_ignore(end.clone())
             .then(decl.clone())
-            .map_with(|((name, rhs), then), extra | Expr::Let {
+            .map_with(|(((cfg, name), rhs), then), extra | Expr::Let {
+                cfg,
                 name,
                 rhs: Box::new(rhs),
                 then: Box::new(then),
-                span: Some(extra.span()),
+                span: extra.span(),
             });
 
-        let r#fn = text::ascii::keyword("fn")
-            .ignore_then(ident)
+        let annotation = just('@')
+            .ignore_then(text::ascii::ident())
+            .try_map(|name: &str, span| match name {
+                "inline" => Ok(FnAttr::Inline),
+                "noinline" => Ok(FnAttr::NoInline),
+                "cold" => Ok(FnAttr::Cold),
+                _ => Err(Rich::custom(span, format!("unknown annotation `@{}`", name))),
+            })
+            .map_with(|attr, extra| (attr, extra.span()))
+            .padded();
+
+        // `requires <cond>` and `ensures <cond>` clauses between a function's
+        // parameters and its body, e.g. `fn f x requires x = 1 / x;`
+        let contract = text::ascii::keyword("requires")
+            .to(ContractKind::Requires)
+            .or(text::ascii::keyword("ensures").to(ContractKind::Ensures))
+            .then(expr.clone())
+            .map_with(|(kind, cond), extra| {
+                let text: &str = extra.slice();
+                let text = text.trim_end().to_owned();
+                (Contract { kind, cond, text }, extra.span())
+            })
+            .padded();
+
+        let r#fn = cfgs.clone()
             .then(
-                ident.repeated()
+                annotation
+                    .repeated()
+                    .collect::<Vec<_>>()
+            )
+            .then(
+                text::ascii::keyword("export")
+                    .padded()
+                    .or_not()
+                    .map(|export| export.is_some())
+            )
+            .then_ignore(text::ascii::keyword("fn"))
+            .then(ident.clone())
+            .then(
+                ident.clone()
+                    .filter(|(arg, _): &Spanned<String>| arg != "requires" && arg != "ensures")
+                    .repeated()
+                    .collect::<Vec<_>>()
+            )
+            .then(
+                contract
+                    .repeated()
                     .collect::<Vec<_>>()
             )
             .then_ignore(just('='))
             .then(expr.clone())
-            .then_ignore(just(';'))
-            .then(decl)
-            .map_with(|(((name, args), body), then), extra|
+            .then_ignore(end.clone())
+            .then(decl.clone())
+            .map_with(
+                |(((((((cfg, attrs), export), name), args), contracts), body), then), extra|
                 Expr::Fn {
+                    cfg,
+                    attrs,
+                    export,
                     name,
                     args,
+                    contracts,
                     body: Box::new(body),
                     then: Box::new(then),
-                    span: Some(extra.span()),
+                    span: extra.span(),
+                }
+            );
+
+        let r#extern = cfgs
+            .then_ignore(text::ascii::keyword("extern").padded())
+            .then_ignore(text::ascii::keyword("fn"))
+            .then(ident.clone())
+            .then(
+                ident.clone().repeated()
+                    .collect::<Vec<_>>()
+            )
+            .then_ignore(end.clone())
+            .then(decl)
+            .map_with(|(((cfg, name), args), then), extra|
+                Expr::Extern {
+                    cfg,
+                    name,
+                    args,
+                    then: Box::new(then),
+                    span: extra.span(),
                 }
             );
 
-        r#let.or(r#fn).or(expr).padded()
+        r#let.or(r#fn).or(r#extern).or(expr).padded()
     });
 
     decl
 }
 
+/// Parses a whole source file: the directives at the top of it, e.g.
+/// `#![fast-math]`, then the files it includes, e.g. `include "common.foo"`,
+/// each on its own line, then the program, in the version of the language
+/// `features` are from.
+pub fn program<'src>(
+    features: Features
+) -> impl Parser<'src, &'src str, Program, Err<Rich<'src, char>>> {
+    let include = text::ascii::keyword("include")
+        .ignore_then(
+            none_of("\"\n")
+                .repeated()
+                .to_slice()
+                .delimited_by(just('"'), just('"'))
+                .map_with(|path: &str, extra| (path.to_owned(), extra.span()))
+                .padded_by(text::inline_whitespace())
+        )
+        .then_ignore(just(';').ignored().or(text::newline()));
+
+    directives()
+        .then(
+            text::whitespace()
+                .ignore_then(include)
+                .repeated()
+                .collect::<Vec<_>>()
+        )
+        .then(parser(features))
+        .map(|((directives, includes), body)| Program {
+            directives,
+            includes,
+            body,
+        })
+}
+
+/// Parses the directives at the top of a source file. Their syntax is the same
+/// in every version of the language, so they can be read on their own before
+/// the rest of the file, to find its `#![version(...)]`.
+pub fn directives<'src>(
+) -> impl Parser<'src, &'src str, Vec<Spanned<Directive>>, Err<Rich<'src, char>>> {
+    let name = any()
+        .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .repeated()
+        .at_least(1)
+        .to_slice();
+
+    let arg = choice((
+        none_of("\"\n")
+            .repeated()
+            .to_slice()
+            .delimited_by(just('"'), just('"'))
+            .map(DirectiveArg::Str),
+        text::int(10).map(DirectiveArg::Int),
+    ))
+    .padded()
+    .delimited_by(just('('), just(')'));
+
+    let directive = just("#![")
+        .ignore_then(name)
+        .then(arg.or_not())
+        .then_ignore(just(']'))
+        // invalid directives are reported without failing the parse, which
+        // would only say the program can't start with `#`
+        .validate(|(name, arg), extra, emitter| {
+            let span = extra.span();
+            let usage = match name {
+                "target" => "#![target(\"<triple>\")]",
+                "fast-math" => "#![fast-math]",
+                "float-width" => "#![float-width(<bits>)]",
+                "version" => "#![version(<n>)]",
+                _ => {
+                    emitter.emit(Rich::custom(span, format!("unknown directive `{}`", name)));
+                    return None;
+                }
+            };
+            let directive = match (name, arg) {
+                ("target", Some(DirectiveArg::Str(triple))) => {
+                    Directive::Target(triple.to_owned())
+                }
+                ("fast-math", None) => Directive::FastMath,
+                ("float-width", Some(DirectiveArg::Int(bits))) => {
+                    Directive::FloatWidth(bits.to_owned())
+                }
+                ("version", Some(DirectiveArg::Int(version))) => {
+                    Directive::Version(version.to_owned())
+                }
+                _ => {
+                    emitter.emit(Rich::custom(span, format!("expected `{}`", usage)));
+                    return None;
+                }
+            };
+            Some((directive, span))
+        })
+        .padded_by(text::inline_whitespace())
+        .then_ignore(just(';').ignored().or(text::newline()));
+
+    text::whitespace()
+        .ignore_then(directive)
+        .repeated()
+        .collect::<Vec<_>>()
+        .map(|directives| directives.into_iter().flatten().collect())
+}
+
+/// The parts of the language that changed between its versions, which the
+/// frontend checks to parse and check a program the way its version did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Features {
+    /// Declarations can end at the end of their line instead of with a `;`,
+    /// and binary operators have to be on the same line as their left
+    /// operand. Since version 2.
+    pub optional_semicolons: bool,
+}
+
+/// The argument given to a directive, e.g. the `"wasm32-wasi"` of
+/// `#![target("wasm32-wasi")]`.
+#[derive(Clone, Copy)]
+enum DirectiveArg<'src> {
+    Str(&'src str),
+    Int(&'src str),
+}
+
+/// A source file: the directives it sets the compiler's options with, the
+/// files it includes, and the program itself.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub directives: Vec<Spanned<Directive>>,
+    /// The paths of the included files, as written.
+    pub includes: Vec<Spanned<String>>,
+    pub body: Expr,
+}
+
+/// Compiler options set from the top of a source file, e.g.
+/// `#![target("wasm32-wasi")]`. Options given on the command line take
+/// precedence over them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Directive {
+    /// `#![target("<triple>")]` - a target to build for, like `--target`.
+    /// Can be given more than once.
+    Target(String),
+    /// `#![fast-math]` - like `--float-model relaxed`.
+    FastMath,
+    /// `#![float-width(<bits>)]` - like `--float-width`.
+    FloatWidth(String),
+    /// `#![version(<n>)]` - like `--lang-version`.
+    Version(String),
+}
+
 /// Abstract Syntax Tree for Foo. This is modified from Chumsky's example to
 /// include spans for diagnostic reporting.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Expr {
-    Num(f64, Option<SimpleSpan>),
-    Var(String, Option<SimpleSpan>),
+    Num(f64, SimpleSpan),
+    Var(String, SimpleSpan),
 
-    Neg(Box<Expr>, Option<SimpleSpan>),
-    Add(Box<Expr>, Box<Expr>, Option<SimpleSpan>),
-    Sub(Box<Expr>, Box<Expr>, Option<SimpleSpan>),
-    Mul(Box<Expr>, Box<Expr>, Option<SimpleSpan>),
-    Div(Box<Expr>, Box<Expr>, Option<SimpleSpan>),
+    Neg(Box<Expr>, SimpleSpan),
+    Add(Box<Expr>, Box<Expr>, SimpleSpan),
+    Sub(Box<Expr>, Box<Expr>, SimpleSpan),
+    Mul(Box<Expr>, Box<Expr>, SimpleSpan),
+    Div(Box<Expr>, Box<Expr>, SimpleSpan),
+    /// A comparison, which is 1 if it holds and 0 if it doesn't.
+    Cmp(CmpOp, Box<Expr>, Box<Expr>, SimpleSpan),
 
-    Call(Spanned<String>, Vec<Expr>, Option<SimpleSpan>),
+    Call(Spanned<String>, Vec<Expr>, SimpleSpan),
     Let {
+        cfg: Vec<Spanned<Cfg>>,
         name: Spanned<String>,
         rhs: Box<Expr>,
         then: Box<Expr>,
-        span: Option<SimpleSpan>,
+        span: SimpleSpan,
     },
     Fn {
+        cfg: Vec<Spanned<Cfg>>,
+        attrs: Vec<Spanned<FnAttr>>,
+        /// Whether the function was declared with `export`, making it visible
+        /// outside of the program under its own name.
+        export: bool,
         name: Spanned<String>,
         args: Vec<Spanned<String>>,
+        contracts: Vec<Spanned<Contract>>,
         body: Box<Expr>,
         then: Box<Expr>,
-        span: Option<SimpleSpan>,
+        span: SimpleSpan,
+    },
+    /// Declaration of a function defined outside of Foo, e.g. in a C file
+    /// linked with the program.
+    Extern {
+        cfg: Vec<Spanned<Cfg>>,
+        name: Spanned<String>,
+        args: Vec<Spanned<String>>,
+        then: Box<Expr>,
+        span: SimpleSpan,
     },
 }
 
+/// The operator of an [`Expr::Cmp`]. Comparisons with NaN don't hold, except
+/// for `!=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    /// `<`
+    Lt,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `>=`
+    Ge,
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+}
+
+impl CmpOp {
+    /// The operator as written, e.g. `<=`.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CmpOp::Lt => "<",
+            CmpOp::Gt => ">",
+            CmpOp::Le => "<=",
+            CmpOp::Ge => ">=",
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+        }
+    }
+
+    /// Whether the comparison holds for two values.
+    pub fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            CmpOp::Lt => left < right,
+            CmpOp::Gt => left > right,
+            CmpOp::Le => left <= right,
+            CmpOp::Ge => left >= right,
+            CmpOp::Eq => left == right,
+            CmpOp::Ne => left != right,
+        }
+    }
+}
+
+/// The condition of a `#[cfg(...)]` attribute, which can be placed before any
+/// declaration to leave it out of the program unless the condition holds, e.g.
+/// `#[cfg(target = "windows")] extern fn _getch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cfg {
+    /// `target = "<name>"` - the target being built for is the triple
+    /// `<name>`, or has `<name>` as its architecture, vendor, OS, or
+    /// environment.
+    Target(String),
+    /// `not(<cfg>)`
+    Not(Box<Cfg>),
+    /// `any(<cfg>, ...)` - at least one of the conditions holds.
+    Any(Vec<Cfg>),
+    /// `all(<cfg>, ...)` - every one of the conditions holds.
+    All(Vec<Cfg>),
+}
+
+/// Annotations that can be placed before a function declaration, e.g.
+/// `@noinline fn f x = x;`, to set LLVM attributes on the function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FnAttr {
+    /// `@inline` - always inline calls to the function.
+    Inline,
+    /// `@noinline` - never inline calls to the function.
+    NoInline,
+    /// `@cold` - the function is rarely called.
+    Cold,
+}
+
+/// A condition a function's calls are checked against, e.g. `requires x`,
+/// which holds if it isn't 0 or NaN.
+#[derive(Clone, Debug)]
+pub struct Contract {
+    pub kind: ContractKind,
+    pub cond: Expr,
+    /// The clause as written, e.g. `requires x - 1`, for reporting it.
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractKind {
+    /// `requires <cond>` - holds for the arguments when the function is
+    /// called.
+    Requires,
+    /// `ensures <cond>` - holds when the function returns, where `result` is
+    /// the value it returns.
+    Ensures,
+}
+
 impl Expr {
-    /// Fill the `span` field of any of the `Expr` types, regardless of type.
-    /// Some of the parsers construct the `Expr` before calling `map_with()` to
-    /// add the span, so this method saves on in-parser logic.
-    pub fn set_span(&mut self, span: SimpleSpan) {
-        let s = match self {
-            Expr::Num(_, s) => s,
-            Expr::Var(_, s) => s,
-            Expr::Neg(_, s) => s,
-            Expr::Add(_, _, s) => s,
-            Expr::Sub(_, _, s) => s,
-            Expr::Mul(_, _, s) => s,
-            Expr::Div(_, _, s) => s,
-            Expr::Call(_, _, s) => s,
-            Expr::Let { span: s, .. } => s,
-            Expr::Fn { span: s, .. } => s,
-        };
-        *s = Some(span);
+    /// Get the `span` field of any of the `Expr` types, regardless of type.
+    pub fn span(&self) -> SimpleSpan {
+        match self {
+            Expr::Num(_, s) => *s,
+            Expr::Var(_, s) => *s,
+            Expr::Neg(_, s) => *s,
+            Expr::Add(_, _, s) => *s,
+            Expr::Sub(_, _, s) => *s,
+            Expr::Mul(_, _, s) => *s,
+            Expr::Div(_, _, s) => *s,
+            Expr::Cmp(_, _, _, s) => *s,
+            Expr::Call(_, _, s) => *s,
+            Expr::Let { span: s, .. } => *s,
+            Expr::Fn { span: s, .. } => *s,
+            Expr::Extern { span: s, .. } => *s,
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+/// A function every Foo program can call without declaring it, e.g. `sqrt(x)`.
+/// A function the program defines or declares with the same name takes the
+/// builtin's place.
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    /// The LLVM intrinsic the function is generated as, e.g. `llvm.sqrt`.
+    pub intrinsic: &'static str,
+    /// Computes the function, for evaluating calls to it at compile time.
+    pub eval: fn(&[f64]) -> f64,
+}
+
+/// The prelude: every builtin function.
+pub static BUILTINS: [Builtin; 13] = [
+    Builtin { name: "sqrt", params: &["x"], intrinsic: "llvm.sqrt", eval: |a| a[0].sqrt() },
+    Builtin { name: "sin", params: &["x"], intrinsic: "llvm.sin", eval: |a| a[0].sin() },
+    Builtin { name: "cos", params: &["x"], intrinsic: "llvm.cos", eval: |a| a[0].cos() },
+    Builtin { name: "exp", params: &["x"], intrinsic: "llvm.exp", eval: |a| a[0].exp() },
+    Builtin { name: "log", params: &["x"], intrinsic: "llvm.log", eval: |a| a[0].ln() },
+    Builtin {
+        name: "pow",
+        params: &["x", "y"],
+        intrinsic: "llvm.pow",
+        eval: |a| a[0].powf(a[1]),
+    },
+    Builtin { name: "abs", params: &["x"], intrinsic: "llvm.fabs", eval: |a| a[0].abs() },
+    Builtin { name: "floor", params: &["x"], intrinsic: "llvm.floor", eval: |a| a[0].floor() },
+    Builtin { name: "ceil", params: &["x"], intrinsic: "llvm.ceil", eval: |a| a[0].ceil() },
+    // rounds halfway cases away from zero, like C's `round`
+    Builtin { name: "round", params: &["x"], intrinsic: "llvm.round", eval: |a| a[0].round() },
+    // both ignore a NaN argument, like C's `fmin` and `fmax`
+    Builtin {
+        name: "min",
+        params: &["x", "y"],
+        intrinsic: "llvm.minnum",
+        eval: |a| a[0].min(a[1]),
+    },
+    Builtin {
+        name: "max",
+        params: &["x", "y"],
+        intrinsic: "llvm.maxnum",
+        eval: |a| a[0].max(a[1]),
+    },
+    // computes `a * b + c` with a single rounding
+    Builtin {
+        name: "fma",
+        params: &["a", "b", "c"],
+        intrinsic: "llvm.fma",
+        eval: |a| a[0].mul_add(a[1], a[2]),
+    },
+];
+
+/// Gets the builtin function with the given name, if there is one.
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
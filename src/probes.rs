@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// What the compiler found out about the machine it runs on the last time it
+/// had to look: the default target triple, the LLVM target each triple it
+/// built for resolved to, and the linker that could be run. They're kept in a
+/// cache file, so later runs can skip initializing every LLVM target and
+/// trying each linker in turn.
+///
+/// The results are keyed by the environment they were found in (the compiler's
+/// version, `PATH`, and `CC`), and found again when it changes, or when
+/// `--refresh-probes` is given.
+///
+/// Of the target machine's configuration, only the target is cached. The rest
+/// isn't probed, so there's nothing to save: the CPU and its features are
+/// always the target's generic ones (see [`crate::llvm::machine_from_target`]),
+/// not the host's, and the relocation model comes from `--no-pie` on each run.
+#[derive(Serialize, Deserialize, Default)]
+struct Probes {
+    key: u64,
+    default_triple: Option<String>,
+    /// The name of the LLVM target each triple resolved to, e.g. `x86-64`.
+    targets: HashMap<String, String>,
+    linker: Option<String>,
+}
+
+static PROBES: OnceLock<Mutex<Probes>> = OnceLock::new();
+
+/// Gets the cached probe results, loading them the first time. Results found
+/// in another environment are dropped.
+fn probes() -> &'static Mutex<Probes> {
+    PROBES.get_or_init(|| {
+        let key = environment_key();
+        let probes = cache_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str::<Probes>(&json).ok())
+            .filter(|probes| probes.key == key)
+            .unwrap_or(Probes { key, ..Probes::default() });
+        Mutex::new(probes)
+    })
+}
+
+/// Forgets the cached results, so everything is probed again and the cache is
+/// rewritten, for `--refresh-probes`.
+pub fn refresh() {
+    let mut probes = probes().lock().unwrap();
+    *probes = Probes { key: environment_key(), ..Probes::default() };
+    save(&probes);
+}
+
+/// Gets the default target triple, probing it with `probe` if it isn't cached.
+pub fn default_triple(probe: impl FnOnce() -> String) -> String {
+    let mut probes = probes().lock().unwrap();
+    if let Some(triple) = &probes.default_triple {
+        return triple.clone();
+    }
+    let triple = probe();
+    probes.default_triple = Some(triple.clone());
+    save(&probes);
+    triple
+}
+
+/// Gets the name of the LLVM target a triple resolved to before, if it's
+/// cached.
+pub fn target(triple: &str) -> Option<String> {
+    probes().lock().unwrap().targets.get(triple).cloned()
+}
+
+/// Records the name of the LLVM target a triple resolved to.
+pub fn set_target(triple: &str, name: &str) {
+    let mut probes = probes().lock().unwrap();
+    if probes.targets.get(triple).map(String::as_str) != Some(name) {
+        probes.targets.insert(triple.to_owned(), name.to_owned());
+        save(&probes);
+    }
+}
+
+/// Gets the linker that could be run before, if it's cached.
+pub fn linker() -> Option<String> {
+    probes().lock().unwrap().linker.clone()
+}
+
+/// Records the linker that could be run.
+pub fn set_linker(linker: &str) {
+    let mut probes = probes().lock().unwrap();
+    if probes.linker.as_deref() != Some(linker) {
+        probes.linker = Some(linker.to_owned());
+        save(&probes);
+    }
+}
+
+/// Writes the results to the cache file. Failing to is harmless, since the
+/// next run only probes again, so errors are ignored.
+fn save(probes: &Probes) {
+    let Some(path) = cache_path() else {
+        return;
+    };
+    let Ok(json) = serde_json::to_string(probes) else {
+        return;
+    };
+    // written beside the cache and moved over it, so a concurrent run never
+    // reads it half-written
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let _ = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(&temp, json))
+        .and_then(|_| std::fs::rename(&temp, &path));
+}
+
+/// Gets the path of the cache file, under the platform's cache directory, or
+/// `None` if there isn't one.
+fn cache_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("foo_llvm").join("probes.json"))
+}
+
+/// Hashes what the probe results depend on: the compiler itself, and the
+/// environment variables that decide which tools can be found.
+pub fn environment_key() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    std::env::current_exe().ok().hash(&mut hasher);
+    for var in ["PATH", "CC"] {
+        std::env::var_os(var).hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cfg,
+    error::Diagnostics,
+    include,
+    messages::Messages,
+    parse::{Expr, Spanned},
+    LangVersion,
+    MessageFormat,
+};
+
+/// How many times each Foo function was called in runs of a program compiled
+/// with `--profile-generate`, read to guide a compile with `--profile-use`.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    counts: HashMap<String, u64>,
+    total: u64,
+}
+
+impl Profile {
+    /// Reads the profile at `path`, written by the instrumented build of the
+    /// program `ast`.
+    ///
+    /// The file is the checksum of the program's functions followed by a
+    /// counter for each function, in the order they're declared, all as 64-bit
+    /// integers in the machine's byte order.
+    pub fn read(path: &Path, ast: &Expr) -> Result<Profile, Box<dyn Error>> {
+        let Ok(data) = std::fs::read(path) else {
+            return Err(format!("couldn't read profile {:#?}", path).into());
+        };
+        let words = data.chunks_exact(8)
+            .map(|bytes| u64::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let functions = functions(ast);
+        if words.first() != Some(&checksum(ast)) || words.len() != functions.len() + 1 {
+            return Err(
+                format!(
+                    "profile {:#?} doesn't match the program; regenerate it with \
+                    `--profile-generate`",
+                    path
+                ).into()
+            );
+        }
+
+        let counts = functions.into_iter()
+            .map(|(name, _)| name.to_owned())
+            .zip(words[1..].iter().copied())
+            .collect();
+        Ok(Profile { counts, total: words[1..].iter().sum() })
+    }
+
+    /// Gets the number of times a function was called.
+    pub fn count(&self, name: &str) -> Option<u64> {
+        self.counts.get(name).copied()
+    }
+
+    /// Checks whether a function is hot: whether at least a tenth of all the
+    /// calls to functions were to it.
+    pub fn is_hot(&self, name: &str) -> bool {
+        self.count(name).is_some_and(|count| count > 0 && count * 10 >= self.total)
+    }
+}
+
+/// Prints the Foo functions in a source file from the most to the least
+/// called, with their call counts and share of all calls from the profile at
+/// `data` (by default, where the program compiled with `--profile-generate`
+/// writes it), and where they're declared.
+pub fn report(src_path: &Path, data: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+    // the program was compiled with the declarations of the files it includes,
+    // and without those its `#[cfg]`s left out
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let features = LangVersion::of(None, &src).features();
+    let ast = include::parse(src_path, &src, features, &mut diags).body;
+    let ast = cfg::filter_host(ast, &mut diags);
+    let data = data.map(Path::to_owned).unwrap_or_else(|| data_path(src_path));
+    if !data.exists() {
+        return Err(
+            format!(
+                "couldn't read {:#?}; compile with `--profile-generate` and run \
+                the program first",
+                data
+            ).into()
+        );
+    }
+    let profile = Profile::read(&data, &ast)?;
+
+    let mut functions = functions(&ast)
+        .into_iter()
+        .map(|(name, span)| (name, span, profile.count(name).unwrap_or_default()))
+        .collect::<Vec<_>>();
+    // the sort is stable, so functions called as often stay in source order
+    functions.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+
+    println!("{:>12}  {:>6}  function", "calls", "share");
+    for (name, span, count) in functions {
+        let share = if profile.total == 0 {
+            0.0
+        } else {
+            count as f64 / profile.total as f64 * 100.0
+        };
+        let (path, line, col) = diags.locate(span.start);
+        println!(
+            "{:>12}  {:>5.1}%  {} ({}:{}:{})",
+            count,
+            share,
+            name,
+            path.display(),
+            line,
+            col
+        );
+    }
+    Ok(())
+}
+
+/// Gets the path the instrumented program writes its profile to, e.g.
+/// `test.profraw`.
+pub fn data_path(src: &Path) -> PathBuf {
+    src.with_extension("profraw")
+}
+
+/// Gets the Foo functions a program defines, in the order they're declared,
+/// with the spans of their names. Counter `n` belongs to the `n`th function.
+pub fn functions(root: &Expr) -> Vec<Spanned<&str>> {
+    let mut functions = vec![];
+    let mut expr = root;
+
+    loop {
+        match expr {
+            Expr::Fn { name, then, .. } => {
+                functions.push((name.0.as_str(), name.1));
+                expr = then;
+            }
+            Expr::Let { then, .. } | Expr::Extern { then, .. } => expr = then,
+            _ => return functions,
+        }
+    }
+}
+
+/// Hashes the names and arities of a program's functions, so that a profile
+/// isn't used for a program whose functions have changed since it was
+/// generated. Edits that keep the functions' signatures, such as to their
+/// bodies, keep the profile usable.
+pub fn checksum(root: &Expr) -> u64 {
+    // FNV-1a, which stays the same across builds, unlike std's hasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut expr = root;
+
+    loop {
+        let (name, arity, then) = match expr {
+            Expr::Fn { name, args, then, .. } => (&name.0, args.len(), then),
+            Expr::Let { then, .. } | Expr::Extern { then, .. } => {
+                expr = then;
+                continue;
+            }
+            _ => return hash,
+        };
+        for byte in name.bytes().chain([0]).chain((arity as u64).to_le_bytes()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        expr = then;
+    }
+}
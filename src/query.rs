@@ -0,0 +1,43 @@
+use std::{error::Error, path::Path};
+
+use crate::{sources::SourceMap, symbols::{self, SymbolTable}};
+
+/// Prints the location of every declaration of `name` in a source file as
+/// `file:line:col`, in order: each `let` that shadows another, and each
+/// function or parameter with the name.
+pub fn defs(src_path: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    let (src, ast) = symbols::parse_file(src_path)?;
+    let table = SymbolTable::build(&ast);
+    let mut starts = table.symbols.iter()
+        .filter(|symbol| symbol.name == name)
+        .map(|symbol| symbol.decl.start)
+        .collect::<Vec<_>>();
+    if starts.is_empty() {
+        return Err(format!("`{}` isn't declared in {}", name, src_path.display()).into());
+    }
+    starts.sort();
+    print_locations(src_path, &src, &starts);
+    Ok(())
+}
+
+/// Prints the location of every reference to a declaration of `name` in a
+/// source file as `file:line:col`, in order.
+pub fn refs(src_path: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    let (src, ast) = symbols::parse_file(src_path)?;
+    let table = SymbolTable::build(&ast);
+    let mut starts = table.symbols.iter()
+        .filter(|symbol| symbol.name == name)
+        .flat_map(|symbol| symbol.refs.iter().map(|r#ref| r#ref.start))
+        .collect::<Vec<_>>();
+    starts.sort();
+    print_locations(src_path, &src, &starts);
+    Ok(())
+}
+
+fn print_locations(src_path: &Path, src: &str, starts: &[usize]) {
+    let sources = SourceMap::new(src_path, src);
+    for &start in starts {
+        let (path, line, col) = sources.locate(start);
+        println!("{}:{}:{}", path.display(), line, col);
+    }
+}
@@ -0,0 +1,84 @@
+use std::{error::Error, path::Path};
+
+use chumsky::{prelude::*, span::SimpleSpan};
+use similar::TextDiff;
+
+use crate::{
+    artifact,
+    parse::program,
+    symbols::{self, SymbolKind, SymbolTable},
+    LangVersion,
+};
+
+/// Renames the variable, parameter, or function whose name is at the byte
+/// `offset` of a source file to `to`, along with every reference to it, then
+/// writes the file back, or prints the change as a unified diff if `diff` is
+/// set.
+///
+/// The renamed program is resolved again to make sure the new name doesn't
+/// capture or get shadowed by another: every reference has to refer to the
+/// same declaration as before.
+pub fn rename(src_path: &Path, offset: usize, to: &str, diff: bool) -> Result<(), Box<dyn Error>> {
+    let (src, ast) = symbols::parse_file(src_path)?;
+    let table = SymbolTable::build(&ast);
+    let Some(symbol) = table.at(offset) else {
+        return Err(format!("no variable or function name at offset {}", offset).into());
+    };
+    if symbol.kind == SymbolKind::Extern {
+        return Err(format!(
+            "`{}` is an extern function, whose name is the symbol it links to",
+            symbol.name
+        ).into());
+    }
+    if text::unicode::ident::<_, _, extra::Default>().parse(to).into_result().is_err() {
+        return Err(format!("`{}` isn't a valid identifier", to).into());
+    }
+
+    let mut edits = symbol.refs.clone();
+    edits.push(symbol.decl);
+    edits.sort_by_key(|span| span.start);
+    let renamed = apply(&src, &edits, to);
+
+    // the positions in the renamed source of the starts of names in the
+    // original, which edits before them have shifted
+    let shift = |offset: usize| {
+        edits.iter()
+            .take_while(|edit| edit.end <= offset)
+            .fold(offset, |offset, edit| offset + to.len() - (edit.end - edit.start))
+    };
+    let expected = table.resolutions()
+        .into_iter()
+        .map(|(r#ref, decl)| (shift(r#ref), decl.map(shift)))
+        .collect();
+    let Ok(renamed_program) = program(LangVersion::of(None, &src).features()).parse(&renamed).into_result() else {
+        return Err(format!("`{}` can't be used as a name here", to).into());
+    };
+    if SymbolTable::build(&renamed_program.body).resolutions() != expected {
+        return Err(format!(
+            "renaming `{}` to `{}` would change what other names refer to",
+            symbol.name,
+            to
+        ).into());
+    }
+
+    if diff {
+        let path = src_path.to_string_lossy();
+        print!("{}", TextDiff::from_lines(&src, &renamed).unified_diff().header(&path, &path));
+    } else {
+        artifact::write(src_path, renamed)?;
+    }
+    Ok(())
+}
+
+/// Replaces each of the sorted, non-overlapping spans in `src` with `to`.
+fn apply(src: &str, edits: &[SimpleSpan], to: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut last = 0;
+    for edit in edits {
+        out.push_str(&src[last..edit.start]);
+        out.push_str(to);
+        last = edit.end;
+    }
+    out.push_str(&src[last..]);
+    out
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use chumsky::{error::Rich, Parser};
+use inkwell::{context::Context, module::Module};
+
+use crate::{
+    cfg,
+    error::{CompilerError, Diagnostics},
+    llvm::{self, CodegenOptions, Instrumentation, LlvmGenerator, PrintFormat},
+    messages::Messages,
+    parse::{parser, Expr, Features},
+    FloatModel,
+    FloatWidth,
+    LangVersion,
+    MessageFormat,
+    Notation,
+};
+
+/// Runs an interactive session, which reads declarations and expressions a line
+/// at a time and prints the value of each expression, for trying things out.
+///
+/// Each function declared is generated into a module kept for the whole
+/// session, so later lines can call it, and each `let`'s value is computed as
+/// it's entered. An expression is generated as a function of its own, which is
+/// run with LLVM's JIT and then removed again.
+///
+/// A line that stops partway, e.g. `fn f x = x +`, is continued by the next;
+/// an empty line gives up on it.
+pub fn repl() -> Result<(), Box<dyn Error>> {
+    let context = Context::create();
+    let module = context.create_module("repl");
+    let builder = context.create_builder();
+    let options = CodegenOptions {
+        print_format: PrintFormat {
+            notation: Notation::Fixed,
+            precision: None,
+            newline: true,
+        },
+        entry: None,
+        entry_symbol: None,
+        float_model: FloatModel::Strict,
+        float_width: FloatWidth::F64,
+        stack_probes: false,
+        stack_probe_size: None,
+        exports: vec![],
+        profile: None,
+        readable_names: None,
+    };
+    let instrumentation = Instrumentation::default();
+    let mut session = Session {
+        generator: LlvmGenerator::incremental(
+            &context,
+            &module,
+            &builder,
+            options,
+            instrumentation
+        ),
+        module: &module,
+        features: LangVersion::LATEST.features(),
+        vars: HashMap::new(),
+        evaluated: 0,
+    };
+
+    let stdin = std::io::stdin();
+    let mut src = String::new();
+    loop {
+        print!("{}", if src.is_empty() { "foo> " } else { "...> " });
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let give_up = line.trim().is_empty();
+        if give_up && src.is_empty() {
+            continue;
+        }
+        src.push_str(&line);
+        if session.enter(&src, give_up) {
+            src.clear();
+        }
+    }
+}
+
+/// What the lines read so far make.
+enum Entry<'src> {
+    /// Declarations, along with the expression after them, if there is one.
+    Complete(Expr),
+    /// The start of something the next line may finish, e.g. `let a =`, with
+    /// the errors it has as it is.
+    Incomplete(Vec<Rich<'src, char>>),
+    /// Syntax errors.
+    Invalid(Vec<Rich<'src, char>>),
+}
+
+/// What a session has declared so far.
+struct Session<'a, 'ctx> {
+    generator: LlvmGenerator<'a, 'ctx>,
+    module: &'a Module<'ctx>,
+    features: Features,
+    /// The values of the `let`s entered.
+    vars: HashMap<String, f64>,
+    /// How many expressions have been evaluated, to name the function of each.
+    evaluated: usize,
+}
+
+impl Session<'_, '_> {
+    /// Enters the lines read so far, printing the values of their `let`s and
+    /// expression, or any errors they have. Returns whether they're done with,
+    /// or `false` if they're waiting for another line, unless `give_up` is set.
+    fn enter(&mut self, src: &str, give_up: bool) -> bool {
+        let path = Path::new("<repl>");
+        let mut diags = Diagnostics::new(path, src, None, Messages::new(MessageFormat::Human));
+        let root = match parse(src, self.features) {
+            Entry::Complete(root) => root,
+            Entry::Incomplete(_) if !give_up => return false,
+            Entry::Incomplete(errs) | Entry::Invalid(errs) => {
+                diags.syntax_errors(errs);
+                diags.print();
+                return true;
+            }
+        };
+        let root = cfg::filter_host(root, &mut diags);
+
+        let mut e = &root;
+        let result = loop {
+            match e {
+                Expr::Extern { name: (name, name_span), then, .. } => {
+                    if !llvm::jit_can_find(name) {
+                        break Err(
+                            CompilerError::at(
+                                *name_span,
+                                format!(
+                                    "`{}` isn't defined anywhere the REPL can find it, so it \
+                                    can't be declared",
+                                    name
+                                ),
+                                "declared here"
+                            )
+                        );
+                    }
+                    if let Err(e) = self.generator.add_decl(e) {
+                        break Err(e);
+                    }
+                    e = then;
+                }
+                Expr::Fn { then, .. } => {
+                    if let Err(e) = self.generator.add_decl(e) {
+                        break Err(e);
+                    }
+                    e = then;
+                }
+                Expr::Let { name: (name, _), rhs, then, .. } => {
+                    match self.evaluate(rhs) {
+                        Ok(value) => self.vars.insert(name.to_owned(), value),
+                        Err(e) => break Err(e),
+                    };
+                    e = then;
+                }
+                // the expression added after declarations entered on their own
+                Expr::Num(_, span) if span.start >= src.len() => break Ok(()),
+                expr => break self.evaluate(expr).map(|value| println!("{}", value)),
+            }
+        };
+        if let Err(e) = result {
+            diags.semantic_error(e);
+        }
+        diags.print();
+        true
+    }
+
+    /// Generates an expression as a function, runs it with the JIT, and
+    /// removes it again, returning its value.
+    fn evaluate(&mut self, expr: &Expr) -> Result<f64, CompilerError> {
+        self.evaluated += 1;
+        let symbol = format!("repl.expr.{}", self.evaluated);
+        let r#fn = self.generator.add_expr_fn(&symbol, &[], expr, &self.vars)?;
+        let value = llvm::call_jit(self.module, &symbol);
+        unsafe { r#fn.delete() };
+        Ok(value?)
+    }
+}
+
+/// Parses the lines read so far. Unlike a program, they can be declarations
+/// without an expression after them.
+fn parse(src: &str, features: Features) -> Entry<'_> {
+    let errs = match parser(features).parse(src).into_result() {
+        Ok(root) => return Entry::Complete(root),
+        Err(errs) => errs,
+    };
+    let parses = |src: String| parser(features).parse(&src).into_result().ok();
+    let sep = if src.ends_with('\n') { "" } else { "\n" };
+
+    // declarations on their own are given an expression to end them, which
+    // is skipped, unless it continues the last of them, e.g. `1 +`
+    if let Some(root) = parses(format!("{}{}0", src, sep)) {
+        let mut e = &root;
+        while let Expr::Let { then, .. } | Expr::Fn { then, .. } | Expr::Extern { then, .. } = e {
+            e = then;
+        }
+        return if e.span().start >= src.len() {
+            Entry::Complete(root)
+        } else {
+            Entry::Incomplete(errs)
+        };
+    }
+    // a declaration left unfinished, e.g. `let a = 1 +`, needs an expression
+    // to finish it, and another to end it
+    if parses(format!("{}{}0\n0", src, sep)).is_some() || errs.iter().all(|e| e.found().is_none())
+    {
+        Entry::Incomplete(errs)
+    } else {
+        Entry::Invalid(errs)
+    }
+}
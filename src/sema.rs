@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    error::Diagnostics,
+    parse::{ContractKind, Expr, FnAttr, Spanned},
+    prelude,
+};
+
+/// The functions declared so far: how many parameters each takes, and the
+/// span of its name where it's declared, by name.
+type Functions<'e> = HashMap<&'e str, (usize, SimpleSpan)>;
+
+/// Checks that a program means something before any code is generated for it:
+/// that every variable and function it names is declared where it's used,
+/// that every call passes as many arguments as its function takes, and that
+/// no function is declared twice, or annotated both `@inline` and `@noinline`.
+/// An error is queued at each problem found, so they're all reported
+/// together, rather than codegen stopping at the first.
+///
+/// Names resolve the same way codegen resolves them: a `let` is visible from
+/// the next declaration on; a function body only sees its own parameters, and
+/// its `ensures` clauses `result` as well; and a function can be called from
+/// its own body and anything after it, in place of a builtin of its name.
+pub fn check(root: &Expr, diags: &mut Diagnostics) {
+    let mut functions = Functions::new();
+    let mut vars = HashSet::new();
+    let mut e = root;
+
+    loop {
+        match e {
+            Expr::Let { name, rhs, then, .. } => {
+                check_expr(rhs, &vars, &functions, diags);
+                vars.insert(name.0.as_str());
+                e = then;
+            }
+            Expr::Fn { attrs, name, args, contracts, body, then, .. } => {
+                declare(name, args.len(), &mut functions, diags);
+                if let Some((first, second)) = inline_conflict(attrs) {
+                    diags.error_with_related(
+                        second,
+                        format!("function `{}` can't be both `@inline` and `@noinline`", name.0),
+                        "conflicting annotation",
+                        vec![(first, "conflicts with this one".to_owned())]
+                    );
+                }
+                let mut params = args.iter().map(|(arg, _)| arg.as_str()).collect::<HashSet<_>>();
+                for (contract, _) in contracts {
+                    if contract.kind == ContractKind::Requires {
+                        check_expr(&contract.cond, &params, &functions, diags);
+                    }
+                }
+                check_expr(body, &params, &functions, diags);
+                params.insert("result");
+                for (contract, _) in contracts {
+                    if contract.kind == ContractKind::Ensures {
+                        check_expr(&contract.cond, &params, &functions, diags);
+                    }
+                }
+                e = then;
+            }
+            Expr::Extern { name, args, then, .. } => {
+                declare(name, args.len(), &mut functions, diags);
+                e = then;
+            }
+            expr => {
+                check_expr(expr, &vars, &functions, diags);
+                return;
+            }
+        }
+    }
+}
+
+/// Finds an `@inline` and an `@noinline` on the same function, returning the
+/// spans of whichever comes first and of the one after it that contradicts it.
+pub fn inline_conflict(attrs: &[Spanned<FnAttr>]) -> Option<(SimpleSpan, SimpleSpan)> {
+    let mut inlining = attrs.iter()
+        .filter(|(attr, _)| matches!(attr, FnAttr::Inline | FnAttr::NoInline));
+    let (first, first_span) = inlining.next()?;
+    inlining.find(|(attr, _)| attr != first).map(|(_, span)| (*first_span, *span))
+}
+
+/// Declares a function, unless one of its name already is.
+fn declare<'e>(
+    (name, span): &'e Spanned<String>,
+    arity: usize,
+    functions: &mut Functions<'e>,
+    diags: &mut Diagnostics
+) {
+    match functions.get(name.as_str()) {
+        Some((_, first)) => diags.error_with_related(
+            *span,
+            format!("function `{}` already exists", name),
+            "declared again here",
+            vec![(*first, "first declared here".to_owned())]
+        ),
+        None => {
+            functions.insert(name, (arity, *span));
+        }
+    }
+}
+
+/// Checks the names used in an expression, where `vars` are the variables it
+/// can see.
+fn check_expr(expr: &Expr, vars: &HashSet<&str>, functions: &Functions, diags: &mut Diagnostics) {
+    let mut stack = vec![expr];
+
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Num(..) => (),
+            Expr::Var(name, span) => {
+                if !vars.contains(name.as_str()) {
+                    diags.error_at(
+                        *span,
+                        format!("variable `{}` not found in scope", name),
+                        "not declared before this"
+                    );
+                }
+            }
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(right);
+                stack.push(left);
+            }
+            Expr::Call((name, name_span), args, span) => {
+                stack.extend(args.iter().rev());
+                // builtins can be shadowed by functions of the same name
+                let (arity, decl) = match functions.get(name.as_str()) {
+                    Some((arity, decl)) => (*arity, Some(*decl)),
+                    None => match prelude::builtin(name) {
+                        Some(builtin) => (builtin.params.len(), None),
+                        None => {
+                            diags.error_at(
+                                *name_span,
+                                format!("function `{}` not found in scope", name),
+                                "not declared before this"
+                            );
+                            continue;
+                        }
+                    },
+                };
+                if args.len() == arity {
+                    continue;
+                }
+                let msg = format!(
+                    "`{}` takes {} argument{}",
+                    name,
+                    arity,
+                    if arity == 1 { "" } else { "s" }
+                );
+                let label = format!("given {}", args.len());
+                match decl {
+                    Some(decl) => diags.error_with_related(
+                        *span,
+                        msg,
+                        label,
+                        vec![(decl, "declared here".to_owned())]
+                    ),
+                    None => diags.error_at(*span, msg, label),
+                }
+            }
+            // declarations only come before the final expression
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chumsky::Parser;
+
+    use super::check;
+    use crate::{
+        error::{Diagnostic, Diagnostics},
+        messages::Messages,
+        parse::program,
+        LangVersion,
+        MessageFormat,
+    };
+
+    /// Parses a program and checks it, returning the errors collected.
+    fn errors(src: &str) -> Vec<Diagnostic> {
+        let parsed = program(LangVersion::LATEST.features())
+            .parse(src)
+            .into_result()
+            .unwrap();
+        let mut diags = Diagnostics::new(
+            Path::new("test.foo"),
+            src,
+            None,
+            Messages::new(MessageFormat::Human)
+        );
+        check(&parsed.body, &mut diags);
+        diags.take()
+    }
+
+    /// Gets the source an error points to.
+    fn at<'src>(src: &'src str, diag: &Diagnostic) -> &'src str {
+        let (range, _) = diag.label.as_ref().unwrap();
+        &src[range.clone()]
+    }
+
+    fn messages(diags: &[Diagnostic]) -> Vec<&str> {
+        diags.iter().map(|diag| diag.msg.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_program() {
+        let src = "let a = 2;\nfn sq x = x * x;\nextern fn g x;\nsq(a) + g(sqrt(a))";
+        assert!(errors(src).is_empty());
+    }
+
+    #[test]
+    fn resolves_names_as_codegen_does() {
+        let src = "let a = 1;\nfn f x = x + a;\nf(b)";
+        let errs = errors(src);
+        assert_eq!(
+            messages(&errs),
+            ["variable `a` not found in scope", "variable `b` not found in scope"]
+        );
+        assert_eq!(at(src, &errs[0]), "a");
+        assert_eq!(at(src, &errs[1]), "b");
+
+        // a function can only be called after it's declared
+        let src = "fn f x = g(x);\nfn g x = x;\nf(1)";
+        assert_eq!(messages(&errors(src)), ["function `g` not found in scope"]);
+
+        // `result` is only visible to `ensures`
+        let src = "fn f x ensures result > 0 requires result > 0 = x;\nf(1)";
+        assert_eq!(messages(&errors(src)), ["variable `result` not found in scope"]);
+    }
+
+    #[test]
+    fn checks_arity() {
+        let src = "fn f x y = x + y;\nf(1) + sqrt(1, 2)";
+        let errs = errors(src);
+        assert_eq!(messages(&errs), ["`f` takes 2 arguments", "`sqrt` takes 1 argument"]);
+        assert_eq!(at(src, &errs[0]), "f(1)");
+        // a function's own points to where it's declared
+        assert_eq!(errs[0].related.len(), 1);
+        assert_eq!(&src[errs[0].related[0].0.clone()], "f");
+        assert!(errs[1].related.is_empty());
+    }
+
+    #[test]
+    fn reports_duplicates_at_both_declarations() {
+        let src = "fn f x = x;\nextern fn f x;\nf(1)";
+        let errs = errors(src);
+        assert_eq!(messages(&errs), ["function `f` already exists"]);
+        let (again, _) = errs[0].label.as_ref().unwrap();
+        let (first, _) = &errs[0].related[0];
+        assert_eq!((first.start, again.start), (3, 22));
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let src = "fn f x = y;\nfn f x = x;\nlet a = g(1);\nf(1, 2) + b";
+        assert_eq!(
+            messages(&errors(src)),
+            [
+                "variable `y` not found in scope",
+                "function `f` already exists",
+                "function `g` not found in scope",
+                "`f` takes 1 argument",
+                "variable `b` not found in scope",
+            ]
+        );
+    }
+}
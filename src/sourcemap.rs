@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chumsky::span::SimpleSpan;
+use serde::Serialize;
+
+use crate::{
+    artifact::Artifact,
+    lines::SourceLines,
+    llvm::{self, IrValue},
+    parse::Expr,
+};
+
+/// Maps each node of a program's AST to the code generated for it, written
+/// next to the source file with `--emit sourcemap`, so that tools can show
+/// which IR and assembly came from which part of the source.
+#[derive(Serialize)]
+struct SourceMap<'a> {
+    source: &'a Path,
+    nodes: Vec<Node<'a>>,
+}
+
+#[derive(Serialize)]
+struct Node<'a> {
+    /// The node's number in a pre-order walk of the AST, which stays the same
+    /// between compiles of the same source with the same options. Nodes
+    /// sharing a span, e.g. after inlining, share a node.
+    id: usize,
+    /// What kind of expression the node is, e.g. `add` or `let`.
+    kind: &'static str,
+    /// The span of the source, in bytes.
+    start: usize,
+    end: usize,
+    /// The line and column of `start`, counting from 1.
+    line: u32,
+    column: u32,
+    /// The symbol of the function a `fn` node defines.
+    symbol: Option<&'a str>,
+    /// The named IR values generated for the node.
+    ir: &'a [IrValue],
+    /// The ranges of lines of assembly generated for the node, counting from
+    /// 1 and inclusive.
+    asm: Vec<[usize; 2]>,
+}
+
+/// Gets the path of the source map for a source file, e.g. `test.sourcemap.json`.
+pub fn map_path(src: &Path) -> PathBuf {
+    src.with_extension("sourcemap.json")
+}
+
+/// Writes the source map for the program in the source file at `path`.
+///
+/// * `symbols` - The symbols defined in the module, with the spans of their
+///   names.
+/// * `values` - The named IR values generated for each span.
+/// * `asm` - The module's assembly, generated with debug info, if it was
+///   compiled for a target.
+pub fn write(
+    path: &Path,
+    lines: &SourceLines,
+    ast: &Expr,
+    symbols: &HashMap<String, SimpleSpan>,
+    values: &HashMap<SimpleSpan, Vec<IrValue>>,
+    asm: Option<&str>
+) -> Result<(), Box<dyn Error>> {
+    let asm_lines = match asm {
+        Some(asm) => asm_lines(asm, &path.file_name().unwrap().to_string_lossy()),
+        None => HashMap::new(),
+    };
+
+    let nodes = nodes(ast)
+        .into_iter()
+        .enumerate()
+        .map(|(id, expr)| {
+            let span = expr.span();
+            let (line, column) = lines.line_col(span.start);
+            let symbol = match expr {
+                Expr::Fn { name: (_, name_span), .. } => symbols.iter()
+                    .find(|(_, span)| *span == name_span)
+                    .map(|(symbol, _)| symbol.as_str()),
+                _ => None,
+            };
+            let ir = values.get(&span).map(Vec::as_slice).unwrap_or_default();
+            // instructions are located at the start of the expression they're
+            // for, which an operation shares with its left operand, except for
+            // a function's prologue, which is at column 0 of its line
+            let locations = match expr {
+                Expr::Fn { .. } => vec![(line, 0), (line, column)],
+                Expr::Let { .. } => vec![(line, column)],
+                _ if !ir.is_empty() => vec![(line, column)],
+                _ => vec![],
+            };
+            let mut asm = locations.iter()
+                .filter_map(|location| asm_lines.get(location))
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            asm.sort();
+            Node {
+                id,
+                kind: kind(expr),
+                start: span.start,
+                end: span.end,
+                line,
+                column,
+                symbol,
+                ir,
+                asm,
+            }
+        })
+        .collect();
+
+    let map = SourceMap { source: path, nodes };
+    let artifact = Artifact::new(&map_path(path))?;
+    serde_json::to_writer_pretty(artifact.create()?, &map)?;
+    artifact.commit()
+}
+
+/// Gets the nodes of the AST in pre-order, keeping only the first of the
+/// nodes sharing a span.
+fn nodes(root: &Expr) -> Vec<&Expr> {
+    let mut nodes = vec![];
+    let mut spans = HashSet::new();
+    let mut stack = vec![root];
+
+    while let Some(expr) = stack.pop() {
+        if spans.insert(expr.span()) {
+            nodes.push(expr);
+        }
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(right);
+                stack.push(left);
+            }
+            Expr::Call(_, args, _) => stack.extend(args.iter().rev()),
+            Expr::Let { rhs, then, .. } => {
+                stack.push(then);
+                stack.push(rhs);
+            }
+            Expr::Fn { body, then, .. } => {
+                stack.push(then);
+                stack.push(body);
+            }
+            Expr::Extern { then, .. } => stack.push(then),
+        }
+    }
+    nodes
+}
+
+fn kind(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Num(..) => "num",
+        Expr::Var(..) => "var",
+        Expr::Neg(..) => "neg",
+        Expr::Add(..) => "add",
+        Expr::Sub(..) => "sub",
+        Expr::Mul(..) => "mul",
+        Expr::Div(..) => "div",
+        Expr::Cmp(..) => "cmp",
+        Expr::Call(..) => "call",
+        Expr::Let { .. } => "let",
+        Expr::Fn { .. } => "fn",
+        Expr::Extern { .. } => "extern",
+    }
+}
+
+/// Groups the lines of instructions in `asm` by the line and column of the
+/// source they were generated from, as ranges of consecutive lines.
+fn asm_lines(asm: &str, src_name: &str) -> HashMap<(u32, u32), Vec<[usize; 2]>> {
+    let mut ranges: HashMap<(u32, u32), Vec<[usize; 2]>> = HashMap::new();
+    let mut current = None;
+    // the location of the last instruction, whose range directives between
+    // it and the next instruction don't break
+    let mut last = None;
+
+    for (i, (line, location)) in asm.lines()
+        .zip(llvm::asm_locations(asm, src_name))
+        .enumerate()
+    {
+        if location.is_some() {
+            current = location;
+        }
+        let line = line.trim();
+        let instruction = !line.is_empty()
+            && !line.ends_with(':')
+            && !line.starts_with(['.', '#', '/', '@', ';']);
+        let Some(location) = current.filter(|_| instruction) else {
+            continue;
+        };
+        let number = i + 1;
+        let location_ranges = ranges.entry(location).or_default();
+        match location_ranges.last_mut() {
+            Some(range) if last == Some(location) => range[1] = number,
+            _ => location_ranges.push([number, number]),
+        }
+        last = Some(location);
+    }
+    ranges
+}
@@ -0,0 +1,109 @@
+use std::{
+    borrow::Cow,
+    cell::OnceCell,
+    ops::Range,
+    path::Path,
+};
+
+use chumsky::span::SimpleSpan;
+use codesnake::LineIndex;
+
+use crate::lines::SourceLines;
+
+/// The source files of a program: the file being compiled, and any files it
+/// includes. Each file is given its own range of offsets, starting after the
+/// end of the file before it, so a span alone says which file it's in.
+pub struct SourceMap<'src> {
+    files: Vec<SourceFile<'src>>,
+}
+
+/// A file in a [`SourceMap`], whose spans start at `start`. Its lines are
+/// indexed once, for everything that locates spans in it to borrow.
+pub struct SourceFile<'src> {
+    pub path: Cow<'src, Path>,
+    pub src: &'src str,
+    pub start: usize,
+    /// Locates spans in the file, e.g. for diagnostic headers like
+    /// `test.foo:3:7`.
+    lines: SourceLines,
+    /// Splits the file into lines for rendering diagnostics, built the first
+    /// time one points into it.
+    index: OnceCell<LineIndex<'src>>,
+}
+
+impl<'src> SourceMap<'src> {
+    /// Creates a source map of the file being compiled, whose spans start at 0.
+    pub fn new(path: &'src Path, src: &'src str) -> SourceMap<'src> {
+        SourceMap {
+            files: vec![SourceFile {
+                path: Cow::Borrowed(path),
+                src,
+                start: 0,
+                lines: SourceLines::new(src),
+                index: OnceCell::new(),
+            }],
+        }
+    }
+
+    /// Adds a file, returning the offset its spans have to be moved by.
+    ///
+    /// Its source is kept for the rest of the process, like the compiled
+    /// file's, so that its index can borrow it.
+    pub fn add(&mut self, path: &Path, src: String) -> usize {
+        let last = self.files.last().unwrap();
+        let start = last.start + last.src.len() + 1;
+        let src = Box::leak(src.into_boxed_str());
+        self.files.push(SourceFile {
+            path: Cow::Owned(path.to_owned()),
+            lines: SourceLines::new(src),
+            src,
+            start,
+            index: OnceCell::new(),
+        });
+        start
+    }
+
+    pub fn files(&self) -> &[SourceFile<'src>] {
+        &self.files
+    }
+
+    /// Gets the lines of the file being compiled.
+    pub fn lines(&self) -> &SourceLines {
+        &self.files[0].lines
+    }
+
+    /// Gets the index of the file an offset is in.
+    pub fn index(&self, offset: usize) -> usize {
+        self.files.partition_point(|file| file.start <= offset) - 1
+    }
+
+    /// Gets the file an offset is in.
+    pub fn file(&self, offset: usize) -> &SourceFile<'src> {
+        &self.files[self.index(offset)]
+    }
+
+    /// Gets the source text a span covers.
+    pub fn source(&self, span: SimpleSpan) -> &str {
+        let file = self.file(span.start);
+        &file.src[file.local(span.into_range())]
+    }
+
+    /// Gets the file an offset is in, and its line and column there.
+    pub fn locate(&self, offset: usize) -> (&Path, u32, u32) {
+        let file = self.file(offset);
+        let (line, col) = file.lines.line_col(offset - file.start);
+        (&file.path, line, col)
+    }
+}
+
+impl<'src> SourceFile<'src> {
+    /// Gets a range of offsets in the file's own source.
+    pub fn local(&self, range: Range<usize>) -> Range<usize> {
+        range.start - self.start..range.end - self.start
+    }
+
+    /// Gets the file's lines, to render a diagnostic pointing into it.
+    pub fn index(&self) -> &LineIndex<'src> {
+        self.index.get_or_init(|| LineIndex::new(self.src))
+    }
+}
@@ -0,0 +1,106 @@
+use std::{error::Error, path::Path};
+
+use crate::{parse::Expr, symbols};
+
+/// The names of the kinds of expression, in the order they're printed.
+const KINDS: [&str; 12] =
+    ["num", "var", "neg", "add", "sub", "mul", "div", "cmp", "call", "let", "fn", "extern"];
+
+/// Prints metrics of a source file's AST, one per line as `name: value`, for
+/// scripts to compare programs by: how many expressions of each kind it has,
+/// how deep it nests (counted the way `--max-ast-depth` counts it), how many
+/// functions it defines, and how many expressions their bodies have on
+/// average.
+pub fn stats(src_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (_, ast) = symbols::parse_file(src_path)?;
+    let mut counts = [0; KINDS.len()];
+    let mut max_depth = 0;
+    let mut body_sizes = vec![];
+    let mut stack = vec![(&ast, 1)];
+
+    while let Some((expr, depth)) = stack.pop() {
+        max_depth = max_depth.max(depth);
+        let kind = match expr {
+            Expr::Num(..) => 0,
+            Expr::Var(..) => 1,
+            Expr::Neg(..) => 2,
+            Expr::Add(..) => 3,
+            Expr::Sub(..) => 4,
+            Expr::Mul(..) => 5,
+            Expr::Div(..) => 6,
+            Expr::Cmp(..) => 7,
+            Expr::Call(..) => 8,
+            Expr::Let { .. } => 9,
+            Expr::Fn { .. } => 10,
+            Expr::Extern { .. } => 11,
+        };
+        counts[kind] += 1;
+
+        match expr {
+            Expr::Num(..) | Expr::Var(..) => (),
+            Expr::Neg(expr, _) => stack.push((expr, depth + 1)),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
+            Expr::Call(_, args, _) => {
+                stack.extend(args.iter().rev().map(|arg| (arg, depth + 1)));
+            }
+            Expr::Let { rhs, then, .. } => {
+                stack.push((then, depth + 1));
+                stack.push((rhs, depth + 1));
+            }
+            Expr::Fn { contracts, body, then, .. } => {
+                body_sizes.push(size(body));
+                stack.push((then, depth + 1));
+                stack.push((body, depth + 1));
+                stack.extend(
+                    contracts.iter().rev().map(|(contract, _)| (&contract.cond, depth + 1))
+                );
+            }
+            Expr::Extern { then, .. } => stack.push((then, depth + 1)),
+        }
+    }
+
+    println!("expressions: {}", counts.iter().sum::<usize>());
+    for (kind, count) in KINDS.iter().zip(counts) {
+        println!("{}: {}", kind, count);
+    }
+    println!("max depth: {}", max_depth);
+    println!("functions: {}", body_sizes.len());
+    let average = if body_sizes.is_empty() {
+        0.0
+    } else {
+        body_sizes.iter().sum::<usize>() as f64 / body_sizes.len() as f64
+    };
+    println!("average body size: {:.1}", average);
+    Ok(())
+}
+
+/// Counts the expressions in a function body.
+fn size(body: &Expr) -> usize {
+    let mut size = 0;
+    let mut stack = vec![body];
+
+    while let Some(expr) = stack.pop() {
+        size += 1;
+        match expr {
+            Expr::Neg(expr, _) => stack.push(expr),
+            Expr::Add(left, right, _)
+            | Expr::Sub(left, right, _)
+            | Expr::Mul(left, right, _)
+            | Expr::Div(left, right, _)
+            | Expr::Cmp(_, left, right, _) => {
+                stack.push(left);
+                stack.push(right);
+            }
+            Expr::Call(_, args, _) => stack.extend(args),
+            _ => (),
+        }
+    }
+    size
+}
@@ -0,0 +1,179 @@
+use std::{error::Error, fmt::Write, path::Path, time::{Duration, Instant}};
+
+use crate::{messages::Messages, run, temp::TempDir, Args, MessageFormat, StressShape};
+
+/// Generates `count` random programs of the given shape and size, compiles
+/// each of them to an object file, and prints how long each took and the
+/// compiler's peak memory use so far, followed by a summary. The programs are
+/// written to `keep` if it's given, or else to a temporary directory.
+///
+/// The same `seed` always generates the same programs, so a run can be
+/// repeated to compare changes to the compiler.
+pub fn stress(
+    count: usize,
+    shape: StressShape,
+    size: usize,
+    seed: u64,
+    keep: Option<&Path>
+) -> Result<(), Box<dyn Error>> {
+    TempDir::scoped(Messages::new(MessageFormat::Human), |temp| {
+        let dir = keep.unwrap_or(temp.path());
+        std::fs::create_dir_all(dir)?;
+        let mut rng = Rng::new(seed);
+        let mut times = vec![];
+
+        for i in 0..count {
+            let src = generate(&mut rng, shape, size);
+            let src_path = dir.join(format!("stress-{}.foo", i));
+            std::fs::write(&src_path, &src)?;
+
+            // every declaration and parenthesis is a level deeper, and the
+            // programs are meant to be valid whatever their size
+            let max_depth = (size * 4 + 100).max(2000).to_string();
+            let args = Args::from_command_line([
+                "foo_llvm".as_ref(),
+                src_path.as_os_str(),
+                "-p".as_ref(),
+                "object".as_ref(),
+                "-o".as_ref(),
+                temp.file("stress.o").as_os_str(),
+                "--max-ast-depth".as_ref(),
+                max_depth.as_ref(),
+                // each program's object replaces the last one's
+                "--force".as_ref(),
+            ])?;
+            let timer = Instant::now();
+            run(args, Messages::new(MessageFormat::Human))
+                .map_err(|e| format!("compiling `{}`: {}", src_path.display(), e))?;
+            let time = timer.elapsed();
+            times.push(time);
+
+            println!(
+                "{}: {} bytes, {}, peak memory {}",
+                src_path.file_name().unwrap().to_string_lossy(),
+                src.len(),
+                millis(time),
+                peak_memory()
+            );
+        }
+
+        if let (Some(min), Some(max)) = (times.iter().min(), times.iter().max()) {
+            let total = times.iter().sum::<Duration>();
+            println!(
+                "{} programs in {}: min {}, mean {}, max {}",
+                times.len(),
+                millis(total),
+                millis(*min),
+                millis(total / times.len() as u32),
+                millis(*max)
+            );
+        }
+        Ok(())
+    })
+}
+
+/// Generates a valid program. Its final expression uses every `let` and calls
+/// the last function, so that nothing it declares is unused.
+fn generate(rng: &mut Rng, shape: StressShape, size: usize) -> String {
+    let size = size.max(1);
+    let (lets, functions, depth) = match shape {
+        StressShape::Nesting => (0, 0, size),
+        StressShape::Functions => (0, size, 3),
+        StressShape::Lets => (size, 0, 3),
+        StressShape::Mixed => (size / 2, size / 2, 8),
+    };
+
+    let mut src = String::new();
+    for i in 0..functions {
+        // a function only calls those declared before it
+        let callees = (0..i).collect::<Vec<_>>();
+        let body = expr(rng, depth, &["a", "b"], &callees);
+        let _ = writeln!(src, "fn f{} a b = {};", i, body);
+    }
+    let callees = (0..functions).collect::<Vec<_>>();
+    let mut vars = vec![];
+    let names = (0..lets).map(|i| format!("v{}", i)).collect::<Vec<_>>();
+    for name in &names {
+        let rhs = expr(rng, depth.min(4), &vars, &callees);
+        let _ = writeln!(src, "let {} = {};", name, rhs);
+        vars.push(name.as_str());
+    }
+
+    let mut result = expr(rng, depth, &[], &[]);
+    for var in vars {
+        result = format!("{} + {}", result, var);
+    }
+    if let Some(last) = functions.checked_sub(1) {
+        result = format!("{} + f{}(1, 2)", result, last);
+    }
+    src.push_str(&result);
+    src.push('\n');
+    src
+}
+
+/// Generates an expression nested `depth` levels deep, from integer
+/// literals, the variables `vars`, and calls to the functions `f<n>` for each
+/// `n` in `callees`, which take two arguments.
+fn expr(rng: &mut Rng, depth: usize, vars: &[&str], callees: &[usize]) -> String {
+    // built from the innermost level out, so deep nesting doesn't recurse
+    let mut expr = leaf(rng, vars);
+    for _ in 1..depth {
+        let other = leaf(rng, vars);
+        expr = match rng.below(6) {
+            0 if !callees.is_empty() => {
+                let callee = callees[rng.below(callees.len())];
+                format!("f{}({}, {})", callee, expr, other)
+            }
+            // every operation keeps its result within the range of its
+            // operands and 100, so even values passed through long chains of
+            // calls never overflow to infinity
+            0 | 1 => format!("({} + {}) / 2", other, expr),
+            2 => format!("({} - {}) / 2", expr, other),
+            3 => format!("({} * {} / 100)", expr, rng.below(100)),
+            4 => format!("({} / {})", expr, rng.below(9) + 1),
+            _ => format!("-{}", expr),
+        };
+    }
+    expr
+}
+
+fn leaf(rng: &mut Rng, vars: &[&str]) -> String {
+    if !vars.is_empty() && rng.below(2) == 0 {
+        vars[rng.below(vars.len())].to_owned()
+    } else {
+        rng.below(100).to_string()
+    }
+}
+
+fn millis(time: Duration) -> String {
+    format!("{:.1} ms", time.as_secs_f64() * 1000.0)
+}
+
+/// Gets the most memory the process has had resident at once, where the
+/// platform reports it.
+fn peak_memory() -> String {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    status.lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map_or("unknown".to_owned(), |kb| format!("{:.1} MiB", kb as f64 / 1024.0))
+}
+
+/// A xorshift generator, which is all the randomness generating programs
+/// needs.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // xorshift gets stuck at 0
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    /// Gets a number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
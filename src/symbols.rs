@@ -0,0 +1,183 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use chumsky::{span::SimpleSpan, Parser};
+
+use crate::{
+    error::Diagnostics,
+    messages::Messages,
+    parse::{program, ContractKind, Expr, Spanned},
+    LangVersion,
+    MessageFormat,
+};
+
+/// What a name is declared as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Extern,
+}
+
+/// A name declared in a program, with the spans of every reference to it.
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The span of the name where it's declared.
+    pub decl: SimpleSpan,
+    pub refs: Vec<SimpleSpan>,
+}
+
+/// The declarations of a program and what each name in it refers to, resolved
+/// the same way codegen resolves them: a `let` is visible from the next
+/// declaration on, until it's shadowed; a function body only sees its own
+/// parameters; and a function can be called from its own body and anything
+/// after it.
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+    /// References that don't resolve to a declaration in the program, such as
+    /// calls to builtins or to a function before it's declared.
+    pub unresolved: Vec<Spanned<String>>,
+}
+
+impl SymbolTable {
+    pub fn build(root: &Expr) -> SymbolTable {
+        let mut table = SymbolTable { symbols: vec![], unresolved: vec![] };
+        let mut vars = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut expr = root;
+
+        loop {
+            match expr {
+                Expr::Let { name, rhs, then, .. } => {
+                    table.refs(rhs, &vars, &functions);
+                    let symbol = table.declare(name, SymbolKind::Variable);
+                    vars.insert(name.0.as_str(), symbol);
+                    expr = then;
+                }
+                Expr::Fn { name, args, contracts, body, then, .. } => {
+                    let symbol = table.declare(name, SymbolKind::Function);
+                    functions.insert(name.0.as_str(), symbol);
+                    let params = args.iter()
+                        .map(|arg| (arg.0.as_str(), table.declare(arg, SymbolKind::Parameter)))
+                        .collect::<HashMap<_, _>>();
+                    // `result` in an `ensures` clause is the value returned,
+                    // not a parameter
+                    let mut returned = params.clone();
+                    returned.remove("result");
+                    for (contract, _) in contracts {
+                        let scope = match contract.kind {
+                            ContractKind::Requires => &params,
+                            ContractKind::Ensures => &returned,
+                        };
+                        table.refs(&contract.cond, scope, &functions);
+                    }
+                    table.refs(body, &params, &functions);
+                    expr = then;
+                }
+                Expr::Extern { name, args, then, .. } => {
+                    let symbol = table.declare(name, SymbolKind::Extern);
+                    functions.insert(name.0.as_str(), symbol);
+                    for arg in args {
+                        table.declare(arg, SymbolKind::Parameter);
+                    }
+                    expr = then;
+                }
+                expr => {
+                    table.refs(expr, &vars, &functions);
+                    break;
+                }
+            }
+        }
+        table
+    }
+
+    /// Finds the symbol whose declaration or one of whose references contains
+    /// the byte offset.
+    pub fn at(&self, offset: usize) -> Option<&Symbol> {
+        let contains = |span: &SimpleSpan| span.start <= offset && offset <= span.end;
+        self.symbols.iter()
+            .find(|symbol| contains(&symbol.decl) || symbol.refs.iter().any(contains))
+    }
+
+    /// Gets the start of the declaration each reference resolves to, by the
+    /// start of the reference, or `None` for unresolved references.
+    pub fn resolutions(&self) -> HashMap<usize, Option<usize>> {
+        let resolved = self.symbols.iter().flat_map(|symbol| {
+            symbol.refs.iter().map(|r#ref| (r#ref.start, Some(symbol.decl.start)))
+        });
+        let unresolved = self.unresolved.iter().map(|(_, span)| (span.start, None));
+        resolved.chain(unresolved).collect()
+    }
+
+    fn declare(&mut self, name: &Spanned<String>, kind: SymbolKind) -> usize {
+        self.symbols.push(Symbol {
+            name: name.0.clone(),
+            kind,
+            decl: name.1,
+            refs: vec![],
+        });
+        self.symbols.len() - 1
+    }
+
+    /// Records the references in an expression, given the indices of the
+    /// symbols the variables and functions in scope refer to.
+    fn refs(
+        &mut self,
+        expr: &Expr,
+        vars: &HashMap<&str, usize>,
+        functions: &HashMap<&str, usize>
+    ) {
+        let mut stack = vec![expr];
+
+        while let Some(expr) = stack.pop() {
+            let (name, scope) = match expr {
+                Expr::Var(name, span) => ((name, *span), vars),
+                Expr::Call((name, span), args, _) => {
+                    stack.extend(args.iter().rev());
+                    ((name, *span), functions)
+                }
+                Expr::Neg(expr, _) => {
+                    stack.push(expr);
+                    continue;
+                }
+                Expr::Add(left, right, _)
+                | Expr::Sub(left, right, _)
+                | Expr::Mul(left, right, _)
+                | Expr::Div(left, right, _)
+                | Expr::Cmp(_, left, right, _) => {
+                    stack.push(right);
+                    stack.push(left);
+                    continue;
+                }
+                Expr::Num(..) | Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => {
+                    continue;
+                }
+            };
+            match scope.get(name.0.as_str()) {
+                Some(&symbol) => self.symbols[symbol].refs.push(name.1),
+                None => self.unresolved.push((name.0.clone(), name.1)),
+            }
+        }
+    }
+}
+
+/// Reads and parses a source file for the tools that work on its names,
+/// printing its syntax errors and exiting if it has any.
+pub fn parse_file(src_path: &Path) -> Result<(String, Expr), Box<dyn Error>> {
+    let Ok(src) = std::fs::read_to_string(src_path) else {
+        return Err("failed to open file".into());
+    };
+
+    let mut diags = Diagnostics::new(src_path, &src, None, Messages::new(MessageFormat::Human));
+    let ast = program(LangVersion::of(None, &src).features())
+        .parse(&src)
+        .into_result()
+        .map(|program| program.body)
+        .unwrap_or_else(|errs| {
+            diags.syntax_errors(errs);
+            diags.abort();
+        });
+    Ok((src, ast))
+}
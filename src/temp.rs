@@ -0,0 +1,44 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::messages::Messages;
+
+/// The directory a single compile writes its intermediate files to, such as
+/// the object files linked into an executable. Each invocation gets its own
+/// under `TMPDIR` (or the platform's temporary directory), so concurrent builds
+/// in the same directory can't clobber each other's intermediates.
+pub struct TempDir {
+    dir: tempfile::TempDir,
+}
+
+impl TempDir {
+    /// Runs `work` with a new temporary directory, then removes the directory
+    /// if `work` succeeded. If it failed, the directory is kept so its
+    /// contents can be inspected, and `messages` says where it is.
+    pub fn scoped<T>(
+        messages: Messages,
+        work: impl FnOnce(&TempDir) -> Result<T, Box<dyn Error>>
+    ) -> Result<T, Box<dyn Error>> {
+        let temp = TempDir {
+            dir: tempfile::Builder::new().prefix("foo_llvm-").tempdir()?,
+        };
+        let res = work(&temp);
+        if res.is_ok() {
+            temp.dir.close()?;
+        } else {
+            messages.kept_intermediates(&temp.dir.keep());
+        }
+        res
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Gets the path of an intermediate file in the directory.
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chumsky::{span::SimpleSpan, Parser};
+
+use crate::{
+    error::Diagnostics,
+    messages::{Message, Messages},
+    parse::{parser, Expr, Features},
+};
+
+/// How long each declaration of a program, and its final expression, took in
+/// one phase of the compiler, for `--time`.
+#[derive(Clone, Debug, Default)]
+pub struct DeclTimes(HashMap<SimpleSpan, Duration>);
+
+impl DeclTimes {
+    /// Adds time spent on a declaration, or on the final expression.
+    pub fn record(&mut self, decl: &Expr, elapsed: Duration) {
+        *self.0.entry(key(decl)).or_default() += elapsed;
+    }
+
+    fn get(&self, decl: &Expr) -> Duration {
+        self.0.get(&key(decl)).copied().unwrap_or_default()
+    }
+}
+
+/// Declarations are told apart by the spans of their names, which stay the
+/// same as the program is transformed, and the final expression by its own.
+fn key(decl: &Expr) -> SimpleSpan {
+    match decl {
+        Expr::Let { name, .. } | Expr::Fn { name, .. } | Expr::Extern { name, .. } => name.1,
+        expr => expr.span(),
+    }
+}
+
+/// Times parsing each declaration. The file is parsed in a single pass, so
+/// each declaration is parsed again on its own for its time, ended with a `;`
+/// and followed by a `0` standing in for the rest of the program.
+pub fn parse_times(root: &Expr, features: Features, diags: &Diagnostics) -> DeclTimes {
+    let decls = decls(root);
+    let codes = decls.iter()
+        .map(|decl| match decl {
+            Expr::Let { .. } | Expr::Fn { .. } | Expr::Extern { .. } => {
+                format!("{};\n0", diags.source(decl_span(decl)))
+            }
+            expr => diags.source(expr.span()).to_owned(),
+        })
+        .collect::<Vec<_>>();
+    let parser = parser(features);
+    let mut times = DeclTimes::default();
+    for (decl, code) in decls.into_iter().zip(&codes) {
+        let started = Instant::now();
+        let _ = parser.parse(code.as_str());
+        times.record(decl, started.elapsed());
+    }
+    times
+}
+
+/// Reports how long each declaration took to parse, to check and evaluate at
+/// compile time, and to generate code for, slowest first: as a table on stderr,
+/// or as a `decl-timing` message each under `--message-format json`.
+pub fn report(
+    root: &Expr,
+    parse: &DeclTimes,
+    sema: &DeclTimes,
+    codegen: &DeclTimes,
+    diags: &Diagnostics,
+    messages: Messages
+) {
+    let mut rows = decls(root)
+        .into_iter()
+        .map(|decl| (decl, [parse.get(decl), sema.get(decl), codegen.get(decl)]))
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|(_, times)| std::cmp::Reverse(times.iter().sum::<Duration>()));
+
+    if messages.enabled() {
+        for (decl, [parse, sema, codegen]) in &rows {
+            let (file, line, column) = diags.locate(key(decl).start);
+            messages.emit(Message::DeclTiming {
+                kind: kind(decl),
+                name: name(decl),
+                file,
+                line,
+                column,
+                parse: parse.as_secs_f64(),
+                sema: sema.as_secs_f64(),
+                codegen: codegen.as_secs_f64(),
+            });
+        }
+        return;
+    }
+
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    eprintln!(
+        "{:>10}  {:>10}  {:>10}  {:>10}  declaration",
+        "parse ms", "sema ms", "codegen ms", "total ms"
+    );
+    let mut totals = [Duration::ZERO; 3];
+    for (decl, times) in &rows {
+        let (file, line, column) = diags.locate(key(decl).start);
+        let total = times.iter().sum::<Duration>();
+        eprintln!(
+            "{:>10.3}  {:>10.3}  {:>10.3}  {:>10.3}  {} ({}:{}:{})",
+            ms(times[0]),
+            ms(times[1]),
+            ms(times[2]),
+            ms(total),
+            match name(decl) {
+                Some(name) => format!("{} {}", kind(decl), name),
+                None => kind(decl).to_owned(),
+            },
+            file.display(),
+            line,
+            column
+        );
+        for (sum, time) in totals.iter_mut().zip(times) {
+            *sum += *time;
+        }
+    }
+    eprintln!(
+        "{:>10.3}  {:>10.3}  {:>10.3}  {:>10.3}  total",
+        ms(totals[0]),
+        ms(totals[1]),
+        ms(totals[2]),
+        ms(totals.iter().sum())
+    );
+}
+
+/// Gets the declarations of a program in order, followed by its final
+/// expression.
+fn decls(root: &Expr) -> Vec<&Expr> {
+    let mut decls = vec![];
+    let mut e = root;
+    loop {
+        decls.push(e);
+        e = match e {
+            Expr::Let { then, .. } | Expr::Fn { then, .. } | Expr::Extern { then, .. } => then,
+            _ => return decls,
+        };
+    }
+}
+
+/// Gets the span of a declaration by itself, up to the end of its value,
+/// body, or last parameter, without the declarations after it, which its own
+/// span runs on through.
+fn decl_span(decl: &Expr) -> SimpleSpan {
+    let end = match decl {
+        Expr::Let { rhs, .. } => rhs.span().end,
+        Expr::Fn { body, .. } => body.span().end,
+        Expr::Extern { name, args, .. } => args.last().unwrap_or(name).1.end,
+        expr => expr.span().end,
+    };
+    SimpleSpan::new(decl.span().start, end)
+}
+
+fn kind(decl: &Expr) -> &'static str {
+    match decl {
+        Expr::Let { .. } => "let",
+        Expr::Fn { .. } => "fn",
+        Expr::Extern { .. } => "extern fn",
+        _ => "final expression",
+    }
+}
+
+fn name(decl: &Expr) -> Option<&str> {
+    match decl {
+        Expr::Let { name, .. } | Expr::Fn { name, .. } | Expr::Extern { name, .. } => {
+            Some(&name.0)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,119 @@
+use std::{error::Error, path::Path};
+
+use inkwell::{
+    context::Context,
+    module::Module,
+    targets::{FileType, TargetMachine},
+    OptimizationLevel,
+};
+
+use similar::TextDiff;
+
+use crate::llvm;
+
+/// Loads an LLVM IR or bitcode file, runs a pass pipeline over it, and writes
+/// it back out, like a small `opt`.
+///
+/// The output is IR if its path ends in `.ll` and bitcode otherwise. Without
+/// an output path, the IR is printed to stderr. If `show_diff` names a
+/// function, a unified diff of its IR before and after the pipeline is printed.
+pub fn opt(
+    input: &Path,
+    output: Option<&Path>,
+    passes: &str,
+    target: Option<&str>,
+    show_diff: Option<&str>
+) -> Result<(), Box<dyn Error>> {
+    let context = Context::create();
+    let module = llvm::load_module(&context, input)?;
+    let machine = module_machine(&module, target, true, OptimizationLevel::Default)?;
+    llvm::set_module_target(&module, &machine);
+
+    let before = show_diff
+        .map(|name| llvm::extract_function(&module, name, &machine))
+        .transpose()?
+        .map(|function| function.to_string());
+
+    llvm::run_passes(&module, &machine, passes)?;
+
+    if let (Some(name), Some(before)) = (show_diff, before) {
+        // the pipeline can remove the function entirely, e.g. by inlining it
+        // into its only caller
+        let after = match llvm::extract_function(&module, name, &machine) {
+            Ok(function) => function.to_string(),
+            Err(_) => {
+                eprintln!("note: `{}` was removed by the pipeline", name);
+                String::new()
+            }
+        };
+        let diff = TextDiff::from_lines(&before, &after);
+        print!(
+            "{}",
+            diff.unified_diff().header(
+                &format!("{} (before)", name),
+                &format!("{} (after {})", name, passes)
+            )
+        );
+    }
+
+    match output {
+        Some(path) if path.extension().is_some_and(|ext| ext == "ll") => {
+            llvm::write_module_to_file(&module, path)?;
+        }
+        Some(path) => llvm::write_bitcode_to_file(&module, path)?,
+        None => llvm::print_module(&module),
+    }
+    Ok(())
+}
+
+/// Compiles an LLVM IR or bitcode file to assembly or an object file, like a
+/// small `llc`.
+///
+/// The output is assembly if its path ends in `.s` and an object file
+/// otherwise; without an output path, it's an object file next to the input.
+/// The code generator optimizes at `level`.
+pub fn llc(
+    input: &Path,
+    output: Option<&Path>,
+    target: Option<&str>,
+    pie: bool,
+    level: OptimizationLevel
+) -> Result<(), Box<dyn Error>> {
+    let context = Context::create();
+    let module = llvm::load_module(&context, input)?;
+    let machine = module_machine(&module, target, pie, level)?;
+    llvm::set_module_target(&module, &machine);
+
+    let output = output.map_or_else(|| input.with_extension("o"), Path::to_owned);
+    let file_type = if output.extension().is_some_and(|ext| ext == "s") {
+        FileType::Assembly
+    } else {
+        FileType::Object
+    };
+    llvm::write_code_to_file(&machine, &module, &output, file_type)
+}
+
+/// Creates the machine to generate code for a module loaded from a file with:
+/// the given target, or else the one the module names, or else the current
+/// machine. It generates code at `level`.
+fn module_machine(
+    module: &Module,
+    target: Option<&str>,
+    pie: bool,
+    level: OptimizationLevel
+) -> Result<TargetMachine, Box<dyn Error>> {
+    let triple = match target {
+        Some(triple) => llvm::target_triple(Some(triple)),
+        None => {
+            let triple = module.get_triple();
+            if triple.as_str().to_bytes().is_empty() {
+                llvm::target_triple(None)
+            } else {
+                triple
+            }
+        }
+    };
+    let target = llvm::init_target(&triple)?;
+    llvm::machine_from_target(&target, &triple, pie, level)
+        .ok_or_else(|| "failed to build target machine".into())
+}